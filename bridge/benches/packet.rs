@@ -0,0 +1,85 @@
+// Cost of the packet (de)serialization every request goes through: bincode
+// serializes the host packets, nom parses the replies of the secondary.
+//
+// The bridge is a binary crate, the packet module is built in here on its own.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+#[allow(dead_code)]
+#[path = "../src/gpio/packet/mod.rs"]
+mod packet;
+
+// The only item of the bridge utils the packet module depends on
+mod utils {
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub struct Version {
+        pub major: u8,
+        pub minor: u8,
+        pub patch: u8,
+    }
+}
+
+use packet::{GpioValue, SecondaryCmd, Serializer};
+
+fn serialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialize");
+    let values: Vec<(u8, GpioValue)> = (0..packet::SET_GPIO_VALUES_MAX as u8)
+        .map(|pin| (pin, GpioValue::High))
+        .collect();
+
+    group.bench_function("GetGpioValue", |b| {
+        b.iter(|| packet::GetGpioValue::new(&mut 0, black_box(3)).serialize())
+    });
+    group.bench_function("SetGpioValue", |b| {
+        b.iter(|| packet::SetGpioValue::new(&mut 0, black_box(3), GpioValue::High).serialize())
+    });
+    group.bench_function("SetGpioValues", |b| {
+        b.iter(|| {
+            packet::SetGpioValues::new(&mut 0, black_box(&values))
+                .unwrap()
+                .serialize()
+        })
+    });
+    group.finish();
+}
+
+fn deserialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("deserialize");
+    let status_is = [SecondaryCmd::StatusIs as u8, 2, 7, 0];
+    let gpio_value_is = [SecondaryCmd::GpioValueIs as u8, 2, 7, GpioValue::High as u8];
+    let unique_id_is = [
+        [SecondaryCmd::UniqueIdIs as u8, 9, 7].as_slice(),
+        &0x0123_4567_89ab_cdef_u64.to_le_bytes(),
+    ]
+    .concat();
+    let gpio_name_is = [
+        [SecondaryCmd::GpioNameIs as u8, 8, 7].as_slice(),
+        b"GPIO_3\0",
+    ]
+    .concat();
+
+    group.bench_function("StatusIs", |b| {
+        b.iter(|| packet::StatusIs::deserialize(black_box(&status_is)))
+    });
+    group.bench_function("GpioValueIs", |b| {
+        b.iter(|| packet::GpioValueIs::deserialize(black_box(&gpio_value_is)))
+    });
+    group.bench_function("UniqueIdIs", |b| {
+        b.iter(|| packet::UniqueIdIs::deserialize(black_box(&unique_id_is)))
+    });
+    group.bench_function("GpioNameIs", |b| {
+        b.iter(|| packet::GpioNameIs::deserialize(black_box(&gpio_name_is)))
+    });
+    group.finish();
+}
+
+// A transport read holding several replies, as split by the reader thread
+fn split(c: &mut Criterion) {
+    let buffer: Vec<u8> = (0..16)
+        .flat_map(|seq| [SecondaryCmd::GpioValueIs as u8, 2, seq, GpioValue::Low as u8])
+        .collect();
+
+    c.bench_function("split", |b| b.iter(|| packet::split(black_box(&buffer))));
+}
+
+criterion_group!(benches, serialize, deserialize, split);
+criterion_main!(benches);
@@ -0,0 +1,185 @@
+use anyhow::{bail, Result};
+use mio_signals::{Signal, Signals};
+use std::time::{Duration, Instant};
+
+use crate::gpio;
+use crate::utils;
+
+#[cfg(test)]
+mod tests;
+
+// Outcome of a toggle benchmark, printed to stdout
+struct Report {
+    pin: u8,
+    name: String,
+    transitions: u64,
+    elapsed: Duration,
+    rtt: Option<gpio::RttStats>,
+    errors: u64,
+    timeouts: u64,
+}
+
+impl Report {
+    fn rate(&self) -> f64 {
+        if self.elapsed.is_zero() {
+            return 0.0;
+        }
+
+        self.transitions as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Pin {} ({}): {} transitions in {:.3}s, {:.1} transitions/s",
+            self.pin,
+            self.name,
+            self.transitions,
+            self.elapsed.as_secs_f64(),
+            self.rate()
+        )?;
+        match &self.rtt {
+            Some(rtt) => writeln!(f, "RTT {{ {} }}", rtt)?,
+            None => writeln!(f, "RTT {{ Samples: 0 }}")?,
+        }
+        write!(f, "Errors: {}, Timeouts: {}", self.errors, self.timeouts)
+    }
+}
+
+fn toggled(value: gpio::GpioValue) -> gpio::GpioValue {
+    match value {
+        gpio::GpioValue::Low => gpio::GpioValue::High,
+        gpio::GpioValue::High => gpio::GpioValue::Low,
+    }
+}
+
+// Toggles the pin as an output as fast as the link allows and prints the
+// achieved rate to stdout, without the Kernel Driver. The pin is put back as
+// it was found, a signal cuts the benchmark short.
+pub fn run(gpio: &gpio::Handle, pin: u8, duration_ms: u64, mut signals: Signals) -> Result<()> {
+    let record = match gpio
+        .pin_records()
+        .into_iter()
+        .find(|record| record.pin == pin)
+    {
+        Some(record) => record,
+        None => bail!("Benchmarked pin {} is not managed by the bridge", pin),
+    };
+    let direction = record.direction.map(gpio::GpioDirection::from);
+    let value = record.value.map(gpio::GpioValue::from);
+
+    log::info!(
+        "Toggling pin {} ({}) for {} ms, the Kernel Driver is not used",
+        pin,
+        record.name,
+        duration_ms
+    );
+
+    let report = toggle(
+        gpio,
+        pin,
+        &record.name,
+        value,
+        Duration::from_millis(duration_ms),
+        &mut signals,
+    );
+    let restored = restore(gpio, pin, direction, value);
+
+    let report = report?;
+    restored?;
+
+    println!("{}", report);
+
+    bail!(utils::Shutdown {
+        reason: utils::ShutdownReason::Benchmark,
+        detail: utils::ProcessExit::Context(anyhow::anyhow!(
+            "Toggled pin {} at {:.1} transitions/s",
+            pin,
+            report.rate()
+        ))
+        .into(),
+    });
+}
+
+fn toggle(
+    gpio: &gpio::Handle,
+    pin: u8,
+    name: &str,
+    value: Option<gpio::GpioValue>,
+    duration: Duration,
+    signals: &mut Signals,
+) -> Result<Report> {
+    if let Err(err) = gpio.set_gpio_direction(pin, gpio::GpioDirection::Output) {
+        bail!(
+            "Failed to set benchmarked pin {} as output, Err: {}",
+            pin,
+            err
+        );
+    }
+
+    // The first write already changes the value the pin had
+    let mut value = toggled(value.unwrap_or(gpio::GpioValue::Low));
+    let mut rtts = vec![];
+    let counters = gpio.link_counters();
+    let start = Instant::now();
+
+    while start.elapsed() < duration {
+        let sent = Instant::now();
+        match gpio.set_gpio_value(pin, value) {
+            Ok(()) => {
+                rtts.push(sent.elapsed());
+                value = toggled(value);
+            }
+            // Counted by the link counters
+            Err(gpio::Error::Recoverable(_)) => (),
+            Err(gpio::Error::Unrecoverable(err)) => bail!("{}", err),
+        }
+
+        if let Some(signal) = signals.receive()? {
+            match signal {
+                Signal::Interrupt | Signal::Terminate | Signal::User1 => {
+                    log::warn!("Received signal: {:?}, benchmark cut short", signal);
+                    break;
+                }
+                _ => log::warn!("Received unexpected signal: {:?}", signal),
+            }
+        }
+    }
+
+    let elapsed = start.elapsed();
+    let after = gpio.link_counters();
+
+    Ok(Report {
+        pin,
+        name: name.to_string(),
+        transitions: rtts.len() as u64,
+        elapsed,
+        rtt: gpio::RttStats::from_samples(&rtts),
+        errors: after.errors - counters.errors,
+        timeouts: after.timeouts - counters.timeouts,
+    })
+}
+
+// An output gets its value back, any other pin its direction. A pin whose
+// direction was never set is left disabled.
+fn restore(
+    gpio: &gpio::Handle,
+    pin: u8,
+    direction: Option<gpio::GpioDirection>,
+    value: Option<gpio::GpioValue>,
+) -> Result<()> {
+    let result = match (direction, value) {
+        (Some(gpio::GpioDirection::Output), Some(value)) => gpio.set_gpio_value(pin, value),
+        (Some(gpio::GpioDirection::Output), None) => Ok(()),
+        (Some(direction), _) => gpio.set_gpio_direction(pin, direction),
+        (None, _) => gpio.set_gpio_direction(pin, gpio::GpioDirection::Disabled),
+    };
+
+    if let Err(err) = result {
+        bail!("Failed to restore benchmarked pin {}, Err: {}", pin, err);
+    }
+
+    Ok(())
+}
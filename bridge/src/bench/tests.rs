@@ -0,0 +1,35 @@
+use super::*;
+
+#[test]
+fn report_rate_and_display() {
+    let report = Report {
+        pin: 3,
+        name: "LED0".to_string(),
+        transitions: 500,
+        elapsed: Duration::from_millis(250),
+        rtt: None,
+        errors: 2,
+        timeouts: 1,
+    };
+
+    assert_eq!(report.rate(), 2000.0);
+    assert_eq!(
+        report.to_string(),
+        "Pin 3 (LED0): 500 transitions in 0.250s, 2000.0 transitions/s\nRTT { Samples: 0 }\nErrors: 2, Timeouts: 1"
+    );
+}
+
+#[test]
+fn report_rate_of_no_time_is_zero() {
+    let report = Report {
+        pin: 3,
+        name: "LED0".to_string(),
+        transitions: 0,
+        elapsed: Duration::ZERO,
+        rtt: None,
+        errors: 0,
+        timeouts: 0,
+    };
+
+    assert_eq!(report.rate(), 0.0);
+}
@@ -0,0 +1,66 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::gpio;
+
+#[cfg(test)]
+mod tests;
+
+// Describes the chip as discovered, for integrators moving to a static board
+// description. The Kernel Driver registers the chip at runtime and does not
+// bind to the node.
+pub fn overlay(unique_id: u64, label: &str, pins: &[gpio::PinInfo]) -> String {
+    let names: Vec<String> = pins.iter().map(|pin| quote(&pin.name)).collect();
+
+    let mut dts = String::new();
+    dts.push_str("/dts-v1/;\n");
+    dts.push_str("/plugin/;\n\n");
+    dts.push_str(&format!(
+        "/* CPC GPIO Expander, unique id {} */\n",
+        unique_id
+    ));
+    dts.push_str("/ {\n");
+    dts.push_str("\tfragment@0 {\n");
+    dts.push_str("\t\ttarget-path = \"/\";\n");
+    dts.push_str("\t\t__overlay__ {\n");
+    dts.push_str("\t\t\tcpc_gpio: cpc-gpio {\n");
+    dts.push_str("\t\t\t\tgpio-controller;\n");
+    dts.push_str("\t\t\t\t#gpio-cells = <2>;\n");
+    dts.push_str(&format!("\t\t\t\tngpios = <{}>;\n", pins.len()));
+    dts.push_str(&format!("\t\t\t\tlabel = {};\n", quote(label)));
+    if !names.is_empty() {
+        dts.push_str(&format!(
+            "\t\t\t\tgpio-line-names = {};\n",
+            names.join(", ")
+        ));
+    }
+    dts.push_str("\t\t\t};\n");
+    dts.push_str("\t\t};\n");
+    dts.push_str("\t};\n");
+    dts.push_str("};\n");
+    dts
+}
+
+pub fn write(path: &Path, gpio: &gpio::Handle) -> Result<()> {
+    let pins: Vec<gpio::PinInfo> = gpio.pins().collect();
+
+    std::fs::write(path, overlay(gpio.unique_id(), &gpio.chip.label, &pins))
+        .with_context(|| format!("Failed to write devicetree overlay ({})", path.display()))?;
+
+    log::info!("Wrote devicetree overlay to {}", path.display());
+
+    Ok(())
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
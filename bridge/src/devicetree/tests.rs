@@ -0,0 +1,41 @@
+use super::*;
+
+fn pin(pin: u8, name: &str) -> gpio::PinInfo {
+    gpio::PinInfo {
+        pin,
+        name: name.to_string(),
+        raw_name: name.to_string(),
+    }
+}
+
+#[test]
+fn overlay_describes_the_chip() {
+    let pins = [pin(0, "LED0"), pin(1, "BTN \"0\"")];
+
+    assert_eq!(
+        overlay(42, "cpc_gpio", &pins),
+        "/dts-v1/;\n\
+         /plugin/;\n\
+         \n\
+         /* CPC GPIO Expander, unique id 42 */\n\
+         / {\n\
+         \tfragment@0 {\n\
+         \t\ttarget-path = \"/\";\n\
+         \t\t__overlay__ {\n\
+         \t\t\tcpc_gpio: cpc-gpio {\n\
+         \t\t\t\tgpio-controller;\n\
+         \t\t\t\t#gpio-cells = <2>;\n\
+         \t\t\t\tngpios = <2>;\n\
+         \t\t\t\tlabel = \"cpc_gpio\";\n\
+         \t\t\t\tgpio-line-names = \"LED0\", \"BTN \\\"0\\\"\";\n\
+         \t\t\t};\n\
+         \t\t};\n\
+         \t};\n\
+         };\n"
+    );
+}
+
+#[test]
+fn overlay_without_pins_has_no_line_names() {
+    assert!(!overlay(42, "cpc_gpio", &[]).contains("gpio-line-names"));
+}
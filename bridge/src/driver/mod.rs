@@ -4,15 +4,20 @@ use neli::{
         nl::{NlmF, NlmFFlags},
         socket::NlFamily,
     },
+    err::{NlError, WrappedError},
     genl::{Genlmsghdr, Nlattr},
     nl::{NlPayload, Nlmsghdr},
     socket::NlSocketHandle,
     types::{Buffer, GenlBuffer},
 };
-use std::sync::{mpsc, Mutex};
+use std::ops::ControlFlow;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 
 mod packet;
 pub use packet::Exit;
+pub use packet::GetAllGpioValues;
 pub use packet::GetGpioValue;
 pub use packet::GpioConfig;
 pub use packet::GpioDirection;
@@ -21,37 +26,311 @@ pub use packet::Packet;
 pub use packet::SetGpioConfig;
 pub use packet::SetGpioDirection;
 pub use packet::SetGpioValue;
+pub use packet::SetGpioValues;
 pub use packet::Status;
 
+#[cfg(test)]
+mod tests;
+
 use crate::utils;
 
+// Most likely another bridge serving the same secondary
+#[derive(thiserror::Error, Debug)]
+#[error("Failed to initialize Kernel Driver, a chip with unique id {unique_id} is already initialized, another bridge instance may be running for this secondary")]
+pub struct AlreadyInitialized {
+    pub unique_id: u64,
+}
+
+// The chip cannot be registered again while one of its GPIO's is requested
+#[derive(thiserror::Error, Debug)]
+#[error(
+    "Failed to add GPIO's to Kernel Driver (UID: {unique_id}), a GPIO of the chip is requested"
+)]
+pub struct ChipBusy {
+    pub unique_id: u64,
+}
+
 pub const VERSION: utils::Version = utils::Version {
     major: 1,
-    minor: 0,
+    minor: 3,
     patch: 0,
 };
 
 const GENL_API_VERSION: u8 = 1;
 const GENL_FAMILY_NAME: &str = "CPC_GPIO_GENL";
-const GENL_MULTICAST_FAMILY_NAME: &str = "CPC_GPIO_GENL_M";
+pub const GENL_MULTICAST_FAMILY_NAME: &str = "CPC_GPIO_GENL_M";
 const GENL_MULTICAST_UID_ALL: u64 = 0;
 
+// CPC_GPIO_TIMEOUT_MSEC, the Kernel Driver gives up on a reply after it
+pub const REPLY_TIMEOUT_MS: u64 = 2000;
+
+// Kernel Driver API minor version from which AddGpios is supported
+const ADD_GPIOS_MINOR: u8 = 3;
+// Kernel Driver API minor version from which UpdateNames is supported
+const UPDATE_NAMES_MINOR: u8 = 3;
+// Kernel Driver API minor version from which the Bridge may send Exit
+const EXIT_MESSAGE_MINOR: u8 = 2;
+
+// The kernel may still be freeing the chip of a previous instance
+const INIT_RETRYABLE_ERRNOS: [i32; 2] = [libc::EAGAIN, libc::EBUSY];
+// Answered when a chip with the same unique id is already initialized
+const INIT_DUPLICATE_ERRNO: i32 = libc::EPERM;
+
+// Status answered by the Kernel Driver to a command. The module answers plain
+// errnos, which say little on their own: their meaning for the command is
+// taken from the module, other codes read as the errno.
+struct KernelStatus {
+    cmd: packet::Command,
+    status: u32,
+}
+
+impl KernelStatus {
+    fn new(cmd: packet::Command, status: u32) -> Self {
+        Self { cmd, status }
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        match (&self.cmd, self.status as i32) {
+            (packet::Command::Init, libc::EPERM) => {
+                Some("a chip with this unique id is already initialized")
+            }
+            (packet::Command::Init, libc::ENOMEM) => Some("the kernel failed to allocate the chip"),
+            (packet::Command::Init, libc::EBUSY) => Some(
+                "the chip of a previous instance is still registered, it is freed once its GPIO's are released",
+            ),
+            (packet::Command::Init, libc::EINVAL) => Some(
+                "the request lacks an attribute, or the GPIO names do not match the GPIO count",
+            ),
+            (packet::Command::Deinit, libc::EPERM) => Some(
+                "a GPIO of the chip is still requested, it must be released before the chip is removed",
+            ),
+            (packet::Command::Deinit, libc::EINVAL) => {
+                Some("the request lacks the unique id")
+            }
+            (packet::Command::AddGpios | packet::Command::UpdateNames, libc::ENODEV) => {
+                Some("the chip is not initialized")
+            }
+            (packet::Command::UpdateNames, libc::EBUSY) => Some(
+                "a GPIO of the chip is requested, the chip is only registered again once they are all released",
+            ),
+            (packet::Command::UpdateNames, libc::EINVAL) => {
+                Some("the GPIO names do not match the registered GPIO count")
+            }
+            (packet::Command::AddGpios, libc::EINVAL) => Some(
+                "the first line does not follow the registered lines, or the GPIO names do not match the GPIO count",
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for KernelStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let errno = std::io::Error::from_raw_os_error(self.status as i32);
+
+        match self.description() {
+            Some(description) => write!(f, "{}, {}", errno, description),
+            None => write!(f, "{}", errno),
+        }
+    }
+}
+
+const READER_MULTICAST_TOKEN: mio::Token = mio::Token(0);
+const READER_STOP_TOKEN: mio::Token = mio::Token(1);
+// Bounds the wait for a stopped reader, which may be blocked on a full channel
+const READER_STOP_TIMEOUT_MS: u64 = 500;
+
+type GenlPacket = Nlmsghdr<u16, Genlmsghdr<packet::Command, packet::Attribute>>;
+
+// Requests to the Kernel Driver and their replies, a test double stands in
+// for the socket in tests
+trait Unicast: Send {
+    fn send(&mut self, packet: GenlPacket) -> Result<()>;
+    fn recv(&mut self) -> Result<Option<GenlPacket>>;
+}
+
+impl Unicast for NlSocketHandle {
+    fn send(&mut self, packet: GenlPacket) -> Result<()> {
+        Ok(NlSocketHandle::send(self, packet)?)
+    }
+
+    fn recv(&mut self) -> Result<Option<GenlPacket>> {
+        loop {
+            match NlSocketHandle::recv(self) {
+                Ok(packet) => return Ok(packet),
+                Err(err) if is_interrupted(&err) => {
+                    log::debug!("Unicast socket read interrupted, retrying");
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+// A reader that can be stopped, see Handle::stop_reader
+struct PollReader {
+    stop: mio::unix::pipe::Sender,
+    thread: std::thread::JoinHandle<()>,
+}
+
+// A line of the registered chip and the secondary pin behind it, the lines
+// are contiguous while the pins may skip the unmanaged ones
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub pin: u32,
+    pub name: String,
+}
+
 pub struct Handle {
     pub exit: utils::ThreadExit,
-    data_rx: Mutex<mpsc::Receiver<Nlmsghdr<u16, Genlmsghdr<packet::Command, packet::Attribute>>>>,
-    unicast: Mutex<NlSocketHandle>,
+    reader: Mutex<Option<PollReader>>,
+    data_rx: Arc<Mutex<mpsc::Receiver<GenlPacket>>>,
+    unicast: Mutex<Box<dyn Unicast>>,
     family_id: u16,
+    driver_version: utils::Version,
+    // Secondary pin behind each registered line
+    lines: Mutex<Vec<u32>>,
+    // Shared with the reader, which filters the requests of other chips
+    unique_id: Arc<AtomicU64>,
+    retry_policy: utils::RetryPolicy,
+    reader_heartbeat: Arc<utils::Heartbeat>,
+}
+
+const RMEM_MAX_PATH: &str = "/proc/sys/net/core/rmem_max";
+
+// SO_RCVBUF silently caps the size at net.core.rmem_max, the clamp is done
+// here so that it can be logged
+fn clamp_rcvbuf(requested: usize, rmem_max: Option<usize>) -> usize {
+    let max = rmem_max.unwrap_or(usize::MAX);
+    requested.min(max).min(libc::c_int::MAX as usize)
+}
+
+fn set_rcvbuf(socket: &NlSocketHandle, name: &str, requested: usize) -> Result<()> {
+    let rmem_max = match std::fs::read_to_string(RMEM_MAX_PATH) {
+        Ok(content) => content.trim().parse::<usize>().ok(),
+        Err(err) => {
+            log::warn!("Failed to read {}, Err: {}", RMEM_MAX_PATH, err);
+            None
+        }
+    };
+
+    let size = clamp_rcvbuf(requested, rmem_max);
+    if size != requested {
+        log::warn!(
+            "{} socket receive buffer of {} bytes clamped to {} bytes",
+            name,
+            requested,
+            size
+        );
+    }
+
+    let value = size as libc::c_int;
+    // SAFETY: the socket descriptor is open for as long as `socket` is
+    // borrowed, value is a live c_int and its size is passed along
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUF,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        bail!(
+            "Failed to set the {} socket receive buffer, Err: {}",
+            name,
+            std::io::Error::last_os_error()
+        );
+    }
+
+    // The Kernel doubles the value to account for its bookkeeping overhead
+    let mut effective: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: as above, the Kernel writes at most len bytes to effective
+    let ret = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUF,
+            &mut effective as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        bail!(
+            "Failed to get the {} socket receive buffer, Err: {}",
+            name,
+            std::io::Error::last_os_error()
+        );
+    }
+
+    log::info!(
+        "{} socket receive buffer: {} bytes (Requested: {})",
+        name,
+        effective,
+        requested
+    );
+
+    Ok(())
+}
+
+// The Kernel Driver may be loaded after the Bridge started, e.g. when the
+// boot does not order the module load first
+pub fn wait_for_driver(timeout_ms: u64, retry_policy: &utils::RetryPolicy) -> Result<()> {
+    let mut unicast = NlSocketHandle::connect(NlFamily::Generic, Some(0), &[])?;
+
+    let now = std::time::Instant::now();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match unicast.resolve_genl_family(GENL_FAMILY_NAME) {
+            Ok(_) => {
+                if attempt > 1 {
+                    log::info!("Kernel Driver loaded (Attempt: {})", attempt);
+                }
+                return Ok(());
+            }
+            Err(err) => {
+                let delay_ms = match retry_policy
+                    .with_timeout(timeout_ms)
+                    .delay_ms(attempt, now.elapsed())
+                {
+                    Some(delay_ms) => delay_ms,
+                    None => bail!(
+                        "The Generic Netlink family ({}) can't be found after {} ms. Is the Kernel Driver loaded? Err: {}",
+                        GENL_FAMILY_NAME,
+                        timeout_ms,
+                        err
+                    ),
+                };
+                log::info!(
+                    "Waiting for the Kernel Driver, retrying in {} ms (Attempt: {}), Err: {}",
+                    delay_ms,
+                    attempt,
+                    err
+                );
+                std::thread::sleep(std::time::Duration::from_millis(delay_ms));
+            }
+        }
+    }
 }
 
 impl Handle {
     pub fn new(
         deinit_and_exit: bool,
+        channel_config: &utils::ChannelConfig,
+        retry_policy: &utils::RetryPolicy,
         unique_id: u64,
         chip_label: &str,
-        names: &Vec<String>,
+        lines: &[Line],
+        socket_config: &utils::SocketConfig,
     ) -> Result<Self> {
         // Connect to generic netlink unicast
         let mut unicast = NlSocketHandle::connect(NlFamily::Generic, Some(0), &[])?;
+        if let Some(size) = socket_config.unicast_rcvbuf {
+            set_rcvbuf(&unicast, "Unicast", size)?;
+        }
 
         let family_id = match unicast.resolve_genl_family(GENL_FAMILY_NAME) {
             Ok(family_id) => family_id,
@@ -63,77 +342,64 @@ impl Handle {
             }
         };
 
-        let multicast_group =
-            match unicast.resolve_nl_mcast_group(GENL_FAMILY_NAME, GENL_MULTICAST_FAMILY_NAME) {
-                Ok(multicast_group) => multicast_group,
-                Err(err) => {
-                    bail!(
-                        "Failed to resolve using Generic Netlink ({}) Multicast ({}), Err: {}",
-                        GENL_FAMILY_NAME,
-                        GENL_MULTICAST_FAMILY_NAME,
-                        err,
-                    );
-                }
-            };
+        let multicast_groups =
+            resolve_multicast_groups(&mut unicast, &socket_config.multicast_groups)?;
 
-        // Connect to generic netlink multicast
-        let mut multicast =
-            NlSocketHandle::connect(NlFamily::Generic, Some(0), &[multicast_group])?;
-
-        let (data_tx, data_rx) = std::sync::mpsc::channel::<
-            Nlmsghdr<u16, Genlmsghdr<packet::Command, packet::Attribute>>,
-        >();
-
-        let (mut exit_sender, exit_receiver) = mio::unix::pipe::new()?;
-
-        std::thread::Builder::new()
-            .name("driver".to_string())
-            .spawn(move || loop {
-                let result = (|| -> Result<()> {
-                    let packet = match multicast.recv() {
-                        Ok(packet) => packet.context("Multicast socked was closed")?,
-                        Err(err) => bail!("Failed to read from Multicast socket, Err: {}", err),
-                    };
-
-                    let filtered = match filter_packet(unique_id, &packet) {
-                        Ok(filtered) => filtered,
-                        Err(err) => bail!("Failed to filter packet, Err: {}", err),
-                    };
-
-                    if !filtered {
-                        if let Err(err) = data_tx.send(packet) {
-                            bail!("Failed to send to Driver channel, Err: {}", err)
-                        }
-                    }
+        // Connect to generic netlink multicast, one socket joins every group
+        let multicast = NlSocketHandle::connect(NlFamily::Generic, Some(0), &multicast_groups)?;
+        if let Some(size) = socket_config.multicast_rcvbuf {
+            set_rcvbuf(&multicast, "Multicast", size)?;
+        }
 
-                    Ok(())
-                })();
+        let (data_tx, data_rx) = data_channel(channel_config);
 
-                if let Err(err) = result {
-                    utils::ThreadExit::notify(&mut exit_sender, &format!("{}", err));
-                    return;
-                }
-            })?;
+        let (exit_sender, exit_receiver) = mio::unix::pipe::new()?;
 
-        let handle = Self {
+        let reader_unique_id = Arc::new(AtomicU64::new(unique_id));
+        let reader_heartbeat = Arc::new(utils::Heartbeat::new("Driver"));
+        let forward = Forward {
+            unique_id: reader_unique_id.clone(),
+            data_tx,
+            heartbeat: reader_heartbeat.clone(),
+        };
+
+        let reader = match socket_config.read {
+            utils::DriverRead::Blocking => {
+                spawn_blocking_reader(multicast, forward, exit_sender)?;
+                None
+            }
+            utils::DriverRead::Poll => Some(spawn_poll_reader(multicast, forward, exit_sender)?),
+        };
+
+        let mut handle = Self {
             exit: utils::ThreadExit {
                 receiver: Mutex::new(exit_receiver),
             },
-            data_rx: Mutex::new(data_rx),
-            unicast: Mutex::new(unicast),
+            reader: Mutex::new(reader),
+            data_rx,
+            unicast: Mutex::new(Box::new(unicast)),
             family_id,
+            driver_version: VERSION,
+            lines: Mutex::new(vec![]),
+            unique_id: reader_unique_id,
+            retry_policy: *retry_policy,
+            reader_heartbeat,
         };
 
-        handle.deinit(unique_id)?;
+        handle.driver_version = handle.deinit_with_version(unique_id)?;
 
         if deinit_and_exit {
-            bail!(utils::ProcessExit::Context(anyhow!(
-                "Deinitialized Kernel Driver (UID: {})",
-                unique_id
-            )));
+            bail!(utils::Shutdown {
+                reason: utils::ShutdownReason::Deinit,
+                detail: utils::ProcessExit::Context(anyhow!(
+                    "Deinitialized Kernel Driver (UID: {})",
+                    unique_id
+                ))
+                .into(),
+            });
         }
 
-        handle.init(unique_id, chip_label, names)?;
+        handle.init(retry_policy, unique_id, chip_label, lines)?;
 
         Ok(handle)
     }
@@ -159,7 +425,7 @@ impl Handle {
                 false,
                 false,
                 packet::Attribute::GpioPin,
-                gpio_pin,
+                self.line(gpio_pin)?,
             )?);
 
             attributes.push(Nlattr::new(
@@ -184,6 +450,58 @@ impl Handle {
         Ok(())
     }
 
+    pub fn get_all_gpio_values_reply(
+        &self,
+        unique_id: u64,
+        batch_id: u32,
+        gpio_values: Vec<u32>,
+        statuses: Vec<packet::Status>,
+    ) -> Result<()> {
+        let mut attributes = GenlBuffer::new();
+
+        attributes.push(Nlattr::new(
+            false,
+            false,
+            packet::Attribute::UniqueId,
+            unique_id,
+        )?);
+
+        attributes.push(Nlattr::new(
+            false,
+            false,
+            packet::Attribute::BatchId,
+            batch_id,
+        )?);
+
+        attributes.push(Nlattr::new(
+            false,
+            false,
+            packet::Attribute::Status,
+            packet::Status::Ok as u32,
+        )?);
+
+        attributes.push(Nlattr::new(
+            false,
+            false,
+            packet::Attribute::GpioValues,
+            gpio_values,
+        )?);
+
+        attributes.push(Nlattr::new(
+            false,
+            false,
+            packet::Attribute::GpioStatuses,
+            statuses
+                .into_iter()
+                .map(|status| status as u32)
+                .collect::<Vec<u32>>(),
+        )?);
+
+        self.send(packet::Command::GetAllGpioValues, attributes)?;
+
+        Ok(())
+    }
+
     pub fn set_gpio_value_reply(
         &self,
         unique_id: u64,
@@ -204,7 +522,7 @@ impl Handle {
                 false,
                 false,
                 packet::Attribute::GpioPin,
-                gpio_pin,
+                self.line(gpio_pin)?,
             )?);
 
             attributes.push(Nlattr::new(
@@ -220,6 +538,42 @@ impl Handle {
         Ok(())
     }
 
+    pub fn set_gpio_values_reply(
+        &self,
+        unique_id: u64,
+        batch_id: u32,
+        status: Option<packet::Status>,
+    ) -> Result<()> {
+        if let Some(status) = status {
+            let mut attributes = GenlBuffer::new();
+
+            attributes.push(Nlattr::new(
+                false,
+                false,
+                packet::Attribute::UniqueId,
+                unique_id,
+            )?);
+
+            attributes.push(Nlattr::new(
+                false,
+                false,
+                packet::Attribute::BatchId,
+                batch_id,
+            )?);
+
+            attributes.push(Nlattr::new(
+                false,
+                false,
+                packet::Attribute::Status,
+                status as u32,
+            )?);
+
+            self.send(packet::Command::SetGpioValues, attributes)?;
+        }
+
+        Ok(())
+    }
+
     pub fn set_gpio_config_reply(
         &self,
         unique_id: u64,
@@ -240,7 +594,7 @@ impl Handle {
                 false,
                 false,
                 packet::Attribute::GpioPin,
-                gpio_pin,
+                self.line(gpio_pin)?,
             )?);
 
             attributes.push(Nlattr::new(
@@ -276,7 +630,7 @@ impl Handle {
                 false,
                 false,
                 packet::Attribute::GpioPin,
-                gpio_pin,
+                self.line(gpio_pin)?,
             )?);
 
             attributes.push(Nlattr::new(
@@ -292,7 +646,234 @@ impl Handle {
         Ok(())
     }
 
+    pub fn reader_heartbeat(&self) -> Arc<utils::Heartbeat> {
+        self.reader_heartbeat.clone()
+    }
+
     pub fn deinit(&self, unique_id: u64) -> Result<()> {
+        self.deinit_with_version(unique_id)?;
+
+        Ok(())
+    }
+
+    // Moves the chip to a new unique id, the requests of the previous one are
+    // filtered out from then on
+    pub fn reinit(
+        &self,
+        previous_id: u64,
+        unique_id: u64,
+        label: &str,
+        lines: &[Line],
+    ) -> Result<()> {
+        self.deinit(previous_id)?;
+        self.unique_id.store(unique_id, Ordering::SeqCst);
+        self.init(&self.retry_policy, unique_id, label, lines)
+    }
+
+    // Stops a --driver-read poll reader, a blocking reader only stops on error
+    pub fn stop_reader(&self) {
+        let mut reader = match self.reader.lock().map(|mut reader| reader.take()) {
+            Ok(Some(reader)) => reader,
+            Ok(None) | Err(_) => return,
+        };
+
+        if let Err(err) = std::io::Write::write_all(&mut reader.stop, &[0]) {
+            log::warn!("Failed to stop the Multicast reader, Err: {}", err);
+            return;
+        }
+
+        let deadline =
+            std::time::Instant::now() + std::time::Duration::from_millis(READER_STOP_TIMEOUT_MS);
+
+        while !reader.thread.is_finished() {
+            if std::time::Instant::now() >= deadline {
+                log::warn!(
+                    "Multicast reader did not stop within {} ms",
+                    READER_STOP_TIMEOUT_MS
+                );
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        let _ = reader.thread.join();
+    }
+
+    pub fn supports_add_gpios(&self) -> bool {
+        self.driver_version.minor >= ADD_GPIOS_MINOR
+    }
+
+    // Appends lines to the chip registration, after the registered ones
+    pub fn add_gpios(&self, unique_id: u64, new_lines: &[Line]) -> Result<()> {
+        if !self.supports_add_gpios() {
+            bail!(
+                "Kernel Driver API (v{}) cannot add GPIO's, restart the Bridge to register them",
+                self.driver_version
+            );
+        }
+
+        if new_lines.is_empty() {
+            bail!("GPIO count cannot be {}", new_lines.len());
+        }
+
+        let mut lines = self.lines.lock().map_err(|err| anyhow!("{}", err))?;
+
+        if let Some(line) = new_lines.iter().find(|line| lines.contains(&line.pin)) {
+            bail!("Pin {} is already registered", line.pin);
+        }
+
+        let first_line = lines.len() as u32;
+        let gpio_names = names(new_lines);
+
+        let mut attributes = GenlBuffer::new();
+
+        attributes.push(Nlattr::new(
+            false,
+            false,
+            packet::Attribute::UniqueId,
+            unique_id,
+        )?);
+
+        attributes.push(Nlattr::new(
+            false,
+            false,
+            packet::Attribute::GpioPin,
+            first_line,
+        )?);
+
+        attributes.push(Nlattr::new(
+            false,
+            false,
+            packet::Attribute::GpioCount,
+            gpio_names.len() as u32,
+        )?);
+
+        attributes.push(Nlattr::new(
+            false,
+            false,
+            packet::Attribute::GpioNames,
+            gpio_names.clone(),
+        )?);
+
+        self.send(packet::Command::AddGpios, attributes)?;
+
+        let packet = self.read_sync()?;
+        let attributes = packet.get_payload()?.get_attr_handle();
+
+        let status = attributes.get_attr_payload_as::<u32>(packet::Attribute::Status)?;
+        if status as i32 == libc::EBUSY {
+            bail!(ChipBusy { unique_id });
+        } else if status != 0 {
+            bail!(
+                "Failed to add GPIO's to Kernel Driver (UID: {}, GPIO's: {:?}), Err: {}",
+                unique_id,
+                gpio_names,
+                KernelStatus::new(packet::Command::AddGpios, status)
+            );
+        }
+
+        lines.extend(new_lines.iter().map(|line| line.pin));
+
+        log::info!(
+            "Added GPIO's to Kernel Driver (UID: {}, First line: {}, GPIO's: {:?})",
+            unique_id,
+            first_line,
+            gpio_names
+        );
+
+        Ok(())
+    }
+
+    pub fn supports_update_names(&self) -> bool {
+        self.driver_version.minor >= UPDATE_NAMES_MINOR
+    }
+
+    // Replaces the label and names of the registered chip, the lines cannot
+    // change
+    pub fn update_names(&self, unique_id: u64, label: &str, lines: &[Line]) -> Result<()> {
+        if !self.supports_update_names() {
+            bail!(
+                "Kernel Driver API (v{}) cannot update GPIO names, restart the Bridge to update them",
+                self.driver_version
+            );
+        }
+
+        let registered = self.lines.lock().map_err(|err| anyhow!("{}", err))?.clone();
+        if !lines
+            .iter()
+            .map(|line| line.pin)
+            .eq(registered.iter().copied())
+        {
+            bail!(
+                "GPIO names ({}) do not match the registered pins ({:?})",
+                lines.len(),
+                registered
+            );
+        }
+
+        let gpio_names = names(lines);
+
+        self.send(
+            packet::Command::UpdateNames,
+            chip_attributes(unique_id, label, &gpio_names)?,
+        )?;
+
+        let packet = self.read_sync()?;
+        let attributes = packet.get_payload()?.get_attr_handle();
+
+        let args = format!(
+            "UID: {:?}, Label: {:?}, GPIO's: {:?}",
+            unique_id, label, gpio_names
+        );
+
+        let status = attributes.get_attr_payload_as::<u32>(packet::Attribute::Status)?;
+        if status != 0 {
+            bail!(
+                "Failed to update Kernel Driver names ({}), Err: {}",
+                args,
+                KernelStatus::new(packet::Command::UpdateNames, status)
+            );
+        } else {
+            log::info!("Updated Kernel Driver names ({})", args);
+        }
+
+        Ok(())
+    }
+
+    pub fn supports_exit_message(&self) -> bool {
+        self.driver_version.minor >= EXIT_MESSAGE_MINOR
+    }
+
+    // Leaves the reason of a failure in the kernel log, the Kernel Driver
+    // does not reply
+    pub fn send_exit_message(&self, unique_id: u64, message: &str) -> Result<()> {
+        if !self.supports_exit_message() {
+            bail!(
+                "Kernel Driver API (v{}) cannot log the exit reason",
+                self.driver_version
+            );
+        }
+
+        let mut attributes = GenlBuffer::new();
+
+        attributes.push(Nlattr::new(
+            false,
+            false,
+            packet::Attribute::UniqueId,
+            unique_id,
+        )?);
+
+        attributes.push(Nlattr::new(
+            false,
+            false,
+            packet::Attribute::Message,
+            message,
+        )?);
+
+        self.send(packet::Command::Exit, attributes)
+    }
+
+    fn deinit_with_version(&self, unique_id: u64) -> Result<utils::Version> {
         let mut attributes = GenlBuffer::new();
 
         attributes.push(Nlattr::new(
@@ -338,11 +919,11 @@ impl Handle {
         if status != 0 {
             bail!(
                 "Failed to deinitialize Kernel Driver, Err: {}",
-                std::io::Error::from_raw_os_error(status as i32)
+                KernelStatus::new(packet::Command::Deinit, status)
             );
         }
 
-        Ok(())
+        Ok(driver_version)
     }
 
     pub fn read(&self) -> Result<Nlmsghdr<u16, Genlmsghdr<packet::Command, packet::Attribute>>> {
@@ -353,147 +934,120 @@ impl Handle {
             .recv()?)
     }
 
+    // The line a pin is registered as
+    fn line(&self, pin: u32) -> Result<u32> {
+        let lines = self.lines.lock().map_err(|err| anyhow!("{}", err))?;
+        line_of(&lines, pin)
+    }
+
     pub fn parse(
         &self,
         packet: Nlmsghdr<u16, Genlmsghdr<packet::Command, packet::Attribute>>,
     ) -> Result<packet::Packet> {
-        let attributes = packet.get_payload()?.get_attr_handle();
-        let payload = packet
-            .nl_payload
-            .get_payload()
-            .context("No payload from Kernel Driver")?;
-
-        match payload.cmd {
-            packet::Command::Exit => {
-                let message = attributes
-                    .get_attr_payload_as_with_len::<String>(packet::Attribute::Message)?;
-
-                Ok(packet::Packet::Exit(packet::Exit { message }))
-            }
-            packet::Command::GetGpioValue => {
-                let pin = attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioPin)?;
-
-                Ok(packet::Packet::GetGpioValue(packet::GetGpioValue { pin }))
-            }
-            packet::Command::SetGpioValue => {
-                let pin = attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioPin)?;
-
-                let value = attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioValue)?;
-
-                let value = packet::GpioValue::try_from(value)?;
-
-                Ok(packet::Packet::SetGpioValue(packet::SetGpioValue {
-                    pin,
-                    value,
-                }))
-            }
-            packet::Command::SetGpioConfig => {
-                let pin = attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioPin)?;
-
-                let config =
-                    attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioConfig)?;
-
-                let config = packet::GpioConfig::try_from(config)?;
-
-                Ok(packet::Packet::SetGpioConfig(packet::SetGpioConfig {
-                    pin,
-                    config,
-                }))
-            }
-            packet::Command::SetGpioDirection => {
-                let pin = attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioPin)?;
-
-                let direction =
-                    attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioDirection)?;
-
-                let direction = packet::GpioDirection::try_from(direction)?;
-
-                Ok(packet::Packet::SetGpioDirection(packet::SetGpioDirection {
-                    pin,
-                    direction,
-                }))
-            }
-            _ => {
-                bail!("[{:#?}] Unknown command", payload.cmd);
-            }
+        let result = parse(&packet).and_then(|packet| {
+            let lines = self.lines.lock().map_err(|err| anyhow!("{}", err))?;
+            to_pins(packet, &lines)
+        });
+
+        // Attribute types and lengths expose a Kernel Driver whose attribute
+        // numbering drifted from the bridge
+        if result.is_err() && log::log_enabled!(log::Level::Debug) {
+            log::debug!(
+                "Failed to parse Kernel Driver packet, Attributes: [{}]",
+                describe_attributes(&packet)
+            );
         }
+
+        result
     }
 }
 
 impl Handle {
-    fn init(&self, unique_id: u64, label: &str, gpio_names: &Vec<String>) -> Result<()> {
+    fn init(
+        &self,
+        retry_policy: &utils::RetryPolicy,
+        unique_id: u64,
+        label: &str,
+        lines: &[Line],
+    ) -> Result<()> {
         if unique_id == GENL_MULTICAST_UID_ALL {
             bail!("Unique ID cannot be {}", GENL_MULTICAST_UID_ALL);
         }
 
-        if gpio_names.is_empty() {
-            bail!("GPIO count cannot be {}", gpio_names.len());
+        if lines.is_empty() {
+            bail!("GPIO count cannot be {}", lines.len());
         }
 
-        let mut attributes = GenlBuffer::new();
-
-        attributes.push(Nlattr::new(
-            false,
-            false,
-            packet::Attribute::UniqueId,
-            unique_id,
-        )?);
-
-        attributes.push(Nlattr::new(
-            false,
-            false,
-            packet::Attribute::GpioCount,
-            gpio_names.len() as u32,
-        )?);
-
-        attributes.push(Nlattr::new(
-            false,
-            false,
-            packet::Attribute::GpioNames,
-            gpio_names.clone(),
-        )?);
+        let gpio_names = names(lines);
+        let now = std::time::Instant::now();
+        let mut attempt = 0;
 
-        attributes.push(Nlattr::new(
-            false,
-            false,
-            packet::Attribute::ChipLabel,
-            label,
-        )?);
-
-        self.send(packet::Command::Init, attributes)?;
+        let status = loop {
+            attempt += 1;
+            let status = self.try_init(unique_id, label, &gpio_names)?;
 
-        let packet = self.read_sync()?;
-
-        let attributes = packet.get_payload()?.get_attr_handle();
+            if !INIT_RETRYABLE_ERRNOS.contains(&(status as i32)) {
+                break status;
+            }
 
-        let status = attributes.get_attr_payload_as::<u32>(packet::Attribute::Status)?;
+            let delay_ms = match retry_policy.delay_ms(attempt, now.elapsed()) {
+                Some(delay_ms) => delay_ms,
+                None => break status,
+            };
+            log::warn!(
+                "Kernel Driver init failed, retrying in {} ms ({}/{}), Err: {}",
+                delay_ms,
+                attempt,
+                retry_policy.attempts,
+                KernelStatus::new(packet::Command::Init, status)
+            );
+            std::thread::sleep(std::time::Duration::from_millis(delay_ms));
+        };
 
         let args = format!(
             "UID: {:?}, Label: {:?}, GPIO's: {:?}",
             unique_id, label, gpio_names
         );
 
-        if status != 0 {
+        if status as i32 == INIT_DUPLICATE_ERRNO {
+            bail!(AlreadyInitialized { unique_id });
+        } else if status != 0 {
             bail!(
                 "Failed to initialize Kernel Driver ({}), Err: {}",
                 args,
-                std::io::Error::from_raw_os_error(status as i32)
+                KernelStatus::new(packet::Command::Init, status)
             );
         } else {
             log::info!("Initialized Kernel Driver ({})", args);
         }
 
+        *self.lines.lock().map_err(|err| anyhow!("{}", err))? =
+            lines.iter().map(|line| line.pin).collect();
+
         Ok(())
     }
 
+    fn try_init(&self, unique_id: u64, label: &str, gpio_names: &[String]) -> Result<u32> {
+        self.send(
+            packet::Command::Init,
+            chip_attributes(unique_id, label, gpio_names)?,
+        )?;
+
+        let packet = self.read_sync()?;
+
+        let attributes = packet.get_payload()?.get_attr_handle();
+
+        Ok(attributes.get_attr_payload_as::<u32>(packet::Attribute::Status)?)
+    }
+
     fn read_sync(&self) -> Result<Nlmsghdr<u16, Genlmsghdr<packet::Command, packet::Attribute>>> {
-        let buffer = self
+        let packet = self
             .unicast
             .lock()
             .map_err(|err| anyhow!("{}", err))?
             .recv()?;
 
-        Ok(buffer.context("Nothing to read from Kernel Driver")?)
+        packet.context("Nothing to read from Kernel Driver")
     }
 
     fn send(
@@ -519,6 +1073,288 @@ impl Handle {
     }
 }
 
+fn parse(packet: &GenlPacket) -> Result<packet::Packet> {
+    let attributes = packet.get_payload()?.get_attr_handle();
+    let payload = packet
+        .nl_payload
+        .get_payload()
+        .context("No payload from Kernel Driver")?;
+
+    match payload.cmd {
+        // Unspec is a placeholder of the generic netlink family, not a request
+        packet::Command::Unspec => Ok(packet::Packet::Discard),
+        packet::Command::Exit => {
+            let message =
+                attributes.get_attr_payload_as_with_len::<String>(packet::Attribute::Message)?;
+
+            Ok(packet::Packet::Exit(packet::Exit { message }))
+        }
+        packet::Command::GetGpioValue => {
+            let pin = attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioPin)?;
+
+            Ok(packet::Packet::GetGpioValue(packet::GetGpioValue { pin }))
+        }
+        packet::Command::GetAllGpioValues => {
+            let batch_id = attributes.get_attr_payload_as::<u32>(packet::Attribute::BatchId)?;
+
+            Ok(packet::Packet::GetAllGpioValues(packet::GetAllGpioValues {
+                batch_id,
+            }))
+        }
+        packet::Command::SetGpioValue => {
+            let pin = attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioPin)?;
+
+            let value = attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioValue)?;
+
+            let value = packet::GpioValue::try_from(value)?;
+
+            Ok(packet::Packet::SetGpioValue(packet::SetGpioValue {
+                pin,
+                value,
+            }))
+        }
+        packet::Command::SetGpioValues => {
+            let batch_id = attributes.get_attr_payload_as::<u32>(packet::Attribute::BatchId)?;
+
+            let pins =
+                attributes.get_attr_payload_as_with_len::<Vec<u32>>(packet::Attribute::GpioPins)?;
+
+            let values = attributes
+                .get_attr_payload_as_with_len::<Vec<u32>>(packet::Attribute::GpioValues)?;
+
+            if pins.len() != values.len() {
+                bail!(
+                    "Mismatched SetGpioValues, {} pins but {} values",
+                    pins.len(),
+                    values.len()
+                );
+            }
+
+            let values = pins
+                .into_iter()
+                .zip(values)
+                .map(|(pin, value)| Ok((pin, packet::GpioValue::try_from(value)?)))
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(packet::Packet::SetGpioValues(packet::SetGpioValues {
+                batch_id,
+                values,
+            }))
+        }
+        packet::Command::SetGpioConfig => {
+            let pin = attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioPin)?;
+
+            let config = attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioConfig)?;
+
+            let config = packet::GpioConfig::try_from(config)?;
+
+            Ok(packet::Packet::SetGpioConfig(packet::SetGpioConfig {
+                pin,
+                config,
+            }))
+        }
+        packet::Command::SetGpioDirection => {
+            let pin = attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioPin)?;
+
+            let direction =
+                attributes.get_attr_payload_as::<u32>(packet::Attribute::GpioDirection)?;
+
+            let direction = packet::GpioDirection::try_from(direction)?;
+
+            Ok(packet::Packet::SetGpioDirection(packet::SetGpioDirection {
+                pin,
+                direction,
+            }))
+        }
+        _ => {
+            bail!("[{:#?}] Unknown command", payload.cmd);
+        }
+    }
+}
+
+fn names(lines: &[Line]) -> Vec<String> {
+    lines.iter().map(|line| line.name.clone()).collect()
+}
+
+fn line_of(lines: &[u32], pin: u32) -> Result<u32> {
+    match lines.iter().position(|registered| *registered == pin) {
+        Some(line) => Ok(line as u32),
+        None => bail!("Pin {} is not registered with the Kernel Driver", pin),
+    }
+}
+
+fn pin_of(lines: &[u32], line: u32) -> Result<u32> {
+    match lines.get(line as usize) {
+        Some(pin) => Ok(*pin),
+        None => bail!("Line {} is not registered with the Kernel Driver", line),
+    }
+}
+
+// The Kernel Driver addresses the lines of the chip, the bridge the pins of
+// the secondary behind them
+fn to_pins(packet: packet::Packet, lines: &[u32]) -> Result<packet::Packet> {
+    Ok(match packet {
+        packet::Packet::GetGpioValue(packet) => {
+            packet::Packet::GetGpioValue(packet::GetGpioValue {
+                pin: pin_of(lines, packet.pin)?,
+            })
+        }
+        packet::Packet::SetGpioValue(packet) => {
+            packet::Packet::SetGpioValue(packet::SetGpioValue {
+                pin: pin_of(lines, packet.pin)?,
+                ..packet
+            })
+        }
+        packet::Packet::SetGpioValues(packet) => {
+            packet::Packet::SetGpioValues(packet::SetGpioValues {
+                batch_id: packet.batch_id,
+                values: packet
+                    .values
+                    .into_iter()
+                    .map(|(line, value)| Ok((pin_of(lines, line)?, value)))
+                    .collect::<Result<_>>()?,
+            })
+        }
+        packet::Packet::SetGpioConfig(packet) => {
+            packet::Packet::SetGpioConfig(packet::SetGpioConfig {
+                pin: pin_of(lines, packet.pin)?,
+                ..packet
+            })
+        }
+        packet::Packet::SetGpioDirection(packet) => {
+            packet::Packet::SetGpioDirection(packet::SetGpioDirection {
+                pin: pin_of(lines, packet.pin)?,
+                ..packet
+            })
+        }
+        packet @ (packet::Packet::Discard
+        | packet::Packet::Exit(_)
+        | packet::Packet::GetAllGpioValues(_)) => packet,
+    })
+}
+
+fn chip_attributes(
+    unique_id: u64,
+    label: &str,
+    gpio_names: &[String],
+) -> Result<GenlBuffer<packet::Attribute, Buffer>> {
+    let mut attributes = GenlBuffer::new();
+
+    attributes.push(Nlattr::new(
+        false,
+        false,
+        packet::Attribute::UniqueId,
+        unique_id,
+    )?);
+
+    attributes.push(Nlattr::new(
+        false,
+        false,
+        packet::Attribute::GpioCount,
+        gpio_names.len() as u32,
+    )?);
+
+    attributes.push(Nlattr::new(
+        false,
+        false,
+        packet::Attribute::GpioNames,
+        gpio_names.to_vec(),
+    )?);
+
+    attributes.push(Nlattr::new(
+        false,
+        false,
+        packet::Attribute::ChipLabel,
+        label,
+    )?);
+
+    // The kernel only registers the batch callbacks of bridges announcing a
+    // version that answers them
+    attributes.push(Nlattr::new(
+        false,
+        false,
+        packet::Attribute::VersionMajor,
+        VERSION.major,
+    )?);
+
+    attributes.push(Nlattr::new(
+        false,
+        false,
+        packet::Attribute::VersionMinor,
+        VERSION.minor,
+    )?);
+
+    attributes.push(Nlattr::new(
+        false,
+        false,
+        packet::Attribute::VersionPatch,
+        VERSION.patch,
+    )?);
+
+    Ok(attributes)
+}
+
+fn describe_attributes(packet: &GenlPacket) -> String {
+    match packet.get_payload() {
+        Ok(payload) => payload
+            .get_attr_handle()
+            .iter()
+            .map(|attr| format!("{:?} ({} bytes)", attr.nla_type.nla_type, attr.nla_len))
+            .collect::<Vec<_>>()
+            .join(", "),
+        Err(err) => err.to_string(),
+    }
+}
+
+// Ids of the multicast groups of the Kernel Driver family, a group that
+// cannot be resolved fails like the family itself
+fn resolve_multicast_groups(unicast: &mut NlSocketHandle, names: &[String]) -> Result<Vec<u32>> {
+    let names = multicast_group_names(names)?;
+
+    let mut groups = vec![];
+    for name in &names {
+        match unicast.resolve_nl_mcast_group(GENL_FAMILY_NAME, name) {
+            Ok(group) => groups.push(group),
+            Err(err) => {
+                bail!(
+                    "Failed to resolve using Generic Netlink ({}) Multicast ({}), Err: {}",
+                    GENL_FAMILY_NAME,
+                    name,
+                    err,
+                );
+            }
+        }
+    }
+
+    if names.len() > 1 {
+        log::info!(
+            "Subscribed to the Kernel Driver multicast groups {:?}",
+            names
+        );
+    }
+
+    Ok(groups)
+}
+
+fn multicast_group_names(names: &[String]) -> Result<Vec<&str>> {
+    let mut unique: Vec<&str> = vec![];
+    for name in names {
+        if name.is_empty() {
+            bail!("Multicast group names cannot be empty");
+        }
+        if unique.contains(&name.as_str()) {
+            bail!("Multicast group {} is listed more than once", name);
+        }
+        unique.push(name);
+    }
+
+    if unique.is_empty() {
+        bail!("At least one multicast group is required");
+    }
+
+    Ok(unique)
+}
+
 fn filter_packet(
     unique_id: u64,
     packet: &Nlmsghdr<u16, Genlmsghdr<packet::Command, packet::Attribute>>,
@@ -532,3 +1368,175 @@ fn filter_packet(
         _ => Ok(true),
     }
 }
+
+fn spawn_blocking_reader(
+    mut multicast: NlSocketHandle,
+    forward: Forward,
+    mut exit_sender: mio::unix::pipe::Sender,
+) -> Result<()> {
+    std::thread::Builder::new()
+        .name("driver".to_string())
+        .spawn(move || loop {
+            let result = (|| -> Result<()> {
+                let packet = loop {
+                    match multicast.recv() {
+                        Ok(packet) => break packet.context("Multicast socked was closed")?,
+                        Err(err) if is_interrupted(&err) => {
+                            log::debug!("Multicast socket read interrupted, retrying");
+                        }
+                        Err(err) => {
+                            bail!("Failed to read from Multicast socket, Err: {}", err)
+                        }
+                    }
+                };
+
+                forward.packet(packet)
+            })();
+
+            if let Err(err) = result {
+                utils::ThreadExit::notify(&mut exit_sender, &format!("{}", err));
+                return;
+            }
+        })?;
+
+    Ok(())
+}
+
+// Selects on the multicast socket and a stop pipe, so that the reader can be
+// stopped without an error
+fn spawn_poll_reader(
+    mut multicast: NlSocketHandle,
+    forward: Forward,
+    mut exit_sender: mio::unix::pipe::Sender,
+) -> Result<PollReader> {
+    let mut poll = mio::Poll::new()?;
+    let (stop, mut stop_receiver) = mio::unix::pipe::new()?;
+
+    multicast.nonblock()?;
+
+    poll.registry().register(
+        &mut mio::unix::SourceFd(&multicast.as_raw_fd()),
+        READER_MULTICAST_TOKEN,
+        mio::Interest::READABLE,
+    )?;
+
+    poll.registry().register(
+        &mut stop_receiver,
+        READER_STOP_TOKEN,
+        mio::Interest::READABLE,
+    )?;
+
+    let thread = std::thread::Builder::new()
+        .name("driver".to_string())
+        .spawn(move || {
+            // Registered with the poll, it must outlive the loop
+            let _stop_receiver = stop_receiver;
+            let mut events = mio::Events::with_capacity(2);
+            loop {
+                let result = (|| -> Result<ControlFlow<()>> {
+                    match poll.poll(&mut events, None) {
+                        Ok(_) => (),
+                        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {
+                            return Ok(ControlFlow::Continue(()));
+                        }
+                        Err(err) => bail!("Failed to poll Multicast socket, Err: {}", err),
+                    }
+
+                    for event in events.iter() {
+                        match event.token() {
+                            READER_STOP_TOKEN => return Ok(ControlFlow::Break(())),
+                            READER_MULTICAST_TOKEN => loop {
+                                // Readiness is edge triggered, the socket must be drained
+                                match multicast.recv() {
+                                    Ok(Some(packet)) => forward.packet(packet)?,
+                                    Ok(None) => break,
+                                    Err(err) if is_interrupted(&err) => (),
+                                    Err(err) => {
+                                        bail!("Failed to read from Multicast socket, Err: {}", err)
+                                    }
+                                }
+                            },
+                            token => log::warn!("Unexpected Multicast reader token: {:?}", token),
+                        }
+                    }
+
+                    Ok(ControlFlow::Continue(()))
+                })();
+
+                match result {
+                    Ok(ControlFlow::Continue(())) => (),
+                    Ok(ControlFlow::Break(())) => {
+                        log::debug!("Multicast reader stopped");
+                        return;
+                    }
+                    Err(err) => {
+                        utils::ThreadExit::notify(&mut exit_sender, &format!("{}", err));
+                        return;
+                    }
+                }
+            }
+        })?;
+
+    Ok(PollReader { stop, thread })
+}
+
+// What a reader needs to hand the requests of the chip over to the router
+// Requests sent by the Kernel Driver right after Init are queued until the
+// router starts, they must never be dropped
+fn data_channel(
+    channel_config: &utils::ChannelConfig,
+) -> (
+    utils::ChannelSender<GenlPacket>,
+    Arc<Mutex<mpsc::Receiver<GenlPacket>>>,
+) {
+    let channel_config = utils::ChannelConfig {
+        overflow: utils::Overflow::Block,
+        ..*channel_config
+    };
+
+    utils::sync_channel("Driver", &channel_config)
+}
+
+struct Forward {
+    unique_id: Arc<AtomicU64>,
+    data_tx: utils::ChannelSender<GenlPacket>,
+    heartbeat: Arc<utils::Heartbeat>,
+}
+
+impl Forward {
+    fn packet(&self, packet: GenlPacket) -> Result<()> {
+        self.heartbeat.begin();
+        let result = forward_packet(self.unique_id.load(Ordering::SeqCst), packet, &self.data_tx);
+        self.heartbeat.end();
+
+        result
+    }
+}
+
+fn forward_packet(
+    unique_id: u64,
+    packet: GenlPacket,
+    data_tx: &utils::ChannelSender<GenlPacket>,
+) -> Result<()> {
+    let filtered = match filter_packet(unique_id, &packet) {
+        Ok(filtered) => filtered,
+        Err(err) => bail!("Failed to filter packet, Err: {}", err),
+    };
+
+    if !filtered {
+        if let Err(err) = data_tx.send(packet) {
+            bail!("Failed to send to Driver channel, Err: {}", err)
+        }
+    }
+
+    Ok(())
+}
+
+fn is_interrupted<T, P>(err: &NlError<T, P>) -> bool {
+    match err {
+        NlError::Wrapped(WrappedError::IOError(err)) => {
+            err.kind() == std::io::ErrorKind::Interrupted
+        }
+        _ => false,
+    }
+}
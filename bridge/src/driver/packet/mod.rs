@@ -8,6 +8,10 @@ pub enum Command {
     SetGpioValue = 5,
     SetGpioConfig = 6,
     SetGpioDirection = 7,
+    GetAllGpioValues = 8,
+    SetGpioValues = 9,
+    AddGpios = 10,
+    UpdateNames = 11,
 }
 impl neli::consts::genl::Cmd for Command {}
 
@@ -27,16 +31,27 @@ pub enum Attribute {
     GpioValue = 11,
     GpioConfig = 12,
     GpioDirection = 13,
+    // One u32 per pin, indexed by pin, or by GpioPins when present
+    GpioValues = 14,
+    // One Status per pin, the matching GpioValues entry is only valid when Ok
+    GpioStatuses = 15,
+    // One u32 per pin, the pins a SetGpioValues applies to
+    GpioPins = 16,
+    // Tags a batch request, echoed in its reply so a late one is told apart
+    BatchId = 17,
 }
 impl neli::consts::genl::NlAttrType for Attribute {}
 
 #[derive(Debug)]
 pub enum Packet {
+    Discard,
     Exit(Exit),
     GetGpioValue(GetGpioValue),
     SetGpioValue(SetGpioValue),
     SetGpioConfig(SetGpioConfig),
     SetGpioDirection(SetGpioDirection),
+    GetAllGpioValues(GetAllGpioValues),
+    SetGpioValues(SetGpioValues),
 }
 
 #[derive(Debug)]
@@ -53,6 +68,11 @@ pub struct SetGpioValue {
     pub value: GpioValue,
 }
 #[derive(Debug)]
+pub struct SetGpioValues {
+    pub batch_id: u32,
+    pub values: Vec<(u32, GpioValue)>,
+}
+#[derive(Debug)]
 pub struct SetGpioConfig {
     pub pin: u32,
     pub config: GpioConfig,
@@ -63,6 +83,11 @@ pub struct SetGpioDirection {
     pub direction: GpioDirection,
 }
 
+#[derive(Debug)]
+pub struct GetAllGpioValues {
+    pub batch_id: u32,
+}
+
 #[derive(Debug, Copy, Clone, num_enum::TryFromPrimitive)]
 #[repr(u32)]
 pub enum Status {
@@ -70,6 +95,7 @@ pub enum Status {
     NotSupported = 1,
     BrokenPipe = 2,
     ProtocolError = 3,
+    InvalidArgument = 4,
     Unknown = u32::MAX,
 }
 
@@ -80,7 +106,7 @@ pub enum GpioValue {
     High = 1,
 }
 
-#[derive(Debug, Copy, Clone, num_enum::TryFromPrimitive)]
+#[derive(Debug, Copy, Clone, PartialEq, num_enum::TryFromPrimitive)]
 #[repr(u32)]
 pub enum GpioDirection {
     Output = 0,
@@ -98,4 +124,9 @@ pub enum GpioConfig {
     DriveOpenDrain = 6,
     DriveOpenSource = 7,
     DrivePushPull = 8,
+    // Sent as the PIN_CONFIG_* value of the running kernel, which
+    // PIN_CONFIG_INPUT_SCHMITT_UV shifted from PIN_CONFIG_MODE_LOW_POWER on
+    InputEnable = 12,
+    #[num_enum(alternatives = [18])]
+    OutputEnable = 19,
 }
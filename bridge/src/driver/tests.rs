@@ -0,0 +1,477 @@
+use super::*;
+
+fn packet(cmd: packet::Command) -> GenlPacket {
+    packet_with_attributes(cmd, GenlBuffer::new())
+}
+
+fn packet_with_attributes(
+    cmd: packet::Command,
+    attributes: GenlBuffer<packet::Attribute, Buffer>,
+) -> GenlPacket {
+    Nlmsghdr::new(
+        None,
+        0,
+        NlmFFlags::empty(),
+        None,
+        None,
+        NlPayload::Payload(Genlmsghdr::new(cmd, GENL_API_VERSION, attributes)),
+    )
+}
+
+#[test]
+fn parse_unspec_is_discarded() {
+    assert!(matches!(
+        parse(&packet(packet::Command::Unspec)),
+        Ok(packet::Packet::Discard)
+    ));
+}
+
+#[test]
+fn parse_init_is_unknown() {
+    assert!(parse(&packet(packet::Command::Init)).is_err());
+}
+
+#[test]
+fn parse_missing_attribute_is_an_error() {
+    let mut attributes = GenlBuffer::new();
+    attributes.push(Nlattr::new(false, false, packet::Attribute::GpioPin, 3u32).unwrap());
+
+    assert!(parse(&packet_with_attributes(
+        packet::Command::SetGpioValue,
+        attributes
+    ))
+    .is_err());
+}
+
+#[test]
+fn describe_attributes_lists_types_and_lengths() {
+    let mut attributes = GenlBuffer::new();
+    attributes.push(Nlattr::new(false, false, packet::Attribute::GpioPin, 3u32).unwrap());
+    attributes.push(Nlattr::new(false, false, packet::Attribute::UniqueId, 1u64).unwrap());
+
+    assert_eq!(
+        describe_attributes(&packet_with_attributes(
+            packet::Command::SetGpioValue,
+            attributes
+        )),
+        "GpioPin (8 bytes), UniqueId (12 bytes)"
+    );
+}
+
+#[test]
+fn chip_attributes_carry_names_and_label() {
+    let names = vec!["a".to_string(), "bc".to_string()];
+    let attributes = chip_attributes(1, "label", &names).unwrap();
+
+    assert_eq!(
+        describe_attributes(&packet_with_attributes(packet::Command::Init, attributes)),
+        "UniqueId (12 bytes), GpioCount (8 bytes), GpioNames (9 bytes), ChipLabel (10 bytes), \
+         VersionMajor (5 bytes), VersionMinor (5 bytes), VersionPatch (5 bytes)"
+    );
+}
+
+#[test]
+fn parse_set_gpio_values_pairs_pins_and_values() {
+    let mut attributes = GenlBuffer::new();
+    attributes.push(Nlattr::new(false, false, packet::Attribute::BatchId, 7u32).unwrap());
+    attributes.push(Nlattr::new(false, false, packet::Attribute::GpioPins, vec![1u32, 4]).unwrap());
+    attributes
+        .push(Nlattr::new(false, false, packet::Attribute::GpioValues, vec![1u32, 0]).unwrap());
+
+    let packet = match parse(&packet_with_attributes(
+        packet::Command::SetGpioValues,
+        attributes,
+    )) {
+        Ok(packet::Packet::SetGpioValues(packet)) => packet,
+        other => panic!("Unexpected {:?}", other),
+    };
+
+    assert_eq!(packet.batch_id, 7);
+    assert!(matches!(
+        packet.values.as_slice(),
+        [(1, packet::GpioValue::High), (4, packet::GpioValue::Low)]
+    ));
+}
+
+#[test]
+fn parse_set_gpio_values_with_mismatched_lengths_is_an_error() {
+    let mut attributes = GenlBuffer::new();
+    attributes.push(Nlattr::new(false, false, packet::Attribute::BatchId, 7u32).unwrap());
+    attributes.push(Nlattr::new(false, false, packet::Attribute::GpioPins, vec![1u32, 4]).unwrap());
+    attributes.push(Nlattr::new(false, false, packet::Attribute::GpioValues, vec![1u32]).unwrap());
+
+    assert!(parse(&packet_with_attributes(
+        packet::Command::SetGpioValues,
+        attributes
+    ))
+    .is_err());
+}
+
+#[test]
+fn parse_batch_requests_carry_their_id() {
+    let mut attributes = GenlBuffer::new();
+    attributes.push(Nlattr::new(false, false, packet::Attribute::BatchId, 9u32).unwrap());
+
+    assert!(matches!(
+        parse(&packet_with_attributes(
+            packet::Command::GetAllGpioValues,
+            attributes
+        )),
+        Ok(packet::Packet::GetAllGpioValues(packet::GetAllGpioValues {
+            batch_id: 9
+        }))
+    ));
+
+    // The reply of an untagged batch could not be matched by the Kernel Driver
+    assert!(parse(&packet(packet::Command::GetAllGpioValues)).is_err());
+}
+
+fn parse_set_gpio_config(config: u32) -> Result<packet::Packet> {
+    let mut attributes = GenlBuffer::new();
+    attributes.push(Nlattr::new(false, false, packet::Attribute::GpioPin, 2u32).unwrap());
+    attributes.push(Nlattr::new(false, false, packet::Attribute::GpioConfig, config).unwrap());
+
+    parse(&packet_with_attributes(
+        packet::Command::SetGpioConfig,
+        attributes,
+    ))
+}
+
+#[test]
+fn parse_output_enable_of_any_kernel() {
+    // PIN_CONFIG_OUTPUT_ENABLE before and after PIN_CONFIG_INPUT_SCHMITT_UV
+    for config in [18, 19] {
+        assert!(matches!(
+            parse_set_gpio_config(config),
+            Ok(packet::Packet::SetGpioConfig(packet::SetGpioConfig {
+                pin: 2,
+                config: packet::GpioConfig::OutputEnable,
+            }))
+        ));
+    }
+
+    assert!(matches!(
+        parse_set_gpio_config(12),
+        Ok(packet::Packet::SetGpioConfig(packet::SetGpioConfig {
+            config: packet::GpioConfig::InputEnable,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn lines_skip_unmanaged_pins() {
+    let lines = [0, 1, 3];
+
+    assert_eq!(line_of(&lines, 3).unwrap(), 2);
+    assert!(line_of(&lines, 2).is_err());
+    assert_eq!(pin_of(&lines, 2).unwrap(), 3);
+    assert!(pin_of(&lines, 3).is_err());
+}
+
+#[test]
+fn to_pins_maps_lines_to_pins() {
+    let lines = [0, 1, 3];
+
+    let packet = packet::Packet::SetGpioDirection(packet::SetGpioDirection {
+        pin: 2,
+        direction: packet::GpioDirection::Input,
+    });
+    assert!(matches!(
+        to_pins(packet, &lines),
+        Ok(packet::Packet::SetGpioDirection(packet::SetGpioDirection {
+            pin: 3,
+            ..
+        }))
+    ));
+
+    let packet = packet::Packet::SetGpioValues(packet::SetGpioValues {
+        batch_id: 1,
+        values: vec![(0, packet::GpioValue::High), (2, packet::GpioValue::Low)],
+    });
+    let values = match to_pins(packet, &lines) {
+        Ok(packet::Packet::SetGpioValues(packet)) => packet.values,
+        other => panic!("Unexpected {:?}", other),
+    };
+    assert!(matches!(
+        values.as_slice(),
+        [(0, packet::GpioValue::High), (3, packet::GpioValue::Low)]
+    ));
+
+    let packet = packet::Packet::GetGpioValue(packet::GetGpioValue { pin: 3 });
+    assert!(to_pins(packet, &lines).is_err());
+}
+
+#[test]
+fn poll_reader_stops_without_error() {
+    let multicast = NlSocketHandle::connect(NlFamily::Generic, Some(0), &[]).unwrap();
+    let channel_config = utils::ChannelConfig {
+        capacity: 1,
+        overflow: utils::Overflow::Block,
+    };
+    let (data_tx, _data_rx) = utils::sync_channel("Driver", &channel_config);
+    let (exit_sender, mut exit_receiver) = mio::unix::pipe::new().unwrap();
+
+    let forward = Forward {
+        unique_id: Arc::new(AtomicU64::new(1)),
+        data_tx,
+        heartbeat: Arc::new(utils::Heartbeat::new("Driver")),
+    };
+
+    let mut reader = spawn_poll_reader(multicast, forward, exit_sender).unwrap();
+    std::io::Write::write_all(&mut reader.stop, &[0]).unwrap();
+    reader.thread.join().unwrap();
+
+    // The exit pipe is only written on error, the reader closed it on return
+    let mut buffer = [0; 1];
+    assert_eq!(
+        std::io::Read::read(&mut exit_receiver, &mut buffer).unwrap(),
+        0
+    );
+}
+
+// A burst sent right after Init, before the router consumes anything, reaches
+// the router worker whole and in order even with a dropping overflow policy
+#[test]
+fn burst_after_init_reaches_the_router_worker() {
+    use clap::Parser;
+
+    const BURST: u32 = 32;
+
+    let config = utils::Config::parse_from([
+        "cpc-gpio-bridge",
+        "--instance",
+        "1",
+        "--channel-capacity",
+        "2",
+        "--channel-overflow",
+        "drop-oldest",
+    ]);
+    let (data_tx, data_rx) = data_channel(&utils::channel_config(&config));
+    let (worker_tx, worker_rx) = crate::router::worker_channel(&config);
+
+    let forward = Forward {
+        unique_id: Arc::new(AtomicU64::new(1)),
+        data_tx,
+        heartbeat: Arc::new(utils::Heartbeat::new("Driver")),
+    };
+    let reader = std::thread::spawn(move || {
+        for pin in 0..BURST {
+            let mut attributes = GenlBuffer::new();
+            attributes.push(Nlattr::new(false, false, packet::Attribute::UniqueId, 1u64).unwrap());
+            attributes.push(Nlattr::new(false, false, packet::Attribute::GpioPin, pin).unwrap());
+            forward
+                .packet(packet_with_attributes(
+                    packet::Command::GetGpioValue,
+                    attributes,
+                ))
+                .unwrap();
+        }
+    });
+
+    // The router only starts once the burst has filled the Driver channel
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let dispatcher = std::thread::spawn(move || {
+        for packet in data_rx.lock().unwrap().iter() {
+            worker_tx.send(parse(&packet).unwrap()).unwrap();
+        }
+    });
+
+    // Then the worker is late too
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    let pins: Vec<u32> = worker_rx
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|packet| match packet {
+            packet::Packet::GetGpioValue(packet) => packet.pin,
+            packet => panic!("Unexpected {:?}", packet),
+        })
+        .collect();
+
+    reader.join().unwrap();
+    dispatcher.join().unwrap();
+    assert_eq!(pins, (0..BURST).collect::<Vec<u32>>());
+}
+
+#[test]
+fn clamp_rcvbuf_to_rmem_max() {
+    assert_eq!(clamp_rcvbuf(4096, Some(212992)), 4096);
+    assert_eq!(clamp_rcvbuf(1 << 20, Some(212992)), 212992);
+    assert_eq!(clamp_rcvbuf(1 << 20, None), 1 << 20);
+    assert_eq!(
+        clamp_rcvbuf(usize::MAX, None),
+        std::os::raw::c_int::MAX as usize
+    );
+}
+
+#[test]
+fn kernel_status_describes_module_codes() {
+    let status = KernelStatus::new(packet::Command::Deinit, libc::EPERM as u32).to_string();
+
+    assert!(status.starts_with(&std::io::Error::from_raw_os_error(libc::EPERM).to_string()));
+    assert!(status.ends_with("it must be released before the chip is removed"));
+}
+
+#[test]
+fn kernel_status_falls_back_to_the_errno() {
+    assert_eq!(
+        KernelStatus::new(packet::Command::Deinit, libc::EIO as u32).to_string(),
+        std::io::Error::from_raw_os_error(libc::EIO).to_string()
+    );
+    assert_eq!(
+        KernelStatus::new(packet::Command::AddGpios, 1).to_string(),
+        std::io::Error::from_raw_os_error(1).to_string()
+    );
+}
+
+#[test]
+fn multicast_group_names_are_unique() {
+    let names = |names: &[&str]| {
+        names
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        multicast_group_names(&names(&[GENL_MULTICAST_FAMILY_NAME])).unwrap(),
+        vec![GENL_MULTICAST_FAMILY_NAME]
+    );
+    assert_eq!(
+        multicast_group_names(&names(&["CONTROL", "DATA"])).unwrap(),
+        vec!["CONTROL", "DATA"]
+    );
+    assert!(multicast_group_names(&names(&["DATA", "DATA"])).is_err());
+    assert!(multicast_group_names(&names(&[""])).is_err());
+    assert!(multicast_group_names(&[]).is_err());
+}
+
+// Stands in for the Kernel Driver on the unicast socket, the requests are
+// recorded and each is answered with the next scripted status
+struct KernelDouble {
+    requests: Arc<Mutex<Vec<GenlPacket>>>,
+    statuses: std::collections::VecDeque<u32>,
+}
+
+impl Unicast for KernelDouble {
+    fn send(&mut self, packet: GenlPacket) -> Result<()> {
+        self.requests.lock().unwrap().push(packet);
+        Ok(())
+    }
+
+    fn recv(&mut self) -> Result<Option<GenlPacket>> {
+        let requests = self.requests.lock().unwrap();
+        let cmd = requests.last().unwrap().get_payload().unwrap().cmd;
+        let status = self.statuses.pop_front().unwrap();
+
+        let mut attributes = GenlBuffer::new();
+        attributes.push(Nlattr::new(false, false, packet::Attribute::Status, status).unwrap());
+
+        Ok(Some(packet_with_attributes(cmd, attributes)))
+    }
+}
+
+fn handle_with_double(
+    driver_version: utils::Version,
+    lines: Vec<u32>,
+    statuses: &[u32],
+) -> (Handle, Arc<Mutex<Vec<GenlPacket>>>) {
+    let requests = Arc::new(Mutex::new(vec![]));
+    let double = KernelDouble {
+        requests: requests.clone(),
+        statuses: statuses.iter().copied().collect(),
+    };
+    let (_exit_sender, exit_receiver) = mio::unix::pipe::new().unwrap();
+    let (_data_tx, data_rx) = mpsc::channel();
+
+    let handle = Handle {
+        exit: utils::ThreadExit {
+            receiver: Mutex::new(exit_receiver),
+        },
+        reader: Mutex::new(None),
+        data_rx: Arc::new(Mutex::new(data_rx)),
+        unicast: Mutex::new(Box::new(double)),
+        family_id: 0,
+        driver_version,
+        lines: Mutex::new(lines),
+        unique_id: Arc::new(AtomicU64::new(1)),
+        retry_policy: utils::RetryPolicy {
+            attempts: 1,
+            interval_ms: 0,
+            backoff: 1,
+            cap_ms: 0,
+            timeout_ms: None,
+        },
+        reader_heartbeat: Arc::new(utils::Heartbeat::new("Driver")),
+    };
+
+    (handle, requests)
+}
+
+fn line(pin: u32, name: &str) -> Line {
+    Line {
+        pin,
+        name: name.to_string(),
+    }
+}
+
+#[test]
+fn update_names_resends_label_and_names() {
+    let (handle, requests) = handle_with_double(VERSION, vec![0, 2], &[0]);
+
+    handle
+        .update_names(1, "label", &[line(0, "a"), line(2, "bc")])
+        .unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        requests[0].get_payload().unwrap().cmd,
+        packet::Command::UpdateNames
+    );
+    assert_eq!(
+        describe_attributes(&requests[0]),
+        "UniqueId (12 bytes), GpioCount (8 bytes), GpioNames (9 bytes), ChipLabel (10 bytes), \
+         VersionMajor (5 bytes), VersionMinor (5 bytes), VersionPatch (5 bytes)"
+    );
+}
+
+#[test]
+fn update_names_reports_the_kernel_status() {
+    let (handle, _) = handle_with_double(VERSION, vec![0], &[libc::EBUSY as u32]);
+
+    let err = handle
+        .update_names(1, "label", &[line(0, "a")])
+        .unwrap_err();
+
+    assert!(err
+        .to_string()
+        .contains("registered again once they are all released"));
+}
+
+#[test]
+fn update_names_keeps_the_registered_pins() {
+    let (handle, requests) = handle_with_double(VERSION, vec![0, 1], &[]);
+
+    assert!(handle.update_names(1, "label", &[line(0, "a")]).is_err());
+    assert!(handle
+        .update_names(1, "label", &[line(0, "a"), line(2, "b")])
+        .is_err());
+    assert!(requests.lock().unwrap().is_empty());
+}
+
+#[test]
+fn update_names_requires_a_recent_kernel_driver() {
+    let version = utils::Version {
+        minor: UPDATE_NAMES_MINOR - 1,
+        ..VERSION
+    };
+    let (handle, requests) = handle_with_double(version, vec![0], &[]);
+
+    assert!(!handle.supports_update_names());
+    assert!(handle.update_names(1, "label", &[line(0, "a")]).is_err());
+    assert!(requests.lock().unwrap().is_empty());
+}
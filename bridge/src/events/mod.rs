@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+
+use crate::gpio;
+use crate::utils;
+
+#[cfg(test)]
+mod tests;
+
+// Lifecycle of the bridge, for host applications that would otherwise parse
+// the logs
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    // Discovery of the secondary completed
+    Initialized {
+        unique_id: u64,
+        label: String,
+        gpio_count: usize,
+    },
+    // The chip is registered with the Kernel Driver
+    DriverInitialized {
+        unique_id: u64,
+        gpio_count: usize,
+    },
+    // Pins reset on SIGUSR2, previous_id is set when the unique id changed
+    Resync {
+        unique_id: u64,
+        failed_pins: Vec<u8>,
+        previous_id: Option<u64>,
+    },
+    // The link went down or back up, after streak requests in a row
+    LinkState {
+        unique_id: u64,
+        state: gpio::LinkState,
+        streak: u32,
+    },
+    ShuttingDown {
+        reason: utils::ShutdownReason,
+        detail: String,
+    },
+}
+
+impl Event {
+    // One line of JSON Lines, written by hand like the health report
+    pub fn to_json(&self, timestamp_ms: u128) -> String {
+        match self {
+            Event::Initialized {
+                unique_id,
+                label,
+                gpio_count,
+            } => format!(
+                "{{\"event\":\"initialized\",\"timestamp_ms\":{},\"unique_id\":{},\"label\":{},\"gpio_count\":{}}}",
+                timestamp_ms,
+                unique_id,
+                quote(label),
+                gpio_count
+            ),
+            Event::DriverInitialized {
+                unique_id,
+                gpio_count,
+            } => format!(
+                "{{\"event\":\"driver_initialized\",\"timestamp_ms\":{},\"unique_id\":{},\"gpio_count\":{}}}",
+                timestamp_ms, unique_id, gpio_count
+            ),
+            Event::Resync {
+                unique_id,
+                failed_pins,
+                previous_id,
+            } => format!(
+                "{{\"event\":\"resync\",\"timestamp_ms\":{},\"unique_id\":{},\"failed_pins\":{:?},\"previous_id\":{}}}",
+                timestamp_ms,
+                unique_id,
+                failed_pins,
+                match previous_id {
+                    Some(id) => id.to_string(),
+                    None => "null".to_string(),
+                }
+            ),
+            Event::LinkState {
+                unique_id,
+                state,
+                streak,
+            } => format!(
+                "{{\"event\":\"link_state\",\"timestamp_ms\":{},\"unique_id\":{},\"state\":\"{}\",\"streak\":{}}}",
+                timestamp_ms,
+                unique_id,
+                state.as_str(),
+                streak
+            ),
+            Event::ShuttingDown { reason, detail } => format!(
+                "{{\"event\":\"shutting_down\",\"timestamp_ms\":{},\"reason\":\"{:?}\",\"detail\":{}}}",
+                timestamp_ms,
+                reason,
+                quote(detail)
+            ),
+        }
+    }
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// An event and the wall-clock time it was published at, in milliseconds since
+// the UNIX epoch so lines of a file appended across restarts stay ordered
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stamped {
+    pub timestamp_ms: u128,
+    pub event: Event,
+}
+
+impl Stamped {
+    pub fn to_json(&self) -> String {
+        self.event.to_json(self.timestamp_ms)
+    }
+}
+
+type Subscriber = Box<dyn Fn(&Stamped) + Send + Sync>;
+
+// Checked before an event is even built, publishing costs nothing while
+// nobody subscribed
+static SUBSCRIBED: AtomicBool = AtomicBool::new(false);
+static SUBSCRIBERS: RwLock<Vec<Subscriber>> = RwLock::new(Vec::new());
+
+// Subscribers run on the publishing thread, an event is delivered before the
+// bridge moves on, even when it is about to exit
+pub fn subscribe(subscriber: impl Fn(&Stamped) + Send + Sync + 'static) {
+    if let Ok(mut subscribers) = SUBSCRIBERS.write() {
+        subscribers.push(Box::new(subscriber));
+        SUBSCRIBED.store(true, Ordering::SeqCst);
+    }
+}
+
+pub fn publish(event: impl FnOnce() -> Event) {
+    if !SUBSCRIBED.load(Ordering::SeqCst) {
+        return;
+    }
+
+    let event = Stamped {
+        timestamp_ms: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis())
+            .unwrap_or_default(),
+        event: event(),
+    };
+    if let Ok(subscribers) = SUBSCRIBERS.read() {
+        for subscriber in subscribers.iter() {
+            subscriber(&event);
+        }
+    }
+}
+
+// Appends every event to the file as a line of JSON
+pub fn write_to(path: &std::path::Path) -> Result<()> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open the events file ({})", path.display()))?;
+
+    let file = Mutex::new(file);
+    let path = path.to_path_buf();
+
+    subscribe(move |event| {
+        let result = match file.lock() {
+            Ok(mut file) => writeln!(file, "{}", event.to_json()),
+            Err(_) => return,
+        };
+
+        if let Err(err) = result {
+            log::warn!("Failed to write event to {}, Err: {}", path.display(), err);
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use super::*;
+
+#[test]
+fn event_json() {
+    let event = Event::Initialized {
+        unique_id: 42,
+        label: "chip \"a\"".to_string(),
+        gpio_count: 16,
+    };
+    assert_eq!(
+        event.to_json(1700000000123),
+        "{\"event\":\"initialized\",\"timestamp_ms\":1700000000123,\"unique_id\":42,\"label\":\"chip \\\"a\\\"\",\"gpio_count\":16}"
+    );
+
+    let event = Event::Resync {
+        unique_id: 42,
+        failed_pins: vec![1, 3],
+        previous_id: None,
+    };
+    assert_eq!(
+        event.to_json(1700000000123),
+        "{\"event\":\"resync\",\"timestamp_ms\":1700000000123,\"unique_id\":42,\"failed_pins\":[1, 3],\"previous_id\":null}"
+    );
+
+    let event = Event::LinkState {
+        unique_id: 42,
+        state: gpio::LinkState::Down,
+        streak: 3,
+    };
+    assert_eq!(
+        event.to_json(1700000000123),
+        "{\"event\":\"link_state\",\"timestamp_ms\":1700000000123,\"unique_id\":42,\"state\":\"down\",\"streak\":3}"
+    );
+
+    let event = Event::ShuttingDown {
+        reason: utils::ShutdownReason::Signal,
+        detail: "Received Interrupt\n".to_string(),
+    };
+    assert_eq!(
+        event.to_json(1700000000123),
+        "{\"event\":\"shutting_down\",\"timestamp_ms\":1700000000123,\"reason\":\"Signal\",\"detail\":\"Received Interrupt\\n\"}"
+    );
+}
+
+#[test]
+fn events_are_written_to_the_file() {
+    let path = std::env::temp_dir().join(format!("events-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    write_to(&path).unwrap();
+    publish(|| Event::DriverInitialized {
+        unique_id: 7,
+        gpio_count: 4,
+    });
+
+    let after = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+
+    let lines = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let line = lines
+        .lines()
+        .find(|line| line.starts_with("{\"event\":\"driver_initialized\""))
+        .unwrap();
+    let (timestamp_ms, rest) = line
+        .strip_prefix("{\"event\":\"driver_initialized\",\"timestamp_ms\":")
+        .and_then(|line| line.split_once(','))
+        .unwrap();
+    assert!(timestamp_ms.parse::<u128>().unwrap() <= after);
+    assert_eq!(rest, "\"unique_id\":7,\"gpio_count\":4}");
+}
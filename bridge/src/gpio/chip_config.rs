@@ -0,0 +1,40 @@
+use super::{packet, preset};
+
+// Desired state of a pin, the parts left out are not changed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PinConfig {
+    pub pin: u8,
+    pub config: Option<packet::GpioConfig>,
+    pub value: Option<packet::GpioValue>,
+    pub direction: Option<packet::GpioDirection>,
+}
+
+// Desired state of a chip, the pins left out are not changed
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChipConfig {
+    pub pins: Vec<PinConfig>,
+}
+
+impl ChipConfig {
+    // The init config, the pins it leaves out are disabled
+    pub fn from_presets(presets: &preset::Presets, gpio_count: u8) -> Self {
+        let pins = (0..gpio_count)
+            .map(|pin| match presets.get(pin) {
+                Some(preset) => PinConfig {
+                    pin,
+                    config: preset.config.map(Into::into),
+                    value: preset.value.map(Into::into),
+                    direction: Some(preset.direction.into()),
+                },
+                None => PinConfig {
+                    pin,
+                    config: None,
+                    value: None,
+                    direction: Some(packet::GpioDirection::Disabled),
+                },
+            })
+            .collect();
+
+        Self { pins }
+    }
+}
@@ -0,0 +1,120 @@
+use std::time::Instant;
+
+use super::{Error, RecoverableError};
+
+// Outcome of the requests exchanged with the secondary since startup
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct LinkCounters {
+    pub last_success: Option<Instant>,
+    pub transactions: u64,
+    pub errors: u64,
+    pub timeouts: u64,
+    // Reset by any reply, a link only timing out is considered down
+    pub consecutive_timeouts: u32,
+    // Replies repeating the seq of the reply accepted last, a firmware bug
+    // rather than a link issue
+    pub duplicate_replies: u64,
+}
+
+impl LinkCounters {
+    pub fn record<T>(&mut self, result: &Result<T, Error>, now: Instant) {
+        self.transactions += 1;
+
+        match result {
+            Ok(_) => {
+                self.last_success = Some(now);
+                self.consecutive_timeouts = 0;
+            }
+            Err(Error::Recoverable(RecoverableError::Timeout(..))) => {
+                self.errors += 1;
+                self.timeouts += 1;
+                self.consecutive_timeouts = self.consecutive_timeouts.saturating_add(1);
+            }
+            Err(_) => {
+                self.errors += 1;
+                self.consecutive_timeouts = 0;
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LinkState {
+    Up,
+    Down,
+}
+
+impl LinkState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkState::Up => "up",
+            LinkState::Down => "down",
+        }
+    }
+}
+
+// Whether the secondary answered the request. A status reply proves the link
+// works even when it fails the request, errors raised before anything is
+// sent say nothing about the link.
+fn answered<T>(result: &Result<T, Error>) -> Option<bool> {
+    match result {
+        Ok(_) | Err(Error::Recoverable(RecoverableError::Packet { .. })) => Some(true),
+        Err(Error::Recoverable(
+            RecoverableError::Timeout(..)
+            | RecoverableError::Deserialization(_)
+            | RecoverableError::Desync(_),
+        ))
+        | Err(Error::Unrecoverable(_)) => Some(false),
+        Err(_) => None,
+    }
+}
+
+// Goes down after down_after unanswered requests in a row, and only back up
+// after up_after answered ones in a row so that a flaky link does not flap
+pub struct LinkTracker {
+    state: LinkState,
+    down_after: u32,
+    up_after: u32,
+    // Consecutive requests that contradict the current state
+    streak: u32,
+}
+
+impl LinkTracker {
+    pub fn new(down_after: u32, up_after: u32) -> Self {
+        Self {
+            state: LinkState::Up,
+            down_after,
+            up_after,
+            streak: 0,
+        }
+    }
+
+    pub fn state(&self) -> LinkState {
+        self.state
+    }
+
+    // The new state and the streak that caused it, on a transition
+    pub fn record<T>(&mut self, result: &Result<T, Error>) -> Option<(LinkState, u32)> {
+        let answered = answered(result)?;
+
+        let (threshold, next) = match (self.state, answered) {
+            (LinkState::Up, false) => (self.down_after, LinkState::Down),
+            (LinkState::Down, true) => (self.up_after, LinkState::Up),
+            _ => {
+                self.streak = 0;
+                return None;
+            }
+        };
+
+        self.streak += 1;
+        if self.streak < threshold {
+            return None;
+        }
+
+        let streak = self.streak;
+        self.state = next;
+        self.streak = 0;
+
+        Some((next, streak))
+    }
+}
@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use thiserror::Error;
+
+use super::packet;
+use crate::utils;
+
+const DIAGNOSTICS_CAPACITY: usize = 64;
+
+#[derive(Error, Clone, Debug)]
+pub enum Diagnostic {
+    #[error("Failed to split buffer: {bytes:?}, Err: {error}")]
+    Split { bytes: Vec<u8>, error: String },
+    #[error("Unknown packet received: {bytes:?}, Err: {error}")]
+    UnknownPacket { bytes: Vec<u8>, error: String },
+    #[error("Unable to deserialize {cmd:?} packet: {bytes:?}, Err: {error}")]
+    Deserialization {
+        cmd: packet::SecondaryCmd,
+        bytes: Vec<u8>,
+        error: String,
+    },
+    #[error("Secondary does not support {cmd:?}: {bytes:?}")]
+    UnsupportedCmd {
+        cmd: packet::HostCmd,
+        bytes: Vec<u8>,
+    },
+}
+
+impl Diagnostic {
+    // Bytes the reader could not make sense of, as opposed to a well formed
+    // packet the secondary was not expected to send
+    fn is_malformed(&self) -> bool {
+        match self {
+            Diagnostic::Split { .. }
+            | Diagnostic::UnknownPacket { .. }
+            | Diagnostic::Deserialization { .. } => true,
+            Diagnostic::UnsupportedCmd { .. } => false,
+        }
+    }
+}
+
+pub struct Sink {
+    sender: utils::ChannelSender<Diagnostic>,
+    malformed: Arc<AtomicU32>,
+    warnings: utils::WarnLimiter,
+}
+
+impl Sink {
+    pub fn report(&self, diagnostic: Diagnostic) {
+        self.warnings.warn(format_args!("{}", diagnostic));
+
+        if diagnostic.is_malformed() {
+            self.malformed.fetch_add(1, Ordering::SeqCst);
+        }
+
+        if let Err(err) = self.sender.send(diagnostic) {
+            log::debug!("Failed to queue diagnostic, Err: {}", err);
+        }
+    }
+
+    // Other warnings of the reader, limited along with the diagnostics
+    pub fn warn(&self, args: std::fmt::Arguments) {
+        self.warnings.warn(args);
+    }
+
+    // A reply was routed, the link is usable again
+    pub fn clear(&self) {
+        self.warnings.clear(format_args!("Link diagnostics"));
+    }
+
+    // Malformed frames reported so far, shared with the link
+    pub fn malformed(&self) -> Arc<AtomicU32> {
+        self.malformed.clone()
+    }
+}
+
+pub fn channel() -> (Sink, Arc<Mutex<mpsc::Receiver<Diagnostic>>>) {
+    let config = utils::ChannelConfig {
+        capacity: DIAGNOSTICS_CAPACITY,
+        overflow: utils::Overflow::DropOldest,
+    };
+
+    let (sender, receiver) = utils::sync_channel("Diagnostics", &config);
+
+    let sink = Sink {
+        sender,
+        malformed: Arc::new(AtomicU32::new(0)),
+        warnings: utils::WarnLimiter::new(),
+    };
+
+    (sink, receiver)
+}
@@ -2,6 +2,7 @@ use anyhow::{bail, Result};
 use thiserror::Error;
 
 use crate::gpio::*;
+use crate::utils;
 
 const CPC_ENDPOINT: libcpc::cpc_endpoint_id = libcpc::cpc_endpoint_id::Service(
     libcpc::sl_cpc_service_endpoint_id_t_enum::SL_CPC_ENDPOINT_GPIO,
@@ -15,10 +16,27 @@ const CPC_WRITE_FLAGS: [libcpc::cpc_endpoint_write_flags_t_enum; 1] =
 
 const CPC_TX_WINDOW_SIZE: u8 = 1;
 
-const CPC_INIT_TIMEOUT_MS: u128 = 2000;
-const CPC_INIT_RETRY_INTERVAL_MS: u64 = 100;
-const CPC_ENDPOINT_INIT_TIMEOUT_MS: u128 = 2000;
-const CPC_ENDPOINT_INIT_RETRY_INTERVAL_MS: u64 = 100;
+// Release of cpc-daemon the libcpc dependency is pinned to in Cargo.toml
+pub const LIBCPC_VERSION: &str = "4.3.0";
+
+const CPC_CTRL_SOCKET_DIR: &str = "cpcd";
+const CPC_CTRL_SOCKET_NAME: &str = "ctrl.cpcd.sock";
+
+// The errno carried by a libcpc error, or else the one libcpc left behind
+fn errno(err: &libcpc::Error) -> Option<i32> {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(err) = source {
+        if let Some(errno) = err
+            .downcast_ref::<std::io::Error>()
+            .and_then(|err| err.raw_os_error())
+        {
+            return Some(errno);
+        }
+        source = err.source();
+    }
+
+    std::io::Error::last_os_error().raw_os_error()
+}
 
 #[derive(Error, Debug)]
 pub enum CpcError {
@@ -32,21 +50,65 @@ pub struct Cpc {
 }
 
 impl Cpc {
-    pub fn new(instance_name: &str, enable_tracing: bool) -> Result<Self> {
+    pub fn validate(instance_name: &str, socket_dir: &std::path::Path) -> Result<()> {
+        let socket = socket_dir
+            .join(CPC_CTRL_SOCKET_DIR)
+            .join(instance_name)
+            .join(CPC_CTRL_SOCKET_NAME);
+
+        if !socket.exists() {
+            bail!(
+                "CPCd instance ({}) not found, {} does not exist. Is CPCd running?",
+                instance_name,
+                socket.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn new(
+        instance_name: &str,
+        enable_tracing: bool,
+        retry_policy: &utils::RetryPolicy,
+        init_timeout_ms: u64,
+        open_timeout_ms: u64,
+    ) -> Result<Self> {
         let now = std::time::Instant::now();
+        let mut attempt = 0;
         let cpc_handle = loop {
+            attempt += 1;
             match libcpc::init(instance_name, enable_tracing, None) {
                 Ok(cpc_handle) => {
                     log::info!("Initialized CPCd ({})", instance_name);
                     break cpc_handle;
                 }
                 Err(err) => {
-                    if now.elapsed().as_millis() >= CPC_INIT_TIMEOUT_MS {
-                        bail!("Is CPCd running? Err: {}", err);
+                    // libcpc fails to initialize with ELIBBAD when its API
+                    // version differs from CPCd
+                    if errno(&err) == Some(libc::ELIBBAD) {
+                        bail!(
+                            "libcpc v{} API version is not compatible with CPCd ({}), rebuild the bridge against the libcpc of the running CPCd. Err: {}",
+                            LIBCPC_VERSION,
+                            instance_name,
+                            err
+                        );
                     }
-                    std::thread::sleep(std::time::Duration::from_millis(
-                        CPC_INIT_RETRY_INTERVAL_MS,
-                    ));
+
+                    let delay_ms = match retry_policy
+                        .with_timeout(init_timeout_ms)
+                        .delay_ms(attempt, now.elapsed())
+                    {
+                        Some(delay_ms) => delay_ms,
+                        None => bail!("Is CPCd running? Err: {}", err),
+                    };
+                    log::debug!(
+                        "CPCd init failed, retrying in {} ms (Attempt: {}), Err: {}",
+                        delay_ms,
+                        attempt,
+                        err
+                    );
+                    std::thread::sleep(std::time::Duration::from_millis(delay_ms));
                 }
             };
         };
@@ -54,19 +116,29 @@ impl Cpc {
         let endpoint = CPC_ENDPOINT;
 
         let now = std::time::Instant::now();
+        let mut attempt = 0;
         let cpc_endpoint = loop {
+            attempt += 1;
             match cpc_handle.open_endpoint(endpoint, CPC_TX_WINDOW_SIZE) {
                 Ok(cpc_endpoint) => {
                     log::info!("Initialized CPC Endpoint ({:?})", endpoint);
                     break cpc_endpoint;
                 }
                 Err(err) => {
-                    if now.elapsed().as_millis() >= CPC_ENDPOINT_INIT_TIMEOUT_MS {
-                        bail!("Failed to initialize CPC Endpoint, Err: {}", err);
-                    }
-                    std::thread::sleep(std::time::Duration::from_millis(
-                        CPC_ENDPOINT_INIT_RETRY_INTERVAL_MS,
-                    ));
+                    let delay_ms = match retry_policy
+                        .with_timeout(open_timeout_ms)
+                        .delay_ms(attempt, now.elapsed())
+                    {
+                        Some(delay_ms) => delay_ms,
+                        None => bail!("Failed to initialize CPC Endpoint, Err: {}", err),
+                    };
+                    log::debug!(
+                        "CPC Endpoint init failed, retrying in {} ms (Attempt: {}), Err: {}",
+                        delay_ms,
+                        attempt,
+                        err
+                    );
+                    std::thread::sleep(std::time::Duration::from_millis(delay_ms));
                 }
             };
         };
@@ -79,17 +151,26 @@ impl Gpio for Cpc {
     fn write(&self, bytes: &[u8]) -> Result<(), Error> {
         self.cpc_endpoint
             .write(bytes, &CPC_WRITE_FLAGS)
-            .map_err(|err| UnrecoverableError::Interface(err.into()))?;
+            .map_err(|err| UnrecoverableError::Interface(CpcError::from(err).into(), None))?;
 
         Ok(())
     }
 
     fn read(&self) -> Result<Vec<u8>, Error> {
-        let bytes = self
-            .cpc_endpoint
-            .read(&CPC_READ_FLAGS)
-            .map_err(|err| UnrecoverableError::Interface(err.into()))?;
+        loop {
+            match self.cpc_endpoint.read(&CPC_READ_FLAGS) {
+                Ok(bytes) => return Ok(bytes),
+                Err(err) => {
+                    if errno(&err) == Some(libc::EINTR) {
+                        log::debug!("CPC Endpoint read interrupted, retrying");
+                        continue;
+                    }
 
-        Ok(bytes)
+                    return Err(
+                        UnrecoverableError::Interface(CpcError::from(err).into(), None).into(),
+                    );
+                }
+            }
+        }
     }
 }
@@ -1,37 +1,68 @@
 use anyhow::{anyhow, Result};
+use bincode::Options;
 use nom::AsBytes;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{mpsc, Mutex};
 use thiserror::Error;
 
 use crate::gpio::*;
 
-const GPIO_COUNT: u8 = 16;
-
 #[derive(Error, Debug)]
 pub enum MockError {
     #[error(transparent)]
     Mock(#[from] anyhow::Error),
+    #[error("Mock secondary shut down, its link was dropped")]
+    ShutDown,
 }
 
+// The mock implements the whole GPIO API, a command listed as unsupported is
+// still advertised, like a firmware built without it would
+const MOCK_CAPABILITIES: u32 = packet::Capabilities::FIRMWARE_INFO
+    | packet::Capabilities::GET_GPIO_CONFIG
+    | packet::Capabilities::SET_GPIO_VALUES
+    | packet::Capabilities::GET_GPIO_RAW
+    | packet::Capabilities::GET_GPIO_STATUS
+    | packet::Capabilities::GET_MAX_GPIO_COUNT
+    | packet::Capabilities::GET_GPIO_VALUES
+    | packet::Capabilities::SET_SEQ_WIDTH
+    | packet::Capabilities::GPIO_VALUE_SOURCE
+    | packet::Capabilities::BUFFER_ENABLE;
+
 #[derive(Debug)]
 struct MockGpio {
     name: String,
     value: GpioValue,
     config: GpioConfig,
+    // Buffer enables are kept apart from the bias and drive mode
+    input_enable: bool,
+    output_enable: bool,
     direction: GpioDirection,
 }
 
 #[derive(Debug)]
 pub struct Mock {
-    tx: Mutex<mpsc::Sender<Vec<u8>>>,
+    // Taken on close, the pending read then sees the channel disconnect
+    tx: Mutex<Option<mpsc::Sender<Vec<u8>>>>,
     rx: Mutex<mpsc::Receiver<Vec<u8>>>,
     unique_id: u64,
     label: String,
+    max_gpio_count: u8,
+    disable_policy: utils::MockDisablePolicy,
+    unsupported_cmds: Vec<packet::HostCmd>,
+    unanswered_cmds: Vec<packet::HostCmd>,
     gpios: Mutex<Vec<MockGpio>>,
+    wide_seq: AtomicBool,
 }
 
 impl Mock {
-    pub fn new(instance_name: &str) -> Result<Self> {
+    pub fn new(
+        instance_name: &str,
+        gpio_count: u8,
+        max_gpio_count: Option<u8>,
+        disable_policy: utils::MockDisablePolicy,
+        unsupported_cmds: &[packet::HostCmd],
+        unanswered_cmds: &[packet::HostCmd],
+    ) -> Result<Self> {
         let (tx, rx) = mpsc::channel();
 
         let unique_id = instance_name.parse().unwrap();
@@ -40,11 +71,13 @@ impl Mock {
 
         let mut gpios = vec![];
 
-        for i in 0..GPIO_COUNT {
+        for i in 0..gpio_count {
             let gpio = MockGpio {
                 name: format!("mock-{}-gpio-{}", unique_id, i),
                 value: GpioValue::Low,
                 config: GpioConfig::BiasDisable,
+                input_enable: false,
+                output_enable: false,
                 direction: GpioDirection::Disabled,
             };
 
@@ -52,11 +85,16 @@ impl Mock {
         }
 
         Ok(Self {
-            tx: Mutex::new(tx),
+            tx: Mutex::new(Some(tx)),
             rx: Mutex::new(rx),
             unique_id,
             label,
+            max_gpio_count: max_gpio_count.unwrap_or(gpio_count),
+            disable_policy,
+            unsupported_cmds: unsupported_cmds.to_vec(),
+            unanswered_cmds: unanswered_cmds.to_vec(),
             gpios: Mutex::new(gpios),
+            wide_seq: AtomicBool::new(false),
         })
     }
 }
@@ -66,23 +104,78 @@ impl Gpio for Mock {
         self.tx
             .lock()
             .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?
+            .as_ref()
+            .ok_or(UnrecoverableError::Interface(
+                MockError::ShutDown.into(),
+                None,
+            ))?
             .send(data.to_vec())
-            .map_err(|err| UnrecoverableError::Interface(anyhow!("{}", err).into()))?;
+            .map_err(|err| {
+                UnrecoverableError::Interface(MockError::from(anyhow!("{}", err)).into(), None)
+            })?;
 
         Ok(())
     }
 
     fn read(&self) -> Result<Vec<u8>, Error> {
-        let data = self
+        let rx = self
             .rx
             .lock()
-            .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?
-            .recv()
-            .map_err(|err| UnrecoverableError::Interface(anyhow!("{}", err).into()))?;
+            .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
+
+        // Like a hung secondary, the request is never answered
+        let data = loop {
+            let data = rx
+                .recv()
+                // The sender is only dropped on close
+                .map_err(|_| UnrecoverableError::Interface(MockError::ShutDown.into(), None))?;
+
+            match packet::HostCmd::try_from(data[0]) {
+                Ok(cmd) if self.unanswered_cmds.contains(&cmd) => continue,
+                _ => break data,
+            }
+        };
+
+        // SetSeqWidth is answered with the width in effect for its request
+        if !self.wide_seq.load(Ordering::SeqCst) || !packet::request_carries_seq(&data) {
+            return self.answer(&data);
+        }
+
+        let (data, seq_high) = packet::narrow(&data)
+            .map_err(|err| UnrecoverableError::Interface(MockError::from(err).into(), None))?;
+        let packet = self.answer(&data)?;
+
+        if !packet::reply_carries_seq(&packet) {
+            return Ok(packet);
+        }
+
+        Ok(packet::widen(&packet, seq_high)
+            .map_err(|err| UnrecoverableError::Interface(MockError::from(err).into(), None))?)
+    }
+
+    fn close(&self) {
+        if let Ok(mut tx) = self.tx.lock() {
+            tx.take();
+        }
+    }
+}
 
+impl Mock {
+    fn answer(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
         let mut packet = vec![];
 
-        let (remaining, header) = deserialize_header(&data).unwrap();
+        let (remaining, header) = deserialize_header(data).unwrap();
+
+        // Like a secondary built without the command, the request goes
+        // unanswered apart from the UnsupportedCmdIs
+        if header.cmd == packet::HostCmd::UnknownCmd || self.unsupported_cmds.contains(&header.cmd)
+        {
+            packet.push(packet::SecondaryCmd::UnsupportedCmdIs as u8);
+            packet.push(1);
+            packet.push(data[0]);
+
+            return Ok(packet);
+        }
 
         match header.cmd {
             packet::HostCmd::GetVersion => {
@@ -97,7 +190,7 @@ impl Gpio for Mock {
                 let len = std::mem::size_of_val(&host_header) as u8
                     + std::mem::size_of_val(&self.unique_id) as u8;
 
-                let mut uid = bincode::serialize(&self.unique_id).unwrap();
+                let mut uid = packet::wire_options().serialize(&self.unique_id).unwrap();
 
                 packet.push(packet::SecondaryCmd::UniqueIdIs as u8);
                 packet.push(len);
@@ -121,6 +214,23 @@ impl Gpio for Mock {
 
                 packet.append(&mut label);
             }
+            packet::HostCmd::GetFirmwareInfo => {
+                let (_, host_header) = deserialize_host_header(remaining).unwrap();
+                let mut firmware_info =
+                    std::ffi::CString::new(format!("mock-{}-firmware", self.unique_id))
+                        .unwrap()
+                        .as_bytes_with_nul()
+                        .as_bytes()
+                        .to_vec();
+
+                let len = std::mem::size_of_val(&host_header) as u8 + firmware_info.len() as u8;
+
+                packet.push(packet::SecondaryCmd::FirmwareInfoIs as u8);
+                packet.push(len);
+                packet.push(host_header.seq);
+
+                packet.append(&mut firmware_info);
+            }
             packet::HostCmd::GetGpioCount => {
                 let gpios = self.gpios.lock().unwrap();
                 let (_, host_header) = deserialize_host_header(remaining).unwrap();
@@ -134,6 +244,28 @@ impl Gpio for Mock {
 
                 packet.push(count);
             }
+            packet::HostCmd::GetCapabilities => {
+                let (_, host_header) = deserialize_host_header(remaining).unwrap();
+                let mut capabilities = MOCK_CAPABILITIES.to_le_bytes().to_vec();
+                let len = std::mem::size_of_val(&host_header) as u8 + capabilities.len() as u8;
+
+                packet.push(packet::SecondaryCmd::CapabilitiesIs as u8);
+                packet.push(len);
+                packet.push(host_header.seq);
+
+                packet.append(&mut capabilities);
+            }
+            packet::HostCmd::GetMaxGpioCount => {
+                let (_, host_header) = deserialize_host_header(remaining).unwrap();
+                let len = std::mem::size_of_val(&host_header) as u8
+                    + std::mem::size_of_val(&self.max_gpio_count) as u8;
+
+                packet.push(packet::SecondaryCmd::MaxGpioCountIs as u8);
+                packet.push(len);
+                packet.push(host_header.seq);
+
+                packet.push(self.max_gpio_count);
+            }
             packet::HostCmd::GetGpioName => {
                 let gpios = self.gpios.lock().unwrap();
                 let (remaining, host_header) = deserialize_host_header(remaining).unwrap();
@@ -158,14 +290,104 @@ impl Gpio for Mock {
                 let (remaining, host_header) = deserialize_host_header(remaining).unwrap();
                 let (_, pin) = deserialize_pin(remaining).unwrap();
                 let value = gpios[pin as usize].value;
+                // An input samples its pad, any other pin reads back its
+                // output register
+                let source = match gpios[pin as usize].direction {
+                    GpioDirection::Input => packet::GpioValueSource::Sampled,
+                    GpioDirection::Output | GpioDirection::Disabled => {
+                        packet::GpioValueSource::Driven
+                    }
+                };
+                // Without GetCapabilities, the bridge expects no source
+                let with_source = !self
+                    .unsupported_cmds
+                    .contains(&packet::HostCmd::GetCapabilities);
                 let len = std::mem::size_of_val(&host_header) as u8
-                    + std::mem::size_of_val(&gpios[pin as usize].value) as u8;
+                    + std::mem::size_of_val(&gpios[pin as usize].value) as u8
+                    + with_source as u8 * std::mem::size_of_val(&source) as u8;
 
                 packet.push(packet::SecondaryCmd::GpioValueIs as u8);
                 packet.push(len);
                 packet.push(host_header.seq);
 
                 packet.push(value as u8);
+                if with_source {
+                    packet.push(source as u8);
+                }
+            }
+            packet::HostCmd::GetGpioValues => {
+                let gpios = self.gpios.lock().unwrap();
+                let (remaining, host_header) = deserialize_host_header(remaining).unwrap();
+                let (mut remaining, count) = deserialize_count(remaining).unwrap();
+                let len = std::mem::size_of_val(&host_header) as u8 + 1 + 2 * count;
+
+                packet.push(packet::SecondaryCmd::GpioValuesIs as u8);
+                packet.push(len);
+                packet.push(host_header.seq);
+
+                packet.push(count);
+                for _ in 0..count {
+                    let (rest, pin) = deserialize_pin(remaining).unwrap();
+                    packet.push(pin);
+                    packet.push(gpios[pin as usize].value as u8);
+                    remaining = rest;
+                }
+            }
+            packet::HostCmd::GetGpioConfig => {
+                let gpios = self.gpios.lock().unwrap();
+                let (remaining, host_header) = deserialize_host_header(remaining).unwrap();
+                let (_, pin) = deserialize_pin(remaining).unwrap();
+                let config = gpios[pin as usize].config;
+                let len = std::mem::size_of_val(&host_header) as u8
+                    + std::mem::size_of_val(&gpios[pin as usize].config) as u8;
+
+                packet.push(packet::SecondaryCmd::GpioConfigIs as u8);
+                packet.push(len);
+                packet.push(host_header.seq);
+
+                packet.push(config as u8);
+            }
+            packet::HostCmd::GetGpioRaw => {
+                let gpios = self.gpios.lock().unwrap();
+                let (remaining, host_header) = deserialize_host_header(remaining).unwrap();
+                let (_, pin) = deserialize_pin(remaining).unwrap();
+                let gpio = &gpios[pin as usize];
+                // Synthetic pad registers: mode, pull, data and buffer enables
+                let mut raw = vec![
+                    gpio.direction as u8,
+                    gpio.config as u8,
+                    gpio.value as u8,
+                    gpio.input_enable as u8,
+                    gpio.output_enable as u8,
+                ];
+                let len = std::mem::size_of_val(&host_header) as u8 + raw.len() as u8;
+
+                packet.push(packet::SecondaryCmd::GpioRawIs as u8);
+                packet.push(len);
+                packet.push(host_header.seq);
+
+                packet.append(&mut raw);
+            }
+            packet::HostCmd::GetGpioStatus => {
+                let gpios = self.gpios.lock().unwrap();
+                let (remaining, host_header) = deserialize_host_header(remaining).unwrap();
+                let (_, pin) = deserialize_pin(remaining).unwrap();
+                let gpio = &gpios[pin as usize];
+                // A pin is driven while its output buffer is on, the mock never faults
+                let mut status = 0;
+                if gpio.direction == GpioDirection::Output || gpio.output_enable {
+                    status |= packet::GpioStatus::DRIVEN;
+                }
+                if gpio.direction == GpioDirection::Input || gpio.input_enable {
+                    status |= packet::GpioStatus::INPUT_ENABLED;
+                }
+                let len = std::mem::size_of_val(&host_header) as u8 + 1;
+
+                packet.push(packet::SecondaryCmd::GpioStatusIs as u8);
+                packet.push(len);
+                packet.push(host_header.seq);
+
+                packet.push(status);
             }
             packet::HostCmd::SetGpioValue => {
                 let mut gpios = self.gpios.lock().unwrap();
@@ -183,6 +405,26 @@ impl Gpio for Mock {
 
                 packet.push(packet::Status::Ok as u8);
             }
+            packet::HostCmd::SetGpioValues => {
+                let mut gpios = self.gpios.lock().unwrap();
+                let (remaining, host_header) = deserialize_host_header(remaining).unwrap();
+                let (mut remaining, count) = deserialize_count(remaining).unwrap();
+                let len =
+                    std::mem::size_of_val(&host_header) as u8 + std::mem::size_of::<Status>() as u8;
+
+                for _ in 0..count {
+                    let (rest, pin) = deserialize_pin(remaining).unwrap();
+                    let (rest, value) = deserialize_value(rest).unwrap();
+                    gpios[pin as usize].value = value;
+                    remaining = rest;
+                }
+
+                packet.push(packet::SecondaryCmd::StatusIs as u8);
+                packet.push(len);
+                packet.push(host_header.seq);
+
+                packet.push(packet::Status::Ok as u8);
+            }
             packet::HostCmd::SetGpioConfig => {
                 let mut gpios = self.gpios.lock().unwrap();
                 let (remaining, host_header) = deserialize_host_header(remaining).unwrap();
@@ -191,7 +433,11 @@ impl Gpio for Mock {
                 let len =
                     std::mem::size_of_val(&host_header) as u8 + std::mem::size_of::<Status>() as u8;
 
-                gpios[pin as usize].config = config;
+                match config {
+                    GpioConfig::InputEnable => gpios[pin as usize].input_enable = true,
+                    GpioConfig::OutputEnable => gpios[pin as usize].output_enable = true,
+                    _ => gpios[pin as usize].config = config,
+                }
 
                 packet.push(packet::SecondaryCmd::StatusIs as u8);
                 packet.push(len);
@@ -207,10 +453,13 @@ impl Gpio for Mock {
                 let len =
                     std::mem::size_of_val(&host_header) as u8 + std::mem::size_of::<Status>() as u8;
 
-                match direction {
-                    GpioDirection::Output => (),
-                    GpioDirection::Input => (),
-                    GpioDirection::Disabled => gpios[pin as usize].value = packet::GpioValue::Low,
+                match (direction, self.disable_policy) {
+                    (GpioDirection::Disabled, utils::MockDisablePolicy::ForceLow) => {
+                        gpios[pin as usize].value = packet::GpioValue::Low
+                    }
+                    (GpioDirection::Disabled, utils::MockDisablePolicy::Retain) => (),
+                    (GpioDirection::Output, _) => (),
+                    (GpioDirection::Input, _) => (),
                 }
 
                 gpios[pin as usize].direction = direction;
@@ -221,7 +470,27 @@ impl Gpio for Mock {
 
                 packet.push(packet::Status::Ok as u8);
             }
-            packet::HostCmd::UnknownCmd => panic!(),
+            packet::HostCmd::SetSeqWidth => {
+                let (remaining, host_header) = deserialize_host_header(remaining).unwrap();
+                let (_, seq_width) = deserialize_seq_width(remaining).unwrap();
+                let len =
+                    std::mem::size_of_val(&host_header) as u8 + std::mem::size_of::<Status>() as u8;
+
+                let status = match seq_width {
+                    1 | 2 => {
+                        self.wide_seq.store(seq_width == 2, Ordering::SeqCst);
+                        packet::Status::Ok
+                    }
+                    _ => packet::Status::NotSupported,
+                };
+
+                packet.push(packet::SecondaryCmd::StatusIs as u8);
+                packet.push(len);
+                packet.push(host_header.seq);
+
+                packet.push(status as u8);
+            }
+            packet::HostCmd::UnknownCmd => unreachable!(),
         }
 
         Ok(packet)
@@ -245,11 +514,21 @@ fn deserialize_host_header(input: &[u8]) -> nom::IResult<&[u8], packet::HostHead
     Ok((remaining, packet::HostHeader { seq }))
 }
 
+fn deserialize_seq_width(input: &[u8]) -> nom::IResult<&[u8], u8> {
+    let (remaining, seq_width) = nom::number::complete::u8(input)?;
+    Ok((remaining, seq_width))
+}
+
 fn deserialize_pin(input: &[u8]) -> nom::IResult<&[u8], u8> {
     let (remaining, pin) = nom::number::complete::u8(input)?;
     Ok((remaining, pin))
 }
 
+fn deserialize_count(input: &[u8]) -> nom::IResult<&[u8], u8> {
+    let (remaining, count) = nom::number::complete::u8(input)?;
+    Ok((remaining, count))
+}
+
 fn deserialize_value(input: &[u8]) -> nom::IResult<&[u8], GpioValue> {
     let (remaining, value) = nom::number::complete::u8(input)?;
     Ok((remaining, GpioValue::try_from(value).unwrap()))
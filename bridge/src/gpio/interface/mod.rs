@@ -1,24 +1,142 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
+use thiserror::Error;
 
 use super::GpioTraits;
 use crate::utils;
 
+#[cfg(not(any(feature = "gpio_cpc", feature = "gpio_mock", feature = "gpio_replay")))]
+compile_error!("At least one of the gpio_cpc, gpio_mock or gpio_replay features must be enabled");
+
 #[cfg(feature = "gpio_mock")]
 mod mock;
-#[cfg(feature = "gpio_mock")]
-pub use mock::MockError as Error;
+
+#[cfg(feature = "gpio_replay")]
+mod replay;
+#[cfg(feature = "gpio_replay")]
+use anyhow::Context;
 
 #[cfg(feature = "gpio_cpc")]
 mod cpc;
 #[cfg(feature = "gpio_cpc")]
-pub use cpc::CpcError as Error;
+pub use cpc::LIBCPC_VERSION;
 
-pub fn new(config: &utils::Config, _trace_config: &utils::TraceConfig) -> Result<Box<GpioTraits>> {
+#[derive(Error, Debug)]
+pub enum Error {
     #[cfg(feature = "gpio_mock")]
-    let interface = mock::Mock::new(&config.instance)?;
+    #[error(transparent)]
+    Mock(#[from] mock::MockError),
+    #[cfg(feature = "gpio_replay")]
+    #[error(transparent)]
+    Replay(#[from] replay::ReplayError),
+    #[cfg(feature = "gpio_cpc")]
+    #[error(transparent)]
+    Cpc(#[from] cpc::CpcError),
+}
+
+// Identifies the transport in timeout and link errors, small enough to be
+// cloned into each of them
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Descriptor {
+    Cpc { instance: String },
+    Mock { instance: String },
+    Replay { trace: Option<std::path::PathBuf> },
+}
+
+impl std::fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Descriptor::Cpc { instance } => write!(f, "Cpc({})", instance),
+            Descriptor::Mock { instance } => write!(f, "Mock({})", instance),
+            Descriptor::Replay { trace: Some(trace) } => write!(f, "Replay({})", trace.display()),
+            Descriptor::Replay { trace: None } => write!(f, "Replay"),
+        }
+    }
+}
+
+pub fn descriptor(config: &utils::Config) -> Descriptor {
+    match config.transport {
+        utils::Transport::Cpc => Descriptor::Cpc {
+            instance: config.instance.clone(),
+        },
+        utils::Transport::Mock => Descriptor::Mock {
+            instance: config.instance.clone(),
+        },
+        #[cfg(feature = "gpio_replay")]
+        utils::Transport::Replay => Descriptor::Replay {
+            trace: config.replay_trace.clone(),
+        },
+        #[cfg(not(feature = "gpio_replay"))]
+        utils::Transport::Replay => Descriptor::Replay { trace: None },
+    }
+}
+
+// Transports are selected at runtime, the features only decide which ones
+// are built in
+fn check_built_in(transport: utils::Transport) -> Result<()> {
+    let (feature, built_in) = match transport {
+        utils::Transport::Cpc => ("gpio_cpc", cfg!(feature = "gpio_cpc")),
+        utils::Transport::Mock => ("gpio_mock", cfg!(feature = "gpio_mock")),
+        utils::Transport::Replay => ("gpio_replay", cfg!(feature = "gpio_replay")),
+    };
+
+    if !built_in {
+        bail!(
+            "The {:?} transport is not built in, rebuild the Bridge with the {} feature",
+            transport,
+            feature
+        );
+    }
+
+    Ok(())
+}
+
+pub fn validate(config: &utils::Config) -> Result<()> {
+    check_built_in(config.transport)?;
 
     #[cfg(feature = "gpio_cpc")]
-    let interface = cpc::Cpc::new(&config.instance, _trace_config.libcpc)?;
+    if config.transport == utils::Transport::Cpc {
+        cpc::Cpc::validate(&config.instance, &config.cpcd_socket_dir)?;
+    }
+
+    #[cfg(feature = "gpio_replay")]
+    if config.transport == utils::Transport::Replay && config.replay_trace.is_none() {
+        bail!("The Replay transport requires --replay-trace");
+    }
+
+    Ok(())
+}
+
+pub fn new(config: &utils::Config, _trace_config: &utils::TraceConfig) -> Result<Box<GpioTraits>> {
+    check_built_in(config.transport)?;
+
+    let interface: Box<GpioTraits> = match config.transport {
+        #[cfg(feature = "gpio_cpc")]
+        utils::Transport::Cpc => Box::new(cpc::Cpc::new(
+            &config.instance,
+            _trace_config.libcpc,
+            &utils::retry_policy(config),
+            config.cpcd_init_timeout_ms,
+            config.endpoint_open_timeout_ms,
+        )?),
+        #[cfg(feature = "gpio_mock")]
+        utils::Transport::Mock => Box::new(mock::Mock::new(
+            &config.instance,
+            config.mock_gpio_count,
+            config.mock_max_gpio_count,
+            config.mock_disable_policy,
+            &config.mock_unsupported_cmd,
+            &config.mock_unanswered_cmd,
+        )?),
+        #[cfg(feature = "gpio_replay")]
+        utils::Transport::Replay => Box::new(replay::Replay::new(
+            config
+                .replay_trace
+                .as_deref()
+                .context("The Replay transport requires --replay-trace")?,
+        )?),
+        #[allow(unreachable_patterns)]
+        transport => unreachable!("{:?} transport checked as built in", transport),
+    };
 
-    Ok(Box::new(interface))
+    Ok(interface)
 }
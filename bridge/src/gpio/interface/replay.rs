@@ -0,0 +1,239 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::VecDeque;
+use std::sync::{mpsc, Mutex};
+use thiserror::Error;
+
+use crate::gpio::*;
+
+const TRACE_COMMENT: char = '#';
+const TRACE_HOST: char = '>';
+const TRACE_SECONDARY: char = '<';
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error(transparent)]
+    Replay(#[from] anyhow::Error),
+}
+
+#[derive(Debug, PartialEq)]
+enum Direction {
+    Host,
+    Secondary,
+}
+
+#[derive(Debug)]
+struct Record {
+    line: usize,
+    direction: Direction,
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Replay {
+    tx: Mutex<mpsc::Sender<Vec<u8>>>,
+    rx: Mutex<mpsc::Receiver<Vec<u8>>>,
+    records: Mutex<VecDeque<Record>>,
+}
+
+impl Replay {
+    pub fn new(path: &std::path::Path) -> Result<Self> {
+        let trace = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read packet trace ({})", path.display()))?;
+
+        let records = parse(&trace)
+            .with_context(|| format!("Failed to parse packet trace ({})", path.display()))?;
+
+        log::info!(
+            "Replaying packet trace ({}, {} records)",
+            path.display(),
+            records.len()
+        );
+
+        let (tx, rx) = mpsc::channel();
+
+        let replay = Self {
+            tx: Mutex::new(tx),
+            rx: Mutex::new(rx),
+            records: Mutex::new(records),
+        };
+
+        replay.release_replies()?;
+
+        Ok(replay)
+    }
+
+    fn release_replies(&self) -> Result<()> {
+        let mut records = self.records.lock().map_err(|err| anyhow!("{}", err))?;
+        let tx = self.tx.lock().map_err(|err| anyhow!("{}", err))?;
+
+        while let Some(record) = records.front() {
+            if record.direction != Direction::Secondary {
+                break;
+            }
+
+            if let Some(record) = records.pop_front() {
+                tx.send(record.bytes)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Gpio for Replay {
+    fn write(&self, bytes: &[u8]) -> Result<(), Error> {
+        let record = self
+            .records
+            .lock()
+            .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?
+            .pop_front();
+
+        match record {
+            Some(record) if record.direction == Direction::Host && record.bytes == bytes => (),
+            Some(record) => {
+                return Err(UnrecoverableError::Interface(
+                    ReplayError::from(anyhow!(
+                    "Packet trace mismatch at line {} (Expected: {:?} {:02x?}, Received: {:02x?})",
+                    record.line,
+                    record.direction,
+                    record.bytes,
+                    bytes
+                ))
+                    .into(),
+                    None,
+                )
+                .into())
+            }
+            None => {
+                return Err(UnrecoverableError::Interface(
+                    ReplayError::from(anyhow!("Packet trace exhausted (Received: {:02x?})", bytes))
+                        .into(),
+                    None,
+                )
+                .into())
+            }
+        }
+
+        self.release_replies()
+            .map_err(|err| UnrecoverableError::Interface(ReplayError::from(err).into(), None))?;
+
+        Ok(())
+    }
+
+    fn read(&self) -> Result<Vec<u8>, Error> {
+        let bytes = self
+            .rx
+            .lock()
+            .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?
+            .recv()
+            .map_err(|err| {
+                UnrecoverableError::Interface(ReplayError::from(anyhow!("{}", err)).into(), None)
+            })?;
+
+        Ok(bytes)
+    }
+}
+
+fn parse(trace: &str) -> Result<VecDeque<Record>> {
+    let mut records = VecDeque::new();
+
+    for (index, line) in trace.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(TRACE_COMMENT) {
+            continue;
+        }
+
+        let direction = match line.chars().next() {
+            Some(TRACE_HOST) => Direction::Host,
+            Some(TRACE_SECONDARY) => Direction::Secondary,
+            _ => bail!(
+                "Line {} must start with '{}' or '{}'",
+                line_number,
+                TRACE_HOST,
+                TRACE_SECONDARY
+            ),
+        };
+
+        let bytes = decode_hex(&line[1..])
+            .with_context(|| format!("Line {} has an invalid hex payload", line_number))?;
+
+        records.push_back(Record {
+            line: line_number,
+            direction,
+            bytes,
+        });
+    }
+
+    Ok(records)
+}
+
+// Pairs of hex digits, whitespace allowed anywhere in between
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let digits: Vec<u8> = hex
+        .bytes()
+        .filter(|byte| !byte.is_ascii_whitespace())
+        .collect();
+    let pairs = digits.chunks_exact(2);
+
+    if digits.is_empty() || !pairs.remainder().is_empty() {
+        bail!("Odd or empty digit count ({})", digits.len());
+    }
+
+    pairs
+        .map(|pair| {
+            let digit = |byte: u8| (byte as char).to_digit(16);
+            match (digit(pair[0]), digit(pair[1])) {
+                (Some(high), Some(low)) => Ok((high << 4 | low) as u8),
+                _ => bail!("Not a hex byte: {:?}", String::from_utf8_lossy(pair)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_records() {
+        let records = parse("# comment\n\n> 01 0a ff\n<0B0c\n").unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].line, 3);
+        assert_eq!(records[0].direction, Direction::Host);
+        assert_eq!(records[0].bytes, vec![0x01, 0x0a, 0xff]);
+        assert_eq!(records[1].line, 4);
+        assert_eq!(records[1].direction, Direction::Secondary);
+        assert_eq!(records[1].bytes, vec![0x0b, 0x0c]);
+    }
+
+    #[test]
+    fn parse_rejects_unknown_direction() {
+        let err = parse("01 02").unwrap_err().to_string();
+
+        assert!(err.contains("Line 1 must start with"), "{}", err);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_payloads() {
+        for line in [
+            ">",
+            "> 0",
+            "> 012",
+            "> 0g",
+            "> +1",
+            "> \u{e9}\u{e9}",
+            "< 0\u{e9}",
+        ] {
+            let err = parse(line).unwrap_err().to_string();
+
+            assert!(
+                err.contains("Line 1 has an invalid hex payload"),
+                "{:?}",
+                line
+            );
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use anyhow::{anyhow, bail, Result};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+
+use super::{
+    diagnostics, interface, packet, Error, GpioTraits, RecoverableError, UnrecoverableError,
+};
+use crate::utils;
+
+// Framing and demultiplexing of a transport, independent of the requests
+// exchanged over it. A reader thread splits whatever the transport reads into
+// packets, replies are queued for the request in flight and anything else is
+// reported as a diagnostic.
+pub struct Link {
+    transport: Arc<Box<GpioTraits>>,
+    descriptor: interface::Descriptor,
+    replies: Arc<Mutex<mpsc::Receiver<Vec<u8>>>>,
+    diagnostics: Arc<Mutex<mpsc::Receiver<diagnostics::Diagnostic>>>,
+    malformed: Arc<AtomicU32>,
+    heartbeat: Arc<utils::Heartbeat>,
+    // Set on drop, the read that fails on the closed transport is expected
+    closing: Arc<AtomicBool>,
+    // Set once the secondary accepted a two-byte seq
+    wide_seq: Arc<AtomicBool>,
+    epochs: Arc<Mutex<Epochs>>,
+}
+
+// High bytes of the two-byte seq. The requests count up with a one-byte seq,
+// the high byte counts its wraparounds.
+struct Epochs {
+    last_low: Option<u8>,
+    high: u8,
+    // High byte last sent with each low byte
+    sent: [u8; 256],
+}
+
+impl Epochs {
+    fn new() -> Self {
+        Self {
+            last_low: None,
+            high: 0,
+            sent: [0; 256],
+        }
+    }
+
+    fn on_send(&mut self, low: u8) -> u8 {
+        if self.last_low.is_some_and(|last_low| low <= last_low) {
+            self.high = self.high.wrapping_add(1);
+        }
+        self.last_low = Some(low);
+        self.sent[low as usize] = self.high;
+        self.high
+    }
+
+    fn high_of(&self, low: u8) -> u8 {
+        self.sent[low as usize]
+    }
+}
+
+impl Link {
+    pub fn spawn(
+        transport: Box<GpioTraits>,
+        descriptor: interface::Descriptor,
+        channel_config: &utils::ChannelConfig,
+    ) -> Result<(Self, utils::ThreadExit)> {
+        let transport = Arc::new(transport);
+        let transport_ref = transport.clone();
+
+        let (replies_tx, replies) = utils::sync_channel("GPIO", channel_config);
+        let (diagnostics, diagnostics_rx) = diagnostics::channel();
+        let malformed = diagnostics.malformed();
+        let (mut exit_sender, exit_receiver) = mio::unix::pipe::new()?;
+        let heartbeat = Arc::new(utils::Heartbeat::new("GPIO"));
+        let heartbeat_ref = heartbeat.clone();
+        let descriptor_ref = descriptor.clone();
+        let closing = Arc::new(AtomicBool::new(false));
+        let closing_ref = closing.clone();
+        let wide_seq = Arc::new(AtomicBool::new(false));
+        let wide_seq_ref = wide_seq.clone();
+        let epochs = Arc::new(Mutex::new(Epochs::new()));
+        let epochs_ref = epochs.clone();
+
+        std::thread::Builder::new()
+            .name("gpio".to_string())
+            .spawn(move || loop {
+                let result = (|| -> Result<ControlFlow<()>> {
+                    let buffer = match transport_ref.read() {
+                        Ok(buffer) => buffer,
+                        Err(_) if closing_ref.load(Ordering::SeqCst) => {
+                            return Ok(ControlFlow::Break(()));
+                        }
+                        Err(err) => bail!(
+                            "Failed to read from GPIO, Err: {}",
+                            err.on_transport(&descriptor_ref)
+                        ),
+                    };
+
+                    heartbeat_ref.begin();
+
+                    match packet::split(&buffer) {
+                        Ok(packets) => {
+                            for packet in packets {
+                                let packet = if wide_seq_ref.load(Ordering::SeqCst)
+                                    && packet::reply_carries_seq(&packet)
+                                {
+                                    match narrow(packet, &epochs_ref, &diagnostics) {
+                                        Some(packet) => packet,
+                                        None => continue,
+                                    }
+                                } else {
+                                    packet
+                                };
+
+                                route(packet, &replies_tx, &diagnostics)?;
+                            }
+                        }
+                        Err(err) => diagnostics.report(diagnostics::Diagnostic::Split {
+                            bytes: buffer,
+                            error: err.to_string(),
+                        }),
+                    };
+
+                    heartbeat_ref.end();
+
+                    Ok(ControlFlow::Continue(()))
+                })();
+
+                match result {
+                    Ok(ControlFlow::Continue(())) => (),
+                    Ok(ControlFlow::Break(())) => {
+                        log::debug!("GPIO reader stopped");
+                        return;
+                    }
+                    Err(err) => {
+                        utils::ThreadExit::notify(&mut exit_sender, &format!("{}", err));
+                        return;
+                    }
+                }
+            })?;
+
+        let link = Self {
+            transport,
+            descriptor,
+            replies,
+            diagnostics: diagnostics_rx,
+            malformed,
+            heartbeat,
+            closing,
+            wide_seq,
+            epochs,
+        };
+
+        let exit = utils::ThreadExit {
+            receiver: Mutex::new(exit_receiver),
+        };
+
+        Ok((link, exit))
+    }
+
+    pub fn heartbeat(&self) -> Arc<utils::Heartbeat> {
+        self.heartbeat.clone()
+    }
+
+    pub fn transport(&self) -> &interface::Descriptor {
+        &self.descriptor
+    }
+
+    // From now on, requests and replies carry a two-byte seq
+    pub fn widen_seq(&self) {
+        self.wide_seq.store(true, Ordering::SeqCst);
+    }
+
+    pub fn write(&self, bytes: &[u8]) -> Result<(), Error> {
+        let wide;
+        let bytes = if self.wide_seq.load(Ordering::SeqCst) && packet::request_carries_seq(bytes) {
+            let high = self
+                .epochs
+                .lock()
+                .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?
+                .on_send(bytes[2]);
+            wide = packet::widen(bytes, high).map_err(RecoverableError::Serialization)?;
+            &wide
+        } else {
+            bytes
+        };
+
+        self.transport
+            .write(bytes)
+            .map_err(|err| err.on_transport(&self.descriptor))
+    }
+
+    // A poisoned channel is as unusable as a disconnected one
+    pub fn recv_timeout(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<Vec<u8>, mpsc::RecvTimeoutError> {
+        match self.replies.lock() {
+            Ok(replies) => replies.recv_timeout(timeout),
+            Err(_) => Err(mpsc::RecvTimeoutError::Disconnected),
+        }
+    }
+
+    // Drops the replies queued so far and forgets the malformed frames
+    // received with them, returns the number of replies dropped
+    pub fn discard(&self) -> usize {
+        self.malformed.store(0, Ordering::SeqCst);
+
+        match self.replies.lock() {
+            Ok(replies) => replies.try_iter().count(),
+            Err(_) => 0,
+        }
+    }
+
+    // Malformed frames received since the last discard
+    pub fn malformed_frames(&self) -> u32 {
+        self.malformed.load(Ordering::SeqCst)
+    }
+
+    pub fn diagnostics(&self) -> Vec<diagnostics::Diagnostic> {
+        match self.diagnostics.lock() {
+            Ok(receiver) => receiver.try_iter().collect(),
+            Err(_) => vec![],
+        }
+    }
+}
+
+// The reader holds the transport too, it would otherwise block on it forever
+impl Drop for Link {
+    fn drop(&mut self) {
+        self.closing.store(true, Ordering::SeqCst);
+        self.transport.close();
+    }
+}
+
+// A reply whose high byte is not the one last sent with its low byte answers
+// a request from before the seq wrapped around, it must not be taken for the
+// reply to the request now carrying that low byte
+fn narrow(
+    packet: Vec<u8>,
+    epochs: &Mutex<Epochs>,
+    diagnostics: &diagnostics::Sink,
+) -> Option<Vec<u8>> {
+    let (narrow, high) = match packet::narrow(&packet) {
+        Ok(narrowed) => narrowed,
+        Err(err) => {
+            diagnostics.report(diagnostics::Diagnostic::Split {
+                bytes: packet,
+                error: err.to_string(),
+            });
+            return None;
+        }
+    };
+
+    let expected = epochs.lock().ok()?.high_of(narrow[2]);
+    if high != expected {
+        diagnostics.warn(format_args!(
+            "Dropped a reply from before the seq wrapped around (Seq: {}, High byte: {}, Expected: {})",
+            narrow[2],
+            high,
+            expected
+        ));
+        return None;
+    }
+
+    Some(narrow)
+}
+
+// Every reply must be routed explicitly, a new SecondaryCmd variant must not
+// compile until it is handled here.
+#[deny(clippy::wildcard_enum_match_arm)]
+fn route(
+    packet: Vec<u8>,
+    data_tx: &utils::ChannelSender<Vec<u8>>,
+    diagnostics: &diagnostics::Sink,
+) -> Result<()> {
+    let rx_cmd = match packet::try_deserialize_cmd(&packet) {
+        Ok(rx_cmd) => rx_cmd,
+        #[cfg(feature = "raw_request")]
+        Err(_) if packet::is_raw_reply(&packet) => {
+            if let Err(err) = data_tx.send(packet) {
+                bail!("Failed to send to GPIO channel, Err: {}", err)
+            }
+            return Ok(());
+        }
+        Err(err) => {
+            diagnostics.report(diagnostics::Diagnostic::UnknownPacket {
+                bytes: packet,
+                error: err.to_string(),
+            });
+            return Ok(());
+        }
+    };
+
+    match rx_cmd {
+        packet::SecondaryCmd::VersionIs
+        | packet::SecondaryCmd::StatusIs
+        | packet::SecondaryCmd::GpioCountIs
+        | packet::SecondaryCmd::GpioNameIs
+        | packet::SecondaryCmd::GpioValueIs
+        | packet::SecondaryCmd::ChipLabelIs
+        | packet::SecondaryCmd::UniqueIdIs
+        | packet::SecondaryCmd::FirmwareInfoIs
+        | packet::SecondaryCmd::GpioConfigIs
+        | packet::SecondaryCmd::GpioRawIs
+        | packet::SecondaryCmd::GpioStatusIs
+        | packet::SecondaryCmd::MaxGpioCountIs
+        | packet::SecondaryCmd::GpioValuesIs
+        | packet::SecondaryCmd::CapabilitiesIs => {
+            if let Err(err) = data_tx.send(packet) {
+                bail!("Failed to send to GPIO channel, Err: {}", err)
+            }
+            diagnostics.clear();
+        }
+        packet::SecondaryCmd::UnsupportedCmdIs => {
+            match packet::UnsupportedCmdIs::deserialize(&packet) {
+                // Also answers the request refused, which then fails at once.
+                // Reported first, the request sees it once it failed.
+                Ok(unsupported) => {
+                    diagnostics.report(diagnostics::Diagnostic::UnsupportedCmd {
+                        cmd: unsupported.unsupported_cmd,
+                        bytes: packet.clone(),
+                    });
+                    if let Err(err) = data_tx.send(packet) {
+                        bail!("Failed to send to GPIO channel, Err: {}", err)
+                    }
+                }
+                Err(err) => diagnostics.report(diagnostics::Diagnostic::Deserialization {
+                    cmd: rx_cmd,
+                    bytes: packet,
+                    error: err.to_string(),
+                }),
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,18 +1,49 @@
-use anyhow::{anyhow, bail, Result};
-use std::sync::Mutex;
-use std::sync::{mpsc, Arc};
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{mpsc, RwLock};
+use std::sync::{Mutex, MutexGuard, TryLockError};
 use thiserror::Error;
 
 use crate::utils;
 
 mod interface;
+pub use interface::Descriptor;
+#[cfg(feature = "gpio_cpc")]
+pub use interface::LIBCPC_VERSION;
+
+#[cfg(all(test, feature = "gpio_mock"))]
+mod tests;
 
 mod packet;
 use self::packet::Serializer;
+
+mod preset;
+
+mod pin_state;
+pub use pin_state::PinRecord;
+
+mod chip_config;
+pub use chip_config::ChipConfig;
+
+mod diagnostics;
+pub use diagnostics::Diagnostic;
+
+mod link;
+
+mod rtt;
+
+mod counters;
+pub use counters::LinkCounters;
+pub use counters::LinkState;
 pub use packet::GpioConfig;
 pub use packet::GpioDirection;
 pub use packet::GpioValue;
+pub use packet::GpioValueSource;
+pub use packet::HostCmd;
+pub use packet::InvalidGpioValue;
 pub use packet::Status;
+pub use rtt::RttStats;
 
 pub const VERSION: utils::Version = utils::Version {
     major: 1,
@@ -21,6 +52,12 @@ pub const VERSION: utils::Version = utils::Version {
 };
 
 const READ_TIMEOUT_MS: u128 = 2000;
+const DRAIN_POLL_MS: u64 = 10;
+// Values may be backed by a slow peripheral on the secondary (e.g. an ADC)
+const SLOW_READ_TIMEOUT_MS: u128 = 5000;
+
+// GPIO_MAX_NAME_SIZE of the GPIO userspace API, including the nul terminator
+const GPIO_NAME_MAX_LEN: usize = 31;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -32,20 +69,77 @@ pub enum Error {
 
 #[derive(Error, Debug)]
 pub enum RecoverableError {
-    #[error("Timeout({0}: {1} ms)")]
-    Timeout(mpsc::RecvTimeoutError, u128),
+    #[error("Timeout({0}: {1} ms, Transport: {2})")]
+    Timeout(mpsc::RecvTimeoutError, u128, interface::Descriptor),
     #[error("Deserializer({0})")]
     Deserialization(anyhow::Error),
     #[error("Serializer({0})")]
     Serialization(anyhow::Error),
-    #[error("Status({0})")]
-    Packet(packet::Status),
+    #[error("Status({status}, Cmd: {cmd:?}{})", format_pin(.pin))]
+    Packet {
+        status: packet::Status,
+        cmd: packet::HostCmd,
+        pin: Option<u8>,
+    },
+    #[error("InvalidPin({0})")]
+    InvalidPin(u8),
+    #[error("DuplicatePin({0})")]
+    DuplicatePin(u8),
+    #[error("Desync({0} consecutive sequence number mismatches)")]
+    Desync(u32),
+    #[error("ShuttingDown")]
+    ShuttingDown,
+}
+
+impl Error {
+    // The pin a status reported by the secondary applies to, only the request
+    // helpers know it
+    fn at_pin(self, pin: u8) -> Self {
+        match self {
+            Error::Recoverable(RecoverableError::Packet { status, cmd, .. }) => {
+                RecoverableError::Packet {
+                    status,
+                    cmd,
+                    pin: Some(pin),
+                }
+                .into()
+            }
+            err => err,
+        }
+    }
+
+    // The transport a link error came from, only the link knows it
+    fn on_transport(self, transport: &interface::Descriptor) -> Self {
+        match self {
+            Error::Unrecoverable(UnrecoverableError::Interface(err, _)) => {
+                UnrecoverableError::Interface(err, Some(transport.clone())).into()
+            }
+            Error::Unrecoverable(UnrecoverableError::Anyhow(err)) => {
+                UnrecoverableError::Anyhow(anyhow!("{}, Transport: {}", err, transport)).into()
+            }
+            err => err,
+        }
+    }
+}
+
+fn format_pin(pin: &Option<u8>) -> String {
+    match pin {
+        Some(pin) => format!(", Pin: {}", pin),
+        None => String::new(),
+    }
+}
+
+fn format_transport(transport: &Option<interface::Descriptor>) -> String {
+    match transport {
+        Some(transport) => format!(", Transport: {}", transport),
+        None => String::new(),
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum UnrecoverableError {
-    #[error(transparent)]
-    Interface(interface::Error),
+    #[error("{0}{}", format_transport(.1))]
+    Interface(#[source] interface::Error, Option<interface::Descriptor>),
     #[error(transparent)]
     Anyhow(anyhow::Error),
 }
@@ -53,114 +147,198 @@ pub enum UnrecoverableError {
 pub trait Gpio {
     fn write(&self, bytes: &[u8]) -> Result<(), Error>;
     fn read(&self) -> Result<Vec<u8>, Error>;
+    // Unblocks a pending read once the link is dropped, the read then fails
+    fn close(&self) {}
 }
 pub type GpioTraits = dyn Gpio + Send + Sync;
 
 pub struct Chip {
-    pub unique_id: u64,
     pub label: String,
+    pub firmware_info: Option<String>,
+    // Hard limit of the firmware, the GPIO count of an older secondary
+    pub max_gpio_count: u8,
     pub gpio_names: Vec<String>,
+    pub raw_gpio_names: Vec<String>,
+    pub fallback_name_pins: Vec<u8>,
+}
+
+// Discovery values read again from the secondary by Handle::rediscover
+#[derive(Debug)]
+pub struct Discovery {
+    pub version: utils::Version,
+    pub capabilities: packet::Capabilities,
+    pub unique_id: u64,
+    pub label: String,
+    pub firmware_info: Option<String>,
+    pub gpio_count: u8,
+    pub max_gpio_count: Option<u8>,
+    pub names: Vec<Result<String>>,
+}
+
+impl std::fmt::Display for Discovery {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "GPIO API: v{}, Capabilities: {}, UID: {}, Label: {:?}",
+            self.version, self.capabilities, self.unique_id, self.label
+        )?;
+        if let Some(firmware_info) = &self.firmware_info {
+            write!(f, ", Firmware: {}", firmware_info)?;
+        }
+        write!(f, ", GPIO count: {}", self.gpio_count)?;
+        if let Some(max_gpio_count) = self.max_gpio_count {
+            write!(f, ", Max GPIO count: {}", max_gpio_count)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PinInfo {
+    pub pin: u8,
+    pub name: String,
+    pub raw_name: String,
 }
 
 pub struct Handle {
     pub exit: utils::ThreadExit,
     pub chip: Chip,
-    gpio: Arc<Box<GpioTraits>>,
-    data_rx: Mutex<mpsc::Receiver<Vec<u8>>>,
+    link: link::Link,
+    gpio_version: utils::Version,
+    capabilities: packet::Capabilities,
     seq: Mutex<u8>,
+    transaction: Mutex<()>,
+    // Set on shutdown, new transactions are refused from then on
+    draining: AtomicBool,
+    cmd_timeouts: Vec<utils::CmdTimeout>,
+    max_seq_mismatches: u32,
+    seq_mismatch_warnings: utils::WarnLimiter,
+    // Seq of the reply accepted last, taken by the first reply repeating it
+    last_accepted_seq: Mutex<Option<u8>>,
+    duplicate_reply_warnings: utils::WarnLimiter,
+    resync_after_malformed: u32,
+    rtt: Mutex<rtt::Rtt>,
+    name_policy: utils::NamePolicy,
+    tolerate_name_errors: bool,
+    gpio_count_override: Option<u8>,
+    // Pins registered after discovery, contiguous with the chip
+    added_pins: RwLock<Vec<PinInfo>>,
+    pin_states: pin_state::PinStates,
+    // Output pins rewritten by reconcile_outputs since startup
+    reconciled: AtomicU64,
+    counters: Mutex<LinkCounters>,
+    link_tracker: Mutex<counters::LinkTracker>,
+    // Re-read on request, the secondary may change it on a firmware update
+    unique_id: AtomicU64,
+    unique_id_override: Option<u64>,
+    default_bias: Option<packet::GpioConfig>,
+    // Left as found, the Kernel Driver never sees them
+    unmanaged_pins: Vec<u8>,
+    // Named groups of pins from the init config
+    banks: Vec<preset::Bank>,
+}
+
+// Bounds the whole discovery, which otherwise scales with the GPIO count
+struct Deadline {
+    start: std::time::Instant,
+    timeout_ms: Option<u64>,
+}
+
+impl Deadline {
+    fn check(&self, progress: &str) -> Result<()> {
+        if let Some(timeout_ms) = self.timeout_ms {
+            if self.start.elapsed().as_millis() >= timeout_ms as u128 {
+                bail!(
+                    "Startup timeout ({} ms) exceeded, discovery got to: {}",
+                    timeout_ms,
+                    progress
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn validate(config: &utils::Config) -> Result<()> {
+    interface::validate(config)
 }
 
 impl Handle {
     pub fn new(config: &utils::Config, trace_config: &utils::TraceConfig) -> Result<Self> {
+        let presets = match &config.init_config {
+            Some(path) => preset::Presets::load(path)?,
+            None => preset::Presets::default(),
+        };
+
         let interface = interface::new(config, trace_config)?;
-        let gpio = Arc::new(interface);
-        let gpio_ref = gpio.clone();
-
-        let (data_tx, data_rx) = mpsc::channel();
-        let (mut exit_sender, exit_receiver) = mio::unix::pipe::new()?;
-
-        std::thread::Builder::new()
-            .name("gpio".to_string())
-            .spawn(move || loop {
-                let result = (|| -> Result<()> {
-                    let buffer = match gpio_ref.read() {
-                        Ok(buffer) => buffer,
-                        Err(err) => bail!("Failed to read from GPIO, Err: {:?}", err),
-                    };
-
-                    match packet::split(&buffer) {
-                        Ok(packets) => {
-                            for packet in packets {
-                                match packet::try_deserialize_cmd(&packet) {
-                                    Ok(rx_cmd) => match rx_cmd {
-                                        packet::SecondaryCmd::VersionIs
-                                        | packet::SecondaryCmd::StatusIs
-                                        | packet::SecondaryCmd::GpioCountIs
-                                        | packet::SecondaryCmd::GpioNameIs
-                                        | packet::SecondaryCmd::GpioValueIs
-                                        | packet::SecondaryCmd::ChipLabelIs
-                                        | packet::SecondaryCmd::UniqueIdIs => {
-                                            if let Err(err) = data_tx.send(packet) {
-                                                bail!(
-                                                    "Failed to send to GPIO channel, Err: {}",
-                                                    err
-                                                )
-                                            }
-                                        }
-                                        packet::SecondaryCmd::UnsupportedCmdIs => {
-                                            match packet::UnsupportedCmdIs::deserialize(&packet) {
-                                                Ok(packet) => log::warn!("{:?}", packet),
-                                                Err(err) => {
-                                                    log::warn!(
-                                                    "Unable to deserialize packet: {:?}, Err: {}",
-                                                    packet,
-                                                    err
-                                                )
-                                                }
-                                            }
-                                        }
-                                    },
-                                    Err(err) => {
-                                        log::warn!(
-                                            "Unknown packet received: {:?}, Err: {}",
-                                            packet,
-                                            err
-                                        );
-                                    }
-                                }
-                            }
-                        }
-                        Err(err) => {
-                            log::warn!("Failed to split buffer: {:?}, Err: {}", buffer, err);
-                        }
-                    };
 
-                    Ok(())
-                })();
+        Self::with_interface(config, &presets, interface)
+    }
 
-                if let Err(err) = result {
-                    utils::ThreadExit::notify(&mut exit_sender, &format!("{}", err));
-                    return;
-                }
-            })?;
+    fn with_interface(
+        config: &utils::Config,
+        presets: &preset::Presets,
+        interface: Box<GpioTraits>,
+    ) -> Result<Self> {
+        let deadline = Deadline {
+            start: std::time::Instant::now(),
+            timeout_ms: config.startup_timeout,
+        };
+
+        let (link, exit) = link::Link::spawn(
+            interface,
+            interface::descriptor(config),
+            &utils::channel_config(config),
+        )?;
 
         let chip = Chip {
-            unique_id: 0,
+            max_gpio_count: 0,
             gpio_names: vec![],
+            raw_gpio_names: vec![],
+            fallback_name_pins: vec![],
             label: String::new(),
+            firmware_info: None,
         };
 
         let mut handle = Self {
-            exit: utils::ThreadExit {
-                receiver: Mutex::new(exit_receiver),
-            },
+            exit,
             chip,
-            gpio,
-            data_rx: Mutex::new(data_rx),
-            seq: Mutex::new(0),
+            link,
+            gpio_version: VERSION,
+            capabilities: packet::Capabilities::default(),
+            // Bumped before every request
+            seq: Mutex::new(config.first_seq.wrapping_sub(1)),
+            transaction: Mutex::new(()),
+            draining: AtomicBool::new(false),
+            cmd_timeouts: config.cmd_timeout.clone(),
+            max_seq_mismatches: config.max_seq_mismatches,
+            seq_mismatch_warnings: utils::WarnLimiter::new(),
+            last_accepted_seq: Mutex::new(None),
+            duplicate_reply_warnings: utils::WarnLimiter::new(),
+            resync_after_malformed: config.resync_after_malformed,
+            rtt: Mutex::new(rtt::Rtt::new()),
+            name_policy: config.name_policy,
+            tolerate_name_errors: config.tolerate_name_errors,
+            gpio_count_override: config.gpio_count,
+            added_pins: RwLock::new(vec![]),
+            pin_states: pin_state::PinStates::new(),
+            reconciled: AtomicU64::new(0),
+            counters: Mutex::new(LinkCounters::default()),
+            link_tracker: Mutex::new(counters::LinkTracker::new(
+                config.link_down_after,
+                config.link_up_after,
+            )),
+            unique_id: AtomicU64::new(0),
+            unique_id_override: config.unique_id,
+            default_bias: config.default_bias.map(Into::into),
+            unmanaged_pins: config.unmanaged_pins.clone(),
+            banks: vec![],
         };
 
         let gpio_version = handle.get_gpio_version()?;
+        handle.gpio_version = gpio_version;
+        deadline.check("version")?;
 
         if VERSION.major != gpio_version.major {
             bail!(
@@ -170,25 +348,235 @@ impl Handle {
             );
         }
 
-        handle.chip.unique_id = handle.get_unique_id()?;
+        handle.capabilities = handle.get_capabilities()?;
+        log::info!("Secondary capabilities: {}", handle.capabilities);
+        deadline.check("capabilities")?;
+
+        if config.seq_width == utils::SeqWidth::Two {
+            handle.widen_seq()?;
+        }
+
+        let mut unique_id = handle.get_unique_id()?;
+
+        if let Some(override_id) = config.unique_id {
+            log::warn!(
+                "Overriding unique id (Secondary: {}, Override: {}), matching against the secondary may break",
+                unique_id,
+                override_id
+            );
+            unique_id = override_id;
+        }
+        handle.unique_id.store(unique_id, Ordering::SeqCst);
+        deadline.check("unique id")?;
 
         handle.chip.label = handle.get_chip_label()?;
+        deadline.check("chip label")?;
+
+        if handle.has(packet::Capabilities::FIRMWARE_INFO) {
+            let firmware_info = handle.get_firmware_info()?;
+            log::info!(
+                "Secondary (UID: {}, Label: {:?}) firmware: {}",
+                handle.unique_id(),
+                handle.chip.label,
+                firmware_info
+            );
+            handle.chip.firmware_info = Some(firmware_info);
+        }
+        deadline.check("firmware info")?;
 
-        let gpio_count = handle.get_gpio_count()?;
+        let mut gpio_count = handle.get_gpio_count()?;
+        deadline.check("GPIO count")?;
 
-        for pin in 0..gpio_count {
-            let name = handle.get_gpio_name(pin)?;
-            handle.chip.gpio_names.push(name);
+        if gpio_count == 0 {
+            bail!(
+                "Secondary (UID: {}, Label: {:?}) reported no GPIO's, are GPIO instances configured on the secondary?",
+                handle.unique_id(),
+                handle.chip.label
+            );
         }
 
-        for pin in 0..gpio_count {
-            handle.set_gpio_direction(pin, packet::GpioDirection::Disabled)?;
+        handle.chip.max_gpio_count = gpio_count;
+        if handle.has(packet::Capabilities::GET_MAX_GPIO_COUNT) {
+            let max_gpio_count = handle.get_max_gpio_count()?;
+            if max_gpio_count < gpio_count {
+                log::warn!(
+                    "Secondary (UID: {}) reports a maximum GPIO count ({}) below its GPIO count ({}), using the GPIO count",
+                    handle.unique_id(),
+                    max_gpio_count,
+                    gpio_count
+                );
+            } else {
+                handle.chip.max_gpio_count = max_gpio_count;
+            }
+        }
+        deadline.check("maximum GPIO count")?;
+
+        if let Some(count) = config.gpio_count {
+            if count > gpio_count {
+                bail!(
+                    "GPIO count override ({}) exceeds the GPIO count of the secondary ({})",
+                    count,
+                    gpio_count
+                );
+            }
+
+            log::info!(
+                "Registering {} of {} GPIO's with the Kernel Driver",
+                count,
+                gpio_count
+            );
+            gpio_count = count;
+        }
+
+        if let Some(pin) = config.unmanaged_pins.iter().find(|pin| **pin >= gpio_count) {
+            bail!(
+                "Unmanaged pin {} exceeds the GPIO count ({})",
+                pin,
+                gpio_count
+            );
+        }
+
+        if (0..gpio_count).all(|pin| config.unmanaged_pins.contains(&pin)) {
+            bail!(
+                "All {} GPIO's are unmanaged, none left to register",
+                gpio_count
+            );
+        }
+
+        let raw_names = handle.get_gpio_names(gpio_count, config.pipeline_depth, &deadline)?;
+
+        for (pin, raw_name) in (0..gpio_count).zip(raw_names) {
+            let (info, fallback) = handle.name_pin(pin, raw_name)?;
+            if fallback {
+                handle.chip.fallback_name_pins.push(pin);
+            }
+
+            handle.chip.gpio_names.push(info.name);
+            handle.chip.raw_gpio_names.push(info.raw_name);
+        }
+
+        for pin in handle.chip.fallback_name_pins.clone() {
+            let name = unique_fallback_name(pin, &handle.chip.gpio_names);
+            if name != handle.chip.gpio_names[pin as usize] {
+                log::warn!(
+                    "Fallback GPIO name taken by another pin (Pin: {}, Name: {:?})",
+                    pin,
+                    name
+                );
+                handle.chip.gpio_names[pin as usize] = name.clone();
+                handle.chip.raw_gpio_names[pin as usize] = name;
+            }
+        }
+
+        if !handle.chip.fallback_name_pins.is_empty() {
+            log::warn!(
+                "Using fallback names for pins {:?}",
+                handle.chip.fallback_name_pins
+            );
+        }
+
+        if config.dump_gpio_raw {
+            handle.dump_gpio_raw(gpio_count);
+        }
+
+        if config.dump_gpio_status {
+            handle.dump_gpio_status(gpio_count);
+        }
+
+        #[cfg(feature = "raw_request")]
+        for request in &config.raw_request {
+            match handle.raw_request(request.cmd, &request.payload) {
+                Ok(reply) => log::info!("Raw request {} reply: {:02x?}", request, reply),
+                Err(err) => log::warn!("Raw request {} failed, Err: {}", request, err),
+            }
+        }
+
+        presets.validate(gpio_count)?;
+
+        if let Some(pin) = config
+            .unmanaged_pins
+            .iter()
+            .find(|pin| presets.get(**pin).is_some())
+        {
+            bail!("Unmanaged pin {} cannot have a preset", pin);
+        }
+
+        if let Some(pin) = config
+            .unmanaged_pins
+            .iter()
+            .find(|pin| presets.bank_of(**pin).is_some())
+        {
+            bail!("Unmanaged pin {} cannot be in a bank", pin);
+        }
+
+        handle.banks = presets.banks.clone();
+        for bank in &handle.banks {
+            let pins: Vec<String> = bank
+                .pins
+                .iter()
+                .map(|pin| format!("{} ({})", pin, handle.chip.gpio_names[*pin as usize]))
+                .collect();
+
+            log::info!("Bank {:?} {{ Pins: [{}] }}", bank.name, pins.join(", "));
+        }
+
+        // Unmanaged pins are not disabled, they stay as the secondary left them
+        let mut chip_config = ChipConfig::from_presets(presets, gpio_count);
+        chip_config
+            .pins
+            .retain(|pin_config| !handle.unmanaged_pins.contains(&pin_config.pin));
+
+        if !handle.unmanaged_pins.is_empty() {
+            log::info!("Leaving pins {:?} unmanaged", handle.unmanaged_pins);
+        }
+
+        for (pin, result) in handle.apply_config(&chip_config)? {
+            match (presets.get(pin), result) {
+                (Some(preset), Ok(())) => {
+                    // The readback reports the bias or drive mode, not the buffer enables
+                    if let Some(config) = preset.config.map(packet::GpioConfig::from) {
+                        if !config.is_buffer_enable() {
+                            handle.verify_gpio_config(pin, config);
+                        }
+                    }
+
+                    log::info!("Applied {:?}", preset);
+                }
+                (Some(preset), Err(err)) => {
+                    log::warn!("Failed to apply {:?}, Err: {}", preset, err)
+                }
+                (None, result) => result?,
+            }
         }
+        deadline.check("GPIO presets")?;
 
         Ok(handle)
     }
 
     pub fn get_gpio_value(&self, pin: u8) -> Result<packet::GpioValueIs, Error> {
+        self.get_gpio_value_timeout(pin, None)
+    }
+
+    // A caller with a latency budget overrides the configured read timeout
+    // for this request only
+    pub fn get_gpio_value_timeout(
+        &self,
+        pin: u8,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<packet::GpioValueIs, Error> {
+        self.check_pin(pin)?;
+
+        let _transaction = self.begin_transaction()?;
+
+        self.read_gpio_value(pin, timeout)
+    }
+
+    // The caller holds the transaction
+    fn read_gpio_value(
+        &self,
+        pin: u8,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<packet::GpioValueIs, Error> {
         let (packet, expected_seq) = {
             let mut seq = self
                 .seq
@@ -202,17 +590,50 @@ impl Handle {
             (packet, seq.clone())
         };
 
-        self.gpio.write(&packet)?;
-
-        let packet = self.read(Some(expected_seq))?;
+        let packet = self
+            .exchange_timeout(
+                packet::HostCmd::GetGpioValue,
+                &packet,
+                Some(expected_seq),
+                timeout,
+            )
+            .map_err(|err| err.at_pin(pin))?;
+
+        let mut packet = if self.has(packet::Capabilities::GPIO_VALUE_SOURCE) {
+            packet::GpioValueIs::deserialize_with_source(&packet)
+        } else {
+            packet::GpioValueIs::deserialize(&packet)
+        }
+        .map_err(RecoverableError::Deserialization)?;
+
+        // An older secondary does not tell, the pin direction last acknowledged
+        // decides what it most likely returned
+        if packet.source.is_none() {
+            packet.source = match self.pin_states.get(pin).direction {
+                Some(packet::GpioDirection::Output) => Some(packet::GpioValueSource::Driven),
+                Some(packet::GpioDirection::Input) => Some(packet::GpioValueSource::Sampled),
+                Some(packet::GpioDirection::Disabled) | None => None,
+            };
+        }
 
-        let packet =
-            packet::GpioValueIs::deserialize(&packet).map_err(RecoverableError::Deserialization)?;
+        if let Ok(value) = packet.value {
+            self.pin_states
+                .update(pin, |state| state.value = Some(value));
+        }
 
         Ok(packet)
     }
 
     pub fn set_gpio_value(&self, pin: u8, value: packet::GpioValue) -> Result<(), Error> {
+        self.check_pin(pin)?;
+
+        let _transaction = self.begin_transaction()?;
+
+        self.send_gpio_value(pin, value)
+    }
+
+    // The caller holds the transaction
+    fn send_gpio_value(&self, pin: u8, value: packet::GpioValue) -> Result<(), Error> {
         let (packet, expected_seq) = {
             let mut seq = self
                 .seq
@@ -226,192 +647,1541 @@ impl Handle {
             (packet, seq.clone())
         };
 
-        self.gpio.write(&packet)?;
+        let _packet = self
+            .exchange(packet::HostCmd::SetGpioValue, &packet, Some(expected_seq))
+            .map_err(|err| err.at_pin(pin))?;
 
-        let _packet = self.read(Some(expected_seq))?;
+        self.pin_states.update(pin, |state| {
+            state.value = Some(value);
+            state.commanded = Some(value);
+        });
 
         Ok(())
     }
 
-    pub fn set_gpio_config(&self, pin: u8, config: packet::GpioConfig) -> Result<(), Error> {
+    // Sets the listed pins with a single command, the secondary applies them
+    // at once and leaves the other pins unchanged
+    pub fn set_gpio_values(&self, values: &[(u8, packet::GpioValue)]) -> Result<(), Error> {
+        for (i, (pin, _)) in values.iter().enumerate() {
+            self.check_pin(*pin)?;
+
+            if values[..i].iter().any(|(other, _)| other == pin) {
+                return Err(RecoverableError::DuplicatePin(*pin).into());
+            }
+        }
+
+        if !self.has(packet::Capabilities::SET_GPIO_VALUES) {
+            return Err(RecoverableError::Packet {
+                status: Status::NotSupported,
+                cmd: packet::HostCmd::SetGpioValues,
+                pin: None,
+            }
+            .into());
+        }
+
+        let _transaction = self.begin_transaction()?;
+
         let (packet, expected_seq) = {
             let mut seq = self
                 .seq
                 .lock()
                 .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
 
-            let packet = packet::SetGpioConfig::new(&mut seq, pin, config)
-                .serialize()
+            let packet = packet::SetGpioValues::new(&mut seq, values)
+                .and_then(|packet| packet.serialize())
                 .map_err(RecoverableError::Serialization)?;
 
-            (packet, seq.clone())
+            (packet, *seq)
         };
 
-        self.gpio.write(&packet)?;
+        let _packet = self.exchange(packet::HostCmd::SetGpioValues, &packet, Some(expected_seq))?;
 
-        let _packet = self.read(Some(expected_seq))?;
+        for (pin, value) in values {
+            self.pin_states.update(*pin, |state| {
+                state.value = Some(*value);
+                state.commanded = Some(*value);
+            });
+        }
 
         Ok(())
     }
 
-    pub fn set_gpio_direction(
+    // Reads the pins in as few requests as the packet length allows. A pin
+    // whose value is unexpected fails on its own, the other pins are still
+    // reported. The timeout override applies to each request, not to all of
+    // them.
+    pub fn get_gpio_values_timeout(
         &self,
-        pin: u8,
-        direction: packet::GpioDirection,
-    ) -> Result<(), Error> {
+        pins: &[u8],
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Vec<packet::PinValue>, Error> {
+        for pin in pins {
+            self.check_pin(*pin)?;
+        }
+
+        if !self.has(packet::Capabilities::GET_GPIO_VALUES) {
+            return Err(RecoverableError::Packet {
+                status: Status::NotSupported,
+                cmd: packet::HostCmd::GetGpioValues,
+                pin: None,
+            }
+            .into());
+        }
+
+        let mut values = vec![];
+
+        for pins in pins.chunks(packet::GET_GPIO_VALUES_MAX) {
+            let _transaction = self.begin_transaction()?;
+
+            let (packet, expected_seq) = {
+                let mut seq = self
+                    .seq
+                    .lock()
+                    .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
+
+                let packet = packet::GetGpioValues::new(&mut seq, pins)
+                    .and_then(|packet| packet.serialize())
+                    .map_err(RecoverableError::Serialization)?;
+
+                (packet, *seq)
+            };
+
+            let packet = self.exchange_timeout(
+                packet::HostCmd::GetGpioValues,
+                &packet,
+                Some(expected_seq),
+                timeout,
+            )?;
+
+            let packet = packet::GpioValuesIs::deserialize(&packet)
+                .map_err(RecoverableError::Deserialization)?;
+
+            let read: Vec<u8> = packet.values.iter().map(|(pin, _)| *pin).collect();
+            if read != pins {
+                return Err(RecoverableError::Deserialization(anyhow!(
+                    "Reply does not match the pins read (Expected: {:?}, Got: {:?})",
+                    pins,
+                    read
+                ))
+                .into());
+            }
+
+            for (pin, value) in &packet.values {
+                if let Ok(value) = value {
+                    self.pin_states
+                        .update(*pin, |state| state.value = Some(*value));
+                }
+            }
+
+            values.extend(packet.values);
+        }
+
+        Ok(values)
+    }
+
+    pub fn get_gpio_config(&self, pin: u8) -> Result<packet::GpioConfig, Error> {
+        self.check_pin(pin)?;
+
+        if !self.has(packet::Capabilities::GET_GPIO_CONFIG) {
+            return Err(RecoverableError::Packet {
+                status: Status::NotSupported,
+                cmd: packet::HostCmd::GetGpioConfig,
+                pin: Some(pin),
+            }
+            .into());
+        }
+
+        let _transaction = self.begin_transaction()?;
+
         let (packet, expected_seq) = {
             let mut seq = self
                 .seq
                 .lock()
                 .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
 
-            let packet = packet::SetGpioDirection::new(&mut seq, pin, direction)
+            let packet = packet::GetGpioConfig::new(&mut seq, pin)
                 .serialize()
                 .map_err(RecoverableError::Serialization)?;
 
-            (packet, seq.clone())
+            (packet, *seq)
         };
 
-        self.gpio.write(&packet)?;
+        let packet = self
+            .exchange(packet::HostCmd::GetGpioConfig, &packet, Some(expected_seq))
+            .map_err(|err| err.at_pin(pin))?;
 
-        let _packet = self.read(Some(expected_seq))?;
+        let packet = packet::GpioConfigIs::deserialize(&packet)
+            .map_err(RecoverableError::Deserialization)?;
 
-        Ok(())
+        Ok(packet.config)
     }
-}
-
-impl Handle {
-    fn get_gpio_version(&self) -> Result<utils::Version> {
-        let packet = packet::GetVersion::new().serialize()?;
 
-        self.gpio.write(&packet)?;
-
-        let packet = self.read(None)?;
-        let packet = packet::VersionIs::deserialize(&packet)?;
+    // Implementation-defined pad registers of a pin, for firmware debugging
+    pub fn get_gpio_raw(&self, pin: u8) -> Result<Vec<u8>, Error> {
+        self.check_pin(pin)?;
+
+        if !self.has(packet::Capabilities::GET_GPIO_RAW) {
+            return Err(RecoverableError::Packet {
+                status: Status::NotSupported,
+                cmd: packet::HostCmd::GetGpioRaw,
+                pin: Some(pin),
+            }
+            .into());
+        }
 
-        Ok(packet.version)
-    }
+        let _transaction = self.begin_transaction()?;
 
-    fn get_unique_id(&self) -> Result<u64> {
         let (packet, expected_seq) = {
-            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+            let mut seq = self
+                .seq
+                .lock()
+                .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
 
-            let packet = packet::GetUniqueId::new(&mut seq).serialize()?;
+            let packet = packet::GetGpioRaw::new(&mut seq, pin)
+                .serialize()
+                .map_err(RecoverableError::Serialization)?;
 
-            (packet, seq.clone())
+            (packet, *seq)
         };
 
-        self.gpio.write(&packet)?;
+        let packet = self
+            .exchange(packet::HostCmd::GetGpioRaw, &packet, Some(expected_seq))
+            .map_err(|err| err.at_pin(pin))?;
 
-        let packet = self.read(Some(expected_seq))?;
-        let packet = packet::UniqueIdIs::deserialize(&packet)?;
+        let packet =
+            packet::GpioRawIs::deserialize(&packet).map_err(RecoverableError::Deserialization)?;
 
-        Ok(packet.unique_id)
+        Ok(packet.raw)
     }
 
-    fn get_chip_label(&self) -> Result<String> {
+    // Whether a pin is driven or floating, which its logic value cannot tell
+    pub fn get_gpio_status(&self, pin: u8) -> Result<packet::GpioStatus, Error> {
+        self.check_pin(pin)?;
+
+        if !self.has(packet::Capabilities::GET_GPIO_STATUS) {
+            return Err(RecoverableError::Packet {
+                status: Status::NotSupported,
+                cmd: packet::HostCmd::GetGpioStatus,
+                pin: Some(pin),
+            }
+            .into());
+        }
+
+        let _transaction = self.begin_transaction()?;
+
         let (packet, expected_seq) = {
-            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+            let mut seq = self
+                .seq
+                .lock()
+                .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
 
-            let packet = packet::GetChipLabel::new(&mut seq).serialize()?;
+            let packet = packet::GetGpioStatus::new(&mut seq, pin)
+                .serialize()
+                .map_err(RecoverableError::Serialization)?;
 
-            (packet, seq.clone())
+            (packet, *seq)
         };
 
-        self.gpio.write(&packet)?;
+        let packet = self
+            .exchange(packet::HostCmd::GetGpioStatus, &packet, Some(expected_seq))
+            .map_err(|err| err.at_pin(pin))?;
 
-        let packet = self.read(Some(expected_seq))?;
-        let packet = packet::ChipLabelIs::deserialize(&packet)?;
+        let packet = packet::GpioStatusIs::deserialize(&packet)
+            .map_err(RecoverableError::Deserialization)?;
 
-        packet.chip_label
+        Ok(packet.status)
     }
 
-    fn get_gpio_count(&self) -> Result<u8> {
+    pub fn set_gpio_config(&self, pin: u8, config: packet::GpioConfig) -> Result<(), Error> {
+        self.check_pin(pin)?;
+
+        let _transaction = self.begin_transaction()?;
+
+        self.send_gpio_config(pin, config)
+    }
+
+    // The caller holds the transaction
+    fn send_gpio_config(&self, pin: u8, config: packet::GpioConfig) -> Result<(), Error> {
+        if config.is_buffer_enable() && !self.has(packet::Capabilities::BUFFER_ENABLE) {
+            return Err(RecoverableError::Packet {
+                status: Status::NotSupported,
+                cmd: packet::HostCmd::SetGpioConfig,
+                pin: Some(pin),
+            }
+            .into());
+        }
+
         let (packet, expected_seq) = {
-            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+            let mut seq = self
+                .seq
+                .lock()
+                .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
 
-            let packet = packet::GetGpioCount::new(&mut seq).serialize()?;
+            let packet = packet::SetGpioConfig::new(&mut seq, pin, config)
+                .serialize()
+                .map_err(RecoverableError::Serialization)?;
 
             (packet, seq.clone())
         };
 
-        self.gpio.write(&packet)?;
+        let _packet = self
+            .exchange(packet::HostCmd::SetGpioConfig, &packet, Some(expected_seq))
+            .map_err(|err| err.at_pin(pin))?;
 
-        let packet = self.read(Some(expected_seq))?;
-        let packet = packet::GpioCountIs::deserialize(&packet)?;
+        self.pin_states.update(pin, |state| match config {
+            packet::GpioConfig::InputEnable => state.input_enable = true,
+            packet::GpioConfig::OutputEnable => state.output_enable = true,
+            _ => state.config = Some(config),
+        });
 
-        Ok(packet.count)
+        Ok(())
     }
 
-    fn get_gpio_name(&self, pin: u8) -> Result<String> {
+    pub fn set_gpio_direction(
+        &self,
+        pin: u8,
+        direction: packet::GpioDirection,
+    ) -> Result<(), Error> {
+        self.check_pin(pin)?;
+
+        let _transaction = self.begin_transaction()?;
+
+        self.send_gpio_direction(pin, direction)
+    }
+
+    // The caller holds the transaction
+    fn send_gpio_direction(&self, pin: u8, direction: packet::GpioDirection) -> Result<(), Error> {
+        // Biased before the direction change, the pin never floats on the
+        // firmware default in between
+        if let (packet::GpioDirection::Input, Some(bias)) = (direction, self.default_bias) {
+            if self.pin_states.get(pin).config.is_none() {
+                match self.send_gpio_config(pin, bias) {
+                    Ok(()) => log::debug!("Applied default bias {:?} to pin {}", bias, pin),
+                    Err(Error::Unrecoverable(err)) => return Err(err.into()),
+                    Err(err) => log::warn!(
+                        "Failed to apply default bias {:?} to pin {}, Err: {}",
+                        bias,
+                        pin,
+                        err
+                    ),
+                }
+            }
+        }
+
         let (packet, expected_seq) = {
-            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+            let mut seq = self
+                .seq
+                .lock()
+                .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
 
-            let packet = packet::GetGpioName::new(&mut seq, pin).serialize()?;
+            let packet = packet::SetGpioDirection::new(&mut seq, pin, direction)
+                .serialize()
+                .map_err(RecoverableError::Serialization)?;
 
             (packet, seq.clone())
         };
 
-        self.gpio.write(&packet)?;
-
-        let packet = self.read(Some(expected_seq))?;
-        let packet = packet::GpioNameIs::deserialize(&packet)?;
+        let _packet = self
+            .exchange(
+                packet::HostCmd::SetGpioDirection,
+                &packet,
+                Some(expected_seq),
+            )
+            .map_err(|err| err.at_pin(pin))?;
+
+        // The value of a disabled pin is up to the secondary
+        self.pin_states.update(pin, |state| {
+            state.direction = Some(direction);
+            if direction == packet::GpioDirection::Disabled {
+                state.value = None;
+            }
+        });
 
-        packet.name
+        Ok(())
     }
+}
+
+impl Handle {
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.link.diagnostics()
+    }
+
+    pub fn reader_heartbeat(&self) -> std::sync::Arc<utils::Heartbeat> {
+        self.link.heartbeat()
+    }
+
+    pub fn transport(&self) -> &Descriptor {
+        self.link.transport()
+    }
+
+    // The pins registered with the Kernel Driver, in the order of its lines
+    pub fn pins(&self) -> impl Iterator<Item = PinInfo> + '_ {
+        let added = match self.added_pins.read() {
+            Ok(added) => added.clone(),
+            Err(_) => vec![],
+        };
+
+        self.chip
+            .gpio_names
+            .iter()
+            .zip(&self.chip.raw_gpio_names)
+            .enumerate()
+            .map(|(pin, (name, raw_name))| PinInfo {
+                pin: pin as u8,
+                name: name.clone(),
+                raw_name: raw_name.clone(),
+            })
+            .filter(|info| !self.unmanaged_pins.contains(&info.pin))
+            .chain(added)
+    }
+
+    // Generation of the pin states, bumped every time a pin state changes
+    pub fn pin_states_generation(&self) -> u64 {
+        self.pin_states.generation()
+    }
+
+    pub fn wait_pin_states_change(
+        &self,
+        generation: u64,
+        timeout: std::time::Duration,
+    ) -> Option<u64> {
+        self.pin_states.wait_change(generation, timeout)
+    }
+
+    // Direction last acknowledged by the secondary, unknown until set
+    #[cfg(feature = "gpio_sim")]
+    pub fn gpio_direction(&self, pin: u8) -> Option<GpioDirection> {
+        self.pin_states.get(pin).direction
+    }
+
+    // Value last acknowledged by the secondary, unknown until set or read
+    #[cfg(feature = "gpio_sim")]
+    pub fn tracked_value(&self, pin: u8) -> Option<GpioValue> {
+        self.pin_states.get(pin).value
+    }
+
+    pub fn pin_records(&self) -> Vec<PinRecord> {
+        let mut records = self.pin_states.records(self.pins());
+
+        for record in &mut records {
+            record.bank = self
+                .banks
+                .iter()
+                .find(|bank| bank.pins.contains(&record.pin))
+                .map(|bank| bank.name.clone());
+        }
+
+        records
+    }
+
+    pub fn pin_count(&self) -> usize {
+        let added = match self.added_pins.read() {
+            Ok(added) => added.len(),
+            Err(_) => 0,
+        };
+
+        self.chip.gpio_names.len() + added
+    }
+
+    // Pins the secondary gained since they were registered, pins beyond a
+    // GPIO count override are never reported
+    pub fn get_new_pins(&self) -> Result<Vec<PinInfo>> {
+        let _transaction = self.begin_transaction()?;
+
+        let mut gpio_count = self.get_gpio_count()?;
+        if let Some(count) = self.gpio_count_override {
+            gpio_count = gpio_count.min(count);
+        }
+
+        // An older secondary cannot tell its maximum, its pins are not bounded
+        if self.has(packet::Capabilities::GET_MAX_GPIO_COUNT)
+            && gpio_count > self.chip.max_gpio_count
+        {
+            bail!(
+                "Secondary (UID: {}) reports {} GPIO's, more than its maximum of {}",
+                self.unique_id(),
+                gpio_count,
+                self.chip.max_gpio_count
+            );
+        }
+
+        let pin_count = self.pin_count();
+        if (gpio_count as usize) < pin_count {
+            bail!(
+                "Secondary (UID: {}) reports {} GPIO's, fewer than the {} registered",
+                self.unique_id(),
+                gpio_count,
+                pin_count
+            );
+        }
+
+        let mut pins = vec![];
+        for pin in pin_count as u8..gpio_count {
+            pins.push(self.name_pin(pin, self.get_gpio_name(pin))?.0);
+        }
+
+        Ok(pins)
+    }
+
+    pub fn register_pins(&self, pins: &[PinInfo]) -> Result<()> {
+        {
+            let mut added = self.added_pins.write().map_err(|err| anyhow!("{}", err))?;
+            let first = self.chip.gpio_names.len() + added.len();
+
+            for (i, info) in pins.iter().enumerate() {
+                if info.pin as usize != first + i {
+                    bail!(
+                        "New pins must be contiguous with the registered pins (Expected: {}, Got: {})",
+                        first + i,
+                        info.pin
+                    );
+                }
+            }
+
+            added.extend_from_slice(pins);
+        }
+
+        for info in pins {
+            self.set_gpio_direction(info.pin, packet::GpioDirection::Disabled)
+                .map_err(|err| anyhow!("Failed to disable new pin {}, Err: {}", info.pin, err))?;
+        }
+
+        Ok(())
+    }
+
+    // Takes back the pins of the last register_pins, e.g. when the Kernel
+    // Driver could not add them. They stay disabled on the secondary.
+    pub fn unregister_pins(&self, pins: &[PinInfo]) -> Result<()> {
+        let mut added = self.added_pins.write().map_err(|err| anyhow!("{}", err))?;
+
+        if !added.ends_with(pins) {
+            bail!("Only the last registered pins can be unregistered");
+        }
+
+        let count = added.len() - pins.len();
+        added.truncate(count);
+
+        Ok(())
+    }
+
+    pub fn reset_all(&self) -> Result<Vec<u8>, Error> {
+        let mut failed = vec![];
+
+        for pin in self.pins().map(|info| info.pin) {
+            match self.set_gpio_direction(pin, packet::GpioDirection::Disabled) {
+                Ok(_) => log::debug!("Reset pin {}", pin),
+                Err(Error::Recoverable(err)) => {
+                    log::warn!("Failed to reset pin {}, Err: {}", pin, err);
+                    failed.push(pin);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(failed)
+    }
+
+    // Applies the whole configuration as one transaction: configs, then
+    // values, then directions, so that an output never drives a stale value.
+    // Steps already acknowledged by the secondary are skipped, a failed step
+    // skips the remaining steps of its pin only.
+    pub fn apply_config(
+        &self,
+        chip_config: &ChipConfig,
+    ) -> Result<BTreeMap<u8, Result<(), Error>>, Error> {
+        let mut results = BTreeMap::new();
+
+        for pin_config in &chip_config.pins {
+            let result = match results.contains_key(&pin_config.pin) {
+                true => Err(RecoverableError::InvalidPin(pin_config.pin).into()),
+                false => self.check_pin(pin_config.pin),
+            };
+            results.insert(pin_config.pin, result);
+        }
+
+        let _transaction = self.begin_transaction()?;
+
+        for pin_config in &chip_config.pins {
+            let (pin, Some(config)) = (pin_config.pin, pin_config.config) else {
+                continue;
+            };
+
+            let state = self.pin_states.get(pin);
+            let applied = match config {
+                packet::GpioConfig::InputEnable => state.input_enable,
+                packet::GpioConfig::OutputEnable => state.output_enable,
+                _ => state.config == Some(config),
+            };
+
+            if !applied {
+                apply_step(&mut results, pin, || self.send_gpio_config(pin, config))?;
+            }
+        }
+
+        for pin_config in &chip_config.pins {
+            let (pin, Some(value)) = (pin_config.pin, pin_config.value) else {
+                continue;
+            };
+
+            if self.pin_states.get(pin).value != Some(value) {
+                apply_step(&mut results, pin, || self.send_gpio_value(pin, value))?;
+            }
+        }
+
+        for pin_config in &chip_config.pins {
+            let (pin, Some(direction)) = (pin_config.pin, pin_config.direction) else {
+                continue;
+            };
+
+            if self.pin_states.get(pin).direction != Some(direction) {
+                apply_step(&mut results, pin, || {
+                    self.send_gpio_direction(pin, direction)
+                })?;
+            }
+        }
+
+        Ok(results)
+    }
+
+    // Reads back every output pin written by the bridge and rewrites the
+    // ones that diverged, e.g. after the secondary reset them. A pin that
+    // fails is logged and left for the next pass, returns the pins rewritten.
+    pub fn reconcile_outputs(&self) -> Result<Vec<u8>, Error> {
+        let mut rewritten = vec![];
+
+        for pin in 0..self.pin_count() as u8 {
+            // Held across the read and the write, a value written meanwhile
+            // by the Kernel Driver is never overwritten
+            let _transaction = self.begin_transaction()?;
+
+            let state = self.pin_states.get(pin);
+            let (Some(packet::GpioDirection::Output), Some(commanded)) =
+                (state.direction, state.commanded)
+            else {
+                continue;
+            };
+
+            let result = self.read_gpio_value(pin, None).and_then(|packet| {
+                let read = match packet.value {
+                    Ok(value) if value == commanded => return Ok(false),
+                    Ok(value) => format!("{:?}", value),
+                    Err(err) => format!("{}", err),
+                };
+
+                log::warn!(
+                    "Pin {} reads {} instead of {:?}, rewriting it",
+                    pin,
+                    read,
+                    commanded
+                );
+                self.send_gpio_value(pin, commanded).map(|()| true)
+            });
+
+            match result {
+                Ok(true) => rewritten.push(pin),
+                Ok(false) => {}
+                Err(Error::Unrecoverable(err)) => return Err(err.into()),
+                Err(err) => log::warn!("Failed to reconcile pin {}, Err: {}", pin, err),
+            }
+        }
+
+        self.reconciled
+            .fetch_add(rewritten.len() as u64, Ordering::SeqCst);
+
+        Ok(rewritten)
+    }
+}
+
+// Runs a step of a pin that did not fail yet, only an unrecoverable error
+// stops the other pins
+fn apply_step(
+    results: &mut BTreeMap<u8, Result<(), Error>>,
+    pin: u8,
+    step: impl FnOnce() -> Result<(), Error>,
+) -> Result<(), Error> {
+    if let Some(Ok(())) = results.get(&pin) {
+        match step() {
+            Err(Error::Unrecoverable(err)) => return Err(err.into()),
+            result => {
+                results.insert(pin, result);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl Handle {
+    // Refuses new requests and waits for the one in flight, so that no frame
+    // interleaves with the teardown. Returns false when the request in flight
+    // outlasted the timeout.
+    pub fn drain(&self, timeout: std::time::Duration) -> bool {
+        self.draining.store(true, Ordering::SeqCst);
+
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            match self.transaction.try_lock() {
+                Ok(_) | Err(TryLockError::Poisoned(_)) => return true,
+                Err(TryLockError::WouldBlock) => {
+                    if std::time::Instant::now() >= deadline {
+                        return false;
+                    }
+                    std::thread::sleep(std::time::Duration::from_millis(DRAIN_POLL_MS));
+                }
+            }
+        }
+    }
+
+    // Discards whatever the link buffered, then exchanges a GetVersion until a
+    // VersionIs comes back. GetVersion carries no seq, a late reply to an
+    // earlier request can not be mistaken for it.
+    pub fn resync(&self) -> Result<(), Error> {
+        let _transaction = self.begin_transaction()?;
+
+        self.send_resync()
+    }
+
+    // The caller holds the transaction
+    fn send_resync(&self) -> Result<(), Error> {
+        let packet = packet::GetVersion::new()
+            .serialize()
+            .map_err(RecoverableError::Serialization)?;
+
+        let mut discarded = self.link.discard();
+        let sent = std::time::Instant::now();
+
+        self.link.write(&packet)?;
+
+        loop {
+            let reply = self.read_window(packet::HostCmd::GetVersion, &[], sent, None)?;
+
+            let clean = matches!(
+                packet::try_deserialize_cmd(&reply),
+                Ok(packet::SecondaryCmd::VersionIs)
+            ) && packet::VersionIs::deserialize(&reply).is_ok();
+
+            if clean {
+                log::info!("Link resynchronized, discarded {} replies", discarded);
+                return Ok(());
+            }
+
+            discarded += 1;
+        }
+    }
+
+    // Serializes the exchanges on the link. Checked under the lock, a request
+    // queued behind the one in flight is refused once draining started.
+    fn begin_transaction(&self) -> Result<MutexGuard<'_, ()>, Error> {
+        let transaction = self
+            .transaction
+            .lock()
+            .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
+
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(RecoverableError::ShuttingDown.into());
+        }
+
+        Ok(transaction)
+    }
+
+    fn check_pin(&self, pin: u8) -> Result<(), Error> {
+        if pin as usize >= self.pin_count() || self.unmanaged_pins.contains(&pin) {
+            return Err(RecoverableError::InvalidPin(pin).into());
+        }
+
+        Ok(())
+    }
+
+    // Logs the pins as discovered, before any preset is applied
+    fn dump_gpio_raw(&self, gpio_count: u8) {
+        for pin in 0..gpio_count {
+            match self.get_gpio_raw(pin) {
+                Ok(raw) => log::info!("Pin {} raw state: {:02x?}", pin, raw),
+                Err(Error::Recoverable(RecoverableError::Packet {
+                    status: Status::NotSupported,
+                    ..
+                })) => {
+                    log::warn!(
+                        "Secondary (Capabilities: {}) cannot read the raw state of a pin",
+                        self.capabilities
+                    );
+                    return;
+                }
+                Err(err) => log::warn!("Failed to read pin {} raw state, Err: {}", pin, err),
+            }
+        }
+    }
+
+    fn dump_gpio_status(&self, gpio_count: u8) {
+        for pin in 0..gpio_count {
+            match self.get_gpio_status(pin) {
+                Ok(status) => log::info!("Pin {} status: {}", pin, status),
+                Err(Error::Recoverable(RecoverableError::Packet {
+                    status: Status::NotSupported,
+                    ..
+                })) => {
+                    log::warn!(
+                        "Secondary (Capabilities: {}) cannot read the status of a pin",
+                        self.capabilities
+                    );
+                    return;
+                }
+                Err(err) => log::warn!("Failed to read pin {} status, Err: {}", pin, err),
+            }
+        }
+    }
+
+    fn verify_gpio_config(&self, pin: u8, expected: packet::GpioConfig) {
+        match self.get_gpio_config(pin) {
+            Ok(config) if config != expected => log::warn!(
+                "GPIO config readback mismatch (Pin: {}, Expected: {:?}, Read: {:?})",
+                pin,
+                expected,
+                config
+            ),
+            Ok(_) => (),
+            Err(Error::Recoverable(RecoverableError::Packet {
+                status: Status::NotSupported,
+                ..
+            })) => (),
+            Err(err) => log::warn!("Failed to read back pin {} config, Err: {}", pin, err),
+        }
+    }
+
+    fn get_gpio_version(&self) -> Result<utils::Version> {
+        let packet = packet::GetVersion::new().serialize()?;
+
+        let packet = self.exchange(packet::HostCmd::GetVersion, &packet, None)?;
+        let packet = packet::VersionIs::deserialize(&packet)?;
+
+        Ok(packet.version)
+    }
+
+    // Older secondaries keep the one-byte seq, the bridge works the same with
+    // either width
+    fn widen_seq(&self) -> Result<()> {
+        if !self.has(packet::Capabilities::SET_SEQ_WIDTH) {
+            log::warn!(
+                "Secondary (Capabilities: {}) does not support a two-byte seq, keeping one byte",
+                self.capabilities
+            );
+            return Ok(());
+        }
+
+        let (packet, expected_seq) = {
+            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+
+            let packet = packet::SetSeqWidth::new(&mut seq, 2).serialize()?;
+
+            (packet, *seq)
+        };
+
+        match self.exchange(packet::HostCmd::SetSeqWidth, &packet, Some(expected_seq)) {
+            Ok(_) => {
+                self.link.widen_seq();
+                log::info!("Using a two-byte seq");
+            }
+            Err(Error::Recoverable(RecoverableError::Packet {
+                status: Status::NotSupported,
+                ..
+            })) => log::warn!("Secondary refused a two-byte seq, keeping one byte"),
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(())
+    }
+
+    fn get_unique_id(&self) -> Result<u64> {
+        let (packet, expected_seq) = {
+            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+
+            let packet = packet::GetUniqueId::new(&mut seq).serialize()?;
+
+            (packet, seq.clone())
+        };
+
+        let packet = self.exchange(packet::HostCmd::GetUniqueId, &packet, Some(expected_seq))?;
+        let packet = packet::UniqueIdIs::deserialize(&packet)?;
+
+        Ok(packet.unique_id)
+    }
+
+    fn get_chip_label(&self) -> Result<String> {
+        let (packet, expected_seq) = {
+            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+
+            let packet = packet::GetChipLabel::new(&mut seq).serialize()?;
+
+            (packet, seq.clone())
+        };
+
+        let packet = self.exchange(packet::HostCmd::GetChipLabel, &packet, Some(expected_seq))?;
+        let packet = packet::ChipLabelIs::deserialize(&packet)?;
+
+        packet.chip_label
+    }
+
+    fn get_firmware_info(&self) -> Result<String> {
+        let (packet, expected_seq) = {
+            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+
+            let packet = packet::GetFirmwareInfo::new(&mut seq).serialize()?;
+
+            (packet, *seq)
+        };
+
+        let packet = self.exchange(
+            packet::HostCmd::GetFirmwareInfo,
+            &packet,
+            Some(expected_seq),
+        )?;
+        let packet = packet::FirmwareInfoIs::deserialize(&packet)?;
+
+        packet.firmware_info
+    }
+
+    fn get_gpio_count(&self) -> Result<u8> {
+        let (packet, expected_seq) = {
+            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+
+            let packet = packet::GetGpioCount::new(&mut seq).serialize()?;
+
+            (packet, seq.clone())
+        };
+
+        let packet = self.exchange(packet::HostCmd::GetGpioCount, &packet, Some(expected_seq))?;
+        let packet = packet::GpioCountIs::deserialize(&packet)?;
+
+        Ok(packet.count)
+    }
+
+    fn has(&self, capability: u32) -> bool {
+        self.capabilities.has(capability)
+    }
+
+    // A secondary refusing GetCapabilities implements none of them
+    fn get_capabilities(&self) -> Result<packet::Capabilities> {
+        let (packet, expected_seq) = {
+            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+
+            let packet = packet::GetCapabilities::new(&mut seq).serialize()?;
+
+            (packet, *seq)
+        };
+
+        match self.exchange(
+            packet::HostCmd::GetCapabilities,
+            &packet,
+            Some(expected_seq),
+        ) {
+            Ok(packet) => Ok(packet::CapabilitiesIs::deserialize(&packet)?.capabilities),
+            Err(Error::Recoverable(RecoverableError::Packet {
+                status: Status::NotSupported,
+                ..
+            })) => Ok(packet::Capabilities::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn get_max_gpio_count(&self) -> Result<u8> {
+        let (packet, expected_seq) = {
+            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+
+            let packet = packet::GetMaxGpioCount::new(&mut seq).serialize()?;
+
+            (packet, *seq)
+        };
+
+        let packet = self.exchange(
+            packet::HostCmd::GetMaxGpioCount,
+            &packet,
+            Some(expected_seq),
+        )?;
+        let packet = packet::MaxGpioCountIs::deserialize(&packet)?;
+
+        Ok(packet.max_count)
+    }
+
+    fn get_gpio_name(&self, pin: u8) -> Result<String> {
+        let (packet, expected_seq) = {
+            let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+
+            let packet = packet::GetGpioName::new(&mut seq, pin).serialize()?;
+
+            (packet, seq.clone())
+        };
+
+        let packet = self
+            .exchange(packet::HostCmd::GetGpioName, &packet, Some(expected_seq))
+            .map_err(|err| err.at_pin(pin))?;
+        let packet = packet::GpioNameIs::deserialize(&packet)?;
+
+        packet.name
+    }
+
+    // Names of the pins below gpio_count, with up to depth GetGpioName requests
+    // in flight. Replies are matched by seq and may come in any order, a
+    // failed request only fails the name of its pin.
+    fn get_gpio_names(
+        &self,
+        gpio_count: u8,
+        depth: u8,
+        deadline: &Deadline,
+    ) -> Result<Vec<Result<String>>> {
+        let mut names: Vec<Option<Result<String>>> = (0..gpio_count).map(|_| None).collect();
+        let mut pending = 0..gpio_count;
+        // Oldest request first, its timeout expires first
+        let mut in_flight: VecDeque<(u8, u8, std::time::Instant)> = VecDeque::new();
+        let mut done = 0;
 
-    fn read(&self, expected_seq: Option<u8>) -> Result<Vec<u8>, Error> {
-        let now = std::time::Instant::now();
-        let mut timeout = READ_TIMEOUT_MS;
         loop {
-            match self
-                .data_rx
+            while in_flight.len() < depth as usize {
+                let Some(pin) = pending.next() else {
+                    break;
+                };
+
+                let (packet, seq) = {
+                    let mut seq = self.seq.lock().map_err(|err| anyhow!("{}", err))?;
+
+                    let packet = packet::GetGpioName::new(&mut seq, pin).serialize()?;
+
+                    (packet, *seq)
+                };
+
+                self.link.write(&packet)?;
+                in_flight.push_back((seq, pin, std::time::Instant::now()));
+            }
+
+            let Some(&(_, _, oldest_sent)) = in_flight.front() else {
+                break;
+            };
+
+            let window: Vec<u8> = in_flight.iter().map(|(seq, _, _)| *seq).collect();
+
+            let (pin, name) =
+                match self.read_window(packet::HostCmd::GetGpioName, &window, oldest_sent, None) {
+                    Ok(packet) => {
+                        let (_, rx_header) = packet::deserialize_headers(&packet)
+                            .map_err(|err| anyhow!(err.to_string()))?
+                            .1;
+                        let index = in_flight
+                            .iter()
+                            .position(|(seq, _, _)| *seq == rx_header.seq)
+                            .context("Reply outside of the request window")?;
+                        let (_, pin, sent) = in_flight.remove(index).unwrap();
+
+                        self.rtt
+                            .lock()
+                            .map_err(|err| anyhow!("{}", err))?
+                            .record(sent.elapsed());
+
+                        let name = check_status(packet::HostCmd::GetGpioName, &packet)
+                            .map_err(|err| err.at_pin(pin))
+                            .map_err(anyhow::Error::from)
+                            .and_then(|_| packet::GpioNameIs::deserialize(&packet)?.name);
+
+                        (pin, name)
+                    }
+                    Err(Error::Recoverable(err)) => {
+                        let (_, pin, _) = in_flight.pop_front().unwrap();
+                        (pin, Err(Error::from(err).into()))
+                    }
+                    Err(err) => return Err(err.into()),
+                };
+
+            names[pin as usize] = Some(name);
+            done += 1;
+            deadline.check(&format!("GPIO name {} of {}", done, gpio_count))?;
+        }
+
+        Ok(names.into_iter().flatten().collect())
+    }
+
+    // Names a pin according to the name policy, the flag is set when the
+    // name of the secondary could not be read
+    fn name_pin(&self, pin: u8, raw_name: Result<String>) -> Result<(PinInfo, bool)> {
+        let (raw_name, fallback) = match raw_name {
+            Ok(raw_name) => (raw_name, false),
+            Err(err) if self.tolerate_name_errors && is_recoverable(&err) => {
+                let name = fallback_name(pin);
+                log::warn!(
+                    "Failed to get GPIO name (Pin: {}, Fallback: {:?}), Err: {}",
+                    pin,
+                    name,
+                    err
+                );
+                (name, true)
+            }
+            Err(err) => return Err(err),
+        };
+
+        let name = match self.name_policy {
+            utils::NamePolicy::Raw => raw_name.clone(),
+            utils::NamePolicy::Normalize => normalize_name(pin, &raw_name),
+        };
+
+        if name != raw_name {
+            log::warn!(
+                "Normalized GPIO name (Pin: {}, Raw: {:?}, Normalized: {:?})",
+                pin,
+                raw_name,
+                name
+            );
+        }
+
+        Ok((
+            PinInfo {
+                pin,
+                name,
+                raw_name,
+            },
+            fallback,
+        ))
+    }
+
+    // The reply timeout of cmd, at most cap: a requester giving up after cap
+    // gains nothing from a later reply
+    pub fn capped_timeout(
+        &self,
+        cmd: packet::HostCmd,
+        cap: std::time::Duration,
+    ) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_ms(cmd) as u64).min(cap)
+    }
+
+    fn timeout_ms(&self, cmd: packet::HostCmd) -> u128 {
+        if let Some(timeout) = self.cmd_timeouts.iter().find(|timeout| timeout.cmd == cmd) {
+            return timeout.ms as u128;
+        }
+
+        match cmd {
+            packet::HostCmd::GetGpioValue | packet::HostCmd::GetGpioValues => SLOW_READ_TIMEOUT_MS,
+            packet::HostCmd::GetVersion
+            | packet::HostCmd::GetUniqueId
+            | packet::HostCmd::GetChipLabel
+            | packet::HostCmd::GetGpioCount
+            | packet::HostCmd::GetGpioName
+            | packet::HostCmd::SetGpioValue
+            | packet::HostCmd::SetGpioConfig
+            | packet::HostCmd::SetGpioDirection
+            | packet::HostCmd::GetFirmwareInfo
+            | packet::HostCmd::GetGpioConfig
+            | packet::HostCmd::SetGpioValues
+            | packet::HostCmd::GetGpioRaw
+            | packet::HostCmd::GetGpioStatus
+            | packet::HostCmd::GetMaxGpioCount
+            | packet::HostCmd::SetSeqWidth
+            | packet::HostCmd::GetCapabilities
+            | packet::HostCmd::UnknownCmd => READ_TIMEOUT_MS,
+        }
+    }
+
+    // Unstable: sends any command with its payload as is and returns the first
+    // reply carrying its seq, whatever the reply. A command the secondary
+    // answers more than once desyncs the link.
+    #[cfg(feature = "raw_request")]
+    pub fn raw_request(&self, cmd: u8, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let _transaction = self.begin_transaction()?;
+
+        let (packet, expected_seq) = {
+            let mut seq = self
+                .seq
                 .lock()
-                .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?
-                .recv_timeout(core::time::Duration::from_millis(timeout as u64))
-            {
+                .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
+
+            let packet = packet::serialize_raw_request(&mut seq, cmd, payload)
+                .map_err(RecoverableError::Serialization)?;
+
+            (packet, *seq)
+        };
+
+        // Unknown commands get the default timeout
+        let host_cmd = packet::HostCmd::try_from(cmd).unwrap_or(packet::HostCmd::UnknownCmd);
+        let sent = std::time::Instant::now();
+
+        self.link.write(&packet)?;
+
+        loop {
+            let reply = self.read_window(host_cmd, &[], sent, None)?;
+            if reply.get(2) == Some(&expected_seq) {
+                return Ok(reply);
+            }
+
+            log::warn!(
+                "Raw request {} {{ Ignoring reply of another seq: {:02x?} }}",
+                cmd,
+                reply
+            );
+        }
+    }
+
+    // Sequence number of the last request sent, to correlate captured traffic
+    pub fn last_seq(&self) -> Option<u8> {
+        self.seq.lock().ok().map(|seq| *seq)
+    }
+
+    pub fn unique_id(&self) -> u64 {
+        self.unique_id.load(Ordering::SeqCst)
+    }
+
+    // Re-reads the unique id of the secondary, returns the previous id if it
+    // changed. An overridden id is kept, the secondary is not asked.
+    pub fn refresh_unique_id(&self) -> Result<Option<u64>, Error> {
+        if self.unique_id_override.is_some() {
+            return Ok(None);
+        }
+
+        let _transaction = self.begin_transaction()?;
+
+        let (packet, expected_seq) = {
+            let mut seq = self
+                .seq
+                .lock()
+                .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
+
+            let packet = packet::GetUniqueId::new(&mut seq)
+                .serialize()
+                .map_err(RecoverableError::Serialization)?;
+
+            (packet, *seq)
+        };
+
+        let packet = self.exchange(packet::HostCmd::GetUniqueId, &packet, Some(expected_seq))?;
+
+        let packet =
+            packet::UniqueIdIs::deserialize(&packet).map_err(RecoverableError::Deserialization)?;
+
+        let previous = self.unique_id.swap(packet.unique_id, Ordering::SeqCst);
+
+        Ok((previous != packet.unique_id).then_some(previous))
+    }
+
+    // Sends the discovery requests again, serialized with the other requests.
+    // Read-only: the registered chip, the unique id and the pin states are
+    // left as they are, see discovery_changes.
+    pub fn rediscover(&self) -> Result<Discovery> {
+        let _transaction = self.begin_transaction()?;
+
+        let version = self.get_gpio_version()?;
+        let capabilities = self.get_capabilities()?;
+        let unique_id = self.get_unique_id()?;
+        let label = self.get_chip_label()?;
+        let firmware_info = match capabilities.has(packet::Capabilities::FIRMWARE_INFO) {
+            true => Some(self.get_firmware_info()?),
+            false => None,
+        };
+        let gpio_count = self.get_gpio_count()?;
+        let max_gpio_count = match capabilities.has(packet::Capabilities::GET_MAX_GPIO_COUNT) {
+            true => Some(self.get_max_gpio_count()?),
+            false => None,
+        };
+        // A failed name does not stop the others, like during discovery
+        let names = (0..gpio_count).map(|pin| self.get_gpio_name(pin)).collect();
+
+        Ok(Discovery {
+            version,
+            capabilities,
+            unique_id,
+            label,
+            firmware_info,
+            gpio_count,
+            max_gpio_count,
+            names,
+        })
+    }
+
+    // Differences between a rediscovery and what the bridge discovered at
+    // startup. More GPIO's are not a change, they may be left out on purpose
+    // or added later on.
+    pub fn discovery_changes(&self, discovery: &Discovery) -> Vec<String> {
+        let mut changes = vec![];
+
+        if discovery.version != self.gpio_version {
+            changes.push(format!(
+                "GPIO API v{} -> v{}",
+                self.gpio_version, discovery.version
+            ));
+        }
+
+        if discovery.capabilities != self.capabilities {
+            changes.push(format!(
+                "Capabilities {} -> {}",
+                self.capabilities, discovery.capabilities
+            ));
+        }
+
+        if self.unique_id_override.is_none() && discovery.unique_id != self.unique_id() {
+            changes.push(format!(
+                "UID {} -> {}",
+                self.unique_id(),
+                discovery.unique_id
+            ));
+        }
+
+        if discovery.label != self.chip.label {
+            changes.push(format!(
+                "Label {:?} -> {:?}",
+                self.chip.label, discovery.label
+            ));
+        }
+
+        if discovery.firmware_info.is_some() && discovery.firmware_info != self.chip.firmware_info {
+            changes.push(format!(
+                "Firmware {:?} -> {:?}",
+                self.chip.firmware_info, discovery.firmware_info
+            ));
+        }
+
+        if (discovery.gpio_count as usize) < self.chip.raw_gpio_names.len() {
+            changes.push(format!(
+                "GPIO count {} -> {}",
+                self.chip.raw_gpio_names.len(),
+                discovery.gpio_count
+            ));
+        }
+
+        for (pin, (raw_name, name)) in self
+            .chip
+            .raw_gpio_names
+            .iter()
+            .zip(&discovery.names)
+            .enumerate()
+        {
+            if let Ok(name) = name {
+                if name != raw_name {
+                    changes.push(format!("Pin {} name {:?} -> {:?}", pin, raw_name, name));
+                }
+            }
+        }
+
+        changes
+    }
+
+    pub fn reconciled_count(&self) -> u64 {
+        self.reconciled.load(Ordering::SeqCst)
+    }
+
+    pub fn link_counters(&self) -> LinkCounters {
+        self.counters
+            .lock()
+            .map(|counters| *counters)
+            .unwrap_or_default()
+    }
+
+    pub fn link_state(&self) -> LinkState {
+        self.link_tracker
+            .lock()
+            .map(|tracker| tracker.state())
+            .unwrap_or(LinkState::Up)
+    }
+
+    pub fn rtt_stats(&self) -> Option<RttStats> {
+        self.rtt.lock().ok()?.stats()
+    }
+
+    // Only replies matching the request are sampled, a failed request
+    // says nothing about the round trip time
+    fn exchange(
+        &self,
+        cmd: packet::HostCmd,
+        packet: &[u8],
+        expected_seq: Option<u8>,
+    ) -> Result<Vec<u8>, Error> {
+        self.exchange_timeout(cmd, packet, expected_seq, None)
+    }
+
+    // Without a timeout override, the reply is awaited for the timeout of cmd
+    fn exchange_timeout(
+        &self,
+        cmd: packet::HostCmd,
+        packet: &[u8],
+        expected_seq: Option<u8>,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Vec<u8>, Error> {
+        let now = std::time::Instant::now();
+
+        let result = self
+            .link
+            .write(packet)
+            .and_then(|()| self.read(cmd, expected_seq, timeout));
+
+        self.counters
+            .lock()
+            .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?
+            .record(&result, std::time::Instant::now());
+
+        let transition = self
+            .link_tracker
+            .lock()
+            .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?
+            .record(&result);
+        if let Some((state, streak)) = transition {
+            match state {
+                LinkState::Down => log::warn!(
+                    "Link down after {} unanswered requests, Transport: {}",
+                    streak,
+                    self.link.transport()
+                ),
+                LinkState::Up => log::info!("Link up after {} answered requests", streak),
+            }
+            crate::events::publish(|| crate::events::Event::LinkState {
+                unique_id: self.unique_id(),
+                state,
+                streak,
+            });
+        }
+
+        let desync = matches!(result, Err(Error::Recoverable(RecoverableError::Desync(_))));
+        let malformed = self.resync_after_malformed > 0
+            && self.link.malformed_frames() >= self.resync_after_malformed;
+        if desync || malformed {
+            if let Err(err) = self.send_resync() {
+                log::warn!("Failed to resynchronize the link, Err: {}", err);
+            }
+        }
+
+        let reply = result?;
+
+        self.rtt
+            .lock()
+            .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?
+            .record(now.elapsed());
+
+        Ok(reply)
+    }
+
+    fn read(
+        &self,
+        cmd: packet::HostCmd,
+        expected_seq: Option<u8>,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Vec<u8>, Error> {
+        let packet = self.read_window(
+            cmd,
+            expected_seq.as_slice(),
+            std::time::Instant::now(),
+            timeout,
+        )?;
+
+        if expected_seq.is_some() {
+            check_status(cmd, &packet)?;
+        }
+
+        Ok(packet)
+    }
+
+    // A reply repeating the seq accepted last answers no outstanding request.
+    // Only its first repeat is a double-reply, a link replaying old replies
+    // keeps failing as a mismatch.
+    fn take_duplicate(&self, seq: u8) -> Result<bool, Error> {
+        let mut last_accepted_seq = self
+            .last_accepted_seq
+            .lock()
+            .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?;
+        if *last_accepted_seq != Some(seq) {
+            return Ok(false);
+        }
+        *last_accepted_seq = None;
+
+        self.counters
+            .lock()
+            .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))?
+            .duplicate_replies += 1;
+
+        Ok(true)
+    }
+
+    // Waits for the reply to any request of the window, until the timeout of
+    // the oldest one sent at oldest_sent. An empty window accepts any reply.
+    fn read_window(
+        &self,
+        cmd: packet::HostCmd,
+        window: &[u8],
+        oldest_sent: std::time::Instant,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<Vec<u8>, Error> {
+        let timeout = timeout
+            .unwrap_or_else(|| core::time::Duration::from_millis(self.timeout_ms(cmd) as u64));
+        let deadline = oldest_sent + timeout;
+        let mut mismatches = 0;
+        loop {
+            // Replies with a mismatched seq must not push the deadline back
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+
+            match self.link.recv_timeout(remaining) {
                 Ok(packet) => {
-                    if let Some(expected_seq) = expected_seq {
+                    // Carries no seq, only the command tells the request it
+                    // refuses
+                    if let Some(unsupported) = packet::unsupported_cmd(&packet) {
+                        if unsupported == cmd {
+                            return Err(RecoverableError::Packet {
+                                status: Status::NotSupported,
+                                cmd,
+                                pin: None,
+                            }
+                            .into());
+                        }
+                        continue;
+                    }
+
+                    if !window.is_empty() {
                         let (header, rx_header) = packet::deserialize_headers(&packet)
                             .map_err(|err| {
                                 RecoverableError::Deserialization(anyhow!(err.to_string()))
                             })?
                             .1;
 
-                        if expected_seq != rx_header.seq {
-                            log::warn!(
-                                "{:?} {{ Sequence number mismatch (Expected: {}, Received: {}) }}",
-                                header.cmd,
-                                expected_seq,
-                                rx_header.seq,
-                            );
-                            continue;
-                        }
+                        if !window.contains(&rx_header.seq) {
+                            if self.take_duplicate(rx_header.seq)? {
+                                self.duplicate_reply_warnings.warn(format_args!(
+                                    "{:?} {{ Duplicate reply (Seq: {}, Expected: {}), likely a firmware double-reply }}",
+                                    header.cmd,
+                                    rx_header.seq,
+                                    format_window(window),
+                                ));
+                            } else {
+                                self.seq_mismatch_warnings.warn(format_args!(
+                                    "{:?} {{ Sequence number mismatch (Expected: {}, Received: {}) }}",
+                                    header.cmd,
+                                    format_window(window),
+                                    rx_header.seq,
+                                ));
+                            }
 
-                        if let packet::SecondaryCmd::StatusIs = header.cmd {
-                            let status = packet::StatusIs::deserialize(&packet)
-                                .map_err(RecoverableError::Deserialization)?;
-                            if status.status != Status::Ok {
-                                return Err(RecoverableError::Packet(status.status).into());
+                            // A link out of sync would otherwise warn on every
+                            // reply until the request times out
+                            mismatches += 1;
+                            if mismatches >= self.max_seq_mismatches {
+                                return Err(RecoverableError::Desync(mismatches).into());
                             }
+                            continue;
                         }
+
+                        self.seq_mismatch_warnings
+                            .clear(format_args!("Sequence number mismatches"));
+                        *self
+                            .last_accepted_seq
+                            .lock()
+                            .map_err(|err| UnrecoverableError::Anyhow(anyhow!("{}", err)))? =
+                            Some(rx_header.seq);
                     }
 
                     return Ok(packet);
                 }
                 Err(err) => match err {
                     mpsc::RecvTimeoutError::Timeout => {
-                        let elapsed = now.elapsed().as_millis();
-                        if elapsed >= timeout {
-                            return Err(RecoverableError::Timeout(err, elapsed).into());
-                        } else {
-                            timeout -= elapsed;
-                        }
+                        return Err(RecoverableError::Timeout(
+                            err,
+                            oldest_sent.elapsed().as_millis(),
+                            self.link.transport().clone(),
+                        )
+                        .into());
                     }
                     mpsc::RecvTimeoutError::Disconnected => {
                         return Err(UnrecoverableError::Anyhow(anyhow!(
-                            "{}",
-                            mpsc::RecvTimeoutError::Disconnected
+                            "{}, Transport: {}",
+                            mpsc::RecvTimeoutError::Disconnected,
+                            self.link.transport()
                         ))
                         .into());
                     }
@@ -420,3 +2190,82 @@ impl Handle {
         }
     }
 }
+
+// A StatusIs reply other than Ok fails the request it answers
+fn check_status(cmd: packet::HostCmd, packet: &[u8]) -> Result<(), Error> {
+    let (header, _) = packet::deserialize_headers(packet)
+        .map_err(|err| RecoverableError::Deserialization(anyhow!(err.to_string())))?
+        .1;
+
+    if let packet::SecondaryCmd::StatusIs = header.cmd {
+        let status =
+            packet::StatusIs::deserialize(packet).map_err(RecoverableError::Deserialization)?;
+        if status.status != Status::Ok {
+            return Err(RecoverableError::Packet {
+                status: status.status,
+                cmd,
+                pin: None,
+            }
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+// A single expected seq is logged as before pipelining, a window as a list
+fn format_window(window: &[u8]) -> String {
+    match window {
+        [seq] => seq.to_string(),
+        _ => format!("{:?}", window),
+    }
+}
+
+fn normalize_name(pin: u8, name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(GPIO_NAME_MAX_LEN)
+        .collect();
+
+    if name.is_empty() {
+        fallback_name(pin)
+    } else {
+        name
+    }
+}
+
+fn fallback_name(pin: u8) -> String {
+    format!("gpio{}", pin)
+}
+
+// The fallback name of a pin is suffixed until no other pin is named alike
+fn unique_fallback_name(pin: u8, names: &[String]) -> String {
+    let taken = |name: &str| {
+        names
+            .iter()
+            .enumerate()
+            .any(|(other, other_name)| other != pin as usize && other_name == name)
+    };
+
+    let name = fallback_name(pin);
+    if !taken(&name) {
+        return name;
+    }
+
+    (1..)
+        .map(|suffix| format!("{}_{}", name, suffix))
+        .find(|name| !taken(name))
+        .unwrap()
+}
+
+// Only a failed request is tolerated, a malformed reply is not
+fn is_recoverable(err: &anyhow::Error) -> bool {
+    matches!(err.downcast_ref::<Error>(), Some(Error::Recoverable(_)))
+}
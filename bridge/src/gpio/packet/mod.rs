@@ -1,4 +1,5 @@
 use anyhow::{bail, Result};
+use bincode::Options;
 use thiserror::Error;
 
 use crate::utils;
@@ -6,6 +7,88 @@ use crate::utils;
 #[cfg(test)]
 mod tests;
 
+#[derive(serde::Serialize, Debug)]
+#[repr(C, packed)]
+pub struct GetGpioStatus {
+    header: Header<HostCmd>,
+    host_header: HostHeader,
+    pin: u8,
+}
+impl Serializer for GetGpioStatus {}
+impl GetGpioStatus {
+    pub fn new(seq: &mut u8, pin: u8) -> Self {
+        let len = Header::<HostCmd>::len(std::mem::size_of::<Self>());
+        Self {
+            header: Header::new(HostCmd::GetGpioStatus, len),
+            host_header: HostHeader::new(seq),
+            pin,
+        }
+    }
+}
+
+// Electrical state of a pin, apart from its logic value. Bits not defined here
+// are reserved and ignored.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GpioStatus(pub u8);
+impl GpioStatus {
+    pub const DRIVEN: u8 = 1 << 0;
+    pub const INPUT_ENABLED: u8 = 1 << 1;
+    pub const ERROR_LATCHED: u8 = 1 << 2;
+
+    // A pin that is not driven is floating, or held by its bias only
+    pub fn driven(&self) -> bool {
+        self.0 & Self::DRIVEN != 0
+    }
+
+    pub fn input_enabled(&self) -> bool {
+        self.0 & Self::INPUT_ENABLED != 0
+    }
+
+    // Set by the firmware on a pad fault (e.g. a short), until the pin is reconfigured
+    pub fn error_latched(&self) -> bool {
+        self.0 & Self::ERROR_LATCHED != 0
+    }
+}
+impl std::fmt::Display for GpioStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", if self.driven() { "driven" } else { "floating" })?;
+        if self.input_enabled() {
+            write!(f, ", input enabled")?;
+        }
+        if self.error_latched() {
+            write!(f, ", error latched")?;
+        }
+        Ok(())
+    }
+}
+#[repr(C, packed)]
+pub struct GpioStatusIs {
+    header: Header<SecondaryCmd>,
+    pub secondary_header: SecondaryHeader,
+    pub status: GpioStatus,
+}
+impl GpioStatusIs {
+    pub fn deserialize(input: &[u8]) -> Result<Self> {
+        let result = || -> nom::IResult<&[u8], (Header<SecondaryCmd>, SecondaryHeader, u8)> {
+            let (remaining, (header, secondary_header)) = deserialize_headers(input)?;
+            let (remaining, status) = nom::number::complete::u8(remaining)?;
+            Ok((remaining, (header, secondary_header, status)))
+        };
+
+        match result() {
+            Ok((remaining, (header, secondary_header, status))) => {
+                check_len(input, remaining)?;
+                Ok(Self {
+                    header,
+                    secondary_header,
+                    status: GpioStatus(status),
+                })
+            }
+            Err(err) => bail!("{}", err),
+        }
+    }
+}
+
 #[derive(
     serde_repr::Serialize_repr,
     serde_repr::Deserialize_repr,
@@ -14,6 +97,7 @@ mod tests;
     Copy,
     Clone,
     Debug,
+    clap::ValueEnum,
 )]
 #[repr(u8)]
 pub enum HostCmd {
@@ -26,6 +110,16 @@ pub enum HostCmd {
     SetGpioValue = 6,
     SetGpioConfig = 7,
     SetGpioDirection = 8,
+    GetFirmwareInfo = 9,
+    GetGpioConfig = 10,
+    SetGpioValues = 11,
+    GetGpioRaw = 12,
+    GetGpioStatus = 13,
+    GetMaxGpioCount = 14,
+    GetGpioValues = 15,
+    SetSeqWidth = 16,
+    GetCapabilities = 17,
+    #[value(skip)]
     UnknownCmd = SecondaryCmd::VersionIs as u8 - 1,
 }
 
@@ -46,6 +140,13 @@ pub enum SecondaryCmd {
     GpioCountIs = 132,
     GpioNameIs = 133,
     GpioValueIs = 134,
+    FirmwareInfoIs = 135,
+    GpioConfigIs = 136,
+    GpioRawIs = 137,
+    GpioStatusIs = 138,
+    MaxGpioCountIs = 139,
+    GpioValuesIs = 140,
+    CapabilitiesIs = 141,
     UnsupportedCmdIs = u8::MAX,
 }
 
@@ -126,9 +227,44 @@ impl SecondaryHeader {
     }
 }
 
+// Multi-byte fields are little-endian on the wire, the byte order of the
+// secondary, whatever the host. bincode::serialize only implies it, spelled
+// out it matches the le_* parsers of the replies.
+pub fn wire_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_little_endian()
+}
+
 pub trait Serializer: serde::Serialize {
     fn serialize(&self) -> Result<Vec<u8>> {
-        Ok(bincode::serialize(&self)?)
+        Ok(wire_options().serialize(&self)?)
+    }
+}
+
+// Any command with an opaque payload, for firmware commands that have no
+// type yet
+#[cfg(feature = "raw_request")]
+pub fn serialize_raw_request(seq: &mut u8, cmd: u8, payload: &[u8]) -> Result<Vec<u8>> {
+    let len = match u8::try_from(std::mem::size_of::<HostHeader>() + payload.len()) {
+        Ok(len) => len,
+        Err(_) => bail!("Payload of {} bytes does not fit a packet", payload.len()),
+    };
+
+    let host_header = HostHeader::new(seq);
+
+    Ok([[cmd, len, host_header.seq].as_slice(), payload].concat())
+}
+
+// The reply to a raw request may be of a command the bridge does not know,
+// yet like any reply it has a length matching its payload and carries a seq
+#[cfg(feature = "raw_request")]
+pub fn is_raw_reply(packet: &[u8]) -> bool {
+    match packet {
+        [cmd, len, _seq, ..] => {
+            SecondaryCmd::try_from(*cmd).is_err() && usize::from(*len) == packet.len() - 2
+        }
+        _ => false,
     }
 }
 
@@ -166,7 +302,10 @@ impl VersionIs {
         }();
 
         match result {
-            Ok(tuple) => Ok(tuple.1),
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
             Err(err) => bail!("{}", err),
         }
     }
@@ -176,7 +315,7 @@ impl VersionIs {
 #[repr(C, packed)]
 pub struct UnsupportedCmdIs {
     header: Header<SecondaryCmd>,
-    unsupported_cmd: HostCmd,
+    pub unsupported_cmd: HostCmd,
 }
 impl UnsupportedCmdIs {
     pub fn deserialize(input: &[u8]) -> Result<Self> {
@@ -194,7 +333,10 @@ impl UnsupportedCmdIs {
         }();
 
         match result {
-            Ok(tuple) => Ok(tuple.1),
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
             Err(err) => bail!("{}", err),
         }
     }
@@ -239,7 +381,135 @@ impl GpioCountIs {
         }();
 
         match result {
-            Ok(tuple) => Ok(tuple.1),
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
+            Err(err) => bail!("{}", err),
+        }
+    }
+}
+
+// Optional commands and reply formats of the GPIO API the secondary
+// implements. Bits not defined here are reserved and ignored.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Capabilities(pub u32);
+impl Capabilities {
+    pub const FIRMWARE_INFO: u32 = 1 << 0;
+    pub const GET_GPIO_CONFIG: u32 = 1 << 1;
+    pub const SET_GPIO_VALUES: u32 = 1 << 2;
+    pub const GET_GPIO_RAW: u32 = 1 << 3;
+    pub const GET_GPIO_STATUS: u32 = 1 << 4;
+    pub const GET_MAX_GPIO_COUNT: u32 = 1 << 5;
+    pub const GET_GPIO_VALUES: u32 = 1 << 6;
+    pub const SET_SEQ_WIDTH: u32 = 1 << 7;
+    // GpioValueIs carries the source of the value
+    pub const GPIO_VALUE_SOURCE: u32 = 1 << 8;
+    // SetGpioConfig takes InputEnable and OutputEnable
+    pub const BUFFER_ENABLE: u32 = 1 << 9;
+
+    pub fn has(&self, capability: u32) -> bool {
+        self.0 & capability == capability
+    }
+}
+impl std::fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#010x}", self.0)
+    }
+}
+
+// Secondaries without it answer UnsupportedCmdIs, they implement none of the
+// capabilities
+#[derive(serde::Serialize, Debug)]
+#[repr(C, packed)]
+pub struct GetCapabilities {
+    header: Header<HostCmd>,
+    host_header: HostHeader,
+}
+impl Serializer for GetCapabilities {}
+impl GetCapabilities {
+    pub fn new(seq: &mut u8) -> Self {
+        let len = Header::<HostCmd>::len(std::mem::size_of::<Self>());
+        Self {
+            header: Header::new(HostCmd::GetCapabilities, len),
+            host_header: HostHeader::new(seq),
+        }
+    }
+}
+#[repr(C, packed)]
+pub struct CapabilitiesIs {
+    header: Header<SecondaryCmd>,
+    pub secondary_header: SecondaryHeader,
+    pub capabilities: Capabilities,
+}
+impl CapabilitiesIs {
+    pub fn deserialize(input: &[u8]) -> Result<Self> {
+        let result = || -> nom::IResult<&[u8], Self> {
+            let (remaining, (header, secondary_header)) = deserialize_headers(input)?;
+            let (remaining, capabilities) = nom::number::complete::le_u32(remaining)?;
+            Ok((
+                remaining,
+                Self {
+                    header,
+                    secondary_header,
+                    capabilities: Capabilities(capabilities),
+                },
+            ))
+        }();
+
+        match result {
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
+            Err(err) => bail!("{}", err),
+        }
+    }
+}
+
+// The most GPIO's the firmware can ever expose, the GPIO count may grow up to it
+#[derive(serde::Serialize, Debug)]
+#[repr(C, packed)]
+pub struct GetMaxGpioCount {
+    header: Header<HostCmd>,
+    host_header: HostHeader,
+}
+impl Serializer for GetMaxGpioCount {}
+impl GetMaxGpioCount {
+    pub fn new(seq: &mut u8) -> Self {
+        let len = Header::<HostCmd>::len(std::mem::size_of::<Self>());
+        Self {
+            header: Header::new(HostCmd::GetMaxGpioCount, len),
+            host_header: HostHeader::new(seq),
+        }
+    }
+}
+#[repr(C, packed)]
+pub struct MaxGpioCountIs {
+    header: Header<SecondaryCmd>,
+    pub secondary_header: SecondaryHeader,
+    pub max_count: u8,
+}
+impl MaxGpioCountIs {
+    pub fn deserialize(input: &[u8]) -> Result<Self> {
+        let result = || -> nom::IResult<&[u8], Self> {
+            let (remaining, (header, secondary_header)) = deserialize_headers(input)?;
+            let (remaining, max_count) = nom::number::complete::u8(remaining)?;
+            Ok((
+                remaining,
+                Self {
+                    header,
+                    secondary_header,
+                    max_count,
+                },
+            ))
+        }();
+
+        match result {
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
             Err(err) => bail!("{}", err),
         }
     }
@@ -273,8 +543,9 @@ impl GpioNameIs {
     pub fn deserialize(input: &[u8]) -> Result<Self> {
         let result = || -> nom::IResult<&[u8], Self> {
             let (remaining, (header, secondary_header)) = deserialize_headers(input)?;
+            let (remaining, bytes) = nom::combinator::rest(remaining)?;
             let name = || -> Result<String> {
-                Ok(std::ffi::CStr::from_bytes_with_nul(remaining)?
+                Ok(std::ffi::CStr::from_bytes_with_nul(bytes)?
                     .to_str()?
                     .to_string())
             }();
@@ -289,7 +560,10 @@ impl GpioNameIs {
         }();
 
         match result {
-            Ok(tuple) => Ok(tuple.1),
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
             Err(err) => bail!("{}", err),
         }
     }
@@ -310,6 +584,10 @@ pub enum GpioValue {
     High = 1,
 }
 
+#[derive(Error, Copy, Clone, Debug)]
+#[error("Unexpected GPIO value (Raw: {0:#04x})")]
+pub struct InvalidGpioValue(pub u8);
+
 #[derive(serde::Serialize, Debug)]
 #[repr(C, packed)]
 pub struct GetGpioValue {
@@ -328,30 +606,59 @@ impl GetGpioValue {
         }
     }
 }
+// What a GpioValueIs reports: the value an output drives, read back from its
+// output register, or the level sampled on an input. Values not defined here
+// are reserved and taken as unreported.
+#[derive(num_enum::TryFromPrimitive, PartialEq, Copy, Clone, Debug)]
+#[repr(u8)]
+pub enum GpioValueSource {
+    Driven = 0,
+    Sampled = 1,
+}
+
 #[repr(C, packed)]
 pub struct GpioValueIs {
     header: Header<SecondaryCmd>,
     pub secondary_header: SecondaryHeader,
-    pub value: Result<GpioValue>,
+    pub value: Result<GpioValue, InvalidGpioValue>,
+    pub source: Option<GpioValueSource>,
 }
 impl GpioValueIs {
     pub fn deserialize(input: &[u8]) -> Result<Self> {
+        Self::deserialize_versioned(input, false)
+    }
+
+    // From v1.9 of the GPIO API the value is followed by its source
+    pub fn deserialize_with_source(input: &[u8]) -> Result<Self> {
+        Self::deserialize_versioned(input, true)
+    }
+
+    fn deserialize_versioned(input: &[u8], with_source: bool) -> Result<Self> {
         let result = || -> nom::IResult<&[u8], Self> {
             let (remaining, (header, secondary_header)) = deserialize_headers(input)?;
-            let (remaining, value) = nom::number::complete::u8(remaining)?;
-            let value = || -> Result<GpioValue> { Ok(GpioValue::try_from(value)?) }();
+            let (remaining, value) = deserialize_gpio_value(remaining)?;
+            let (remaining, source) = if with_source {
+                let (remaining, source) = nom::number::complete::u8(remaining)?;
+                (remaining, GpioValueSource::try_from(source).ok())
+            } else {
+                (remaining, None)
+            };
             Ok((
                 remaining,
                 Self {
                     header,
                     secondary_header,
                     value,
+                    source,
                 },
             ))
         }();
 
         match result {
-            Ok(tuple) => Ok(tuple.1),
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
             Err(err) => bail!("{}", err),
         }
     }
@@ -377,6 +684,130 @@ impl SetGpioValue {
         }
     }
 }
+// The length of a packet limits how many pins can be set at once
+pub const SET_GPIO_VALUES_MAX: usize =
+    (u8::MAX as usize - std::mem::size_of::<HostHeader>() - 1) / 2;
+
+// Sets the listed pins in one go, the pins that are not listed keep their
+// value. Serialized as the pin count followed by (pin, value) pairs.
+#[derive(Debug)]
+pub struct SetGpioValues {
+    header: Header<HostCmd>,
+    host_header: HostHeader,
+    values: Vec<(u8, GpioValue)>,
+}
+impl Serializer for SetGpioValues {}
+impl serde::Serialize for SetGpioValues {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeTuple;
+
+        let mut tuple = serializer.serialize_tuple(3 + self.values.len())?;
+        tuple.serialize_element(&self.header)?;
+        tuple.serialize_element(&self.host_header)?;
+        tuple.serialize_element(&(self.values.len() as u8))?;
+        for value in &self.values {
+            tuple.serialize_element(value)?;
+        }
+        tuple.end()
+    }
+}
+impl SetGpioValues {
+    pub fn new(seq: &mut u8, values: &[(u8, GpioValue)]) -> Result<Self> {
+        if values.len() > SET_GPIO_VALUES_MAX {
+            bail!(
+                "Cannot set more than {} pins at once, got {}",
+                SET_GPIO_VALUES_MAX,
+                values.len()
+            );
+        }
+
+        let len = std::mem::size_of::<HostHeader>() + 1 + 2 * values.len();
+        Ok(Self {
+            header: Header::new(HostCmd::SetGpioValues, len as u8),
+            host_header: HostHeader::new(seq),
+            values: values.to_vec(),
+        })
+    }
+}
+// The length of a packet limits how many pins can be read at once
+pub const GET_GPIO_VALUES_MAX: usize = u8::MAX as usize - std::mem::size_of::<HostHeader>() - 1;
+
+// Reads the listed pins in one go. Serialized as the pin count followed by
+// the pins.
+#[derive(Debug)]
+pub struct GetGpioValues {
+    header: Header<HostCmd>,
+    host_header: HostHeader,
+    pins: Vec<u8>,
+}
+impl Serializer for GetGpioValues {}
+impl serde::Serialize for GetGpioValues {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeTuple;
+
+        let mut tuple = serializer.serialize_tuple(3 + self.pins.len())?;
+        tuple.serialize_element(&self.header)?;
+        tuple.serialize_element(&self.host_header)?;
+        tuple.serialize_element(&(self.pins.len() as u8))?;
+        for pin in &self.pins {
+            tuple.serialize_element(pin)?;
+        }
+        tuple.end()
+    }
+}
+impl GetGpioValues {
+    pub fn new(seq: &mut u8, pins: &[u8]) -> Result<Self> {
+        if pins.len() > GET_GPIO_VALUES_MAX {
+            bail!(
+                "Cannot read more than {} pins at once, got {}",
+                GET_GPIO_VALUES_MAX,
+                pins.len()
+            );
+        }
+
+        let len = std::mem::size_of::<HostHeader>() + 1 + pins.len();
+        Ok(Self {
+            header: Header::new(HostCmd::GetGpioValues, len as u8),
+            host_header: HostHeader::new(seq),
+            pins: pins.to_vec(),
+        })
+    }
+}
+// A pin and its value as read, an unexpected value only fails its own pin
+pub type PinValue = (u8, Result<GpioValue, InvalidGpioValue>);
+
+#[repr(C)]
+pub struct GpioValuesIs {
+    header: Header<SecondaryCmd>,
+    pub secondary_header: SecondaryHeader,
+    pub values: Vec<PinValue>,
+}
+impl GpioValuesIs {
+    pub fn deserialize(input: &[u8]) -> Result<Self> {
+        let result = || -> nom::IResult<&[u8], Self> {
+            let (remaining, (header, secondary_header)) = deserialize_headers(input)?;
+            let (remaining, values) = deserialize_elements(remaining, |input| {
+                nom::sequence::pair(nom::number::complete::u8, deserialize_gpio_value)(input)
+            })?;
+            Ok((
+                remaining,
+                Self {
+                    header,
+                    secondary_header,
+                    values,
+                },
+            ))
+        }();
+
+        match result {
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
+            Err(err) => bail!("{}", err),
+        }
+    }
+}
 #[repr(C, packed)]
 pub struct StatusIs {
     header: Header<SecondaryCmd>,
@@ -400,7 +831,10 @@ impl StatusIs {
         }();
 
         match result {
-            Ok(tuple) => Ok(tuple.1),
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
             Err(err) => bail!("{}", err),
         }
     }
@@ -410,6 +844,7 @@ impl StatusIs {
     serde_repr::Serialize_repr,
     serde_repr::Deserialize_repr,
     num_enum::TryFromPrimitive,
+    PartialEq,
     Copy,
     Clone,
     Debug,
@@ -422,6 +857,15 @@ pub enum GpioConfig {
     DriveOpenDrain = 3,
     DriveOpenSource = 4,
     DrivePushPull = 5,
+    InputEnable = 6,
+    OutputEnable = 7,
+}
+impl GpioConfig {
+    // Enables a pad buffer independently of the direction, rather than
+    // replacing the bias or drive mode of the pin
+    pub fn is_buffer_enable(&self) -> bool {
+        matches!(self, GpioConfig::InputEnable | GpioConfig::OutputEnable)
+    }
 }
 
 #[derive(serde::Serialize, Debug)]
@@ -445,10 +889,98 @@ impl SetGpioConfig {
     }
 }
 
+#[derive(serde::Serialize, Debug)]
+#[repr(C, packed)]
+pub struct GetGpioConfig {
+    header: Header<HostCmd>,
+    host_header: HostHeader,
+    pin: u8,
+}
+impl Serializer for GetGpioConfig {}
+impl GetGpioConfig {
+    pub fn new(seq: &mut u8, pin: u8) -> Self {
+        let len = Header::<HostCmd>::len(std::mem::size_of::<Self>());
+        Self {
+            header: Header::new(HostCmd::GetGpioConfig, len),
+            host_header: HostHeader::new(seq),
+            pin,
+        }
+    }
+}
+#[repr(C, packed)]
+pub struct GpioConfigIs {
+    header: Header<SecondaryCmd>,
+    pub secondary_header: SecondaryHeader,
+    pub config: GpioConfig,
+}
+impl GpioConfigIs {
+    pub fn deserialize(input: &[u8]) -> Result<Self> {
+        let result = || -> nom::IResult<&[u8], (Header<SecondaryCmd>, SecondaryHeader, u8)> {
+            let (remaining, (header, secondary_header)) = deserialize_headers(input)?;
+            let (remaining, config) = nom::number::complete::u8(remaining)?;
+            Ok((remaining, (header, secondary_header, config)))
+        };
+
+        match result() {
+            Ok((remaining, (header, secondary_header, config))) => {
+                check_len(input, remaining)?;
+                Ok(Self {
+                    header,
+                    secondary_header,
+                    config: GpioConfig::try_from(config)?,
+                })
+            }
+            Err(err) => bail!("{}", err),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+#[repr(C, packed)]
+pub struct GetGpioRaw {
+    header: Header<HostCmd>,
+    host_header: HostHeader,
+    pin: u8,
+}
+impl Serializer for GetGpioRaw {}
+impl GetGpioRaw {
+    pub fn new(seq: &mut u8, pin: u8) -> Self {
+        let len = Header::<HostCmd>::len(std::mem::size_of::<Self>());
+        Self {
+            header: Header::new(HostCmd::GetGpioRaw, len),
+            host_header: HostHeader::new(seq),
+            pin,
+        }
+    }
+}
+// The raw pad registers of a pin, the layout is up to the firmware
+#[repr(C)]
+pub struct GpioRawIs {
+    header: Header<SecondaryCmd>,
+    pub secondary_header: SecondaryHeader,
+    pub raw: Vec<u8>,
+}
+impl GpioRawIs {
+    pub fn deserialize(input: &[u8]) -> Result<Self> {
+        match deserialize_headers(input) {
+            Ok((raw, (header, secondary_header))) => {
+                check_len(input, &[])?;
+                Ok(Self {
+                    header,
+                    secondary_header,
+                    raw: raw.to_vec(),
+                })
+            }
+            Err(err) => bail!("{}", err),
+        }
+    }
+}
+
 #[derive(
     serde_repr::Serialize_repr,
     serde_repr::Deserialize_repr,
     num_enum::TryFromPrimitive,
+    PartialEq,
     Copy,
     Clone,
     Debug,
@@ -460,6 +992,79 @@ pub enum GpioDirection {
     Disabled = 2,
 }
 
+// Switches the link to seq_width bytes of sequence number from the next
+// request on, answered with the width still in effect
+#[derive(serde::Serialize, Debug)]
+#[repr(C, packed)]
+pub struct SetSeqWidth {
+    header: Header<HostCmd>,
+    host_header: HostHeader,
+    seq_width: u8,
+}
+impl Serializer for SetSeqWidth {}
+impl SetSeqWidth {
+    pub fn new(seq: &mut u8, seq_width: u8) -> Self {
+        let len = Header::<HostCmd>::len(std::mem::size_of::<Self>());
+        Self {
+            header: Header::new(HostCmd::SetSeqWidth, len),
+            host_header: HostHeader::new(seq),
+            seq_width,
+        }
+    }
+}
+
+// A two-byte sequence number is little-endian on the wire: its low byte is
+// where the one-byte seq would be and its high byte follows it. The packets
+// are built and parsed with the one-byte seq, they are only widened on the
+// way out and narrowed on the way in.
+pub fn request_carries_seq(packet: &[u8]) -> bool {
+    packet.first() != Some(&(HostCmd::GetVersion as u8))
+}
+
+// The command a well-formed UnsupportedCmdIs refuses, None for any other reply
+pub fn unsupported_cmd(packet: &[u8]) -> Option<HostCmd> {
+    if packet.first() != Some(&(SecondaryCmd::UnsupportedCmdIs as u8)) {
+        return None;
+    }
+
+    UnsupportedCmdIs::deserialize(packet)
+        .ok()
+        .map(|unsupported| unsupported.unsupported_cmd)
+}
+
+pub fn reply_carries_seq(packet: &[u8]) -> bool {
+    !matches!(
+        packet.first().map(|cmd| SecondaryCmd::try_from(*cmd)),
+        Some(Ok(SecondaryCmd::VersionIs | SecondaryCmd::UnsupportedCmdIs))
+    )
+}
+
+pub fn widen(packet: &[u8], seq_high: u8) -> Result<Vec<u8>> {
+    if packet.len() < 3 {
+        bail!("Packet too short to carry a seq: {:?}", packet);
+    }
+
+    let mut wide = packet.to_vec();
+    wide[1] = match wide[1].checked_add(1) {
+        Some(len) => len,
+        None => bail!("Packet too long to widen its seq: {:?}", packet),
+    };
+    wide.insert(3, seq_high);
+    Ok(wide)
+}
+
+// Returns the packet with a one-byte seq and the high byte it carried
+pub fn narrow(packet: &[u8]) -> Result<(Vec<u8>, u8)> {
+    if packet.len() < 4 || packet[1] < 2 {
+        bail!("Packet too short to carry a two-byte seq: {:?}", packet);
+    }
+
+    let mut narrow = packet.to_vec();
+    narrow[1] -= 1;
+    let seq_high = narrow.remove(3);
+    Ok((narrow, seq_high))
+}
+
 #[derive(serde::Serialize, Debug)]
 #[repr(C, packed)]
 pub struct SetGpioDirection {
@@ -519,7 +1124,10 @@ impl UniqueIdIs {
         };
 
         match result() {
-            Ok(tuple) => Ok(tuple.1),
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
             Err(err) => bail!("{}", err),
         }
     }
@@ -551,8 +1159,9 @@ impl ChipLabelIs {
     pub fn deserialize(input: &[u8]) -> Result<Self> {
         let result = || -> nom::IResult<&[u8], Self> {
             let (remaining, (header, secondary_header)) = deserialize_headers(input)?;
+            let (remaining, bytes) = nom::combinator::rest(remaining)?;
             let chip_label = || -> Result<String> {
-                Ok(std::ffi::CStr::from_bytes_with_nul(remaining)?
+                Ok(std::ffi::CStr::from_bytes_with_nul(bytes)?
                     .to_str()?
                     .to_string())
             }();
@@ -567,7 +1176,62 @@ impl ChipLabelIs {
         };
 
         match result() {
-            Ok(tuple) => Ok(tuple.1),
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
+            Err(err) => bail!("{}", err),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+#[repr(C, packed)]
+pub struct GetFirmwareInfo {
+    header: Header<HostCmd>,
+    host_header: HostHeader,
+}
+impl Serializer for GetFirmwareInfo {}
+impl GetFirmwareInfo {
+    pub fn new(seq: &mut u8) -> Self {
+        let len = (std::mem::size_of::<Self>() - std::mem::size_of::<Header<HostCmd>>()) as u8;
+        Self {
+            header: Header::new(HostCmd::GetFirmwareInfo, len),
+            host_header: HostHeader::new(seq),
+        }
+    }
+}
+#[repr(C, packed)]
+pub struct FirmwareInfoIs {
+    header: Header<SecondaryCmd>,
+    secondary_header: SecondaryHeader,
+    pub firmware_info: Result<String>,
+}
+impl FirmwareInfoIs {
+    pub fn deserialize(input: &[u8]) -> Result<Self> {
+        let result = || -> nom::IResult<&[u8], Self> {
+            let (remaining, (header, secondary_header)) = deserialize_headers(input)?;
+            let (remaining, bytes) = nom::combinator::rest(remaining)?;
+            let firmware_info = || -> Result<String> {
+                Ok(std::ffi::CStr::from_bytes_with_nul(bytes)?
+                    .to_str()?
+                    .to_string())
+            }();
+            Ok((
+                remaining,
+                Self {
+                    header,
+                    secondary_header,
+                    firmware_info,
+                },
+            ))
+        };
+
+        match result() {
+            Ok((remaining, packet)) => {
+                check_len(input, remaining)?;
+                Ok(packet)
+            }
             Err(err) => bail!("{}", err),
         }
     }
@@ -632,6 +1296,57 @@ fn deserialize_header(input: &[u8]) -> nom::IResult<&[u8], Header<SecondaryCmd>>
     Ok((remaining, Header::new(cmd, len)))
 }
 
+// The length declared in a header disagrees with the payload, most likely a
+// corrupt link. Parsing such a packet would yield garbage values.
+#[derive(Error, Copy, Clone, Debug)]
+#[error("Malformed {cmd:?} packet (Declared length: {declared}, Actual: {actual})")]
+pub struct Malformed {
+    pub cmd: SecondaryCmd,
+    pub declared: u8,
+    pub actual: usize,
+}
+
+// `remaining` is what the parser left of `input`, the payload it consumed must
+// be exactly as long as declared and nothing may be left over
+fn check_len(input: &[u8], remaining: &[u8]) -> Result<(), Malformed> {
+    let header_len = std::mem::size_of::<Header<SecondaryCmd>>();
+    let declared = input[1];
+    let actual = input.len() - header_len - remaining.len();
+
+    if remaining.is_empty() && declared as usize == actual {
+        return Ok(());
+    }
+
+    Err(Malformed {
+        cmd: SecondaryCmd::try_from(input[0]).unwrap_or(SecondaryCmd::UnsupportedCmdIs),
+        declared,
+        actual,
+    })
+}
+
+// An unexpected value is kept as the error of its own element rather than
+// failing the whole reply
+fn deserialize_gpio_value(
+    input: &[u8],
+) -> nom::IResult<&[u8], Result<GpioValue, InvalidGpioValue>> {
+    let (remaining, value) = nom::number::complete::u8(input)?;
+    Ok((
+        remaining,
+        GpioValue::try_from(value).map_err(|_| InvalidGpioValue(value)),
+    ))
+}
+
+// An array of a batch reply, its count followed by the elements. The elements
+// carry their own Result so that a bad one leaves the others usable, only a
+// truncated array fails the reply.
+fn deserialize_elements<'a, T>(
+    input: &'a [u8],
+    element: impl FnMut(&'a [u8]) -> nom::IResult<&'a [u8], T>,
+) -> nom::IResult<&'a [u8], Vec<T>> {
+    let (remaining, count) = nom::number::complete::u8(input)?;
+    nom::multi::count(element, count as usize)(remaining)
+}
+
 fn deserialize_secondary_header(input: &[u8]) -> nom::IResult<&[u8], SecondaryHeader> {
     let (remaining, seq) = nom::number::complete::u8(input)?;
     Ok((remaining, SecondaryHeader::new(seq)))
@@ -0,0 +1,416 @@
+use super::*;
+
+// Serializes like a big-endian host that would not pin the wire byte order
+fn serialize_big_endian(packet: &impl Serializer) -> Vec<u8> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_big_endian()
+        .serialize(packet)
+        .unwrap()
+}
+
+fn assert_byte_order_free(packet: &impl Serializer) {
+    assert_eq!(
+        Serializer::serialize(packet).unwrap(),
+        serialize_big_endian(packet)
+    );
+}
+
+#[test]
+fn set_gpio_values_serialize() {
+    let mut seq = 4;
+    let packet = SetGpioValues::new(&mut seq, &[(1, GpioValue::High), (3, GpioValue::Low)])
+        .unwrap()
+        .serialize()
+        .unwrap();
+
+    assert_eq!(
+        packet,
+        vec![HostCmd::SetGpioValues as u8, 6, 5, 2, 1, 1, 3, 0]
+    );
+}
+
+#[test]
+fn set_gpio_values_too_many() {
+    let values = vec![(0, GpioValue::Low); SET_GPIO_VALUES_MAX + 1];
+
+    assert!(SetGpioValues::new(&mut 0, &values).is_err());
+}
+
+#[test]
+fn gpio_value_is_deserialize() {
+    let packet = [SecondaryCmd::GpioValueIs as u8, 2, 7, GpioValue::High as u8];
+
+    let packet = GpioValueIs::deserialize(&packet).unwrap();
+
+    assert_eq!(packet.secondary_header.seq, 7);
+    assert_eq!(packet.value.unwrap(), GpioValue::High);
+}
+
+#[test]
+fn gpio_value_is_deserialize_with_source() {
+    let packet = [
+        SecondaryCmd::GpioValueIs as u8,
+        3,
+        7,
+        GpioValue::Low as u8,
+        GpioValueSource::Sampled as u8,
+    ];
+
+    let packet = GpioValueIs::deserialize_with_source(&packet).unwrap();
+
+    assert_eq!(packet.value.unwrap(), GpioValue::Low);
+    assert_eq!(packet.source, Some(GpioValueSource::Sampled));
+}
+
+#[test]
+fn gpio_value_is_deserialize_reserved_source() {
+    let packet = [SecondaryCmd::GpioValueIs as u8, 3, 7, 1, 0xA5];
+
+    let packet = GpioValueIs::deserialize_with_source(&packet).unwrap();
+
+    assert_eq!(packet.value.unwrap(), GpioValue::High);
+    assert_eq!(packet.source, None);
+}
+
+#[test]
+fn gpio_value_is_deserialize_missing_source() {
+    let packet = [SecondaryCmd::GpioValueIs as u8, 2, 7, 1];
+
+    assert!(GpioValueIs::deserialize_with_source(&packet).is_err());
+}
+
+#[test]
+fn gpio_value_is_deserialize_invalid_value() {
+    let packet = [SecondaryCmd::GpioValueIs as u8, 2, 7, 0xA5];
+
+    let packet = GpioValueIs::deserialize(&packet).unwrap();
+
+    match packet.value {
+        Err(InvalidGpioValue(raw)) => assert_eq!(raw, 0xA5),
+        Ok(value) => panic!("Unexpected value: {:?}", value),
+    }
+}
+
+#[test]
+fn firmware_info_is_deserialize() {
+    let packet = [
+        [SecondaryCmd::FirmwareInfoIs as u8, 5, 9].as_slice(),
+        b"1.2\0",
+    ]
+    .concat();
+
+    let packet = FirmwareInfoIs::deserialize(&packet).unwrap();
+
+    assert_eq!(packet.secondary_header.seq, 9);
+    assert_eq!(packet.firmware_info.unwrap(), "1.2");
+}
+
+#[test]
+fn gpio_config_is_deserialize() {
+    let packet = [
+        SecondaryCmd::GpioConfigIs as u8,
+        2,
+        3,
+        GpioConfig::BiasPullUp as u8,
+    ];
+
+    let packet = GpioConfigIs::deserialize(&packet).unwrap();
+
+    assert_eq!(packet.secondary_header.seq, 3);
+    assert_eq!(packet.config, GpioConfig::BiasPullUp);
+}
+
+#[test]
+fn gpio_config_buffer_enables_round_trip() {
+    for config in [GpioConfig::InputEnable, GpioConfig::OutputEnable] {
+        let mut seq = 0;
+        let request = SetGpioConfig::new(&mut seq, 1, config).serialize().unwrap();
+        let reply = [SecondaryCmd::GpioConfigIs as u8, 2, seq, request[4]];
+
+        assert_eq!(GpioConfigIs::deserialize(&reply).unwrap().config, config);
+    }
+}
+
+#[test]
+fn gpio_config_is_deserialize_invalid_config() {
+    let packet = [SecondaryCmd::GpioConfigIs as u8, 2, 3, 0xA5];
+
+    assert!(GpioConfigIs::deserialize(&packet).is_err());
+}
+
+#[test]
+fn gpio_status_is_deserialize() {
+    let packet = [
+        SecondaryCmd::GpioStatusIs as u8,
+        2,
+        3,
+        GpioStatus::INPUT_ENABLED | GpioStatus::ERROR_LATCHED | 0x80,
+    ];
+
+    let packet = GpioStatusIs::deserialize(&packet).unwrap();
+    let status = packet.status;
+
+    assert!(!status.driven());
+    assert!(status.input_enabled());
+    assert!(status.error_latched());
+}
+
+#[test]
+fn gpio_status_is_deserialize_truncated() {
+    let packet = [SecondaryCmd::GpioStatusIs as u8, 1, 3];
+
+    assert!(GpioStatusIs::deserialize(&packet).is_err());
+}
+
+#[test]
+fn gpio_raw_is_deserialize() {
+    let packet = [SecondaryCmd::GpioRawIs as u8, 4, 7, 0xde, 0xad, 0xbe];
+
+    let packet = GpioRawIs::deserialize(&packet).unwrap();
+
+    assert_eq!(packet.secondary_header.seq, 7);
+    assert_eq!(packet.raw, vec![0xde, 0xad, 0xbe]);
+}
+
+fn malformed(result: Result<impl Sized>) -> Malformed {
+    match result {
+        Ok(_) => panic!("Unexpected success"),
+        Err(err) => *err
+            .downcast_ref::<Malformed>()
+            .unwrap_or_else(|| panic!("Unexpected error: {}", err)),
+    }
+}
+
+#[test]
+fn gpio_value_is_deserialize_trailing_bytes() {
+    let packet = [SecondaryCmd::GpioValueIs as u8, 3, 7, 1, 0];
+
+    let err = malformed(GpioValueIs::deserialize(&packet));
+
+    assert_eq!(err.declared, 3);
+    assert_eq!(err.actual, 2);
+}
+
+#[test]
+fn gpio_value_is_deserialize_short_declared_len() {
+    let packet = [SecondaryCmd::GpioValueIs as u8, 1, 7, 1];
+
+    let err = malformed(GpioValueIs::deserialize(&packet));
+
+    assert_eq!(err.declared, 1);
+    assert_eq!(err.actual, 2);
+}
+
+#[test]
+fn firmware_info_is_deserialize_truncated_payload() {
+    let packet = [
+        [SecondaryCmd::FirmwareInfoIs as u8, 8, 9].as_slice(),
+        b"1.2\0",
+    ]
+    .concat();
+
+    let err = malformed(FirmwareInfoIs::deserialize(&packet));
+
+    assert_eq!(err.declared, 8);
+    assert_eq!(err.actual, 5);
+}
+
+#[test]
+fn gpio_raw_is_deserialize_long_declared_len() {
+    let packet = [SecondaryCmd::GpioRawIs as u8, 6, 7, 0xde, 0xad, 0xbe];
+
+    let err = malformed(GpioRawIs::deserialize(&packet));
+
+    assert_eq!(err.declared, 6);
+    assert_eq!(err.actual, 4);
+}
+
+#[test]
+fn max_gpio_count_is_deserialize() {
+    let packet = [SecondaryCmd::MaxGpioCountIs as u8, 2, 6, 32];
+
+    let packet = MaxGpioCountIs::deserialize(&packet).unwrap();
+
+    assert_eq!(packet.secondary_header.seq, 6);
+    assert_eq!(packet.max_count, 32);
+}
+
+#[test]
+fn capabilities_is_deserialize() {
+    let packet = [
+        SecondaryCmd::CapabilitiesIs as u8,
+        5,
+        6,
+        0x05,
+        0x01,
+        0x00,
+        0x80,
+    ];
+
+    let packet = CapabilitiesIs::deserialize(&packet).unwrap();
+    let capabilities = packet.capabilities;
+
+    assert_eq!(packet.secondary_header.seq, 6);
+    assert_eq!(capabilities, Capabilities(0x8000_0105));
+    assert!(capabilities.has(Capabilities::FIRMWARE_INFO));
+    assert!(capabilities.has(Capabilities::GPIO_VALUE_SOURCE));
+    assert!(!capabilities.has(Capabilities::GET_GPIO_CONFIG));
+}
+
+#[test]
+fn unsupported_cmd_names_the_refused_request() {
+    assert_eq!(
+        unsupported_cmd(&[
+            SecondaryCmd::UnsupportedCmdIs as u8,
+            1,
+            HostCmd::GetGpioRaw as u8
+        ]),
+        Some(HostCmd::GetGpioRaw)
+    );
+    assert_eq!(
+        unsupported_cmd(&[SecondaryCmd::UnsupportedCmdIs as u8, 1, 0x7e]),
+        Some(HostCmd::UnknownCmd)
+    );
+
+    assert_eq!(
+        unsupported_cmd(&[SecondaryCmd::UnsupportedCmdIs as u8, 2, 12]),
+        None
+    );
+    assert_eq!(
+        unsupported_cmd(&[SecondaryCmd::StatusIs as u8, 1, 12]),
+        None
+    );
+}
+
+#[test]
+fn get_gpio_values_serialize() {
+    let packet = GetGpioValues::new(&mut 4, &[1, 3])
+        .unwrap()
+        .serialize()
+        .unwrap();
+
+    assert_eq!(packet, vec![HostCmd::GetGpioValues as u8, 4, 5, 2, 1, 3]);
+}
+
+#[test]
+fn get_gpio_values_too_many() {
+    let pins = vec![0; GET_GPIO_VALUES_MAX + 1];
+
+    assert!(GetGpioValues::new(&mut 0, &pins).is_err());
+}
+
+#[test]
+fn gpio_values_is_deserialize_bad_element() {
+    let packet = [
+        SecondaryCmd::GpioValuesIs as u8,
+        8,
+        7,
+        3,
+        0,
+        GpioValue::High as u8,
+        1,
+        0xA5,
+        2,
+        GpioValue::Low as u8,
+    ];
+
+    let packet = GpioValuesIs::deserialize(&packet).unwrap();
+
+    assert_eq!(packet.secondary_header.seq, 7);
+    assert!(matches!(packet.values[0], (0, Ok(GpioValue::High))));
+    assert!(matches!(packet.values[1], (1, Err(InvalidGpioValue(0xA5)))));
+    assert!(matches!(packet.values[2], (2, Ok(GpioValue::Low))));
+}
+
+#[test]
+fn gpio_values_is_deserialize_truncated_array() {
+    let packet = [SecondaryCmd::GpioValuesIs as u8, 4, 7, 2, 0, 1];
+
+    assert!(GpioValuesIs::deserialize(&packet).is_err());
+}
+
+#[test]
+fn host_packets_are_byte_order_free() {
+    // Host packets only carry bytes, hosts of either byte order send the same
+    assert_byte_order_free(&GetVersion::new());
+    assert_byte_order_free(&GetUniqueId::new(&mut 0));
+    assert_byte_order_free(&GetGpioValue::new(&mut 0, 3));
+    assert_byte_order_free(&SetGpioValue::new(&mut 0, 3, GpioValue::High));
+    assert_byte_order_free(&SetGpioConfig::new(&mut 0, 3, GpioConfig::DriveOpenDrain));
+    assert_byte_order_free(&SetGpioDirection::new(&mut 0, 3, GpioDirection::Output));
+    assert_byte_order_free(&GetGpioStatus::new(&mut 0, 3));
+    assert_byte_order_free(
+        &SetGpioValues::new(&mut 0, &[(1, GpioValue::High), (3, GpioValue::Low)]).unwrap(),
+    );
+    assert_byte_order_free(&GetGpioValues::new(&mut 0, &[1, 3]).unwrap());
+}
+
+#[test]
+fn unique_id_is_little_endian() {
+    let unique_id: u64 = 0x0102_0304_0506_0708;
+    let reply =
+        |bytes: [u8; 8]| [[SecondaryCmd::UniqueIdIs as u8, 9, 3].as_slice(), &bytes].concat();
+
+    let packet = UniqueIdIs::deserialize(&reply(unique_id.to_le_bytes())).unwrap();
+    assert_eq!({ packet.unique_id }, unique_id);
+
+    assert_eq!(
+        wire_options().serialize(&unique_id).unwrap(),
+        unique_id.to_le_bytes()
+    );
+
+    // Nothing tells a big-endian secondary apart, it is off spec and its id
+    // reads byte-swapped
+    let packet = UniqueIdIs::deserialize(&reply(unique_id.to_be_bytes())).unwrap();
+    assert_eq!({ packet.unique_id }, unique_id.swap_bytes());
+}
+
+#[test]
+fn seq_is_widened_and_narrowed() {
+    let mut seq = 4;
+    let packet = SetSeqWidth::new(&mut seq, 2).serialize().unwrap();
+    assert_eq!(packet, vec![HostCmd::SetSeqWidth as u8, 2, 5, 2]);
+
+    let wide = widen(&packet, 0x12).unwrap();
+    assert_eq!(wide, vec![HostCmd::SetSeqWidth as u8, 3, 5, 0x12, 2]);
+    assert_eq!(narrow(&wide).unwrap(), (packet, 0x12));
+
+    assert!(widen(&[HostCmd::GetGpioCount as u8, 0], 0).is_err());
+    assert!(narrow(&[SecondaryCmd::StatusIs as u8, 1, 5]).is_err());
+}
+
+#[test]
+fn version_carries_no_seq() {
+    let packet = GetVersion::new().serialize().unwrap();
+    assert!(!request_carries_seq(&packet));
+    assert!(request_carries_seq(
+        &GetGpioCount::new(&mut 0).serialize().unwrap()
+    ));
+
+    assert!(!reply_carries_seq(&[
+        SecondaryCmd::VersionIs as u8,
+        3,
+        1,
+        8,
+        0
+    ]));
+    assert!(!reply_carries_seq(&[
+        SecondaryCmd::UnsupportedCmdIs as u8,
+        1,
+        16
+    ]));
+    assert!(reply_carries_seq(&[SecondaryCmd::StatusIs as u8, 2, 5, 0]));
+}
+
+#[cfg(feature = "raw_request")]
+#[test]
+fn raw_reply_needs_header_and_unknown_cmd() {
+    assert!(is_raw_reply(&[0xee, 1, 7]));
+    assert!(is_raw_reply(&[0xee, 3, 7, 1, 2]));
+
+    assert!(!is_raw_reply(&[]));
+    assert!(!is_raw_reply(&[0xee, 0]));
+    assert!(!is_raw_reply(&[0xee, 2, 7]));
+    assert!(!is_raw_reply(&[SecondaryCmd::StatusIs as u8, 2, 7, 0]));
+}
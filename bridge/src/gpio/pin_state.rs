@@ -0,0 +1,132 @@
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use super::{packet, preset, PinInfo};
+
+// Last state of a pin acknowledged by the secondary, unknown until the bridge
+// sets or reads it
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PinState {
+    pub direction: Option<packet::GpioDirection>,
+    pub config: Option<packet::GpioConfig>,
+    pub input_enable: bool,
+    pub output_enable: bool,
+    pub value: Option<packet::GpioValue>,
+    // Last value written by the bridge, unlike value it survives reads and
+    // disabling the pin
+    pub commanded: Option<packet::GpioValue>,
+}
+
+// A pin as written to the state file, spelled like the init config
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct PinRecord {
+    pub pin: u8,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bank: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<preset::Direction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<preset::Config>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub input_enable: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub output_enable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<preset::Value>,
+}
+
+struct States {
+    // Bumped on every change, lets observers tell whether they are behind
+    generation: u64,
+    pins: Vec<PinState>,
+}
+
+pub struct PinStates {
+    states: Mutex<States>,
+    changed: Condvar,
+}
+
+impl PinStates {
+    pub fn new() -> Self {
+        Self {
+            states: Mutex::new(States {
+                generation: 0,
+                pins: vec![],
+            }),
+            changed: Condvar::new(),
+        }
+    }
+
+    pub fn update(&self, pin: u8, update: impl FnOnce(&mut PinState)) {
+        let mut states = self.lock();
+
+        if pin as usize >= states.pins.len() {
+            states.pins.resize(pin as usize + 1, PinState::default());
+        }
+
+        let state = &mut states.pins[pin as usize];
+        let previous = *state;
+        update(state);
+
+        if *state != previous {
+            states.generation += 1;
+            self.changed.notify_all();
+        }
+    }
+
+    pub fn get(&self, pin: u8) -> PinState {
+        self.lock()
+            .pins
+            .get(pin as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    // Returns the current generation once it moved past `generation`, or None
+    // on timeout
+    pub fn wait_change(&self, generation: u64, timeout: Duration) -> Option<u64> {
+        let states = self.lock();
+        let (states, _) = self
+            .changed
+            .wait_timeout_while(states, timeout, |states| states.generation == generation)
+            .unwrap_or_else(|err| err.into_inner());
+
+        (states.generation != generation).then_some(states.generation)
+    }
+
+    pub fn records(&self, pins: impl Iterator<Item = PinInfo>) -> Vec<PinRecord> {
+        let states = self.lock();
+
+        pins.map(|info| {
+            let state = states
+                .pins
+                .get(info.pin as usize)
+                .copied()
+                .unwrap_or_default();
+
+            PinRecord {
+                pin: info.pin,
+                name: info.name,
+                bank: None,
+                direction: state.direction.map(Into::into),
+                config: state.config.map(Into::into),
+                input_enable: state.input_enable,
+                output_enable: state.output_enable,
+                value: state.value.map(Into::into),
+            }
+        })
+        .collect()
+    }
+
+    // The states are plain data, a panic while holding the lock leaves them
+    // consistent
+    fn lock(&self) -> MutexGuard<'_, States> {
+        self.states.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
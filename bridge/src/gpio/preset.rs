@@ -0,0 +1,214 @@
+use anyhow::{bail, Context, Result};
+
+use super::packet;
+use crate::utils;
+
+#[derive(serde::Deserialize, serde::Serialize, Copy, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Direction {
+    Output,
+    Input,
+    Disabled,
+}
+impl From<Direction> for packet::GpioDirection {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Output => packet::GpioDirection::Output,
+            Direction::Input => packet::GpioDirection::Input,
+            Direction::Disabled => packet::GpioDirection::Disabled,
+        }
+    }
+}
+impl From<packet::GpioDirection> for Direction {
+    fn from(direction: packet::GpioDirection) -> Self {
+        match direction {
+            packet::GpioDirection::Output => Direction::Output,
+            packet::GpioDirection::Input => Direction::Input,
+            packet::GpioDirection::Disabled => Direction::Disabled,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Copy, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Config {
+    BiasDisable,
+    BiasPullDown,
+    BiasPullUp,
+    DriveOpenDrain,
+    DriveOpenSource,
+    DrivePushPull,
+    InputEnable,
+    OutputEnable,
+}
+impl From<Config> for packet::GpioConfig {
+    fn from(config: Config) -> Self {
+        match config {
+            Config::BiasDisable => packet::GpioConfig::BiasDisable,
+            Config::BiasPullDown => packet::GpioConfig::BiasPullDown,
+            Config::BiasPullUp => packet::GpioConfig::BiasPullUp,
+            Config::DriveOpenDrain => packet::GpioConfig::DriveOpenDrain,
+            Config::DriveOpenSource => packet::GpioConfig::DriveOpenSource,
+            Config::DrivePushPull => packet::GpioConfig::DrivePushPull,
+            Config::InputEnable => packet::GpioConfig::InputEnable,
+            Config::OutputEnable => packet::GpioConfig::OutputEnable,
+        }
+    }
+}
+impl From<utils::DefaultBias> for packet::GpioConfig {
+    fn from(bias: utils::DefaultBias) -> Self {
+        match bias {
+            utils::DefaultBias::Disable => packet::GpioConfig::BiasDisable,
+            utils::DefaultBias::PullDown => packet::GpioConfig::BiasPullDown,
+            utils::DefaultBias::PullUp => packet::GpioConfig::BiasPullUp,
+        }
+    }
+}
+impl From<packet::GpioConfig> for Config {
+    fn from(config: packet::GpioConfig) -> Self {
+        match config {
+            packet::GpioConfig::BiasDisable => Config::BiasDisable,
+            packet::GpioConfig::BiasPullDown => Config::BiasPullDown,
+            packet::GpioConfig::BiasPullUp => Config::BiasPullUp,
+            packet::GpioConfig::DriveOpenDrain => Config::DriveOpenDrain,
+            packet::GpioConfig::DriveOpenSource => Config::DriveOpenSource,
+            packet::GpioConfig::DrivePushPull => Config::DrivePushPull,
+            packet::GpioConfig::InputEnable => Config::InputEnable,
+            packet::GpioConfig::OutputEnable => Config::OutputEnable,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Copy, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Value {
+    Low,
+    High,
+}
+impl From<Value> for packet::GpioValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Low => packet::GpioValue::Low,
+            Value::High => packet::GpioValue::High,
+        }
+    }
+}
+impl From<packet::GpioValue> for Value {
+    fn from(value: packet::GpioValue) -> Self {
+        match value {
+            packet::GpioValue::Low => Value::Low,
+            packet::GpioValue::High => Value::High,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    pub pin: u8,
+    pub direction: Direction,
+    pub config: Option<Config>,
+    pub value: Option<Value>,
+}
+
+// Named group of pins, bridge-side metadata that never reaches the secondary
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Bank {
+    pub name: String,
+    pub pins: Vec<u8>,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Presets {
+    #[serde(default)]
+    pub pins: Vec<Preset>,
+    #[serde(default)]
+    pub banks: Vec<Bank>,
+}
+
+impl Presets {
+    pub fn load(path: &std::path::Path) -> Result<Self> {
+        let presets = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read init config ({})", path.display()))?;
+
+        toml::from_str(&presets)
+            .with_context(|| format!("Failed to parse init config ({})", path.display()))
+    }
+
+    pub fn validate(&self, gpio_count: u8) -> Result<()> {
+        let mut pins = vec![];
+
+        for preset in &self.pins {
+            if preset.pin >= gpio_count {
+                bail!(
+                    "Init config pin {} is out of range (GPIO count: {})",
+                    preset.pin,
+                    gpio_count
+                );
+            }
+
+            if pins.contains(&preset.pin) {
+                bail!("Init config pin {} is specified more than once", preset.pin);
+            }
+
+            pins.push(preset.pin);
+        }
+
+        self.validate_banks(gpio_count)
+    }
+
+    // A pin belongs to one bank at most
+    fn validate_banks(&self, gpio_count: u8) -> Result<()> {
+        let mut names: Vec<&str> = vec![];
+        let mut banked: Vec<(u8, &str)> = vec![];
+
+        for bank in &self.banks {
+            if bank.name.is_empty() {
+                bail!("Init config bank name cannot be empty");
+            }
+
+            if names.contains(&bank.name.as_str()) {
+                bail!(
+                    "Init config bank {:?} is specified more than once",
+                    bank.name
+                );
+            }
+
+            names.push(&bank.name);
+
+            for pin in &bank.pins {
+                if *pin >= gpio_count {
+                    bail!(
+                        "Init config bank {:?} pin {} is out of range (GPIO count: {})",
+                        bank.name,
+                        pin,
+                        gpio_count
+                    );
+                }
+
+                if let Some((_, other)) = banked.iter().find(|(banked, _)| banked == pin) {
+                    bail!(
+                        "Init config pin {} is in more than one bank ({:?}, {:?})",
+                        pin,
+                        other,
+                        bank.name
+                    );
+                }
+
+                banked.push((*pin, &bank.name));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, pin: u8) -> Option<&Preset> {
+        self.pins.iter().find(|preset| preset.pin == pin)
+    }
+
+    pub fn bank_of(&self, pin: u8) -> Option<&Bank> {
+        self.banks.iter().find(|bank| bank.pins.contains(&pin))
+    }
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+// Stats are logged every time the ring is refilled
+const RTT_SAMPLES: usize = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RttStats {
+    pub samples: usize,
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    pub p99: Duration,
+}
+
+impl std::fmt::Display for RttStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Samples: {}, Min: {:?}, Avg: {:?}, Max: {:?}, P99: {:?}",
+            self.samples, self.min, self.avg, self.max, self.p99
+        )
+    }
+}
+
+impl RttStats {
+    pub fn from_samples(samples: &[Duration]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_unstable();
+
+        let p99 = (sorted.len() * 99).div_ceil(100) - 1;
+
+        Some(Self {
+            samples: sorted.len(),
+            min: sorted[0],
+            avg: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            max: sorted[sorted.len() - 1],
+            p99: sorted[p99],
+        })
+    }
+}
+
+// Round-trip times of the most recent request/reply pairs
+pub struct Rtt {
+    samples: Vec<Duration>,
+    next: usize,
+}
+
+impl Rtt {
+    pub fn new() -> Self {
+        Self {
+            samples: Vec::with_capacity(RTT_SAMPLES),
+            next: 0,
+        }
+    }
+
+    pub fn record(&mut self, rtt: Duration) {
+        if self.samples.len() < RTT_SAMPLES {
+            self.samples.push(rtt);
+        } else {
+            self.samples[self.next] = rtt;
+        }
+
+        self.next = (self.next + 1) % RTT_SAMPLES;
+
+        if self.next == 0 {
+            if let Some(stats) = self.stats() {
+                log::debug!("Link RTT {{ {} }}", stats);
+            }
+        }
+    }
+
+    pub fn stats(&self) -> Option<RttStats> {
+        RttStats::from_samples(&self.samples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_of_empty_ring_are_none() {
+        assert_eq!(Rtt::new().stats(), None);
+    }
+
+    #[test]
+    fn stats_cover_only_the_latest_samples() {
+        let mut rtt = Rtt::new();
+
+        // Overwritten once the ring wraps
+        for _ in 0..RTT_SAMPLES {
+            rtt.record(Duration::from_millis(1000));
+        }
+
+        for ms in 1..=100 {
+            rtt.record(Duration::from_millis(ms));
+        }
+
+        let stats = rtt.stats().unwrap();
+
+        assert_eq!(stats.samples, RTT_SAMPLES);
+        assert_eq!(stats.min, Duration::from_millis(1));
+        assert_eq!(stats.max, Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn stats_report_p99() {
+        let mut rtt = Rtt::new();
+
+        for ms in 1..=100 {
+            rtt.record(Duration::from_millis(ms));
+        }
+
+        let stats = rtt.stats().unwrap();
+
+        assert_eq!(stats.samples, 100);
+        assert_eq!(stats.min, Duration::from_millis(1));
+        assert_eq!(stats.avg, Duration::from_micros(50500));
+        assert_eq!(stats.max, Duration::from_millis(100));
+        assert_eq!(stats.p99, Duration::from_millis(99));
+    }
+}
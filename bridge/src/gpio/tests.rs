@@ -0,0 +1,1732 @@
+use clap::Parser;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::*;
+
+const REQUEST_COUNT: usize = 2000;
+const THREAD_COUNT: usize = 4;
+
+struct Recorder {
+    interface: Box<GpioTraits>,
+    seqs: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Gpio for Recorder {
+    fn write(&self, bytes: &[u8]) -> Result<(), Error> {
+        if bytes[0] != packet::HostCmd::GetVersion as u8 {
+            self.seqs.lock().unwrap().push(bytes[2]);
+        }
+        self.interface.write(bytes)
+    }
+
+    fn read(&self) -> Result<Vec<u8>, Error> {
+        self.interface.read()
+    }
+}
+
+// Command and pin of the pin requests sent
+type Sent = Arc<Mutex<Vec<(u8, u8)>>>;
+
+// Records the pin requests sent while enabled
+struct Commands {
+    interface: Box<GpioTraits>,
+    enabled: Arc<AtomicBool>,
+    sent: Sent,
+}
+
+impl Gpio for Commands {
+    fn write(&self, bytes: &[u8]) -> Result<(), Error> {
+        if self.enabled.load(Ordering::SeqCst) {
+            self.sent.lock().unwrap().push((bytes[0], bytes[3]));
+        }
+        self.interface.write(bytes)
+    }
+
+    fn read(&self) -> Result<Vec<u8>, Error> {
+        self.interface.read()
+    }
+}
+
+// Faults injected by Faulty between the bridge and the mock secondary, each
+// one off until set
+#[derive(Default)]
+struct Faults {
+    // Delays every reply
+    delay_ms: AtomicU64,
+    // Stale-seq copies preceding every reply carrying a seq
+    mismatches: AtomicUsize,
+    // Sends the next reply twice, like a firmware double-reply
+    doubled: AtomicBool,
+    // Garbage frames of an unknown command preceding the next reply, too
+    // short to carry a seq
+    garbage: AtomicUsize,
+    // Takes one from the high byte of the two-byte seq of the replies, as if
+    // they answered requests from before the seq wrapped around
+    stale_epoch: AtomicBool,
+    // Replies with a stale copy stale_ms after the secondary, then with the
+    // reply itself reply_ms later, or never when reply_ms is u64::MAX
+    stale_ms: AtomicU64,
+    reply_ms: AtomicU64,
+    // Replaces the unique id reported while not 0, like a firmware update
+    unique_id: AtomicU64,
+    // Swaps every two consecutive name replies, which only completes with at
+    // least two name requests in flight
+    reorder_names: bool,
+    // Never replies to the name request of this pin
+    nameless_pin: Option<u8>,
+    // Frames written
+    frames: Mutex<Vec<Vec<u8>>>,
+}
+
+struct Faulty {
+    interface: Box<GpioTraits>,
+    faults: Arc<Faults>,
+    // Reply held back by reply_ms or reorder_names
+    pending: Mutex<Option<(Vec<u8>, u64)>>,
+}
+
+impl Gpio for Faulty {
+    fn write(&self, bytes: &[u8]) -> Result<(), Error> {
+        self.faults.frames.lock().unwrap().push(bytes.to_vec());
+
+        if bytes[0] == packet::HostCmd::GetGpioName as u8
+            && Some(bytes[3]) == self.faults.nameless_pin
+        {
+            return Ok(());
+        }
+        self.interface.write(bytes)
+    }
+
+    fn read(&self) -> Result<Vec<u8>, Error> {
+        let faults = &self.faults;
+
+        if let Some((bytes, delay_ms)) = self.pending.lock().unwrap().take() {
+            std::thread::sleep(std::time::Duration::from_millis(delay_ms));
+            return Ok(bytes);
+        }
+
+        let mut bytes = self.interface.read()?;
+
+        if faults.reorder_names && bytes[0] == packet::SecondaryCmd::GpioNameIs as u8 {
+            let next = self.interface.read()?;
+            *self.pending.lock().unwrap() = Some((bytes, 0));
+            bytes = next;
+        }
+
+        let unique_id = faults.unique_id.load(Ordering::SeqCst);
+        if bytes[0] == packet::SecondaryCmd::UniqueIdIs as u8 && unique_id != 0 {
+            bytes[3..11].copy_from_slice(&unique_id.to_le_bytes());
+        }
+
+        if faults.stale_epoch.load(Ordering::SeqCst) && packet::reply_carries_seq(&bytes) {
+            bytes[3] = bytes[3].wrapping_sub(1);
+        }
+
+        std::thread::sleep(std::time::Duration::from_millis(
+            faults.delay_ms.load(Ordering::SeqCst),
+        ));
+
+        let stale_ms = faults.stale_ms.load(Ordering::SeqCst);
+        if stale_ms != 0 {
+            std::thread::sleep(std::time::Duration::from_millis(stale_ms));
+
+            let mut stale = bytes.clone();
+            stale[2] = stale[2].wrapping_sub(1);
+
+            let reply_ms = faults.reply_ms.load(Ordering::SeqCst);
+            if reply_ms != u64::MAX {
+                *self.pending.lock().unwrap() = Some((bytes, reply_ms));
+            }
+
+            return Ok(stale);
+        }
+
+        if faults.doubled.swap(false, Ordering::SeqCst) {
+            bytes = bytes.repeat(2);
+        }
+
+        let mismatches = faults.mismatches.load(Ordering::SeqCst);
+        if mismatches != 0 && bytes[0] != packet::SecondaryCmd::VersionIs as u8 {
+            let mut stale = bytes.clone();
+            stale[2] = stale[2].wrapping_sub(1);
+
+            let mut buffer = stale.repeat(mismatches);
+            buffer.extend(bytes);
+            bytes = buffer;
+        }
+
+        let mut buffer = [0xee, 0x00].repeat(faults.garbage.swap(0, Ordering::SeqCst));
+        buffer.extend(bytes);
+        Ok(buffer)
+    }
+}
+
+// Reports when the transport is dropped, i.e. once the reader released it
+struct Dropped {
+    interface: Box<GpioTraits>,
+    dropped: Arc<AtomicBool>,
+}
+
+impl Gpio for Dropped {
+    fn write(&self, bytes: &[u8]) -> Result<(), Error> {
+        self.interface.write(bytes)
+    }
+
+    fn read(&self) -> Result<Vec<u8>, Error> {
+        self.interface.read()
+    }
+
+    fn close(&self) {
+        self.interface.close()
+    }
+}
+
+impl Drop for Dropped {
+    fn drop(&mut self) {
+        self.dropped.store(true, Ordering::SeqCst);
+    }
+}
+
+fn parse_config(args: &[&str]) -> utils::Config {
+    utils::Config::parse_from(
+        ["cpc-gpio-bridge", "--instance", "1", "--transport", "mock"]
+            .iter()
+            .chain(args.iter()),
+    )
+}
+
+fn try_new_handle(args: &[&str]) -> Result<(Handle, Arc<Mutex<Vec<u8>>>)> {
+    let config = parse_config(args);
+    let trace_config = utils::trace(&config);
+    let seqs = Arc::new(Mutex::new(vec![]));
+
+    let recorder = Recorder {
+        interface: interface::new(&config, &trace_config)?,
+        seqs: seqs.clone(),
+    };
+
+    let handle = Handle::with_interface(&config, &preset::Presets::default(), Box::new(recorder))?;
+
+    Ok((handle, seqs))
+}
+
+fn new_handle_with_args(args: &[&str]) -> (Handle, Arc<Mutex<Vec<u8>>>) {
+    try_new_handle(args).unwrap()
+}
+
+fn new_handle() -> (Handle, Arc<Mutex<Vec<u8>>>) {
+    new_handle_with_args(&[])
+}
+
+fn try_new_faulty_handle(args: &[&str], faults: Faults) -> Result<(Handle, Arc<Faults>)> {
+    let config = parse_config(args);
+    let trace_config = utils::trace(&config);
+    let faults = Arc::new(faults);
+
+    let faulty = Faulty {
+        interface: interface::new(&config, &trace_config)?,
+        faults: faults.clone(),
+        pending: Mutex::new(None),
+    };
+
+    let handle = Handle::with_interface(&config, &preset::Presets::default(), Box::new(faulty))?;
+
+    Ok((handle, faults))
+}
+
+fn new_faulty_handle(args: &[&str]) -> (Handle, Arc<Faults>) {
+    try_new_faulty_handle(args, Faults::default()).unwrap()
+}
+
+fn new_commands_handle(args: &[&str]) -> (Handle, Arc<AtomicBool>, Sent) {
+    let config = parse_config(args);
+    let trace_config = utils::trace(&config);
+    let enabled = Arc::new(AtomicBool::new(false));
+    let sent = Arc::new(Mutex::new(vec![]));
+
+    let commands = Commands {
+        interface: interface::new(&config, &trace_config).unwrap(),
+        enabled: enabled.clone(),
+        sent: sent.clone(),
+    };
+
+    let handle =
+        Handle::with_interface(&config, &preset::Presets::default(), Box::new(commands)).unwrap();
+
+    (handle, enabled, sent)
+}
+
+fn pin_config(
+    pin: u8,
+    config: Option<GpioConfig>,
+    value: Option<GpioValue>,
+    direction: Option<GpioDirection>,
+) -> chip_config::PinConfig {
+    chip_config::PinConfig {
+        pin,
+        config,
+        value,
+        direction,
+    }
+}
+
+fn assert_gap_free(seqs: &[u8]) {
+    assert!(seqs.len() > u8::MAX as usize);
+    assert_eq!(seqs[0], 1);
+
+    for window in seqs.windows(2) {
+        assert_eq!(window[1], window[0].wrapping_add(1), "{:?}", window);
+    }
+
+    assert!(seqs.windows(2).any(|window| window == [u8::MAX, 0]));
+}
+
+#[test]
+fn seq_is_monotonic() {
+    let (handle, seqs) = new_handle();
+    let gpio_count = handle.chip.gpio_names.len();
+
+    for i in 0..REQUEST_COUNT {
+        let pin = (i % gpio_count) as u8;
+        if i % 2 == 0 {
+            handle.get_gpio_value(pin).unwrap();
+        } else {
+            handle.set_gpio_value(pin, GpioValue::High).unwrap();
+        }
+    }
+
+    assert_gap_free(&seqs.lock().unwrap());
+}
+
+#[test]
+fn seq_is_monotonic_across_threads() {
+    let (handle, seqs) = new_handle();
+    let handle = Arc::new(handle);
+    let gpio_count = handle.chip.gpio_names.len();
+
+    let threads: Vec<_> = (0..THREAD_COUNT)
+        .map(|thread| {
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                for i in 0..REQUEST_COUNT / THREAD_COUNT {
+                    let pin = ((thread + i) % gpio_count) as u8;
+                    handle.get_gpio_value(pin).unwrap();
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_gap_free(&seqs.lock().unwrap());
+}
+
+#[test]
+fn gpio_count_override() {
+    let (handle, _) = new_handle_with_args(&["--gpio-count", "4"]);
+
+    assert_eq!(handle.chip.gpio_names.len(), 4);
+    assert!(handle.get_gpio_value(3).is_ok());
+    assert!(matches!(
+        handle.get_gpio_value(4),
+        Err(Error::Recoverable(RecoverableError::InvalidPin(4)))
+    ));
+}
+
+#[test]
+fn gpio_count_zero() {
+    let err = match try_new_handle(&["--mock-gpio-count", "0"]) {
+        Ok(_) => panic!("Handle created without GPIO's"),
+        Err(err) => err.to_string(),
+    };
+
+    assert!(err.contains("reported no GPIO's"), "{}", err);
+    assert!(err.contains("UID: 1"), "{}", err);
+    assert!(err.contains("mock-1-label"), "{}", err);
+}
+
+#[test]
+fn cmd_timeout_defaults() {
+    let (handle, _) = new_handle();
+
+    assert_eq!(
+        handle.timeout_ms(HostCmd::GetGpioValue),
+        SLOW_READ_TIMEOUT_MS
+    );
+    assert_eq!(
+        handle.timeout_ms(HostCmd::SetGpioDirection),
+        READ_TIMEOUT_MS
+    );
+}
+
+#[test]
+fn cmd_timeout_per_cmd() {
+    let (handle, faults) = new_faulty_handle(&[
+        "--cmd-timeout",
+        "get-gpio-value=1000",
+        "--cmd-timeout",
+        "set-gpio-direction=100",
+    ]);
+
+    faults.delay_ms.store(300, Ordering::SeqCst);
+
+    assert!(handle.get_gpio_value(0).is_ok());
+
+    let now = std::time::Instant::now();
+    assert!(matches!(
+        handle.set_gpio_direction(0, GpioDirection::Input),
+        Err(Error::Recoverable(RecoverableError::Timeout(..)))
+    ));
+    assert!(now.elapsed() < std::time::Duration::from_millis(300));
+}
+
+#[test]
+fn per_call_timeout_overrides_the_configured_one() {
+    let (handle, faults) = new_faulty_handle(&["--cmd-timeout", "get-gpio-value=1000"]);
+
+    faults.delay_ms.store(300, Ordering::SeqCst);
+
+    let now = std::time::Instant::now();
+    assert!(matches!(
+        handle.get_gpio_value_timeout(0, Some(std::time::Duration::from_millis(100))),
+        Err(Error::Recoverable(RecoverableError::Timeout(..)))
+    ));
+    assert!(now.elapsed() < std::time::Duration::from_millis(300));
+
+    assert!(matches!(
+        handle.get_gpio_values_timeout(&[0, 1], Some(std::time::Duration::from_millis(100))),
+        Err(Error::Recoverable(RecoverableError::Timeout(..)))
+    ));
+
+    // Unspecified, the configured timeout applies
+    assert!(handle.get_gpio_value_timeout(0, None).is_ok());
+}
+
+#[test]
+fn per_call_timeout_outlasts_the_configured_one() {
+    let (handle, faults) = new_faulty_handle(&["--cmd-timeout", "get-gpio-value=100"]);
+
+    faults.delay_ms.store(300, Ordering::SeqCst);
+
+    assert!(handle
+        .get_gpio_value_timeout(0, Some(std::time::Duration::from_millis(1000)))
+        .is_ok());
+}
+
+#[test]
+fn capped_timeout_keeps_shorter_timeouts() {
+    let cap = std::time::Duration::from_millis(2000);
+
+    let (handle, _) = new_handle();
+    assert_eq!(handle.capped_timeout(HostCmd::GetGpioValue, cap), cap);
+    assert_eq!(handle.capped_timeout(HostCmd::SetGpioValue, cap), cap);
+
+    let (handle, _) = new_handle_with_args(&["--cmd-timeout", "get-gpio-value=100"]);
+    assert_eq!(
+        handle.capped_timeout(HostCmd::GetGpioValue, cap),
+        std::time::Duration::from_millis(100)
+    );
+}
+
+#[test]
+fn timeout_names_the_transport() {
+    let (handle, faults) = new_faulty_handle(&["--cmd-timeout", "get-gpio-value=100"]);
+
+    faults.delay_ms.store(300, Ordering::SeqCst);
+
+    let err = match handle.get_gpio_value(0) {
+        Err(err) => err,
+        Ok(_) => panic!("The request did not time out"),
+    };
+    match &err {
+        Error::Recoverable(RecoverableError::Timeout(_, _, transport)) => assert_eq!(
+            *transport,
+            Descriptor::Mock {
+                instance: "1".to_string()
+            }
+        ),
+        err => panic!("{}", err),
+    }
+    assert!(err.to_string().ends_with("Transport: Mock(1))"), "{}", err);
+}
+
+#[test]
+fn cmd_timeout_rejects_invalid() {
+    for arg in ["get-gpio-value", "unknown-cmd=100", "get-gpio-value=0"] {
+        assert!(
+            utils::Config::try_parse_from([
+                "cpc-gpio-bridge",
+                "--instance",
+                "1",
+                "--cmd-timeout",
+                arg
+            ])
+            .is_err(),
+            "{}",
+            arg
+        );
+    }
+}
+
+#[test]
+fn seq_mismatches_below_limit() {
+    let (handle, faults) = new_faulty_handle(&["--max-seq-mismatches", "4"]);
+
+    faults.mismatches.store(3, Ordering::SeqCst);
+
+    assert!(handle.get_gpio_value(0).is_ok());
+}
+
+#[test]
+fn seq_mismatch_storm_aborts() {
+    let (handle, faults) = new_faulty_handle(&["--max-seq-mismatches", "4"]);
+
+    faults.mismatches.store(4, Ordering::SeqCst);
+
+    let now = std::time::Instant::now();
+    assert!(matches!(
+        handle.get_gpio_value(0),
+        Err(Error::Recoverable(RecoverableError::Desync(4)))
+    ));
+    assert!(now.elapsed().as_millis() < SLOW_READ_TIMEOUT_MS);
+}
+
+#[test]
+fn duplicate_reply_is_flagged() {
+    let (handle, faults) = new_faulty_handle(&[]);
+
+    faults.doubled.store(true, Ordering::SeqCst);
+    handle.set_gpio_value(0, GpioValue::High).unwrap();
+
+    // The second copy is only read while waiting for the next reply
+    let counters = handle.link_counters();
+    assert!(handle.get_gpio_value(0).is_ok());
+
+    let after = handle.link_counters();
+    assert_eq!(after.duplicate_replies, counters.duplicate_replies + 1);
+    assert_eq!(after.errors, counters.errors);
+}
+
+#[test]
+fn stale_replies_are_not_duplicates() {
+    let (handle, faults) = new_faulty_handle(&["--max-seq-mismatches", "4"]);
+
+    faults.mismatches.store(3, Ordering::SeqCst);
+    assert!(handle.get_gpio_value(0).is_ok());
+
+    // Only the first copy repeats the seq of a reply just accepted
+    assert_eq!(handle.link_counters().duplicate_replies, 1);
+}
+
+#[test]
+fn seq_mismatch_keeps_the_deadline() {
+    let (handle, faults) = new_faulty_handle(&["--cmd-timeout", "get-gpio-value=300"]);
+
+    // The reply lands 400 ms in, past the deadline but within a timeout
+    // restarted by the stale reply
+    faults.stale_ms.store(200, Ordering::SeqCst);
+    faults.reply_ms.store(200, Ordering::SeqCst);
+
+    let now = std::time::Instant::now();
+    assert!(matches!(
+        handle.get_gpio_value(0),
+        Err(Error::Recoverable(RecoverableError::Timeout(..)))
+    ));
+    assert!(now.elapsed().as_millis() >= 300);
+}
+
+#[test]
+fn seq_mismatch_then_reply_before_the_deadline() {
+    let (handle, faults) = new_faulty_handle(&["--cmd-timeout", "get-gpio-value=1000"]);
+
+    faults.stale_ms.store(200, Ordering::SeqCst);
+    faults.reply_ms.store(200, Ordering::SeqCst);
+
+    assert!(handle.get_gpio_value(0).is_ok());
+}
+
+#[test]
+fn value_source_follows_the_direction() {
+    let (handle, _) = new_handle();
+
+    handle.set_gpio_direction(0, GpioDirection::Input).unwrap();
+    handle.set_gpio_direction(1, GpioDirection::Output).unwrap();
+
+    assert_eq!(
+        handle.get_gpio_value(0).unwrap().source,
+        Some(GpioValueSource::Sampled)
+    );
+    assert_eq!(
+        handle.get_gpio_value(1).unwrap().source,
+        Some(GpioValueSource::Driven)
+    );
+}
+
+#[test]
+fn value_source_is_inferred_from_older_secondaries() {
+    let (handle, _) = new_handle_with_args(&["--mock-unsupported-cmd", "get-capabilities"]);
+
+    assert_eq!(handle.get_gpio_value(0).unwrap().source, None);
+
+    handle.set_gpio_direction(0, GpioDirection::Input).unwrap();
+    handle.set_gpio_direction(1, GpioDirection::Output).unwrap();
+
+    assert_eq!(
+        handle.get_gpio_value(0).unwrap().source,
+        Some(GpioValueSource::Sampled)
+    );
+    assert_eq!(
+        handle.get_gpio_value(1).unwrap().source,
+        Some(GpioValueSource::Driven)
+    );
+}
+
+#[test]
+fn drain_waits_for_the_request_in_flight() {
+    let (handle, faults) = new_faulty_handle(&[]);
+    faults.delay_ms.store(200, Ordering::SeqCst);
+
+    std::thread::scope(|scope| {
+        let in_flight = scope.spawn(|| handle.get_gpio_value(0));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        let now = std::time::Instant::now();
+        assert!(handle.drain(std::time::Duration::from_millis(1000)));
+        assert!(now.elapsed().as_millis() >= 100);
+
+        assert!(in_flight.join().unwrap().is_ok());
+    });
+
+    assert!(matches!(
+        handle.get_gpio_value(0),
+        Err(Error::Recoverable(RecoverableError::ShuttingDown))
+    ));
+}
+
+#[test]
+fn drain_gives_up_at_the_timeout() {
+    let (handle, faults) = new_faulty_handle(&[]);
+    faults.delay_ms.store(300, Ordering::SeqCst);
+
+    std::thread::scope(|scope| {
+        let in_flight = scope.spawn(|| handle.get_gpio_value(0));
+        std::thread::sleep(std::time::Duration::from_millis(50));
+
+        assert!(!handle.drain(std::time::Duration::from_millis(50)));
+
+        assert!(in_flight.join().unwrap().is_ok());
+    });
+}
+
+#[test]
+fn unsupported_cmd_fails_the_request() {
+    let (handle, _) = new_handle_with_args(&[
+        "--mock-unsupported-cmd",
+        "get-gpio-config",
+        "--cmd-timeout",
+        "get-gpio-config=10000",
+    ]);
+
+    let now = std::time::Instant::now();
+    assert!(matches!(
+        handle.get_gpio_config(0),
+        Err(Error::Recoverable(RecoverableError::Packet {
+            status: Status::NotSupported,
+            cmd: HostCmd::GetGpioConfig,
+            pin: Some(0),
+        }))
+    ));
+    assert!(now.elapsed() < std::time::Duration::from_millis(10000));
+
+    assert!(matches!(
+        handle.diagnostics().as_slice(),
+        [Diagnostic::UnsupportedCmd {
+            cmd: HostCmd::GetGpioConfig,
+            ..
+        }]
+    ));
+
+    // The unsupported reply carries no seq, the link stays in sync
+    assert!(handle.get_gpio_value(0).is_ok());
+}
+
+#[test]
+fn capabilities_gate_the_optional_commands() {
+    let (handle, seqs) = new_handle_with_args(&["--mock-unsupported-cmd", "get-capabilities"]);
+
+    assert_eq!(handle.capabilities, packet::Capabilities::default());
+    assert_eq!(handle.chip.firmware_info, None);
+
+    // Refused without a request reaching the secondary
+    let sent = seqs.lock().unwrap().len();
+    assert!(matches!(
+        handle.get_gpio_config(0),
+        Err(Error::Recoverable(RecoverableError::Packet {
+            status: Status::NotSupported,
+            ..
+        }))
+    ));
+    assert!(matches!(
+        handle.get_gpio_values_timeout(&[0, 1], None),
+        Err(Error::Recoverable(RecoverableError::Packet {
+            status: Status::NotSupported,
+            ..
+        }))
+    ));
+    assert_eq!(seqs.lock().unwrap().len(), sent);
+}
+
+#[test]
+fn gpio_config_round_trip() {
+    let (handle, _) = new_handle();
+
+    handle.set_gpio_config(0, GpioConfig::BiasPullUp).unwrap();
+    assert_eq!(handle.get_gpio_config(0).unwrap(), GpioConfig::BiasPullUp);
+
+    handle
+        .set_gpio_config(0, GpioConfig::DriveOpenDrain)
+        .unwrap();
+    assert_eq!(
+        handle.get_gpio_config(0).unwrap(),
+        GpioConfig::DriveOpenDrain
+    );
+
+    // A buffer enable leaves the drive mode read back as it is
+    handle.set_gpio_config(0, GpioConfig::OutputEnable).unwrap();
+    assert_eq!(
+        handle.get_gpio_config(0).unwrap(),
+        GpioConfig::DriveOpenDrain
+    );
+    assert!(handle.pin_states.get(0).output_enable);
+}
+
+#[test]
+fn buffer_enables_need_the_capability() {
+    let (handle, _) = new_handle_with_args(&["--mock-unsupported-cmd", "get-capabilities"]);
+
+    assert!(matches!(
+        handle.set_gpio_config(0, GpioConfig::InputEnable),
+        Err(Error::Recoverable(RecoverableError::Packet {
+            status: Status::NotSupported,
+            cmd: HostCmd::SetGpioConfig,
+            pin: Some(0),
+        }))
+    ));
+    assert!(!handle.pin_states.get(0).input_enable);
+
+    assert!(handle.set_gpio_config(0, GpioConfig::BiasPullUp).is_ok());
+}
+
+#[test]
+fn capabilities_are_read_at_discovery() {
+    let (handle, _) = new_handle();
+
+    assert!(handle.has(packet::Capabilities::GET_GPIO_CONFIG));
+    assert!(handle.chip.firmware_info.is_some());
+    assert!(handle.get_gpio_config(0).is_ok());
+}
+
+#[test]
+fn desync_resyncs_the_link() {
+    let (handle, faults) = new_faulty_handle(&["--max-seq-mismatches", "1"]);
+
+    faults.mismatches.store(1, Ordering::SeqCst);
+    assert!(matches!(
+        handle.get_gpio_value(0),
+        Err(Error::Recoverable(RecoverableError::Desync(1)))
+    ));
+
+    // The reply to the aborted request was discarded, it does not count as a
+    // mismatch of the next one
+    faults.mismatches.store(0, Ordering::SeqCst);
+    assert!(handle.get_gpio_value(0).is_ok());
+}
+
+#[test]
+fn malformed_frames_resync_the_link() {
+    let (handle, faults) = new_faulty_handle(&[]);
+
+    faults.garbage.store(3, Ordering::SeqCst);
+    assert!(handle.get_gpio_value(0).is_ok());
+    assert_eq!(handle.link.malformed_frames(), 0);
+
+    let (handle, faults) = new_faulty_handle(&["--resync-after-malformed", "0"]);
+
+    faults.garbage.store(3, Ordering::SeqCst);
+    assert!(handle.get_gpio_value(0).is_ok());
+    assert_eq!(handle.link.malformed_frames(), 3);
+}
+
+#[test]
+fn resync_on_demand() {
+    let (handle, _) = new_handle();
+
+    assert!(handle.resync().is_ok());
+    assert!(handle.get_gpio_value(0).is_ok());
+}
+
+#[test]
+fn seq_width_defaults_to_one_byte() {
+    let (handle, faults) = new_faulty_handle(&[]);
+
+    faults.frames.lock().unwrap().clear();
+    assert!(handle.get_gpio_value(3).is_ok());
+
+    let frames = faults.frames.lock().unwrap();
+    assert_eq!(frames[0][1], 2);
+    assert_eq!(frames[0][3], 3);
+}
+
+#[test]
+fn seq_width_two_is_negotiated() {
+    let (handle, faults) = new_faulty_handle(&["--seq-width", "two"]);
+
+    faults.frames.lock().unwrap().clear();
+    for _ in 0..300 {
+        assert!(handle.get_gpio_value(3).is_ok());
+    }
+
+    // The high byte follows the low byte and counts its wraparounds
+    let frames = faults.frames.lock().unwrap();
+    assert_eq!(frames[0][1], 3);
+    assert_eq!(frames[0][4], 3);
+    assert_eq!(frames.last().unwrap()[3], 1);
+}
+
+#[test]
+fn wide_seq_drops_replies_from_before_a_wraparound() {
+    let (handle, faults) =
+        new_faulty_handle(&["--seq-width", "two", "--cmd-timeout", "get-gpio-value=100"]);
+
+    faults.stale_epoch.store(true, Ordering::SeqCst);
+    assert!(matches!(
+        handle.get_gpio_value(0),
+        Err(Error::Recoverable(RecoverableError::Timeout(..)))
+    ));
+
+    faults.stale_epoch.store(false, Ordering::SeqCst);
+    assert!(handle.get_gpio_value(0).is_ok());
+}
+
+#[test]
+fn rtt_skips_failed_requests() {
+    let (handle, faults) = new_faulty_handle(&["--max-seq-mismatches", "4"]);
+    let samples = handle.rtt_stats().unwrap().samples;
+
+    faults.mismatches.store(4, Ordering::SeqCst);
+    assert!(handle.get_gpio_value(0).is_err());
+    assert_eq!(handle.rtt_stats().unwrap().samples, samples);
+
+    faults.mismatches.store(0, Ordering::SeqCst);
+    assert!(handle.get_gpio_value(0).is_ok());
+    assert_eq!(handle.rtt_stats().unwrap().samples, samples + 1);
+}
+
+#[test]
+fn firmware_info() {
+    let (handle, _) = new_handle();
+
+    assert_eq!(
+        handle.chip.firmware_info.as_deref(),
+        Some("mock-1-firmware")
+    );
+}
+
+#[test]
+fn pins_enumerates_chip() {
+    let (handle, _) = new_handle_with_args(&["--mock-gpio-count", "3"]);
+
+    let pins: Vec<_> = handle
+        .pins()
+        .map(|info| (info.pin, info.name, info.raw_name))
+        .collect();
+
+    assert_eq!(
+        pins,
+        (0..3)
+            .map(|pin| {
+                let name = format!("mock-1-gpio-{}", pin);
+                (pin, name.clone(), name)
+            })
+            .collect::<Vec<_>>()
+    );
+}
+
+// Registers only the first count pins, as if the secondary grew afterwards
+fn shrink_chip(handle: &mut Handle, count: usize) {
+    handle.chip.gpio_names.truncate(count);
+    handle.chip.raw_gpio_names.truncate(count);
+}
+
+#[test]
+fn new_pins_are_registered() {
+    let (mut handle, _) = new_handle_with_args(&["--mock-gpio-count", "4"]);
+    shrink_chip(&mut handle, 2);
+
+    assert!(handle.get_gpio_value(3).is_err());
+
+    let pins = handle.get_new_pins().unwrap();
+    assert_eq!(
+        pins.iter().map(|info| info.pin).collect::<Vec<_>>(),
+        vec![2, 3]
+    );
+
+    handle.register_pins(&pins).unwrap();
+
+    assert_eq!(handle.pin_count(), 4);
+    assert_eq!(handle.pins().last(), pins.last().cloned());
+    assert!(handle.get_gpio_value(3).is_ok());
+    assert!(handle.get_new_pins().unwrap().is_empty());
+}
+
+#[test]
+fn new_pins_must_be_contiguous() {
+    let (mut handle, _) = new_handle_with_args(&["--mock-gpio-count", "4"]);
+    shrink_chip(&mut handle, 2);
+
+    let pins = handle.get_new_pins().unwrap();
+
+    assert!(handle.register_pins(&pins[1..]).is_err());
+    assert_eq!(handle.pin_count(), 2);
+}
+
+#[test]
+fn new_pins_are_unregistered_on_rollback() {
+    let (mut handle, _) = new_handle_with_args(&["--mock-gpio-count", "4"]);
+    shrink_chip(&mut handle, 2);
+
+    let pins = handle.get_new_pins().unwrap();
+    handle.register_pins(&pins).unwrap();
+
+    assert!(handle.unregister_pins(&pins[..1]).is_err());
+    handle.unregister_pins(&pins).unwrap();
+
+    assert_eq!(handle.pin_count(), 2);
+    assert_eq!(handle.get_new_pins().unwrap(), pins);
+}
+
+#[test]
+fn new_pins_respect_gpio_count() {
+    let (mut handle, _) = new_handle_with_args(&["--mock-gpio-count", "4", "--gpio-count", "3"]);
+    shrink_chip(&mut handle, 2);
+
+    let pins = handle.get_new_pins().unwrap();
+
+    assert_eq!(
+        pins.iter().map(|info| info.pin).collect::<Vec<_>>(),
+        vec![2]
+    );
+}
+
+#[test]
+fn mock_disable_policy() {
+    for (policy, expected) in [("force-low", GpioValue::Low), ("retain", GpioValue::High)] {
+        let (handle, _) = new_handle_with_args(&["--mock-disable-policy", policy]);
+
+        handle.set_gpio_value(0, GpioValue::High).unwrap();
+        handle
+            .set_gpio_direction(0, GpioDirection::Disabled)
+            .unwrap();
+
+        assert_eq!(handle.get_gpio_value(0).unwrap().value.unwrap(), expected);
+    }
+}
+
+#[test]
+fn set_gpio_values_leaves_other_pins() {
+    let (handle, _) = new_handle();
+
+    handle
+        .set_gpio_values(&[(0, GpioValue::High), (2, GpioValue::High)])
+        .unwrap();
+
+    assert_eq!(
+        handle.get_gpio_value(0).unwrap().value.unwrap(),
+        GpioValue::High
+    );
+    assert_eq!(
+        handle.get_gpio_value(1).unwrap().value.unwrap(),
+        GpioValue::Low
+    );
+    assert_eq!(
+        handle.get_gpio_value(2).unwrap().value.unwrap(),
+        GpioValue::High
+    );
+}
+
+#[test]
+fn set_gpio_values_rejects_duplicate_pins() {
+    let (handle, _) = new_handle();
+
+    assert!(matches!(
+        handle.set_gpio_values(&[(1, GpioValue::High), (1, GpioValue::Low)]),
+        Err(Error::Recoverable(RecoverableError::DuplicatePin(1)))
+    ));
+}
+
+#[test]
+fn pin_records_track_acknowledged_changes() {
+    let (handle, _) = new_handle();
+    let generation = handle.pin_states_generation();
+
+    handle.set_gpio_direction(1, GpioDirection::Output).unwrap();
+    handle.set_gpio_value(1, GpioValue::High).unwrap();
+
+    assert!(handle.pin_states_generation() > generation);
+
+    let record = &handle.pin_records()[1];
+    assert!(matches!(record.direction, Some(preset::Direction::Output)));
+    assert!(matches!(record.value, Some(preset::Value::High)));
+    assert!(record.config.is_none());
+
+    // The value of a disabled pin is up to the secondary
+    handle
+        .set_gpio_direction(1, GpioDirection::Disabled)
+        .unwrap();
+
+    assert!(handle.pin_records()[1].value.is_none());
+}
+
+fn banks(init_config: &str) -> preset::Presets {
+    toml::from_str(init_config).unwrap()
+}
+
+#[test]
+fn pin_records_carry_the_bank() {
+    let config = parse_config(&[]);
+    let trace_config = utils::trace(&config);
+    let presets = banks(
+        r#"
+        [[banks]]
+        name = "leds"
+        pins = [1, 2]
+        "#,
+    );
+
+    let handle = Handle::with_interface(
+        &config,
+        &presets,
+        interface::new(&config, &trace_config).unwrap(),
+    )
+    .unwrap();
+    let records = handle.pin_records();
+
+    assert_eq!(records[0].bank, None);
+    assert_eq!(records[1].bank.as_deref(), Some("leds"));
+    assert_eq!(records[2].bank.as_deref(), Some("leds"));
+}
+
+#[test]
+fn banks_are_validated() {
+    for init_config in [
+        // Out of range
+        "[[banks]]\nname = \"leds\"\npins = [16]",
+        // Overlapping
+        "[[banks]]\nname = \"leds\"\npins = [1, 2]\n[[banks]]\nname = \"spi_bus\"\npins = [2, 3]",
+        // Repeated within the bank
+        "[[banks]]\nname = \"leds\"\npins = [1, 1]",
+        // Named twice
+        "[[banks]]\nname = \"leds\"\npins = [1]\n[[banks]]\nname = \"leds\"\npins = [2]",
+        "[[banks]]\nname = \"\"\npins = [1]",
+    ] {
+        assert!(banks(init_config).validate(16).is_err(), "{}", init_config);
+    }
+
+    assert!(banks(
+        "[[banks]]\nname = \"leds\"\npins = [1, 2]\n[[banks]]\nname = \"spi_bus\"\npins = [3]"
+    )
+    .validate(16)
+    .is_ok());
+}
+
+#[test]
+fn unmanaged_pin_cannot_be_in_a_bank() {
+    let config = parse_config(&["--unmanaged-pins", "2"]);
+    let trace_config = utils::trace(&config);
+    let presets = banks("[[banks]]\nname = \"leds\"\npins = [1, 2]");
+
+    assert!(Handle::with_interface(
+        &config,
+        &presets,
+        interface::new(&config, &trace_config).unwrap(),
+    )
+    .is_err());
+}
+
+#[test]
+fn gpio_status_tracks_buffers() {
+    let (handle, _) = new_handle();
+
+    let status = handle.get_gpio_status(3).unwrap();
+    assert!(!status.driven());
+    assert!(!status.input_enabled());
+
+    handle.set_gpio_direction(3, GpioDirection::Output).unwrap();
+    let status = handle.get_gpio_status(3).unwrap();
+    assert!(status.driven());
+    assert!(!status.input_enabled());
+
+    handle.set_gpio_config(3, GpioConfig::InputEnable).unwrap();
+    let status = handle.get_gpio_status(3).unwrap();
+    assert!(status.driven());
+    assert!(status.input_enabled());
+    assert!(!status.error_latched());
+
+    handle.set_gpio_direction(3, GpioDirection::Input).unwrap();
+    assert!(!handle.get_gpio_status(3).unwrap().driven());
+}
+
+#[test]
+fn apply_config_orders_steps_across_pins() {
+    let (handle, enabled, sent) = new_commands_handle(&[]);
+    enabled.store(true, Ordering::SeqCst);
+
+    let chip_config = ChipConfig {
+        pins: vec![
+            pin_config(
+                1,
+                Some(GpioConfig::DriveOpenDrain),
+                Some(GpioValue::High),
+                Some(GpioDirection::Output),
+            ),
+            pin_config(
+                4,
+                Some(GpioConfig::BiasPullUp),
+                None,
+                Some(GpioDirection::Input),
+            ),
+        ],
+    };
+
+    let results = handle.apply_config(&chip_config).unwrap();
+    assert!(results.values().all(|result| result.is_ok()));
+
+    assert_eq!(
+        *sent.lock().unwrap(),
+        vec![
+            (HostCmd::SetGpioConfig as u8, 1),
+            (HostCmd::SetGpioConfig as u8, 4),
+            (HostCmd::SetGpioValue as u8, 1),
+            (HostCmd::SetGpioDirection as u8, 1),
+            (HostCmd::SetGpioDirection as u8, 4),
+        ]
+    );
+
+    assert_eq!(
+        handle.get_gpio_raw(1).unwrap()[..3],
+        [
+            GpioDirection::Output as u8,
+            GpioConfig::DriveOpenDrain as u8,
+            GpioValue::High as u8
+        ]
+    );
+}
+
+#[test]
+fn apply_config_skips_acknowledged_steps() {
+    let (handle, enabled, sent) = new_commands_handle(&[]);
+
+    let chip_config = ChipConfig {
+        pins: vec![pin_config(
+            2,
+            Some(GpioConfig::BiasPullDown),
+            Some(GpioValue::Low),
+            Some(GpioDirection::Output),
+        )],
+    };
+
+    handle.apply_config(&chip_config).unwrap();
+
+    enabled.store(true, Ordering::SeqCst);
+    handle.set_gpio_value(2, GpioValue::High).unwrap();
+    handle.apply_config(&chip_config).unwrap();
+
+    assert_eq!(
+        *sent.lock().unwrap(),
+        vec![
+            (HostCmd::SetGpioValue as u8, 2),
+            (HostCmd::SetGpioValue as u8, 2),
+        ]
+    );
+}
+
+#[test]
+fn apply_config_reports_per_pin() {
+    let (handle, _) = new_handle_with_args(&["--mock-gpio-count", "4"]);
+
+    let chip_config = ChipConfig {
+        pins: vec![
+            pin_config(0, None, None, Some(GpioDirection::Input)),
+            pin_config(0, None, None, Some(GpioDirection::Output)),
+            pin_config(9, None, None, Some(GpioDirection::Input)),
+            pin_config(3, None, None, Some(GpioDirection::Input)),
+        ],
+    };
+
+    let results = handle.apply_config(&chip_config).unwrap();
+
+    assert!(matches!(
+        results[&0],
+        Err(Error::Recoverable(RecoverableError::InvalidPin(0)))
+    ));
+    assert!(matches!(
+        results[&9],
+        Err(Error::Recoverable(RecoverableError::InvalidPin(9)))
+    ));
+    assert!(results[&3].is_ok());
+    assert_eq!(
+        handle.pin_records()[3].direction.map(GpioDirection::from),
+        Some(GpioDirection::Input)
+    );
+}
+
+#[test]
+fn gpio_raw_reflects_pin_state() {
+    let (handle, _) = new_handle();
+
+    handle
+        .set_gpio_config(2, GpioConfig::DriveOpenDrain)
+        .unwrap();
+    handle.set_gpio_direction(2, GpioDirection::Output).unwrap();
+    handle.set_gpio_value(2, GpioValue::High).unwrap();
+
+    assert_eq!(
+        handle.get_gpio_raw(2).unwrap(),
+        vec![
+            GpioDirection::Output as u8,
+            GpioConfig::DriveOpenDrain as u8,
+            GpioValue::High as u8,
+            0,
+            0
+        ]
+    );
+}
+
+#[test]
+fn buffer_enable_keeps_drive_mode() {
+    let (handle, _) = new_handle();
+
+    handle
+        .set_gpio_config(3, GpioConfig::DrivePushPull)
+        .unwrap();
+    handle.set_gpio_config(3, GpioConfig::InputEnable).unwrap();
+
+    assert_eq!(
+        handle.get_gpio_config(3).unwrap(),
+        GpioConfig::DrivePushPull
+    );
+    assert_eq!(handle.get_gpio_raw(3).unwrap()[3..], [1, 0]);
+
+    let record = &handle.pin_records()[3];
+    assert!(matches!(record.config, Some(preset::Config::DrivePushPull)));
+    assert!(record.input_enable);
+    assert!(!record.output_enable);
+}
+
+#[test]
+fn gpio_config_readback() {
+    let (handle, _) = new_handle();
+
+    assert_eq!(handle.get_gpio_config(1).unwrap(), GpioConfig::BiasDisable);
+
+    handle
+        .set_gpio_config(1, GpioConfig::DriveOpenDrain)
+        .unwrap();
+
+    assert_eq!(
+        handle.get_gpio_config(1).unwrap(),
+        GpioConfig::DriveOpenDrain
+    );
+}
+
+fn try_new_nameless_handle(args: &[&str], pin: u8) -> Result<Handle> {
+    let faults = Faults {
+        nameless_pin: Some(pin),
+        ..Default::default()
+    };
+
+    let args = [
+        &[
+            "--mock-gpio-count",
+            "4",
+            "--cmd-timeout",
+            "get-gpio-name=100",
+        ],
+        args,
+    ]
+    .concat();
+
+    try_new_faulty_handle(&args, faults).map(|(handle, _)| handle)
+}
+
+#[test]
+fn name_errors_are_fatal_by_default() {
+    assert!(try_new_nameless_handle(&[], 2).is_err());
+}
+
+#[test]
+fn name_errors_tolerated() {
+    let handle = try_new_nameless_handle(&["--tolerate-name-errors"], 2).unwrap();
+
+    assert_eq!(handle.chip.gpio_names[1], "mock-1-gpio-1");
+    assert_eq!(handle.chip.gpio_names[2], "gpio2");
+    assert_eq!(handle.chip.gpio_names[3], "mock-1-gpio-3");
+    assert_eq!(handle.chip.fallback_name_pins, vec![2]);
+}
+
+#[test]
+fn names_are_used_as_reported_by_default() {
+    let config = parse_config(&[]);
+
+    assert_eq!(config.name_policy, utils::NamePolicy::Raw);
+}
+
+#[test]
+fn normalize_name_keeps_valid_names() {
+    assert_eq!(normalize_name(0, "LED_0-a.b"), "LED_0-a.b");
+}
+
+#[test]
+fn normalize_name_replaces_invalid_characters() {
+    assert_eq!(normalize_name(0, "led 0/red\u{e9}"), "led_0_red_");
+}
+
+#[test]
+fn normalize_name_truncates() {
+    let name = "a".repeat(GPIO_NAME_MAX_LEN + 8);
+
+    assert_eq!(normalize_name(0, &name), "a".repeat(GPIO_NAME_MAX_LEN));
+}
+
+#[test]
+fn normalize_name_names_empty_names() {
+    assert_eq!(normalize_name(7, ""), "gpio7");
+}
+
+#[test]
+fn malformed_name_is_not_tolerated() {
+    let (handle, _) = new_handle_with_args(&["--tolerate-name-errors"]);
+
+    let timeout: Result<String> = Err(Error::from(RecoverableError::Timeout(
+        std::sync::mpsc::RecvTimeoutError::Timeout,
+        100,
+        handle.transport().clone(),
+    ))
+    .into());
+    assert!(handle.name_pin(0, timeout).unwrap().1);
+    assert!(handle.name_pin(0, Err(anyhow!("Malformed name"))).is_err());
+}
+
+#[test]
+fn fallback_name_is_unique() {
+    let names: Vec<String> = ["gpio1", "gpio0", "gpio0_1", "b"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+
+    assert_eq!(unique_fallback_name(0, &names), "gpio0_2");
+    assert_eq!(unique_fallback_name(1, &names), "gpio1_1");
+    assert_eq!(unique_fallback_name(3, &names), "gpio3");
+}
+
+#[test]
+fn name_errors_tolerated_in_pipeline() {
+    let handle =
+        try_new_nameless_handle(&["--tolerate-name-errors", "--pipeline-depth", "3"], 1).unwrap();
+
+    assert_eq!(handle.chip.gpio_names[0], "mock-1-gpio-0");
+    assert_eq!(handle.chip.gpio_names[1], "gpio1");
+    assert_eq!(handle.chip.gpio_names[2], "mock-1-gpio-2");
+    assert_eq!(handle.chip.gpio_names[3], "mock-1-gpio-3");
+    assert_eq!(handle.chip.fallback_name_pins, vec![1]);
+}
+
+#[test]
+fn pipelined_discovery_matches_sequential() {
+    let (sequential, _) = new_handle_with_args(&["--mock-gpio-count", "7"]);
+    let (pipelined, _) = new_handle_with_args(&["--mock-gpio-count", "7", "--pipeline-depth", "4"]);
+
+    assert_eq!(pipelined.chip.gpio_names, sequential.chip.gpio_names);
+}
+
+#[test]
+fn pipelined_discovery_accepts_out_of_order_replies() {
+    let faults = Faults {
+        reorder_names: true,
+        ..Default::default()
+    };
+
+    let (handle, _) =
+        try_new_faulty_handle(&["--mock-gpio-count", "6", "--pipeline-depth", "4"], faults)
+            .unwrap();
+
+    for pin in 0..6 {
+        assert_eq!(handle.chip.gpio_names[pin], format!("mock-1-gpio-{}", pin));
+    }
+    assert!(handle.diagnostics().is_empty());
+}
+
+#[test]
+fn startup_timeout() {
+    let now = std::time::Instant::now();
+
+    let faults = Faults {
+        delay_ms: AtomicU64::new(50),
+        ..Default::default()
+    };
+
+    let err = match try_new_faulty_handle(&["--startup-timeout", "200"], faults) {
+        Ok(_) => panic!("Handle created past the startup timeout"),
+        Err(err) => err.to_string(),
+    };
+
+    assert!(err.contains("Startup timeout (200 ms)"), "{}", err);
+    assert!(now.elapsed() < std::time::Duration::from_millis(16 * 50));
+}
+
+#[test]
+fn max_gpio_count_is_stored() {
+    let (handle, _) =
+        new_handle_with_args(&["--mock-gpio-count", "4", "--mock-max-gpio-count", "8"]);
+
+    assert_eq!(handle.chip.max_gpio_count, 8);
+}
+
+#[test]
+fn max_gpio_count_below_gpio_count_falls_back() {
+    let (handle, _) =
+        new_handle_with_args(&["--mock-gpio-count", "4", "--mock-max-gpio-count", "2"]);
+
+    assert_eq!(handle.chip.max_gpio_count, 4);
+    assert_eq!(handle.pin_count(), 4);
+}
+
+#[test]
+fn new_pins_respect_max_gpio_count() {
+    let (mut handle, _) = new_handle_with_args(&["--mock-gpio-count", "4"]);
+    shrink_chip(&mut handle, 2);
+    handle.chip.max_gpio_count = 3;
+
+    assert!(handle.get_new_pins().is_err());
+}
+
+#[test]
+fn status_error_names_the_request() {
+    let err: Error = RecoverableError::Packet {
+        status: Status::NotSupported,
+        cmd: packet::HostCmd::SetGpioConfig,
+        pin: None,
+    }
+    .into();
+    assert_eq!(err.to_string(), "Status(NotSupported, Cmd: SetGpioConfig)");
+
+    let err = err.at_pin(3);
+    assert_eq!(
+        err.to_string(),
+        "Status(NotSupported, Cmd: SetGpioConfig, Pin: 3)"
+    );
+}
+
+#[test]
+fn at_pin_leaves_other_errors() {
+    let err = Error::from(RecoverableError::InvalidPin(5)).at_pin(3);
+
+    assert!(matches!(
+        err,
+        Error::Recoverable(RecoverableError::InvalidPin(5))
+    ));
+}
+
+#[test]
+fn first_seq() {
+    let (handle, seqs) = new_handle_with_args(&["--first-seq", "254"]);
+
+    let seqs = seqs.lock().unwrap().clone();
+    assert_eq!(&seqs[..3], &[254, 255, 0]);
+    assert_eq!(handle.last_seq(), seqs.last().copied());
+}
+
+#[cfg(not(feature = "gpio_cpc"))]
+#[test]
+fn transport_not_built_in() {
+    let config =
+        utils::Config::parse_from(["cpc-gpio-bridge", "--instance", "1", "--transport", "cpc"]);
+
+    let err = interface::validate(&config).unwrap_err();
+
+    assert!(err.to_string().contains("gpio_cpc"), "{}", err);
+}
+
+#[test]
+fn get_gpio_values() {
+    let (handle, _) = new_handle_with_args(&["--mock-gpio-count", "4"]);
+    handle.set_gpio_value(2, GpioValue::High).unwrap();
+
+    let values = handle.get_gpio_values_timeout(&[3, 2], None).unwrap();
+
+    assert!(matches!(
+        values[..],
+        [(3, Ok(GpioValue::Low)), (2, Ok(GpioValue::High))]
+    ));
+}
+
+#[test]
+fn get_gpio_values_invalid_pin() {
+    let (handle, _) = new_handle_with_args(&["--mock-gpio-count", "4"]);
+
+    assert!(matches!(
+        handle.get_gpio_values_timeout(&[0, 4], None),
+        Err(Error::Recoverable(RecoverableError::InvalidPin(4)))
+    ));
+}
+
+#[test]
+fn reconcile_rewrites_diverged_outputs() {
+    let (handle, _) = new_handle_with_args(&["--mock-disable-policy", "force-low"]);
+
+    handle.set_gpio_direction(2, GpioDirection::Output).unwrap();
+    handle.set_gpio_value(2, GpioValue::High).unwrap();
+    handle.set_gpio_direction(4, GpioDirection::Input).unwrap();
+    handle.set_gpio_value(4, GpioValue::High).unwrap();
+
+    assert!(handle.reconcile_outputs().unwrap().is_empty());
+
+    // Like a secondary reset, force-low drops the value the pin was set to
+    handle
+        .set_gpio_direction(2, GpioDirection::Disabled)
+        .unwrap();
+    handle.set_gpio_direction(2, GpioDirection::Output).unwrap();
+
+    assert_eq!(handle.reconcile_outputs().unwrap(), vec![2]);
+    assert_eq!(
+        handle.get_gpio_value(2).unwrap().value.unwrap(),
+        GpioValue::High
+    );
+    assert_eq!(handle.reconciled_count(), 1);
+
+    assert!(handle.reconcile_outputs().unwrap().is_empty());
+}
+
+#[test]
+fn link_counters_track_timeouts() {
+    let (handle, _) = new_handle_with_args(&[
+        "--mock-unanswered-cmd",
+        "get-gpio-config",
+        "--cmd-timeout",
+        "get-gpio-config=50",
+    ]);
+    let counters = handle.link_counters();
+    assert!(counters.last_success.is_some());
+
+    assert!(handle.get_gpio_config(0).is_err());
+    assert!(handle.get_gpio_config(0).is_err());
+
+    let timed_out = handle.link_counters();
+    assert_eq!(timed_out.transactions, counters.transactions + 2);
+    assert_eq!(timed_out.timeouts, counters.timeouts + 2);
+    assert_eq!(timed_out.consecutive_timeouts, 2);
+
+    // Any reply brings the link back
+    assert!(handle.get_gpio_value(0).is_ok());
+    assert_eq!(handle.link_counters().consecutive_timeouts, 0);
+}
+
+#[test]
+fn link_state_needs_consecutive_outcomes() {
+    let (handle, _) = new_handle_with_args(&[
+        "--mock-unanswered-cmd",
+        "get-gpio-config",
+        "--cmd-timeout",
+        "get-gpio-config=50",
+        "--link-down-after",
+        "2",
+        "--link-up-after",
+        "2",
+    ]);
+    assert_eq!(handle.link_state(), LinkState::Up);
+
+    // A reply in between restarts the count
+    assert!(handle.get_gpio_config(0).is_err());
+    assert!(handle.get_gpio_value(0).is_ok());
+    assert!(handle.get_gpio_config(0).is_err());
+    assert_eq!(handle.link_state(), LinkState::Up);
+
+    assert!(handle.get_gpio_config(0).is_err());
+    assert_eq!(handle.link_state(), LinkState::Down);
+
+    assert!(handle.get_gpio_value(0).is_ok());
+    assert_eq!(handle.link_state(), LinkState::Down);
+    assert!(handle.get_gpio_value(0).is_ok());
+    assert_eq!(handle.link_state(), LinkState::Up);
+}
+
+#[test]
+fn link_state_ignores_status_replies() {
+    let mut tracker = counters::LinkTracker::new(1, 1);
+
+    let refused: Result<(), Error> = Err(RecoverableError::Packet {
+        status: Status::NotSupported,
+        cmd: packet::HostCmd::SetGpioConfig,
+        pin: None,
+    }
+    .into());
+    assert_eq!(tracker.record(&refused), None);
+
+    let shutting_down: Result<(), Error> = Err(RecoverableError::ShuttingDown.into());
+    assert_eq!(tracker.record(&shutting_down), None);
+    assert_eq!(tracker.state(), LinkState::Up);
+}
+
+#[test]
+fn rediscover_matches_the_startup_discovery() {
+    let (handle, _) = new_handle();
+
+    let discovery = handle.rediscover().unwrap();
+    assert_eq!(discovery.unique_id, handle.unique_id());
+    assert_eq!(discovery.label, handle.chip.label);
+    assert_eq!(discovery.names.len(), handle.chip.raw_gpio_names.len());
+    assert!(handle.discovery_changes(&discovery).is_empty());
+
+    // Pins and their states are left alone
+    assert_eq!(handle.pin_count(), handle.chip.gpio_names.len());
+}
+
+#[test]
+fn discovery_changes_are_reported() {
+    let (handle, _) = new_handle();
+
+    let mut discovery = handle.rediscover().unwrap();
+    discovery.label = "renamed".to_string();
+    discovery.names[1] = Ok("RENAMED".to_string());
+    discovery.names[2] = Err(anyhow!("failed"));
+    discovery.gpio_count -= 1;
+
+    assert_eq!(
+        handle.discovery_changes(&discovery),
+        vec![
+            format!("Label {:?} -> \"renamed\"", handle.chip.label),
+            format!(
+                "GPIO count {} -> {}",
+                handle.chip.raw_gpio_names.len(),
+                discovery.gpio_count
+            ),
+            format!(
+                "Pin 1 name {:?} -> \"RENAMED\"",
+                handle.chip.raw_gpio_names[1]
+            ),
+        ]
+    );
+}
+
+#[test]
+fn refresh_unique_id_reports_a_change() {
+    let (handle, faults) = new_faulty_handle(&[]);
+    assert_eq!(handle.unique_id(), 1);
+    assert_eq!(handle.refresh_unique_id().unwrap(), None);
+
+    faults.unique_id.store(42, Ordering::SeqCst);
+    assert_eq!(handle.refresh_unique_id().unwrap(), Some(1));
+    assert_eq!(handle.unique_id(), 42);
+    assert_eq!(handle.refresh_unique_id().unwrap(), None);
+}
+
+#[test]
+fn refresh_unique_id_keeps_the_override() {
+    let (handle, faults) = new_faulty_handle(&["--unique-id", "7"]);
+    faults.unique_id.store(42, Ordering::SeqCst);
+
+    assert_eq!(handle.refresh_unique_id().unwrap(), None);
+    assert_eq!(handle.unique_id(), 7);
+}
+
+#[cfg(feature = "raw_request")]
+#[test]
+fn raw_request_returns_the_reply() {
+    let (handle, _) = new_handle();
+
+    let reply = handle.raw_request(HostCmd::GetUniqueId as u8, &[]).unwrap();
+
+    assert_eq!(reply[0], packet::SecondaryCmd::UniqueIdIs as u8);
+    assert_eq!(reply[2], handle.last_seq().unwrap());
+    assert_eq!(reply[3..], 1u64.to_le_bytes());
+
+    // The typed layer is still in sync
+    assert!(handle.get_gpio_value(0).is_ok());
+}
+
+#[test]
+fn default_bias_applies_to_unconfigured_inputs() {
+    let (handle, _) = new_handle_with_args(&["--default-bias", "pull-up"]);
+
+    handle.set_gpio_direction(3, GpioDirection::Input).unwrap();
+    assert_eq!(handle.get_gpio_config(3).unwrap(), GpioConfig::BiasPullUp);
+
+    // A config set by the Kernel Driver is left alone
+    handle.set_gpio_config(4, GpioConfig::BiasPullDown).unwrap();
+    handle.set_gpio_direction(4, GpioDirection::Input).unwrap();
+    assert_eq!(handle.get_gpio_config(4).unwrap(), GpioConfig::BiasPullDown);
+
+    handle.set_gpio_direction(5, GpioDirection::Output).unwrap();
+    assert!(handle.pin_records()[5].config.is_none());
+}
+
+#[test]
+fn default_bias_is_off_by_default() {
+    let (handle, _) = new_handle();
+
+    handle.set_gpio_direction(3, GpioDirection::Input).unwrap();
+    assert!(handle.pin_records()[3].config.is_none());
+}
+
+#[test]
+fn unmanaged_pins_are_left_alone() {
+    let (handle, _) = new_handle_with_args(&["--unmanaged-pins", "2,5"]);
+
+    // Never disabled at startup, unlike the other pins
+    assert_eq!(handle.pin_states.get(2).direction, None);
+    assert_eq!(
+        handle.pin_states.get(1).direction,
+        Some(GpioDirection::Disabled)
+    );
+
+    let pins: Vec<u8> = handle.pins().map(|info| info.pin).collect();
+    assert_eq!(pins, [0, 1, 3, 4, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+
+    assert!(matches!(
+        handle.set_gpio_direction(2, GpioDirection::Output),
+        Err(Error::Recoverable(RecoverableError::InvalidPin(2)))
+    ));
+    assert!(handle.reset_all().unwrap().is_empty());
+    assert_eq!(handle.pin_states.get(5).direction, None);
+}
+
+#[test]
+fn unmanaged_pins_are_validated() {
+    assert!(try_new_handle(&["--unmanaged-pins", "16"]).is_err());
+    assert!(try_new_handle(&["--mock-gpio-count", "2", "--unmanaged-pins", "0,1"]).is_err());
+}
+
+#[test]
+fn dropping_the_handle_stops_the_reader() {
+    let config = parse_config(&[]);
+    let trace_config = utils::trace(&config);
+    let dropped = Arc::new(AtomicBool::new(false));
+
+    let interface = Dropped {
+        interface: interface::new(&config, &trace_config).unwrap(),
+        dropped: dropped.clone(),
+    };
+
+    let handle =
+        Handle::with_interface(&config, &preset::Presets::default(), Box::new(interface)).unwrap();
+    drop(handle);
+
+    let now = std::time::Instant::now();
+    while !dropped.load(Ordering::SeqCst) {
+        assert!(now.elapsed() < std::time::Duration::from_millis(500));
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+}
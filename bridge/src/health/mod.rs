@@ -0,0 +1,184 @@
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::gpio;
+use crate::utils;
+
+#[cfg(test)]
+mod tests;
+
+// Bounds how long a client that never sends its request holds the endpoint
+const CLIENT_TIMEOUT_MS: u64 = 1000;
+const REQUEST_MAX_LEN: usize = 4096;
+
+pub struct Handle {
+    pub exit: utils::ThreadExit,
+}
+
+impl Handle {
+    pub fn new(
+        gpio: Arc<gpio::Handle>,
+        ready: Arc<utils::Ready>,
+        addr: SocketAddr,
+        libcpc_trace: bool,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .with_context(|| format!("Failed to bind the health endpoint ({})", addr))?;
+
+        log::info!("Serving health on http://{}", addr);
+
+        let (mut exit_sender, exit_receiver) = mio::unix::pipe::new()?;
+
+        std::thread::Builder::new()
+            .name("health".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(err) => {
+                            utils::ThreadExit::notify(
+                                &mut exit_sender,
+                                &format!("Health endpoint failed to accept, Err: {}", err),
+                            );
+                            return;
+                        }
+                    };
+
+                    // A misbehaving client only fails its own probe
+                    if let Err(err) = serve(stream, &gpio, &ready, libcpc_trace) {
+                        log::debug!("Health request failed, Err: {}", err);
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            exit: utils::ThreadExit {
+                receiver: std::sync::Mutex::new(exit_receiver),
+            },
+        })
+    }
+}
+
+// Any path is answered, probes only look at the status
+fn serve(
+    mut stream: TcpStream,
+    gpio: &gpio::Handle,
+    ready: &utils::Ready,
+    libcpc_trace: bool,
+) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_millis(CLIENT_TIMEOUT_MS)))?;
+    stream.set_write_timeout(Some(Duration::from_millis(CLIENT_TIMEOUT_MS)))?;
+
+    let mut request = vec![];
+    let mut buffer = [0; 512];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let len = stream.read(&mut buffer)?;
+        if len == 0 || request.len() + len > REQUEST_MAX_LEN {
+            break;
+        }
+        request.extend_from_slice(&buffer[..len]);
+    }
+
+    let (status, body) = if request.starts_with(b"GET ") || request.starts_with(b"HEAD ") {
+        let report = Report::new(
+            &gpio.link_counters(),
+            gpio.link_state(),
+            gpio.reconciled_count(),
+            ready.wait(Duration::ZERO),
+            Trace {
+                level: log::max_level(),
+                libcpc: libcpc_trace,
+            },
+            Instant::now(),
+        );
+        (report.status(), report.to_json())
+    } else {
+        ("405 Method Not Allowed", String::new())
+    };
+
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    if !request.starts_with(b"HEAD ") {
+        response.push_str(&body);
+    }
+
+    stream.write_all(response.as_bytes())?;
+
+    Ok(())
+}
+
+// Tracing in effect, the level is read from the logger as it is answered
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Trace {
+    level: log::LevelFilter,
+    libcpc: bool,
+}
+
+#[derive(Debug, PartialEq)]
+struct Report {
+    link_up: bool,
+    ready: bool,
+    last_success_ms: Option<u128>,
+    counters: gpio::LinkCounters,
+    reconciled: u64,
+    trace: Trace,
+}
+
+impl Report {
+    fn new(
+        counters: &gpio::LinkCounters,
+        link: gpio::LinkState,
+        reconciled: u64,
+        ready: bool,
+        trace: Trace,
+        now: Instant,
+    ) -> Self {
+        Self {
+            link_up: link == gpio::LinkState::Up,
+            ready,
+            last_success_ms: counters
+                .last_success
+                .map(|last| now.saturating_duration_since(last).as_millis()),
+            counters: *counters,
+            reconciled,
+            trace,
+        }
+    }
+
+    fn status(&self) -> &'static str {
+        match self.link_up {
+            true => "200 OK",
+            false => "503 Service Unavailable",
+        }
+    }
+
+    // Written by hand, the body is flat and only holds numbers, booleans and
+    // fixed words
+    fn to_json(&self) -> String {
+        let last_success_ms = match self.last_success_ms {
+            Some(ms) => ms.to_string(),
+            None => "null".to_string(),
+        };
+
+        format!(
+            "{{\"link\":\"{}\",\"ready\":{},\"last_success_ms_ago\":{},\"transactions\":{},\"errors\":{},\"timeouts\":{},\"consecutive_timeouts\":{},\"duplicate_replies\":{},\"reconciled\":{},\"log_level\":\"{}\",\"libcpc_trace\":{}}}",
+            if self.link_up { "up" } else { "down" },
+            self.ready,
+            last_success_ms,
+            self.counters.transactions,
+            self.counters.errors,
+            self.counters.timeouts,
+            self.counters.consecutive_timeouts,
+            self.counters.duplicate_replies,
+            self.reconciled,
+            self.trace.level.as_str().to_lowercase(),
+            self.trace.libcpc
+        )
+    }
+}
@@ -0,0 +1,48 @@
+use super::*;
+
+const TRACE: Trace = Trace {
+    level: log::LevelFilter::Info,
+    libcpc: false,
+};
+
+#[test]
+fn status_follows_the_link_state() {
+    let now = Instant::now();
+    let counters = gpio::LinkCounters::default();
+
+    let report = Report::new(&counters, gpio::LinkState::Up, 0, true, TRACE, now);
+    assert_eq!(report.status(), "200 OK");
+
+    let report = Report::new(&counters, gpio::LinkState::Down, 0, true, TRACE, now);
+    assert_eq!(report.status(), "503 Service Unavailable");
+}
+
+#[test]
+fn report_json() {
+    let now = Instant::now();
+    let counters = gpio::LinkCounters {
+        last_success: Some(now - Duration::from_millis(250)),
+        transactions: 10,
+        errors: 3,
+        timeouts: 2,
+        consecutive_timeouts: 0,
+        duplicate_replies: 1,
+    };
+
+    assert_eq!(
+        Report::new(&counters, gpio::LinkState::Up, 1, true, TRACE, now).to_json(),
+        "{\"link\":\"up\",\"ready\":true,\"last_success_ms_ago\":250,\"transactions\":10,\"errors\":3,\"timeouts\":2,\"consecutive_timeouts\":0,\"duplicate_replies\":1,\"reconciled\":1,\"log_level\":\"info\",\"libcpc_trace\":false}"
+    );
+
+    let report = Report::new(
+        &gpio::LinkCounters::default(),
+        gpio::LinkState::Up,
+        0,
+        false,
+        TRACE,
+        now,
+    );
+    assert!(report
+        .to_json()
+        .starts_with("{\"link\":\"up\",\"ready\":false,\"last_success_ms_ago\":null,"));
+}
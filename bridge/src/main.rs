@@ -1,19 +1,83 @@
 use mio_signals::{Signal, Signals};
 
+mod bench;
+mod devicetree;
 mod driver;
+mod events;
 mod gpio;
+mod health;
+mod monitor;
 mod router;
+#[cfg(feature = "gpio_sim")]
+mod sim;
+mod state_file;
+#[cfg(feature = "uinput")]
+mod uinput;
 mod utils;
 
 fn main() -> ! {
+    utils::Monotonic::start();
+
     let config: utils::Config = clap::Parser::parse();
     let trace_config = utils::trace(&config);
+    let channel_config = utils::channel_config(&config);
+    let retry_policy = utils::retry_policy(&config);
 
-    env_logger::Builder::new()
+    let mut logger = env_logger::Builder::new();
+    logger
         .filter(Some(module_path!()), trace_config.bridge)
         .format_target(false)
-        .format_timestamp(Some(env_logger::TimestampPrecision::Millis))
-        .init();
+        .format_timestamp(Some(env_logger::TimestampPrecision::Millis));
+
+    utils::set_warn_limit(utils::warn_limit(&config));
+
+    if config.log_tag != utils::LogTag::None {
+        utils::set_log_tag(&config.instance);
+
+        logger.format(|buf, record| {
+            use std::io::Write;
+
+            writeln!(
+                buf,
+                "[{} {:<5} {}] {}",
+                buf.timestamp_millis(),
+                buf.default_styled_level(record.level()),
+                utils::log_tag(),
+                record.args()
+            )
+        });
+    }
+
+    #[cfg(feature = "gpio_cpc")]
+    match trace_config
+        .libcpc_file
+        .as_deref()
+        .map(utils::redirect_libcpc_trace)
+    {
+        Some(Ok(stderr)) => {
+            logger.target(env_logger::Target::Pipe(Box::new(stderr)));
+        }
+        Some(Err(err)) => {
+            logger.init();
+            utils::exit(err);
+        }
+        None => (),
+    }
+
+    logger.init();
+
+    if config.print_config {
+        match toml::to_string(&config) {
+            Ok(config) => {
+                print!("{}", config);
+                std::process::exit(0);
+            }
+            Err(err) => utils::exit(anyhow::anyhow!(
+                "Failed to serialize the configuration, Err: {}",
+                err
+            )),
+        }
+    }
 
     log::info!(
         "[CPC GPIO Bridge v{}] [GPIO API v{}] [Driver API v{}]",
@@ -22,26 +86,102 @@ fn main() -> ! {
         driver::VERSION
     );
 
+    #[cfg(feature = "gpio_cpc")]
+    log::info!("[libcpc v{}]", gpio::LIBCPC_VERSION);
+
+    log::info!("Trace {{ {} }}", trace_config);
+
     log::info!("{:?}", config);
 
     let run = || {
-        let lock_file = std::path::Path::new(&config.lock_dir)
-            .join(format!("cpc-gpio-bridge-{}.lock", config.instance));
+        gpio::validate(&config)?;
+
+        if let Some(events_file) = &config.events_file {
+            events::write_to(events_file)?;
+        }
+
+        let _bridge_lock = if config.no_lock {
+            log::warn!("Running without the bridge lock, only one bridge may run per instance");
+            None
+        } else {
+            let lock_file = std::path::Path::new(&config.lock_dir)
+                .join(format!("cpc-gpio-bridge-{}.lock", config.instance));
+
+            Some(utils::lock_bridge(
+                &lock_file,
+                config.lock_wait,
+                &retry_policy,
+            )?)
+        };
+
+        let signals =
+            Signals::new(Signal::Interrupt | Signal::Terminate | Signal::User1 | Signal::User2)?;
+
+        let gpio = std::sync::Arc::new(gpio::Handle::new(&config, &trace_config)?);
+        log::info!("Connected to the secondary over {}", gpio.transport());
 
-        let _bridge_lock = utils::lock_bridge(&lock_file)?;
+        // Only needs the secondary, libgpiod tools work before the Kernel
+        // Driver is loaded
+        #[cfg(feature = "gpio_sim")]
+        let _sim = sim::Handle::new(gpio.clone(), config.sim_poll_interval_ms)?;
 
-        let signals = Signals::new(Signal::Interrupt | Signal::Terminate | Signal::User1)?;
+        events::publish(|| events::Event::Initialized {
+            unique_id: gpio.unique_id(),
+            label: gpio.chip.label.clone(),
+            gpio_count: gpio.pins().count(),
+        });
 
-        let gpio = gpio::Handle::new(&config, &trace_config)?;
+        if let Some(path) = &config.emit_dt {
+            devicetree::write(path, &gpio)?;
+        }
+
+        if config.log_tag == utils::LogTag::UniqueId {
+            utils::set_log_tag(gpio.unique_id().to_string());
+        }
+
+        if !config.monitor.is_empty() {
+            return monitor::run(&gpio, &config.monitor, config.monitor_interval_ms, signals);
+        }
+
+        if let Some(pin) = config.bench_toggle {
+            return bench::run(&gpio, pin, config.bench_duration_ms, signals);
+        }
+
+        if let Some(timeout_ms) = config.wait_for_driver {
+            driver::wait_for_driver(timeout_ms, &retry_policy)?;
+        }
 
         let driver = driver::Handle::new(
             config.deinit,
-            gpio.chip.unique_id,
+            &channel_config,
+            &retry_policy,
+            gpio.unique_id(),
             &gpio.chip.label,
-            &gpio.chip.gpio_names,
-        )?;
+            &gpio.pins().map(Into::into).collect::<Vec<_>>(),
+            &utils::socket_config(&config),
+        )
+        .map_err(|err| {
+            if err.is::<driver::AlreadyInitialized>() {
+                let lock_dir = std::path::Path::new(&config.lock_dir);
+                anyhow::anyhow!(
+                    "{}, {}",
+                    err,
+                    utils::format_lock_holders(lock_dir, &utils::lock_holders(lock_dir))
+                )
+            } else {
+                err
+            }
+        })?;
+        events::publish(|| events::Event::DriverInitialized {
+            unique_id: gpio.unique_id(),
+            gpio_count: gpio.pins().count(),
+        });
+
+        let ready = std::sync::Arc::new(utils::Ready::new(config.ready_file.clone()));
 
-        router::process_loop(signals, driver, gpio)?;
+        router::process_loop(&config, signals, driver, gpio, &ready).map_err(|err| {
+            utils::Shutdown::with_default(err, utils::ShutdownReason::RouterFailure)
+        })?;
 
         Ok(())
     };
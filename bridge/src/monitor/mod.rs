@@ -0,0 +1,101 @@
+use anyhow::{bail, Result};
+use mio::{Events, Interest, Poll, Token};
+use mio_signals::{Signal, Signals};
+use std::collections::BTreeMap;
+
+use crate::gpio;
+use crate::utils;
+
+#[cfg(test)]
+mod tests;
+
+const SIGNAL_TOKEN: Token = Token(0);
+
+// Last value printed per pin, a value is only printed once it changed
+#[derive(Default)]
+struct Tracker {
+    last: BTreeMap<u8, gpio::GpioValue>,
+}
+
+impl Tracker {
+    fn update(&mut self, pin: u8, value: gpio::GpioValue) -> bool {
+        self.last.insert(pin, value) != Some(value)
+    }
+}
+
+// Polls the pins as inputs and prints their value changes to stdout, without
+// the Kernel Driver, until a signal stops the bridge
+pub fn run(gpio: &gpio::Handle, pins: &[u8], interval_ms: u64, mut signals: Signals) -> Result<()> {
+    let names: BTreeMap<u8, String> = gpio.pins().map(|info| (info.pin, info.name)).collect();
+
+    for pin in pins {
+        if !names.contains_key(pin) {
+            bail!("Monitored pin {} is not managed by the bridge", pin);
+        }
+
+        if let Err(err) = gpio.set_gpio_direction(*pin, gpio::GpioDirection::Input) {
+            bail!("Failed to set monitored pin {} as input, Err: {}", pin, err);
+        }
+    }
+
+    log::info!(
+        "Monitoring pins {:?} every {} ms, the Kernel Driver is not used",
+        pins,
+        interval_ms
+    );
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(1);
+    poll.registry()
+        .register(&mut signals, SIGNAL_TOKEN, Interest::READABLE)?;
+
+    let mut tracker = Tracker::default();
+    let interval = std::time::Duration::from_millis(interval_ms);
+
+    loop {
+        for pin in pins {
+            let value = match gpio.get_gpio_value(*pin) {
+                Ok(packet) => match packet.value {
+                    Ok(value) => value,
+                    Err(err) => {
+                        log::warn!("Failed to read monitored pin {}, Err: {}", pin, err);
+                        continue;
+                    }
+                },
+                Err(gpio::Error::Recoverable(err)) => {
+                    log::warn!("Failed to read monitored pin {}, Err: {}", pin, err);
+                    continue;
+                }
+                Err(gpio::Error::Unrecoverable(err)) => bail!("{}", err),
+            };
+
+            if tracker.update(*pin, value) {
+                println!(
+                    "[{}] Pin {} ({}): {:?}",
+                    utils::Monotonic::now(),
+                    pin,
+                    names[pin],
+                    value
+                );
+            }
+        }
+
+        poll.poll(&mut events, Some(interval))?;
+
+        while let Some(signal) = signals.receive()? {
+            match signal {
+                Signal::Interrupt | Signal::Terminate | Signal::User1 => {
+                    bail!(utils::Shutdown {
+                        reason: utils::ShutdownReason::Signal,
+                        detail: utils::ProcessExit::Context(anyhow::anyhow!(
+                            "Received signal: {:?}",
+                            signal
+                        ))
+                        .into(),
+                    });
+                }
+                _ => log::warn!("Received unexpected signal: {:?}", signal),
+            }
+        }
+    }
+}
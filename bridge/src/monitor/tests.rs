@@ -0,0 +1,12 @@
+use super::*;
+
+#[test]
+fn tracker_reports_changes_only() {
+    let mut tracker = Tracker::default();
+
+    assert!(tracker.update(0, gpio::GpioValue::Low));
+    assert!(!tracker.update(0, gpio::GpioValue::Low));
+    assert!(tracker.update(1, gpio::GpioValue::Low));
+    assert!(tracker.update(0, gpio::GpioValue::High));
+    assert!(!tracker.update(1, gpio::GpioValue::Low));
+}
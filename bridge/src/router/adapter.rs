@@ -2,17 +2,36 @@ use anyhow::{bail, Result};
 
 use crate::driver;
 use crate::gpio;
+use crate::utils;
 
 impl TryFrom<&gpio::RecoverableError> for driver::Status {
     type Error = anyhow::Error;
     fn try_from(err: &gpio::RecoverableError) -> Result<Self, Self::Error> {
         match err {
-            gpio::RecoverableError::Timeout(timeout, ms) => {
-                bail!("Timeout({}: {} ms)", timeout, ms)
+            gpio::RecoverableError::Timeout(timeout, ms, transport) => {
+                bail!("Timeout({}: {} ms, Transport: {})", timeout, ms, transport)
             }
             gpio::RecoverableError::Deserialization(_) => Ok(driver::Status::ProtocolError),
             gpio::RecoverableError::Serialization(_) => Ok(driver::Status::ProtocolError),
-            gpio::RecoverableError::Packet(status) => Ok(status.into()),
+            gpio::RecoverableError::Packet { status, .. } => Ok(status.into()),
+            gpio::RecoverableError::InvalidPin(_) => Ok(driver::Status::ProtocolError),
+            gpio::RecoverableError::DuplicatePin(_) => Ok(driver::Status::InvalidArgument),
+            gpio::RecoverableError::Desync(mismatches) => {
+                bail!(
+                    "Desync({} consecutive sequence number mismatches)",
+                    mismatches
+                )
+            }
+            gpio::RecoverableError::ShuttingDown => Ok(driver::Status::BrokenPipe),
+        }
+    }
+}
+
+impl From<gpio::PinInfo> for driver::Line {
+    fn from(info: gpio::PinInfo) -> Self {
+        driver::Line {
+            pin: info.pin as u32,
+            name: info.name,
         }
     }
 }
@@ -28,6 +47,12 @@ impl From<&gpio::Status> for driver::Status {
     }
 }
 
+impl From<&gpio::InvalidGpioValue> for driver::Status {
+    fn from(_: &gpio::InvalidGpioValue) -> Self {
+        driver::Status::ProtocolError
+    }
+}
+
 impl From<&anyhow::Error> for driver::Status {
     fn from(err: &anyhow::Error) -> Self {
         if let Some(err) = err.downcast_ref::<gpio::RecoverableError>() {
@@ -57,6 +82,21 @@ impl From<driver::GpioDirection> for gpio::GpioDirection {
     }
 }
 
+// Firmware without a distinct disabled state gets an unbiased Input instead,
+// the config is applied before the direction
+pub fn gpio_direction(
+    direction: driver::GpioDirection,
+    mapping: utils::DisableMapping,
+) -> (Option<gpio::GpioConfig>, gpio::GpioDirection) {
+    match (direction, mapping) {
+        (driver::GpioDirection::Disabled, utils::DisableMapping::Emulate) => (
+            Some(gpio::GpioConfig::BiasDisable),
+            gpio::GpioDirection::Input,
+        ),
+        (direction, _) => (None, direction.into()),
+    }
+}
+
 impl From<driver::GpioConfig> for gpio::GpioConfig {
     fn from(config: driver::GpioConfig) -> gpio::GpioConfig {
         match config {
@@ -66,6 +106,8 @@ impl From<driver::GpioConfig> for gpio::GpioConfig {
             driver::GpioConfig::DriveOpenDrain => gpio::GpioConfig::DriveOpenDrain,
             driver::GpioConfig::DriveOpenSource => gpio::GpioConfig::DriveOpenSource,
             driver::GpioConfig::DrivePushPull => gpio::GpioConfig::DrivePushPull,
+            driver::GpioConfig::InputEnable => gpio::GpioConfig::InputEnable,
+            driver::GpioConfig::OutputEnable => gpio::GpioConfig::OutputEnable,
         }
     }
 }
@@ -1,30 +1,209 @@
 use anyhow::{anyhow, bail, Result};
 use mio::{Events, Interest, Poll, Token};
 use mio_signals::{Signal, Signals};
+use std::ops::ControlFlow;
 use std::sync::Arc;
 use std::sync::Mutex;
 
 use crate::driver;
+use crate::events;
 use crate::gpio;
 use crate::utils;
 
 mod adapter;
 
+#[cfg(test)]
+mod tests;
+
 const SIGNAL_EXIT_TOKEN: Token = Token(0);
 const GPIO_EXIT_TOKEN: Token = Token(1);
 const DRIVER_EXIT_TOKEN: Token = Token(2);
 const ROUTER_EXIT_TOKEN: Token = Token(3);
 const DRIVER_UNLOAD_EXIT_TOKEN: Token = Token(4);
+const HEALTH_EXIT_TOKEN: Token = Token(5);
+
+const REDISCOVER_POLL_MS: u64 = 500;
+
+// A request in flight on shutdown has completed or timed out by then, unless
+// its command timeout was raised above the default
+const DRAIN_TIMEOUT_MS: u64 = 2000;
+
+// A SIGTERM waits out the grace period before the bridge shuts down, which
+// lets a supervisor that sent it by mistake take it back
+struct ShutdownGrace {
+    grace: std::time::Duration,
+    deadline: Option<std::time::Instant>,
+}
+
+impl ShutdownGrace {
+    fn new(grace_ms: u64) -> Self {
+        Self {
+            grace: std::time::Duration::from_millis(grace_ms),
+            deadline: None,
+        }
+    }
+
+    // Whether the shutdown is deferred, a second SIGTERM does not wait again
+    fn arm(&mut self, now: std::time::Instant) -> bool {
+        if self.grace.is_zero() || self.deadline.is_some() {
+            return false;
+        }
+
+        self.deadline = Some(now + self.grace);
+        true
+    }
+
+    // Whether a deferred shutdown was cancelled
+    fn cancel(&mut self) -> bool {
+        self.deadline.take().is_some()
+    }
+
+    fn remaining(&self, now: std::time::Instant) -> Option<std::time::Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(now))
+    }
+
+    fn expired(&self, now: std::time::Instant) -> bool {
+        self.deadline.is_some_and(|deadline| now >= deadline)
+    }
+}
+
+// Some hardware needs time after a direction change before a read is valid
+struct Settle {
+    delay: std::time::Duration,
+    changed: Vec<Option<std::time::Instant>>,
+}
+
+impl Settle {
+    fn new(delay_us: u64, gpio_count: usize) -> Self {
+        Self {
+            delay: std::time::Duration::from_micros(delay_us),
+            changed: vec![None; gpio_count],
+        }
+    }
+
+    fn on_direction_changed(&mut self, pin: u32, now: std::time::Instant) {
+        if self.delay.is_zero() {
+            return;
+        }
+
+        // Pins added after startup grow the table on their first change
+        if pin as usize >= self.changed.len() {
+            self.changed.resize(pin as usize + 1, None);
+        }
+
+        self.changed[pin as usize] = Some(now);
+    }
+
+    fn remaining(&self, pin: u32, now: std::time::Instant) -> Option<std::time::Duration> {
+        let changed = (*self.changed.get(pin as usize)?)?;
+
+        self.delay
+            .checked_sub(now.duration_since(changed))
+            .filter(|remaining| !remaining.is_zero())
+    }
+}
+
+// Policies applied by the router workers to the Kernel Driver requests
+#[derive(Copy, Clone, Debug)]
+struct Policies {
+    config_not_supported: utils::NotSupportedPolicy,
+    input_write: utils::InputWritePolicy,
+    config_direction: utils::ConfigDirectionPolicy,
+    verify_writes: utils::VerifyWritesPolicy,
+    disable_mapping: utils::DisableMapping,
+}
+
+// Direction of each pin as last set by the Kernel Driver, pins it never set
+// are unknown
+struct Directions {
+    directions: Vec<Option<driver::GpioDirection>>,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum InputWrite {
+    Forward,
+    Reject,
+    SwitchToOutput,
+}
+
+impl Directions {
+    fn new(gpio_count: usize) -> Self {
+        Self {
+            directions: vec![None; gpio_count],
+        }
+    }
+
+    fn on_direction_changed(&mut self, pin: u32, direction: driver::GpioDirection) {
+        if pin as usize >= self.directions.len() {
+            self.directions.resize(pin as usize + 1, None);
+        }
+
+        self.directions[pin as usize] = Some(direction);
+    }
+
+    fn on_value_write(&self, pin: u32, policy: utils::InputWritePolicy) -> InputWrite {
+        let is_input = matches!(
+            self.directions.get(pin as usize),
+            Some(Some(driver::GpioDirection::Input))
+        );
+
+        match (is_input, policy) {
+            (false, _) | (true, utils::InputWritePolicy::Forward) => InputWrite::Forward,
+            (true, utils::InputWritePolicy::Reject) => InputWrite::Reject,
+            (true, utils::InputWritePolicy::Switch) => InputWrite::SwitchToOutput,
+        }
+    }
+
+    // Why a config is unlikely to apply to the pin, firmwares usually reject a
+    // bias on an Output and a drive mode on an Input. Pins of unknown
+    // direction are not checked.
+    fn config_mismatch(&self, pin: u32, config: driver::GpioConfig) -> Option<&'static str> {
+        let direction = (*self.directions.get(pin as usize)?)?;
+
+        match (direction, config) {
+            (
+                driver::GpioDirection::Output,
+                driver::GpioConfig::BiasPullDown | driver::GpioConfig::BiasPullUp,
+            ) => Some("A bias only applies to a pin configured as Input"),
+            (
+                driver::GpioDirection::Input,
+                driver::GpioConfig::DriveOpenDrain
+                | driver::GpioConfig::DriveOpenSource
+                | driver::GpioConfig::DrivePushPull,
+            ) => Some("A drive mode only applies to a pin configured as Output"),
+            _ => None,
+        }
+    }
+}
+
+// Kernel Driver requests are never dropped, a busy worker holds back the
+// dispatcher instead
+pub fn worker_channel(
+    config: &utils::Config,
+) -> (
+    utils::ChannelSender<driver::Packet>,
+    Arc<Mutex<std::sync::mpsc::Receiver<driver::Packet>>>,
+) {
+    let channel_config = utils::ChannelConfig {
+        overflow: utils::Overflow::Block,
+        ..utils::channel_config(config)
+    };
+
+    utils::sync_channel("Router worker", &channel_config)
+}
 
 pub fn process_loop(
+    config: &utils::Config,
     mut signals: Signals,
     mut driver: driver::Handle,
-    mut gpio: gpio::Handle,
+    gpio: Arc<gpio::Handle>,
+    ready: &Arc<utils::Ready>,
 ) -> Result<()> {
     let mut poll = Poll::new()?;
     let mut events = Events::with_capacity(4);
 
-    let (mut router_exit_sender, router_exit_receiver) = mio::unix::pipe::new()?;
+    let (router_exit_sender, router_exit_receiver) = mio::unix::pipe::new()?;
     let mut router_exit = utils::ThreadExit {
         receiver: Mutex::new(router_exit_receiver),
     };
@@ -56,9 +235,10 @@ pub fn process_loop(
         .register(&mut signals, SIGNAL_EXIT_TOKEN, Interest::READABLE)?;
 
     poll.registry().register(
-        gpio.exit
+        &mut *gpio
+            .exit
             .receiver
-            .get_mut()
+            .lock()
             .map_err(|err| anyhow!("{}", err))?,
         GPIO_EXIT_TOKEN,
         Interest::READABLE,
@@ -74,124 +254,522 @@ pub fn process_loop(
         Interest::READABLE,
     )?;
 
-    let gpio = Arc::new(gpio);
-    let gpio_ref = gpio.clone();
+    #[cfg(feature = "uinput")]
+    let _uinput = match &config.uinput_map {
+        Some(path) => Some(crate::uinput::Handle::new(
+            gpio.clone(),
+            path,
+            config.uinput_poll_interval_ms,
+        )?),
+        None => None,
+    };
+
+    let _state_file = match &config.state_file {
+        Some(path) => Some(crate::state_file::Handle::new(
+            gpio.clone(),
+            path,
+            config.state_file_debounce_ms,
+        )?),
+        None => None,
+    };
+
+    let mut health = match config.health_addr {
+        Some(addr) => Some(crate::health::Handle::new(
+            gpio.clone(),
+            ready.clone(),
+            addr,
+            utils::trace(config).libcpc,
+        )?),
+        None => None,
+    };
+
+    if let Some(health) = &mut health {
+        poll.registry().register(
+            health
+                .exit
+                .receiver
+                .get_mut()
+                .map_err(|err| anyhow!("{}", err))?,
+            HEALTH_EXIT_TOKEN,
+            Interest::READABLE,
+        )?;
+    }
 
     let driver = Arc::new(driver);
     let driver_ref = driver.clone();
 
+    let policies = Policies {
+        config_not_supported: config.config_not_supported,
+        input_write: config.input_write,
+        config_direction: config.config_direction,
+        verify_writes: config.verify_writes,
+        disable_mapping: config.disable_mapping,
+    };
+    let workers = config.router_workers as usize;
+    let router_exit_sender = Arc::new(Mutex::new(router_exit_sender));
+    let mut worker_txs = vec![];
+
+    for worker in 0..workers {
+        let (worker_tx, worker_rx) = worker_channel(config);
+        let gpio = gpio.clone();
+        let driver = driver.clone();
+        let router_exit_sender = router_exit_sender.clone();
+        let mut settle = Settle::new(config.settle_us, gpio.pin_count());
+        let mut directions = Directions::new(gpio.pin_count());
+
+        std::thread::Builder::new()
+            .name(format!("router-{}", worker))
+            .spawn(move || loop {
+                // Disconnected once the dispatcher is gone
+                let packet = match worker_rx.lock().map(|worker_rx| worker_rx.recv()) {
+                    Ok(Ok(packet)) => packet,
+                    Ok(Err(_)) => return,
+                    Err(err) => {
+                        notify_router_exit(&router_exit_sender, &format!("{}", err));
+                        return;
+                    }
+                };
+
+                let result = on_packet(
+                    &driver,
+                    &gpio,
+                    &packet,
+                    policies,
+                    &mut settle,
+                    &mut directions,
+                );
+
+                if let Err(err) = result {
+                    notify_router_exit(&router_exit_sender, &format!("{}", err));
+                    return;
+                }
+            })?;
+
+        worker_txs.push(worker_tx);
+    }
+
+    if let Some(interval_ms) = config.hot_add_interval_ms {
+        if !driver.supports_add_gpios() {
+            bail!("Kernel Driver cannot add GPIO's, --hot-add-interval-ms requires Kernel Driver API v1.3 or later");
+        }
+
+        let gpio = gpio.clone();
+        let driver = driver.clone();
+        let router_exit_sender = router_exit_sender.clone();
+        let ready = ready.clone();
+
+        std::thread::Builder::new()
+            .name("hot-add".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(std::time::Duration::from_millis(interval_ms));
+
+                // New pins are only announced once the router is processing
+                if !ready.wait(std::time::Duration::ZERO) {
+                    continue;
+                }
+
+                if let Err(err) = on_hot_add(&driver, &gpio) {
+                    notify_router_exit(&router_exit_sender, &format!("{}", err));
+                    return;
+                }
+            })?;
+    }
+
+    if let Some(window_ms) = config.stall_window_ms {
+        let heartbeats = [gpio.reader_heartbeat(), driver.reader_heartbeat()];
+        let router_exit_sender = router_exit_sender.clone();
+        let window = std::time::Duration::from_millis(window_ms);
+
+        std::thread::Builder::new()
+            .name("supervisor".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(window / 2);
+
+                for heartbeat in &heartbeats {
+                    if let Some(busy) = heartbeat.stalled(window) {
+                        notify_router_exit(
+                            &router_exit_sender,
+                            &format!(
+                                "{} reader thread stalled for {} ms (Window: {} ms)",
+                                heartbeat.name,
+                                busy.as_millis(),
+                                window_ms
+                            ),
+                        );
+                        return;
+                    }
+                }
+            })?;
+    }
+
+    if let Some(interval_ms) = config.reconcile_interval_ms {
+        let gpio = gpio.clone();
+        let router_exit_sender = router_exit_sender.clone();
+        let ready = ready.clone();
+
+        std::thread::Builder::new()
+            .name("reconcile".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(std::time::Duration::from_millis(interval_ms));
+
+                // Presets are applied before the router starts processing
+                if !ready.wait(std::time::Duration::ZERO) {
+                    continue;
+                }
+
+                match gpio.reconcile_outputs() {
+                    Ok(pins) if !pins.is_empty() => {
+                        log::info!("Reconciled output pin(s): {:?}", pins)
+                    }
+                    Ok(_) => {}
+                    Err(gpio::Error::Unrecoverable(err)) => {
+                        notify_router_exit(&router_exit_sender, &format!("{}", err));
+                        return;
+                    }
+                    Err(err) => log::debug!("Reconciliation skipped, Err: {}", err),
+                }
+            })?;
+    }
+
+    if let Some(path) = config.rediscover_file.clone() {
+        let gpio = gpio.clone();
+        let router_exit_sender = router_exit_sender.clone();
+        let ready = ready.clone();
+
+        std::thread::Builder::new()
+            .name("rediscover".to_string())
+            .spawn(move || loop {
+                std::thread::sleep(std::time::Duration::from_millis(REDISCOVER_POLL_MS));
+
+                // Discovery only runs again once the startup is complete
+                if !ready.wait(std::time::Duration::ZERO) {
+                    continue;
+                }
+
+                match take_trigger(&path) {
+                    Ok(true) => on_rediscover(&gpio),
+                    Ok(false) => {}
+                    Err(err) => {
+                        notify_router_exit(&router_exit_sender, &format!("{}", err));
+                        return;
+                    }
+                }
+            })?;
+    }
+
     std::thread::Builder::new()
         .name("router".to_string())
         .spawn(move || {
-            let gpio = gpio_ref;
             let driver = driver_ref;
             loop {
                 let packet = match driver.read() {
                     Ok(packet) => packet,
                     Err(err) => {
-                        utils::ThreadExit::notify(
-                            &mut router_exit_sender,
+                        notify_router_exit(
+                            &router_exit_sender,
                             &format!("Failed to read from Driver channel, Err: {}", err),
                         );
                         return;
                     }
                 };
 
-                let result = match driver.parse(packet) {
-                    Ok(packet) => match &packet {
-                        driver::Packet::GetGpioValue(packet) => {
-                            on_gpio_get_value(&driver, &gpio, packet)
-                        }
-                        driver::Packet::SetGpioValue(packet) => {
-                            on_gpio_set_value(&driver, &gpio, packet)
-                        }
-                        driver::Packet::SetGpioConfig(packet) => {
-                            on_gpio_set_config(&driver, &gpio, packet)
-                        }
-                        driver::Packet::SetGpioDirection(packet) => {
-                            on_gpio_set_direction(&driver, &gpio, packet)
-                        }
-                        driver::Packet::Exit(packet) => {
-                            utils::ThreadExit::notify(
-                                &mut driver_unload_exit_sender,
-                                &format!("{}", packet.message),
-                            );
-                            return;
-                        }
-                    },
-                    Err(err) => Err(err),
+                let packet = match driver.parse(packet) {
+                    Ok(driver::Packet::Exit(packet)) => {
+                        utils::ThreadExit::notify(
+                            &mut driver_unload_exit_sender,
+                            &format!("{}", packet.message),
+                        );
+                        return;
+                    }
+                    Ok(driver::Packet::Discard) => {
+                        log::debug!("Discarded Kernel Driver packet");
+                        continue;
+                    }
+                    Ok(packet) => packet,
+                    Err(err) => {
+                        notify_router_exit(&router_exit_sender, &format!("{}", err));
+                        return;
+                    }
                 };
 
-                if let Err(err) = result {
-                    utils::ThreadExit::notify(&mut router_exit_sender, &format!("{}", err));
+                let worker = worker_index(&packet, worker_txs.len());
+
+                if let Err(err) = worker_txs[worker].send(packet) {
+                    notify_router_exit(
+                        &router_exit_sender,
+                        &format!("Failed to send to router-{}, Err: {}", worker, err),
+                    );
                     return;
                 }
             }
         })?;
 
-    loop {
-        poll.poll(&mut events, None)?;
-        for event in events.iter() {
-            match event.token() {
-                SIGNAL_EXIT_TOKEN => on_signal_exit(&mut signals, &driver, &gpio)?,
-                GPIO_EXIT_TOKEN => on_gpio_thread_exit(&driver, &gpio)?,
-                DRIVER_EXIT_TOKEN => on_driver_thread_exit(&driver, &gpio)?,
-                ROUTER_EXIT_TOKEN => on_router_thread_exit(&router_exit, &driver, &gpio)?,
-                DRIVER_UNLOAD_EXIT_TOKEN => on_driver_unload_exit(&driver_unload_exit)?,
-                _ => log::warn!("Unexpected event: {:?}", event),
+    ready.mark()?;
+
+    let mut shutdown_grace = ShutdownGrace::new(config.shutdown_grace_ms);
+
+    let result = (|| -> Result<()> {
+        loop {
+            poll.poll(
+                &mut events,
+                shutdown_grace.remaining(std::time::Instant::now()),
+            )?;
+            for event in events.iter() {
+                match event.token() {
+                    SIGNAL_EXIT_TOKEN => on_signal(
+                        &mut signals,
+                        &mut shutdown_grace,
+                        &driver,
+                        &gpio,
+                        config.log_tag,
+                    )?,
+                    GPIO_EXIT_TOKEN => on_gpio_thread_exit(&driver, &gpio, config.last_will)?,
+                    DRIVER_EXIT_TOKEN => on_driver_thread_exit(&driver, &gpio, config.last_will)?,
+                    ROUTER_EXIT_TOKEN => {
+                        on_router_thread_exit(&router_exit, &driver, &gpio, config.last_will)?
+                    }
+                    DRIVER_UNLOAD_EXIT_TOKEN => on_driver_unload_exit(&driver_unload_exit)?,
+                    HEALTH_EXIT_TOKEN => {
+                        if let Some(health) = &health {
+                            on_health_thread_exit(&health.exit, &driver, &gpio)?
+                        }
+                    }
+                    _ => log::warn!("Unexpected event: {:?}", event),
+                }
+            }
+            if shutdown_grace.expired(std::time::Instant::now()) {
+                on_shutdown(Signal::Terminate, &driver, &gpio)?;
             }
         }
+    })();
+
+    // The chip is deinitialized by now, no more requests are expected
+    driver.stop_reader();
+
+    result
+}
+
+// Requests for a pin always go to the same worker, which preserves the
+// ordering of their replies
+fn worker_index(packet: &driver::Packet, workers: usize) -> usize {
+    let pin = match packet {
+        driver::Packet::GetGpioValue(packet) => packet.pin,
+        driver::Packet::SetGpioValue(packet) => packet.pin,
+        driver::Packet::SetGpioConfig(packet) => packet.pin,
+        driver::Packet::SetGpioDirection(packet) => packet.pin,
+        // Pins of a SetGpioValues are applied together by a single worker, which
+        // only knows the directions of the pins it owns
+        driver::Packet::GetAllGpioValues(_)
+        | driver::Packet::SetGpioValues(_)
+        | driver::Packet::Discard
+        | driver::Packet::Exit(_) => 0,
+    };
+
+    pin as usize % workers
+}
+
+fn on_packet(
+    driver: &driver::Handle,
+    gpio: &gpio::Handle,
+    packet: &driver::Packet,
+    policies: Policies,
+    settle: &mut Settle,
+    directions: &mut Directions,
+) -> Result<()> {
+    let input_write = policies.input_write;
+
+    match packet {
+        driver::Packet::GetGpioValue(packet) => on_gpio_get_value(driver, gpio, packet, settle),
+        driver::Packet::GetAllGpioValues(packet) => on_gpio_get_all_values(driver, gpio, packet),
+        driver::Packet::SetGpioValue(packet) => {
+            on_gpio_set_value(driver, gpio, packet, policies, settle, directions)
+        }
+        driver::Packet::SetGpioValues(packet) => {
+            on_gpio_set_values(driver, gpio, packet, input_write, settle, directions)
+        }
+        driver::Packet::SetGpioConfig(packet) => {
+            on_gpio_set_config(driver, gpio, packet, policies, directions)
+        }
+        driver::Packet::SetGpioDirection(packet) => {
+            on_gpio_set_direction(driver, gpio, packet, policies, settle, directions)
+        }
+        driver::Packet::Discard => Ok(()),
+        driver::Packet::Exit(packet) => bail!("Unexpected {:?} on a router worker", packet),
     }
 }
 
-fn on_gpio_thread_exit(driver: &driver::Handle, gpio: &gpio::Handle) -> Result<()> {
-    if let Err(err) = driver.deinit(gpio.chip.unique_id) {
-        bail!(format!("{}, {}", gpio.exit, err));
-    } else {
-        bail!(format!("{}", gpio.exit));
+fn on_hot_add(driver: &driver::Handle, gpio: &gpio::Handle) -> Result<()> {
+    let pins = gpio.get_new_pins()?;
+
+    if pins.is_empty() {
+        return Ok(());
+    }
+
+    // Registered with the bridge first, so that the kernel requests for the
+    // new pins are not rejected as invalid
+    gpio.register_pins(&pins)?;
+
+    let lines: Vec<driver::Line> = pins.iter().cloned().map(Into::into).collect();
+    match driver.add_gpios(gpio.unique_id(), &lines) {
+        // The chip is only registered again once its GPIO's are released
+        Err(err) if err.is::<driver::ChipBusy>() => {
+            log::warn!("{}, retrying on the next poll", err);
+            gpio.unregister_pins(&pins)
+        }
+        result => result,
     }
 }
 
-fn on_driver_thread_exit(driver: &driver::Handle, gpio: &gpio::Handle) -> Result<()> {
-    if let Err(err) = driver.deinit(gpio.chip.unique_id) {
-        bail!(format!("{}, {}", driver.exit, err));
-    } else {
-        bail!(format!("{}", driver.exit));
+fn notify_router_exit(sender: &Mutex<mio::unix::pipe::Sender>, message: &str) {
+    match sender.lock() {
+        Ok(mut sender) => utils::ThreadExit::notify(&mut sender, message),
+        Err(err) => utils::exit(anyhow!("{}, Err: {}", message, err)),
     }
 }
 
+// Takes the message already read from the exit pipe, a second read would
+// come back empty
+fn send_last_will(driver: &driver::Handle, gpio: &gpio::Handle, last_will: bool, message: &str) {
+    if !last_will {
+        return;
+    }
+
+    if let Err(err) = driver.send_exit_message(gpio.unique_id(), message) {
+        log::warn!(
+            "Failed to send the last will to the Kernel Driver, Err: {}",
+            err
+        );
+    }
+}
+
+fn on_gpio_thread_exit(
+    driver: &driver::Handle,
+    gpio: &gpio::Handle,
+    last_will: bool,
+) -> Result<()> {
+    log_diagnostics(gpio);
+
+    let message = gpio.exit.to_string();
+    send_last_will(driver, gpio, last_will, &message);
+
+    let detail = match driver.deinit(gpio.unique_id()) {
+        Err(err) => anyhow!("{}, {}", message, err),
+        Ok(_) => anyhow!("{}", message),
+    };
+
+    bail!(utils::Shutdown {
+        reason: utils::ShutdownReason::GpioThreadFailure,
+        detail,
+    });
+}
+
+fn on_driver_thread_exit(
+    driver: &driver::Handle,
+    gpio: &gpio::Handle,
+    last_will: bool,
+) -> Result<()> {
+    log_diagnostics(gpio);
+
+    let message = driver.exit.to_string();
+    send_last_will(driver, gpio, last_will, &message);
+
+    let detail = match driver.deinit(gpio.unique_id()) {
+        Err(err) => anyhow!("{}, {}", message, err),
+        Ok(_) => anyhow!("{}", message),
+    };
+
+    bail!(utils::Shutdown {
+        reason: utils::ShutdownReason::DriverThreadFailure,
+        detail,
+    });
+}
+
 fn on_router_thread_exit(
     exit: &utils::ThreadExit,
     driver: &driver::Handle,
     gpio: &gpio::Handle,
+    last_will: bool,
 ) -> Result<()> {
-    if let Err(err) = driver.deinit(gpio.chip.unique_id) {
-        bail!(format!("{}, {}", exit, err));
-    } else {
-        bail!(format!("{}", exit));
+    log_diagnostics(gpio);
+
+    let message = exit.to_string();
+    send_last_will(driver, gpio, last_will, &message);
+
+    let detail = match driver.deinit(gpio.unique_id()) {
+        Err(err) => anyhow!("{}, {}", message, err),
+        Ok(_) => anyhow!("{}", message),
+    };
+
+    bail!(utils::Shutdown {
+        reason: utils::ShutdownReason::RouterFailure,
+        detail,
+    });
+}
+
+// The GPIO's still work, but an orchestrator probing the endpoint would
+// restart the bridge anyway
+fn on_health_thread_exit(
+    exit: &utils::ThreadExit,
+    driver: &driver::Handle,
+    gpio: &gpio::Handle,
+) -> Result<()> {
+    let message = exit.to_string();
+
+    let detail = match driver.deinit(gpio.unique_id()) {
+        Err(err) => anyhow!("{}, {}", message, err),
+        Ok(_) => anyhow!("{}", message),
+    };
+
+    bail!(utils::Shutdown {
+        reason: utils::ShutdownReason::HealthFailure,
+        detail,
+    });
+}
+
+fn log_diagnostics(gpio: &gpio::Handle) {
+    let diagnostics = gpio.diagnostics();
+
+    if let Some(last) = diagnostics.last() {
+        log::error!(
+            "{} link diagnostic(s) preceded the exit, last: {}",
+            diagnostics.len(),
+            last
+        );
     }
 }
 
 fn on_driver_unload_exit(exit: &utils::ThreadExit) -> Result<()> {
-    bail!(utils::ProcessExit::Context(anyhow!(format!("{}", exit))));
+    bail!(utils::Shutdown {
+        reason: utils::ShutdownReason::DriverUnload,
+        detail: utils::ProcessExit::Context(anyhow!(format!("{}", exit))).into(),
+    });
 }
 
-fn on_signal_exit(
+fn on_signal(
     signals: &mut Signals,
+    shutdown_grace: &mut ShutdownGrace,
     driver: &driver::Handle,
     gpio: &gpio::Handle,
+    log_tag: utils::LogTag,
 ) -> Result<()> {
     loop {
         if let Some(signal) = signals.receive()? {
             match signal {
+                Signal::Terminate if shutdown_grace.arm(std::time::Instant::now()) => {
+                    log::warn!(
+                        "Received signal: {:?}, shutting down in {} ms unless cancelled by {:?}",
+                        signal,
+                        shutdown_grace.grace.as_millis(),
+                        Signal::User1
+                    )
+                }
+                Signal::User1 if shutdown_grace.cancel() => {
+                    log::warn!("Received signal: {:?}, shutdown cancelled", signal)
+                }
                 Signal::Interrupt | Signal::Terminate | Signal::User1 => {
-                    let context = format!("Received signal: {:?}", signal);
-                    if let Err(err) = driver.deinit(gpio.chip.unique_id) {
-                        bail!(format!("{}, {}", context, err));
-                    } else {
-                        bail!(utils::ProcessExit::Context(anyhow!(context)));
-                    }
+                    on_shutdown(signal, driver, gpio)?
                 }
+                Signal::User2 => on_reset_all(driver, gpio, log_tag)?,
                 _ => log::warn!("Received unexpected signal: {:?}", signal),
             }
         } else {
@@ -202,18 +780,198 @@ fn on_signal_exit(
     Ok(())
 }
 
+fn on_shutdown(signal: Signal, driver: &driver::Handle, gpio: &gpio::Handle) -> Result<()> {
+    let context = format!("Received signal: {:?}", signal);
+    if let Some(stats) = gpio.rtt_stats() {
+        log::info!("Link RTT {{ {} }}", stats);
+    }
+    if gpio.reconciled_count() > 0 {
+        log::info!("Reconciled output pins: {}", gpio.reconciled_count());
+    }
+    if let Some(seq) = gpio.last_seq() {
+        log::info!("Last request seq: {}", seq);
+    }
+    if !gpio.drain(std::time::Duration::from_millis(DRAIN_TIMEOUT_MS)) {
+        log::warn!(
+            "GPIO request still in flight after {} ms, deinitializing anyway",
+            DRAIN_TIMEOUT_MS
+        );
+    }
+    let detail = match driver.deinit(gpio.unique_id()) {
+        Err(err) => anyhow!("{}, {}", context, err),
+        Ok(_) => utils::ProcessExit::Context(anyhow!(context)).into(),
+    };
+
+    bail!(utils::Shutdown {
+        reason: utils::ShutdownReason::Signal,
+        detail,
+    });
+}
+
+// A trigger file that is created again while discovery runs is only taken on
+// the next poll
+fn take_trigger(path: &std::path::Path) -> Result<bool> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => bail!(
+            "Failed to remove the rediscover file ({}), Err: {}",
+            path.display(),
+            err
+        ),
+    }
+}
+
+// Read-only check that the secondary still answers discovery as it did at
+// startup, nothing is reset or registered again
+fn on_rediscover(gpio: &gpio::Handle) {
+    let discovery = match gpio.rediscover() {
+        Ok(discovery) => discovery,
+        Err(err) => {
+            log::warn!("Rediscovery failed, Err: {}", err);
+            return;
+        }
+    };
+
+    log::info!("Rediscovery {{ {} }}", discovery);
+    for (pin, name) in discovery.names.iter().enumerate() {
+        match name {
+            Ok(name) => log::info!("Rediscovered pin {} name: {:?}", pin, name),
+            Err(err) => log::warn!("Failed to get GPIO name (Pin: {}), Err: {}", pin, err),
+        }
+    }
+
+    let changes = gpio.discovery_changes(&discovery);
+    if changes.is_empty() {
+        log::info!("Rediscovery matches the startup discovery");
+    } else {
+        log::warn!("Secondary changed since startup: {}", changes.join(", "));
+    }
+}
+
+fn on_reset_all(
+    driver: &driver::Handle,
+    gpio: &gpio::Handle,
+    log_tag: utils::LogTag,
+) -> Result<()> {
+    // Replies buffered from before the reset must not answer its requests
+    if let Err(err) = gpio.resync() {
+        log::warn!("Failed to resynchronize the link, Err: {}", err);
+    }
+
+    let failed_pins = match gpio.reset_all() {
+        Ok(failed) => {
+            if failed.is_empty() {
+                log::info!("Reset all pins");
+            } else {
+                log::warn!("Reset all pins, failed pins: {:?}", failed);
+            }
+            failed
+        }
+        Err(err) => {
+            let context = format!("Failed to reset all pins, Err: {}", err);
+            if let Err(err) = driver.deinit(gpio.unique_id()) {
+                bail!(format!("{}, {}", context, err));
+            } else {
+                bail!(context);
+            }
+        }
+    };
+
+    match gpio.refresh_unique_id() {
+        Ok(Some(previous_id)) => {
+            on_unique_id_changed(driver, gpio, previous_id, log_tag)?;
+            events::publish(|| events::Event::Resync {
+                unique_id: gpio.unique_id(),
+                failed_pins,
+                previous_id: Some(previous_id),
+            });
+            return Ok(());
+        }
+        Ok(None) => {}
+        Err(err) => log::warn!("Failed to re-read the unique id, Err: {}", err),
+    }
+
+    if driver.supports_update_names() {
+        let lines: Vec<driver::Line> = gpio.pins().map(Into::into).collect();
+        if let Err(err) = driver.update_names(gpio.unique_id(), &gpio.chip.label, &lines) {
+            log::warn!("Failed to resync GPIO names, Err: {}", err);
+        }
+    }
+
+    events::publish(|| events::Event::Resync {
+        unique_id: gpio.unique_id(),
+        failed_pins,
+        previous_id: None,
+    });
+
+    Ok(())
+}
+
+// The Kernel Driver filters requests by unique id, the chip must be moved to
+// the new id or its requests would silently go unanswered
+fn on_unique_id_changed(
+    driver: &driver::Handle,
+    gpio: &gpio::Handle,
+    previous_id: u64,
+    log_tag: utils::LogTag,
+) -> Result<()> {
+    let unique_id = gpio.unique_id();
+
+    log::warn!(
+        "Secondary unique id changed (Previous: {}, Current: {}), reinitializing the Kernel Driver",
+        previous_id,
+        unique_id
+    );
+
+    if log_tag == utils::LogTag::UniqueId {
+        utils::set_log_tag(unique_id.to_string());
+    }
+
+    let lines: Vec<driver::Line> = gpio.pins().map(Into::into).collect();
+    driver
+        .reinit(previous_id, unique_id, &gpio.chip.label, &lines)
+        .map_err(|err| {
+            anyhow!(
+                "Failed to reinitialize the Kernel Driver with unique id {}, Err: {}",
+                unique_id,
+                err
+            )
+        })
+}
+
 fn on_gpio_get_value(
     driver: &driver::Handle,
     gpio: &gpio::Handle,
     packet: &driver::GetGpioValue,
+    settle: &Settle,
 ) -> Result<()> {
-    log::debug!("UID {{ {:?} }} {:?}", gpio.chip.unique_id, packet);
-    let (value, status) = match gpio.get_gpio_value(packet.pin.try_into()?) {
+    log::debug!(
+        "[{}] UID {{ {:?} }} {:?}",
+        utils::Monotonic::now(),
+        gpio.unique_id(),
+        packet
+    );
+    if let Some(remaining) = settle.remaining(packet.pin, std::time::Instant::now()) {
+        std::thread::sleep(remaining);
+    }
+    let (value, status) = match gpio.get_gpio_value_timeout(
+        packet.pin.try_into()?,
+        driver_timeout(gpio, gpio::HostCmd::GetGpioValue),
+    ) {
         Ok(gpio_value) => match gpio_value.value {
-            Ok(value) => (Some(value as u32), Some(driver::Status::Ok)),
+            Ok(value) => {
+                log::debug!(
+                    "{:?}, Value: {:?} (Source: {:?})",
+                    packet,
+                    value,
+                    gpio_value.source
+                );
+                (Some(value as u32), Some(driver::Status::Ok))
+            }
             Err(err) => {
                 log::warn!("{:?}, Err: {}", packet, err);
-                (None, (&err).try_into().ok())
+                (None, Some((&err).into()))
             }
         },
         Err(err) => match err {
@@ -225,7 +983,109 @@ fn on_gpio_get_value(
         },
     };
 
-    driver.get_gpio_value_reply(gpio.chip.unique_id, packet.pin, value, status)?;
+    driver.get_gpio_value_reply(gpio.unique_id(), packet.pin, value, status)?;
+
+    Ok(())
+}
+
+// Reads for the Kernel Driver wait no longer than it does, a later reply would
+// only hold up the link
+fn driver_timeout(gpio: &gpio::Handle, cmd: gpio::HostCmd) -> Option<std::time::Duration> {
+    Some(gpio.capped_timeout(
+        cmd,
+        std::time::Duration::from_millis(driver::REPLY_TIMEOUT_MS),
+    ))
+}
+
+// A pin and its value, or why it could not be read
+type Reading = (
+    u8,
+    Result<Result<gpio::GpioValue, gpio::InvalidGpioValue>, gpio::Error>,
+);
+
+// One value and status per managed pin, in line order, a pin failing to be
+// read only fails its own line
+fn all_values_reply(
+    packet: &driver::GetAllGpioValues,
+    readings: Vec<Reading>,
+) -> Result<(Vec<u32>, Vec<driver::Status>)> {
+    let mut values = vec![];
+    let mut statuses = vec![];
+
+    for (pin, reading) in readings {
+        let (value, status) = match reading {
+            Ok(Ok(value)) => (value as u32, driver::Status::Ok),
+            Ok(Err(err)) => {
+                log::warn!("{:?} {{ Pin: {} }}, Err: {}", packet, pin, err);
+                (0, (&err).into())
+            }
+            Err(gpio::Error::Recoverable(err)) => {
+                log::warn!("{:?} {{ Pin: {} }}, Err: {}", packet, pin, err);
+                (0, (&err).try_into().unwrap_or(driver::Status::Unknown))
+            }
+            Err(gpio::Error::Unrecoverable(err)) => bail!("{}", err),
+        };
+
+        values.push(value);
+        statuses.push(status);
+    }
+
+    Ok((values, statuses))
+}
+
+// A failed pin is reported through its status, the dump itself only fails
+// on an unrecoverable error.
+fn on_gpio_get_all_values(
+    driver: &driver::Handle,
+    gpio: &gpio::Handle,
+    packet: &driver::GetAllGpioValues,
+) -> Result<()> {
+    log::debug!(
+        "[{}] UID {{ {:?} }} {:?}",
+        utils::Monotonic::now(),
+        gpio.unique_id(),
+        packet
+    );
+    let pins: Vec<u8> = gpio.pins().map(|info| info.pin).collect();
+
+    // Secondaries older than GetGpioValues are read one pin at a time
+    let readings: Vec<Reading> = match gpio
+        .get_gpio_values_timeout(&pins, driver_timeout(gpio, gpio::HostCmd::GetGpioValues))
+    {
+        Ok(values) => values
+            .into_iter()
+            .map(|(pin, value)| (pin, Ok(value)))
+            .collect(),
+        Err(gpio::Error::Recoverable(gpio::RecoverableError::Packet {
+            status: gpio::Status::NotSupported,
+            ..
+        })) => pins
+            .iter()
+            .map(|pin| {
+                let timeout = driver_timeout(gpio, gpio::HostCmd::GetGpioValue);
+                (
+                    *pin,
+                    gpio.get_gpio_value_timeout(*pin, timeout)
+                        .map(|packet| packet.value),
+                )
+            })
+            .collect(),
+        Err(gpio::Error::Recoverable(err)) => {
+            log::warn!("{:?}, Err: {}", packet, err);
+            let status = (&err).try_into().unwrap_or(driver::Status::Unknown);
+            driver.get_all_gpio_values_reply(
+                gpio.unique_id(),
+                packet.batch_id,
+                vec![0; pins.len()],
+                vec![status; pins.len()],
+            )?;
+            return Ok(());
+        }
+        Err(gpio::Error::Unrecoverable(err)) => bail!("{}", err),
+    };
+
+    let (values, statuses) = all_values_reply(packet, readings)?;
+    driver.get_all_gpio_values_reply(gpio.unique_id(), packet.batch_id, values, statuses)?;
 
     Ok(())
 }
@@ -234,9 +1094,120 @@ fn on_gpio_set_value(
     driver: &driver::Handle,
     gpio: &gpio::Handle,
     packet: &driver::SetGpioValue,
+    policies: Policies,
+    settle: &mut Settle,
+    directions: &mut Directions,
+) -> Result<()> {
+    log::debug!(
+        "[{}] UID {{ {:?} }} {:?}",
+        utils::Monotonic::now(),
+        gpio.unique_id(),
+        packet
+    );
+
+    if let ControlFlow::Break(status) = check_input_write(
+        gpio,
+        packet,
+        packet.pin,
+        policies.input_write,
+        settle,
+        directions,
+    )? {
+        driver.set_gpio_value_reply(gpio.unique_id(), packet.pin, status)?;
+        return Ok(());
+    }
+
+    let pin = packet.pin.try_into()?;
+    let status = match gpio.set_gpio_value(pin, packet.value.into()) {
+        Ok(_) if policies.verify_writes == utils::VerifyWritesPolicy::Off => {
+            Some(driver::Status::Ok)
+        }
+        Ok(_) => verify_write_status(
+            packet,
+            gpio.get_gpio_value_timeout(pin, driver_timeout(gpio, gpio::HostCmd::GetGpioValue))
+                .map(|readback| (readback.value, readback.source)),
+            policies.verify_writes,
+        )?,
+        Err(err) => match err {
+            gpio::Error::Recoverable(err) => {
+                log::warn!("{:?}, Err: {}", packet, err);
+                (&err).try_into().ok()
+            }
+            gpio::Error::Unrecoverable(err) => bail!("{}", err),
+        },
+    };
+
+    driver.set_gpio_value_reply(gpio.unique_id(), packet.pin, status)?;
+
+    Ok(())
+}
+
+// Applies the input write policy to a value write of a pin, breaks with the
+// status to reply when the write must not be forwarded
+fn check_input_write(
+    gpio: &gpio::Handle,
+    packet: &impl std::fmt::Debug,
+    pin: u32,
+    input_write: utils::InputWritePolicy,
+    settle: &mut Settle,
+    directions: &mut Directions,
+) -> Result<ControlFlow<Option<driver::Status>>> {
+    match directions.on_value_write(pin, input_write) {
+        InputWrite::Forward => (),
+        InputWrite::Reject => {
+            log::warn!("{:?}, Err: Pin {} is configured as Input", packet, pin);
+            return Ok(ControlFlow::Break(Some(driver::Status::NotSupported)));
+        }
+        InputWrite::SwitchToOutput => {
+            log::info!("{:?}, switching pin {} from Input to Output", packet, pin);
+            match gpio.set_gpio_direction(pin.try_into()?, gpio::GpioDirection::Output) {
+                Ok(_) => {
+                    settle.on_direction_changed(pin, std::time::Instant::now());
+                    directions.on_direction_changed(pin, driver::GpioDirection::Output);
+                }
+                Err(gpio::Error::Recoverable(err)) => {
+                    log::warn!("{:?}, Err: {}", packet, err);
+                    return Ok(ControlFlow::Break((&err).try_into().ok()));
+                }
+                Err(gpio::Error::Unrecoverable(err)) => bail!("{}", err),
+            }
+        }
+    }
+
+    Ok(ControlFlow::Continue(()))
+}
+
+fn on_gpio_set_values(
+    driver: &driver::Handle,
+    gpio: &gpio::Handle,
+    packet: &driver::SetGpioValues,
+    input_write: utils::InputWritePolicy,
+    settle: &mut Settle,
+    directions: &mut Directions,
 ) -> Result<()> {
-    log::debug!("UID {{ {:?} }} {:?}", gpio.chip.unique_id, packet);
-    let status = match gpio.set_gpio_value(packet.pin.try_into()?, packet.value.into()) {
+    log::debug!(
+        "[{}] UID {{ {:?} }} {:?}",
+        utils::Monotonic::now(),
+        gpio.unique_id(),
+        packet
+    );
+
+    for (pin, _) in &packet.values {
+        if let ControlFlow::Break(status) =
+            check_input_write(gpio, packet, *pin, input_write, settle, directions)?
+        {
+            driver.set_gpio_values_reply(gpio.unique_id(), packet.batch_id, status)?;
+            return Ok(());
+        }
+    }
+
+    let values = packet
+        .values
+        .iter()
+        .map(|(pin, value)| Ok(((*pin).try_into()?, (*value).into())))
+        .collect::<Result<Vec<_>>>()?;
+
+    let status = match gpio.set_gpio_values(&values) {
         Ok(_) => Some(driver::Status::Ok),
         Err(err) => match err {
             gpio::Error::Recoverable(err) => {
@@ -247,7 +1218,7 @@ fn on_gpio_set_value(
         },
     };
 
-    driver.set_gpio_value_reply(gpio.chip.unique_id, packet.pin, status)?;
+    driver.set_gpio_values_reply(gpio.unique_id(), packet.batch_id, status)?;
 
     Ok(())
 }
@@ -256,11 +1227,115 @@ fn on_gpio_set_config(
     driver: &driver::Handle,
     gpio: &gpio::Handle,
     packet: &driver::SetGpioConfig,
+    policies: Policies,
+    directions: &Directions,
 ) -> Result<()> {
-    log::debug!("UID {{ {:?} }} {:?}", gpio.chip.unique_id, packet);
-    let status = match gpio.set_gpio_config(packet.pin.try_into()?, packet.config.into()) {
+    log::debug!(
+        "[{}] UID {{ {:?} }} {:?}",
+        utils::Monotonic::now(),
+        gpio.unique_id(),
+        packet
+    );
+
+    if let Some(reason) = directions.config_mismatch(packet.pin, packet.config) {
+        match policies.config_direction {
+            utils::ConfigDirectionPolicy::Warn => log::warn!("{:?}, {}", packet, reason),
+            utils::ConfigDirectionPolicy::Reject => {
+                log::warn!("{:?}, Err: {}", packet, reason);
+                driver.set_gpio_config_reply(
+                    gpio.unique_id(),
+                    packet.pin,
+                    Some(driver::Status::NotSupported),
+                )?;
+                return Ok(());
+            }
+        }
+    }
+
+    let result = gpio.set_gpio_config(packet.pin.try_into()?, packet.config.into());
+    let status = set_gpio_config_status(packet, result, policies.config_not_supported)?;
+
+    driver.set_gpio_config_reply(gpio.unique_id(), packet.pin, status)?;
+
+    Ok(())
+}
+
+// Status of a value write once the pin was read back, a pin that did not reach
+// the value fails the write only with the fail policy. A failed read back
+// leaves the write as it is, and so does a mismatch on a pin whose level is
+// sampled: an input does not take the written value.
+fn verify_write_status(
+    packet: &driver::SetGpioValue,
+    readback: Result<
+        (
+            Result<gpio::GpioValue, gpio::InvalidGpioValue>,
+            Option<gpio::GpioValueSource>,
+        ),
+        gpio::Error,
+    >,
+    verify_writes: utils::VerifyWritesPolicy,
+) -> Result<Option<driver::Status>> {
+    let expected: gpio::GpioValue = packet.value.into();
+
+    let (read, source) = match readback {
+        Ok(readback) => readback,
+        Err(gpio::Error::Recoverable(err)) => {
+            log::warn!("{:?}, Failed to read back the value, Err: {}", packet, err);
+            return Ok(Some(driver::Status::Ok));
+        }
+        Err(gpio::Error::Unrecoverable(err)) => bail!("{}", err),
+    };
+
+    match read {
+        Ok(value) if value == expected => return Ok(Some(driver::Status::Ok)),
+        Ok(value) if source == Some(gpio::GpioValueSource::Sampled) => {
+            log::debug!(
+                "{:?}, Read back a sampled level (Expected: {:?}, Read: {:?})",
+                packet,
+                expected,
+                value
+            );
+            return Ok(Some(driver::Status::Ok));
+        }
+        Ok(value) => log::warn!(
+            "{:?}, Read back mismatch (Expected: {:?}, Read: {:?})",
+            packet,
+            expected,
+            value
+        ),
+        Err(err) => log::warn!(
+            "{:?}, Read back mismatch (Expected: {:?}, Err: {})",
+            packet,
+            expected,
+            err
+        ),
+    }
+
+    match verify_writes {
+        utils::VerifyWritesPolicy::Fail => Ok(Some(driver::Status::Unknown)),
+        utils::VerifyWritesPolicy::Off | utils::VerifyWritesPolicy::Warn => {
+            Ok(Some(driver::Status::Ok))
+        }
+    }
+}
+
+fn set_gpio_config_status(
+    packet: &driver::SetGpioConfig,
+    result: Result<(), gpio::Error>,
+    not_supported: utils::NotSupportedPolicy,
+) -> Result<Option<driver::Status>> {
+    let status = match result {
         Ok(_) => Some(driver::Status::Ok),
         Err(err) => match err {
+            gpio::Error::Recoverable(
+                err @ gpio::RecoverableError::Packet {
+                    status: gpio::Status::NotSupported,
+                    ..
+                },
+            ) if not_supported == utils::NotSupportedPolicy::Ignore => {
+                log::warn!("{:?}, Ignored: {}", packet, err);
+                Some(driver::Status::Ok)
+            }
             gpio::Error::Recoverable(err) => {
                 log::warn!("{:?}, Err: {}", packet, err);
                 (&err).try_into().ok()
@@ -269,19 +1344,37 @@ fn on_gpio_set_config(
         },
     };
 
-    driver.set_gpio_config_reply(gpio.chip.unique_id, packet.pin, status)?;
-
-    Ok(())
+    Ok(status)
 }
 
 fn on_gpio_set_direction(
     driver: &driver::Handle,
     gpio: &gpio::Handle,
     packet: &driver::SetGpioDirection,
+    policies: Policies,
+    settle: &mut Settle,
+    directions: &mut Directions,
 ) -> Result<()> {
-    log::debug!("UID {{ {:?} }} {:?}", gpio.chip.unique_id, packet);
-    let status = match gpio.set_gpio_direction(packet.pin.try_into()?, packet.direction.into()) {
-        Ok(_) => Some(driver::Status::Ok),
+    log::debug!(
+        "[{}] UID {{ {:?} }} {:?}",
+        utils::Monotonic::now(),
+        gpio.unique_id(),
+        packet
+    );
+    let pin = packet.pin.try_into()?;
+    let result = match adapter::gpio_direction(packet.direction, policies.disable_mapping) {
+        (Some(config), direction) => gpio
+            .set_gpio_config(pin, config)
+            .and_then(|_| gpio.set_gpio_direction(pin, direction)),
+        (None, direction) => gpio.set_gpio_direction(pin, direction),
+    };
+
+    let status = match result {
+        Ok(_) => {
+            settle.on_direction_changed(packet.pin, std::time::Instant::now());
+            directions.on_direction_changed(packet.pin, packet.direction);
+            Some(driver::Status::Ok)
+        }
         Err(err) => match err {
             gpio::Error::Recoverable(err) => {
                 log::warn!("{:?}, Err: {}", packet, err);
@@ -291,7 +1384,7 @@ fn on_gpio_set_direction(
         },
     };
 
-    driver.set_gpio_direction_reply(gpio.chip.unique_id, packet.pin, status)?;
+    driver.set_gpio_direction_reply(gpio.unique_id(), packet.pin, status)?;
 
     Ok(())
 }
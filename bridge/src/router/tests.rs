@@ -0,0 +1,436 @@
+use super::*;
+
+fn not_supported() -> Result<(), gpio::Error> {
+    Err(gpio::RecoverableError::Packet {
+        status: gpio::Status::NotSupported,
+        cmd: gpio::HostCmd::SetGpioConfig,
+        pin: Some(0),
+    }
+    .into())
+}
+
+fn packet() -> driver::SetGpioConfig {
+    driver::SetGpioConfig {
+        pin: 0,
+        config: driver::GpioConfig::BiasPullUp,
+    }
+}
+
+#[test]
+fn set_gpio_config_not_supported_forward() {
+    let status = set_gpio_config_status(
+        &packet(),
+        not_supported(),
+        utils::NotSupportedPolicy::Forward,
+    )
+    .unwrap();
+
+    assert!(matches!(status, Some(driver::Status::NotSupported)));
+}
+
+#[test]
+fn set_gpio_config_not_supported_ignore() {
+    let status = set_gpio_config_status(
+        &packet(),
+        not_supported(),
+        utils::NotSupportedPolicy::Ignore,
+    )
+    .unwrap();
+
+    assert!(matches!(status, Some(driver::Status::Ok)));
+}
+
+#[test]
+fn set_gpio_config_invalid_pin_ignore() {
+    let result = Err(gpio::RecoverableError::Packet {
+        status: gpio::Status::InvalidPin,
+        cmd: gpio::HostCmd::SetGpioConfig,
+        pin: Some(0),
+    }
+    .into());
+
+    let status =
+        set_gpio_config_status(&packet(), result, utils::NotSupportedPolicy::Ignore).unwrap();
+
+    assert!(matches!(status, Some(driver::Status::ProtocolError)));
+}
+
+#[test]
+fn settle_after_direction_change() {
+    let now = std::time::Instant::now();
+    let mut settle = Settle::new(500, 2);
+
+    assert_eq!(settle.remaining(0, now), None);
+
+    settle.on_direction_changed(0, now);
+
+    assert_eq!(
+        settle.remaining(0, now + std::time::Duration::from_micros(200)),
+        Some(std::time::Duration::from_micros(300))
+    );
+    assert_eq!(
+        settle.remaining(0, now + std::time::Duration::from_micros(500)),
+        None
+    );
+    assert_eq!(settle.remaining(1, now), None);
+}
+
+#[test]
+fn settle_disabled_by_default() {
+    let now = std::time::Instant::now();
+    let mut settle = Settle::new(0, 2);
+
+    settle.on_direction_changed(0, now);
+
+    assert_eq!(settle.remaining(0, now), None);
+}
+
+#[test]
+fn settle_grows_with_added_pins() {
+    let now = std::time::Instant::now();
+    let mut settle = Settle::new(500, 2);
+
+    settle.on_direction_changed(4, now);
+
+    assert_eq!(
+        settle.remaining(4, now),
+        Some(std::time::Duration::from_micros(500))
+    );
+    assert_eq!(settle.remaining(3, now), None);
+}
+
+#[test]
+fn shutdown_grace_disabled_by_default() {
+    let mut grace = ShutdownGrace::new(0);
+    let now = std::time::Instant::now();
+
+    assert!(!grace.arm(now));
+    assert_eq!(grace.remaining(now), None);
+    assert!(!grace.expired(now));
+}
+
+#[test]
+fn shutdown_grace_expires() {
+    let mut grace = ShutdownGrace::new(500);
+    let now = std::time::Instant::now();
+
+    assert!(grace.arm(now));
+    assert_eq!(
+        grace.remaining(now + std::time::Duration::from_millis(200)),
+        Some(std::time::Duration::from_millis(300))
+    );
+    assert!(!grace.expired(now + std::time::Duration::from_millis(499)));
+    assert!(grace.expired(now + std::time::Duration::from_millis(500)));
+    // A second SIGTERM meanwhile shuts down at once
+    assert!(!grace.arm(now));
+}
+
+#[test]
+fn shutdown_grace_cancelled() {
+    let mut grace = ShutdownGrace::new(500);
+    let now = std::time::Instant::now();
+
+    assert!(!grace.cancel());
+    assert!(grace.arm(now));
+    assert!(grace.cancel());
+    assert!(!grace.expired(now + std::time::Duration::from_millis(500)));
+    assert!(grace.arm(now));
+}
+
+#[test]
+fn input_write_strict() {
+    let mut directions = Directions::new(2);
+    directions.on_direction_changed(0, driver::GpioDirection::Input);
+    directions.on_direction_changed(1, driver::GpioDirection::Output);
+
+    let policy = utils::InputWritePolicy::Reject;
+
+    assert_eq!(directions.on_value_write(0, policy), InputWrite::Reject);
+    assert_eq!(directions.on_value_write(1, policy), InputWrite::Forward);
+    // Never set by the Kernel Driver
+    assert_eq!(directions.on_value_write(5, policy), InputWrite::Forward);
+}
+
+#[test]
+fn input_write_permissive() {
+    let mut directions = Directions::new(2);
+    directions.on_direction_changed(0, driver::GpioDirection::Input);
+
+    assert_eq!(
+        directions.on_value_write(0, utils::InputWritePolicy::Switch),
+        InputWrite::SwitchToOutput
+    );
+    assert_eq!(
+        directions.on_value_write(0, utils::InputWritePolicy::Forward),
+        InputWrite::Forward
+    );
+
+    directions.on_direction_changed(0, driver::GpioDirection::Output);
+
+    assert_eq!(
+        directions.on_value_write(0, utils::InputWritePolicy::Switch),
+        InputWrite::Forward
+    );
+}
+
+#[test]
+fn config_matching_direction() {
+    let mut directions = Directions::new(3);
+    directions.on_direction_changed(0, driver::GpioDirection::Output);
+    directions.on_direction_changed(1, driver::GpioDirection::Input);
+    directions.on_direction_changed(2, driver::GpioDirection::Disabled);
+
+    assert_eq!(
+        directions.config_mismatch(0, driver::GpioConfig::DrivePushPull),
+        None
+    );
+    assert_eq!(
+        directions.config_mismatch(0, driver::GpioConfig::BiasDisable),
+        None
+    );
+    assert_eq!(
+        directions.config_mismatch(1, driver::GpioConfig::BiasPullUp),
+        None
+    );
+    assert_eq!(
+        directions.config_mismatch(2, driver::GpioConfig::DriveOpenDrain),
+        None
+    );
+    // Never set by the Kernel Driver
+    assert_eq!(
+        directions.config_mismatch(5, driver::GpioConfig::BiasPullDown),
+        None
+    );
+}
+
+#[test]
+fn config_mismatching_direction() {
+    let mut directions = Directions::new(2);
+    directions.on_direction_changed(0, driver::GpioDirection::Output);
+    directions.on_direction_changed(1, driver::GpioDirection::Input);
+
+    assert!(directions
+        .config_mismatch(0, driver::GpioConfig::BiasPullUp)
+        .is_some());
+    assert!(directions
+        .config_mismatch(0, driver::GpioConfig::BiasPullDown)
+        .is_some());
+    assert!(directions
+        .config_mismatch(1, driver::GpioConfig::DrivePushPull)
+        .is_some());
+    assert!(directions
+        .config_mismatch(1, driver::GpioConfig::DriveOpenSource)
+        .is_some());
+}
+
+#[test]
+fn worker_index_is_per_pin() {
+    let get = |pin| driver::Packet::GetGpioValue(driver::GetGpioValue { pin });
+    let set = |pin| {
+        driver::Packet::SetGpioDirection(driver::SetGpioDirection {
+            pin,
+            direction: driver::GpioDirection::Input,
+        })
+    };
+
+    assert_eq!(worker_index(&get(5), 4), worker_index(&set(5), 4));
+    assert_ne!(worker_index(&get(5), 4), worker_index(&get(6), 4));
+    assert_eq!(worker_index(&get(5), 1), 0);
+}
+
+fn set_value_packet() -> driver::SetGpioValue {
+    driver::SetGpioValue {
+        pin: 0,
+        value: driver::GpioValue::High,
+    }
+}
+
+#[test]
+fn verify_write_matching_value() {
+    let status = verify_write_status(
+        &set_value_packet(),
+        Ok((Ok(gpio::GpioValue::High), None)),
+        utils::VerifyWritesPolicy::Fail,
+    )
+    .unwrap();
+
+    assert!(matches!(status, Some(driver::Status::Ok)));
+}
+
+#[test]
+fn verify_write_mismatch_warn() {
+    let status = verify_write_status(
+        &set_value_packet(),
+        Ok((Ok(gpio::GpioValue::Low), None)),
+        utils::VerifyWritesPolicy::Warn,
+    )
+    .unwrap();
+
+    assert!(matches!(status, Some(driver::Status::Ok)));
+}
+
+#[test]
+fn verify_write_mismatch_fail() {
+    for read in [Ok(gpio::GpioValue::Low), Err(gpio::InvalidGpioValue(0xA5))] {
+        let status = verify_write_status(
+            &set_value_packet(),
+            Ok((read, None)),
+            utils::VerifyWritesPolicy::Fail,
+        )
+        .unwrap();
+
+        assert!(matches!(status, Some(driver::Status::Unknown)));
+    }
+}
+
+#[test]
+fn verify_write_sampled_mismatch() {
+    let status = verify_write_status(
+        &set_value_packet(),
+        Ok((
+            Ok(gpio::GpioValue::Low),
+            Some(gpio::GpioValueSource::Sampled),
+        )),
+        utils::VerifyWritesPolicy::Fail,
+    )
+    .unwrap();
+
+    assert!(matches!(status, Some(driver::Status::Ok)));
+}
+
+#[test]
+fn verify_write_driven_mismatch_fail() {
+    let status = verify_write_status(
+        &set_value_packet(),
+        Ok((
+            Ok(gpio::GpioValue::Low),
+            Some(gpio::GpioValueSource::Driven),
+        )),
+        utils::VerifyWritesPolicy::Fail,
+    )
+    .unwrap();
+
+    assert!(matches!(status, Some(driver::Status::Unknown)));
+}
+
+#[test]
+fn verify_write_failed_read_back() {
+    let transport = gpio::Descriptor::Mock {
+        instance: "cpcd_0".to_string(),
+    };
+    let readback = Err(gpio::RecoverableError::Timeout(
+        std::sync::mpsc::RecvTimeoutError::Timeout,
+        2000,
+        transport,
+    )
+    .into());
+
+    let status = verify_write_status(
+        &set_value_packet(),
+        readback,
+        utils::VerifyWritesPolicy::Fail,
+    )
+    .unwrap();
+
+    assert!(matches!(status, Some(driver::Status::Ok)));
+}
+
+#[test]
+fn disabled_direction_native() {
+    assert!(matches!(
+        adapter::gpio_direction(
+            driver::GpioDirection::Disabled,
+            utils::DisableMapping::Native
+        ),
+        (None, gpio::GpioDirection::Disabled)
+    ));
+}
+
+#[test]
+fn disabled_direction_emulated() {
+    assert!(matches!(
+        adapter::gpio_direction(
+            driver::GpioDirection::Disabled,
+            utils::DisableMapping::Emulate
+        ),
+        (
+            Some(gpio::GpioConfig::BiasDisable),
+            gpio::GpioDirection::Input
+        )
+    ));
+
+    // Only Disabled is emulated
+    assert!(matches!(
+        adapter::gpio_direction(
+            driver::GpioDirection::Output,
+            utils::DisableMapping::Emulate
+        ),
+        (None, gpio::GpioDirection::Output)
+    ));
+}
+
+#[test]
+fn all_values_reply_per_pin_statuses() {
+    let readings = vec![
+        (0, Ok(Ok(gpio::GpioValue::High))),
+        (1, Ok(Err(gpio::InvalidGpioValue(7)))),
+        (
+            2,
+            Err(gpio::RecoverableError::Packet {
+                status: gpio::Status::NotSupported,
+                cmd: gpio::HostCmd::GetGpioValue,
+                pin: Some(2),
+            }
+            .into()),
+        ),
+        (3, Ok(Ok(gpio::GpioValue::Low))),
+    ];
+
+    let (values, statuses) =
+        all_values_reply(&driver::GetAllGpioValues { batch_id: 1 }, readings).unwrap();
+
+    assert_eq!(values, vec![1, 0, 0, 0]);
+    assert!(matches!(
+        statuses.as_slice(),
+        [
+            driver::Status::Ok,
+            driver::Status::ProtocolError,
+            driver::Status::NotSupported,
+            driver::Status::Ok
+        ]
+    ));
+}
+
+#[test]
+fn all_values_reply_unrecoverable_fails() {
+    let readings = vec![
+        (0, Ok(Ok(gpio::GpioValue::High))),
+        (
+            1,
+            Err(gpio::UnrecoverableError::Anyhow(anyhow::anyhow!("gone")).into()),
+        ),
+    ];
+
+    assert!(all_values_reply(&driver::GetAllGpioValues { batch_id: 1 }, readings).is_err());
+}
+
+#[test]
+fn duplicate_pin_status_differs_from_invalid_pin() {
+    let duplicate = driver::Status::try_from(&gpio::RecoverableError::DuplicatePin(1)).unwrap();
+    let invalid = driver::Status::try_from(&gpio::RecoverableError::InvalidPin(1)).unwrap();
+
+    assert!(matches!(duplicate, driver::Status::InvalidArgument));
+    assert!(matches!(invalid, driver::Status::ProtocolError));
+}
+
+#[test]
+fn rediscover_file_is_taken_once() {
+    let path = std::env::temp_dir().join(format!("rediscover-{}", std::process::id()));
+
+    assert!(!take_trigger(&path).unwrap());
+
+    std::fs::write(&path, "").unwrap();
+    assert!(take_trigger(&path).unwrap());
+    assert!(!path.exists());
+    assert!(!take_trigger(&path).unwrap());
+}
@@ -0,0 +1,218 @@
+use anyhow::{bail, Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::gpio;
+
+#[cfg(test)]
+mod tests;
+
+// https://docs.kernel.org/admin-guide/gpio/gpio-sim.html
+const CONFIGFS_GPIO_SIM_DIR: &str = "/sys/kernel/config/gpio-sim";
+const SYSFS_PLATFORM_DIR: &str = "/sys/devices/platform";
+const BANK: &str = "bank0";
+
+pub struct Handle {
+    configfs: PathBuf,
+    stop: Arc<AtomicBool>,
+    mirror: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Handle {
+    pub fn new(gpio: Arc<gpio::Handle>, poll_interval_ms: u64) -> Result<Self> {
+        let configfs =
+            Path::new(CONFIGFS_GPIO_SIM_DIR).join(format!("cpc-gpio-bridge-{}", gpio.unique_id()));
+
+        if configfs.exists() {
+            log::warn!("Removing stale gpio-sim ({})", configfs.display());
+            teardown(&configfs)?;
+        }
+
+        create(&configfs, &gpio)
+            .with_context(|| format!("Failed to create gpio-sim ({})", configfs.display()))?;
+
+        // From here on the simulated chip is removed when the handle is dropped
+        let mut handle = Self {
+            configfs,
+            stop: Arc::new(AtomicBool::new(false)),
+            mirror: None,
+        };
+
+        let dev_name = read(&handle.configfs.join("dev_name"))?;
+        let chip_name = read(&handle.configfs.join(BANK).join("chip_name"))?;
+        let lines = Path::new(SYSFS_PLATFORM_DIR)
+            .join(dev_name)
+            .join(&chip_name);
+
+        log::info!(
+            "Mirroring {} GPIO's to gpio-sim ({})",
+            gpio.pins().count(),
+            chip_name
+        );
+
+        let stop = handle.stop.clone();
+        handle.mirror = Some(std::thread::Builder::new().name("sim".to_string()).spawn(
+            move || {
+                let poll_interval = std::time::Duration::from_millis(poll_interval_ms);
+                let mut last: Vec<_> = gpio.pins().map(|info| (info.pin, None)).collect();
+                let mut generation = gpio.pin_states_generation();
+                while !stop.load(Ordering::Relaxed) {
+                    if let Err(err) = mirror(&gpio, &lines, &mut last) {
+                        log::error!("Stopped mirroring to gpio-sim, Err: {}", err);
+                        return;
+                    }
+                    // Pin state changes are mirrored at once, the interval
+                    // only bounds how late a consumer value is forwarded
+                    if let Some(changed) = gpio.wait_pin_states_change(generation, poll_interval) {
+                        generation = changed;
+                    }
+                }
+            },
+        )?);
+
+        Ok(handle)
+    }
+}
+
+// The mirror is joined first so that it does not write to a chip being removed
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(mirror) = self.mirror.take() {
+            let _ = mirror.join();
+        }
+
+        if let Err(err) = teardown(&self.configfs) {
+            log::warn!(
+                "Failed to remove gpio-sim ({}), Err: {}",
+                self.configfs.display(),
+                err
+            );
+        }
+    }
+}
+
+fn create(configfs: &Path, gpio: &gpio::Handle) -> Result<()> {
+    let bank = configfs.join(BANK);
+
+    std::fs::create_dir(configfs)?;
+    std::fs::create_dir(&bank)?;
+
+    write(&bank.join("label"), &gpio.chip.label)?;
+    write(&bank.join("num_lines"), &gpio.pins().count().to_string())?;
+
+    // Lines are contiguous, the pins behind them skip the unmanaged ones
+    for (line, info) in gpio.pins().enumerate() {
+        let line = bank.join(format!("line{}", line));
+        std::fs::create_dir(&line)?;
+        write(&line.join("name"), &info.name)?;
+    }
+
+    write(&configfs.join("live"), "1")
+}
+
+fn teardown(configfs: &Path) -> Result<()> {
+    let live = configfs.join("live");
+    if read(&live)? == "1" {
+        write(&live, "0")?;
+    }
+
+    let bank = configfs.join(BANK);
+    if bank.exists() {
+        for entry in std::fs::read_dir(&bank)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                std::fs::remove_dir(path)?;
+            }
+        }
+        std::fs::remove_dir(&bank)?;
+    }
+
+    std::fs::remove_dir(configfs)?;
+
+    Ok(())
+}
+
+// Only outputs are mirrored, from the value the bridge tracks for them: the
+// secondary is never polled. A line value that differs from the last mirrored
+// one was driven by a gpio-sim consumer and is forwarded to the secondary, the
+// acknowledged value is then mirrored back through the line pull.
+fn mirror(
+    gpio: &gpio::Handle,
+    lines: &Path,
+    last: &mut [(u8, Option<gpio::GpioValue>)],
+) -> Result<()> {
+    for (line, (pin, last)) in last.iter_mut().enumerate() {
+        let pin = *pin;
+        let line = lines.join(format!("sim_gpio{}", line));
+
+        let Some(mut value) = mirrored(gpio.gpio_direction(pin), gpio.tracked_value(pin)) else {
+            // Mirrored again from scratch once the pin is an output
+            *last = None;
+            continue;
+        };
+
+        let sim_value = read_value(&line)?;
+        if should_forward(*last, sim_value) {
+            match gpio.set_gpio_value(pin, sim_value) {
+                Ok(_) => value = sim_value,
+                Err(gpio::Error::Recoverable(err)) => {
+                    log::warn!("Failed to mirror gpio-sim pin {}, Err: {}", pin, err)
+                }
+                Err(gpio::Error::Unrecoverable(err)) => bail!("{}", err),
+            }
+        }
+
+        if *last != Some(value) || sim_value != value {
+            let pull = match value {
+                gpio::GpioValue::Low => "pull-down",
+                gpio::GpioValue::High => "pull-up",
+            };
+            write(&line.join("pull"), pull)?;
+        }
+
+        *last = Some(read_value(&line)?);
+    }
+
+    Ok(())
+}
+
+// The value to mirror, known for outputs only
+fn mirrored(
+    direction: Option<gpio::GpioDirection>,
+    value: Option<gpio::GpioValue>,
+) -> Option<gpio::GpioValue> {
+    match direction {
+        Some(gpio::GpioDirection::Output) => value,
+        _ => None,
+    }
+}
+
+fn should_forward(last: Option<gpio::GpioValue>, sim_value: gpio::GpioValue) -> bool {
+    last.is_some() && last != Some(sim_value)
+}
+
+fn read_value(line: &Path) -> Result<gpio::GpioValue> {
+    match read(&line.join("value"))?.as_str() {
+        "0" => Ok(gpio::GpioValue::Low),
+        "1" => Ok(gpio::GpioValue::High),
+        value => bail!(
+            "Unexpected gpio-sim value ({}): {:?}",
+            line.display(),
+            value
+        ),
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    Ok(std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?
+        .trim()
+        .to_string())
+}
+
+fn write(path: &Path, value: &str) -> Result<()> {
+    std::fs::write(path, value).with_context(|| format!("Failed to write {}", path.display()))
+}
@@ -0,0 +1,66 @@
+use clap::Parser;
+
+use super::*;
+use crate::utils;
+
+#[test]
+fn consumer_value_is_forwarded() {
+    assert!(should_forward(
+        Some(gpio::GpioValue::Low),
+        gpio::GpioValue::High
+    ));
+    assert!(should_forward(
+        Some(gpio::GpioValue::High),
+        gpio::GpioValue::Low
+    ));
+}
+
+#[test]
+fn unchanged_value_is_not_forwarded() {
+    assert!(!should_forward(
+        Some(gpio::GpioValue::High),
+        gpio::GpioValue::High
+    ));
+    assert!(!should_forward(None, gpio::GpioValue::High));
+}
+
+#[test]
+fn only_outputs_are_mirrored() {
+    assert_eq!(
+        mirrored(
+            Some(gpio::GpioDirection::Output),
+            Some(gpio::GpioValue::High)
+        ),
+        Some(gpio::GpioValue::High)
+    );
+    assert_eq!(mirrored(Some(gpio::GpioDirection::Output), None), None);
+
+    assert_eq!(
+        mirrored(
+            Some(gpio::GpioDirection::Input),
+            Some(gpio::GpioValue::High)
+        ),
+        None
+    );
+    assert_eq!(
+        mirrored(
+            Some(gpio::GpioDirection::Disabled),
+            Some(gpio::GpioValue::Low)
+        ),
+        None
+    );
+    // Never configured
+    assert_eq!(mirrored(None, None), None);
+}
+
+#[test]
+fn poll_interval_rejects_zero() {
+    assert!(utils::Config::try_parse_from([
+        "cpc-gpio-bridge",
+        "--instance",
+        "1",
+        "--sim-poll-interval-ms",
+        "0"
+    ])
+    .is_err());
+}
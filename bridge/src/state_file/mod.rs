@@ -0,0 +1,102 @@
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::gpio;
+
+// Bounds how long the writer takes to notice that the bridge is stopping
+const STOP_POLL_MS: u64 = 100;
+
+#[derive(serde::Serialize)]
+struct Contents {
+    // Set once the bridge stopped, the pins are then only the last known state
+    stale: bool,
+    pins: Vec<gpio::PinRecord>,
+}
+
+pub struct Handle {
+    path: PathBuf,
+    gpio: Arc<gpio::Handle>,
+    stop: Arc<AtomicBool>,
+    writer: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Handle {
+    pub fn new(gpio: Arc<gpio::Handle>, path: &Path, debounce_ms: u64) -> Result<Self> {
+        let mut generation = gpio.pin_states_generation();
+
+        // An unwritable path is reported at startup rather than on the first change
+        write(path, &gpio, false)?;
+
+        log::info!("Mirroring GPIO state to {}", path.display());
+
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let writer = {
+            let gpio = gpio.clone();
+            let path = path.to_path_buf();
+            let stop = stop.clone();
+
+            std::thread::Builder::new()
+                .name("state-file".to_string())
+                .spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        let poll = std::time::Duration::from_millis(STOP_POLL_MS);
+                        if gpio.wait_pin_states_change(generation, poll).is_none() {
+                            continue;
+                        }
+
+                        // Changes within the debounce window are coalesced into one write
+                        std::thread::sleep(std::time::Duration::from_millis(debounce_ms));
+                        generation = gpio.pin_states_generation();
+
+                        if let Err(err) = write(&path, &gpio, false) {
+                            log::warn!("{}", err);
+                        }
+                    }
+                })?
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            gpio,
+            stop,
+            writer: Some(writer),
+        })
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+
+        if let Err(err) = write(&self.path, &self.gpio, true) {
+            log::warn!("{}", err);
+        }
+    }
+}
+
+// Written next to the target and renamed over it, readers never see a
+// partially written file
+fn write(path: &Path, gpio: &gpio::Handle, stale: bool) -> Result<()> {
+    let contents = toml::to_string(&Contents {
+        stale,
+        pins: gpio.pin_records(),
+    })
+    .context("Failed to serialize GPIO state")?;
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+
+    std::fs::write(&tmp, contents)
+        .with_context(|| format!("Failed to write state file ({})", tmp.display()))?;
+
+    std::fs::rename(&tmp, path)
+        .with_context(|| format!("Failed to replace state file ({})", path.display()))
+}
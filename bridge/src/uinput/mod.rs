@@ -0,0 +1,229 @@
+use anyhow::{bail, Context, Result};
+use evdev::{uinput::VirtualDeviceBuilder, AttributeSet, EventType, InputEvent, Key, SwitchType};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::gpio;
+
+#[cfg(test)]
+mod tests;
+
+// Input event of a mapped pin, a key is pressed or a switch is on while the
+// pin is active
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Code {
+    Key(Key),
+    Switch(SwitchType),
+}
+
+impl Code {
+    fn event(&self, active: bool) -> InputEvent {
+        match self {
+            Code::Key(key) => InputEvent::new(EventType::KEY, key.0, active as i32),
+            Code::Switch(switch) => InputEvent::new(EventType::SWITCH, switch.0, active as i32),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Mapping {
+    pin: u8,
+    key: Option<String>,
+    switch: Option<String>,
+    // Buttons pulled up read low while pressed
+    #[serde(default)]
+    active_low: bool,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct Map {
+    #[serde(default)]
+    pins: Vec<Mapping>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Input {
+    pin: u8,
+    code: Code,
+    active_low: bool,
+}
+
+impl Input {
+    fn is_active(&self, value: gpio::GpioValue) -> bool {
+        (value == gpio::GpioValue::High) != self.active_low
+    }
+}
+
+// Every pin is managed by the bridge and mapped once, to a single known key
+// or switch
+fn parse(map: &str, managed: &[u8]) -> Result<Vec<Input>> {
+    let map: Map = toml::from_str(map)?;
+    let mut inputs: Vec<Input> = vec![];
+
+    for mapping in map.pins {
+        if !managed.contains(&mapping.pin) {
+            bail!(
+                "uinput map pin {} is not managed by the bridge",
+                mapping.pin
+            );
+        }
+
+        if inputs.iter().any(|input| input.pin == mapping.pin) {
+            bail!("uinput map pin {} is mapped more than once", mapping.pin);
+        }
+
+        let code = match (&mapping.key, &mapping.switch) {
+            (Some(key), None) => Code::Key(
+                key.parse()
+                    .map_err(|_| anyhow::anyhow!("Unknown key code {:?}", key))
+                    .with_context(|| format!("uinput map pin {}", mapping.pin))?,
+            ),
+            (None, Some(switch)) => Code::Switch(
+                switch
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Unknown switch code {:?}", switch))
+                    .with_context(|| format!("uinput map pin {}", mapping.pin))?,
+            ),
+            _ => bail!(
+                "uinput map pin {} must set exactly one of key or switch",
+                mapping.pin
+            ),
+        };
+
+        inputs.push(Input {
+            pin: mapping.pin,
+            code,
+            active_low: mapping.active_low,
+        });
+    }
+
+    Ok(inputs)
+}
+
+pub struct Handle {
+    stop: Arc<AtomicBool>,
+    poller: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Handle {
+    pub fn new(gpio: Arc<gpio::Handle>, path: &Path, poll_interval_ms: u64) -> Result<Self> {
+        let map = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read uinput map ({})", path.display()))?;
+        let managed: Vec<u8> = gpio.pins().map(|info| info.pin).collect();
+        let inputs = parse(&map, &managed)
+            .with_context(|| format!("Failed to parse uinput map ({})", path.display()))?;
+
+        for input in &inputs {
+            if let Err(err) = gpio.set_gpio_direction(input.pin, gpio::GpioDirection::Input) {
+                bail!(
+                    "Failed to set uinput pin {} as input, Err: {}",
+                    input.pin,
+                    err
+                );
+            }
+        }
+
+        let mut keys = AttributeSet::<Key>::new();
+        let mut switches = AttributeSet::<SwitchType>::new();
+        for input in &inputs {
+            match input.code {
+                Code::Key(key) => keys.insert(key),
+                Code::Switch(switch) => switches.insert(switch),
+            }
+        }
+
+        let name = format!("cpc-gpio-bridge-{}", gpio.unique_id());
+        let mut device = VirtualDeviceBuilder::new()
+            .and_then(|builder| builder.name(&name).with_keys(&keys))
+            .and_then(|builder| builder.with_switches(&switches))
+            .and_then(|builder| builder.build())
+            .context("Failed to create the uinput device, is /dev/uinput writable?")?;
+
+        log::info!(
+            "Emitting input events of {} pin(s) as uinput device {}",
+            inputs.len(),
+            name
+        );
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let poller = {
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name("uinput".to_string())
+                .spawn(move || {
+                    let mut last = vec![None; inputs.len()];
+                    while !stop.load(Ordering::Relaxed) {
+                        if let Err(err) = emit(&gpio, &inputs, &mut last, &mut device) {
+                            log::error!("Stopped emitting input events, Err: {}", err);
+                            return;
+                        }
+                        std::thread::sleep(std::time::Duration::from_millis(poll_interval_ms));
+                    }
+                })?
+        };
+
+        Ok(Self {
+            stop,
+            poller: Some(poller),
+        })
+    }
+}
+
+// The uinput device is owned by the poller, joining it closes the device
+// which the kernel then removes
+impl Drop for Handle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(poller) = self.poller.take() {
+            let _ = poller.join();
+        }
+
+        log::info!("Removed the uinput device");
+    }
+}
+
+// Keys are only reported on a transition, a switch also reports the state it
+// is first read in
+fn emit(
+    gpio: &gpio::Handle,
+    inputs: &[Input],
+    last: &mut [Option<bool>],
+    device: &mut evdev::uinput::VirtualDevice,
+) -> Result<()> {
+    for (input, last) in inputs.iter().zip(last.iter_mut()) {
+        let value = match gpio.get_gpio_value(input.pin) {
+            Ok(packet) => match packet.value {
+                Ok(value) => value,
+                Err(err) => {
+                    log::warn!("Failed to read uinput pin {}, Err: {}", input.pin, err);
+                    continue;
+                }
+            },
+            Err(gpio::Error::Recoverable(err)) => {
+                log::warn!("Failed to read uinput pin {}, Err: {}", input.pin, err);
+                continue;
+            }
+            Err(gpio::Error::Unrecoverable(err)) => bail!("{}", err),
+        };
+
+        let active = input.is_active(value);
+        if should_emit(input.code, *last, active) {
+            log::debug!("Pin {} {{ {:?}: {} }}", input.pin, input.code, active);
+            device.emit(&[input.code.event(active)])?;
+        }
+        *last = Some(active);
+    }
+
+    Ok(())
+}
+
+fn should_emit(code: Code, last: Option<bool>, active: bool) -> bool {
+    match last {
+        Some(last) => last != active,
+        None => matches!(code, Code::Switch(_)),
+    }
+}
@@ -0,0 +1,71 @@
+use super::*;
+
+const MANAGED: [u8; 4] = [0, 1, 2, 3];
+
+#[test]
+fn map_parse() {
+    let inputs = parse(
+        r#"
+        [[pins]]
+        pin = 1
+        key = "KEY_POWER"
+        active-low = true
+
+        [[pins]]
+        pin = 3
+        switch = "SW_LID"
+        "#,
+        &MANAGED,
+    )
+    .unwrap();
+
+    assert_eq!(
+        inputs,
+        vec![
+            Input {
+                pin: 1,
+                code: Code::Key(Key::KEY_POWER),
+                active_low: true,
+            },
+            Input {
+                pin: 3,
+                code: Code::Switch(SwitchType::SW_LID),
+                active_low: false,
+            },
+        ]
+    );
+    assert!(inputs[0].is_active(gpio::GpioValue::Low));
+    assert!(inputs[1].is_active(gpio::GpioValue::High));
+}
+
+#[test]
+fn map_rejects_invalid_pins() {
+    for map in [
+        // Not managed
+        "[[pins]]\npin = 4\nkey = \"KEY_A\"",
+        // Mapped twice
+        "[[pins]]\npin = 0\nkey = \"KEY_A\"\n[[pins]]\npin = 0\nkey = \"KEY_B\"",
+        // Neither or both codes
+        "[[pins]]\npin = 0",
+        "[[pins]]\npin = 0\nkey = \"KEY_A\"\nswitch = \"SW_LID\"",
+        // Unknown codes
+        "[[pins]]\npin = 0\nkey = \"KEY_NONE\"",
+        "[[pins]]\npin = 0\nswitch = \"KEY_A\"",
+    ] {
+        assert!(parse(map, &MANAGED).is_err(), "{}", map);
+    }
+}
+
+#[test]
+fn keys_only_emit_transitions() {
+    let key = Code::Key(Key::KEY_A);
+    let switch = Code::Switch(SwitchType::SW_LID);
+
+    assert!(!should_emit(key, None, true));
+    assert!(should_emit(switch, None, false));
+
+    for code in [key, switch] {
+        assert!(should_emit(code, Some(false), true));
+        assert!(!should_emit(code, Some(true), true));
+    }
+}
@@ -1,7 +1,11 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use std::{
     io::{Read, Write},
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc, Arc, Condvar, Mutex, Weak,
+    },
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 
@@ -17,7 +21,8 @@ impl std::fmt::Display for Version {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, clap::ValueEnum)]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Trace {
     None,
     Bridge,
@@ -25,7 +30,221 @@ pub enum Trace {
     All,
 }
 
-#[derive(clap::Parser, Debug)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transport {
+    Cpc,
+    Mock,
+    Replay,
+}
+
+// CPC when it is built in, a build for testing only defaults to its transport
+impl Default for Transport {
+    fn default() -> Self {
+        if cfg!(feature = "gpio_cpc") {
+            Transport::Cpc
+        } else if cfg!(feature = "gpio_mock") {
+            Transport::Mock
+        } else {
+            Transport::Replay
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NamePolicy {
+    Raw,
+    Normalize,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotSupportedPolicy {
+    Forward,
+    Ignore,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InputWritePolicy {
+    Forward,
+    Reject,
+    Switch,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConfigDirectionPolicy {
+    Warn,
+    Reject,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DisableMapping {
+    Native,
+    Emulate,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DefaultBias {
+    Disable,
+    PullDown,
+    PullUp,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VerifyWritesPolicy {
+    Off,
+    Warn,
+    Fail,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogTag {
+    None,
+    Instance,
+    UniqueId,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SeqWidth {
+    One,
+    Two,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DriverRead {
+    Blocking,
+    Poll,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overflow {
+    Block,
+    DropOldest,
+}
+
+#[cfg(feature = "gpio_mock")]
+#[derive(Copy, Clone, PartialEq, Eq, Debug, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MockDisablePolicy {
+    ForceLow,
+    Retain,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct CmdTimeout {
+    pub cmd: crate::gpio::HostCmd,
+    pub ms: u64,
+}
+
+// Written back the way it is parsed, e.g. `get-gpio-value=100`
+impl serde::Serialize for CmdTimeout {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{}={}", value_name(&self.cmd), self.ms))
+    }
+}
+
+fn value_name(value: &impl clap::ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "gpio_mock")]
+fn serialize_value_names<S: serde::Serializer>(
+    values: &[crate::gpio::HostCmd],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(values.iter().map(value_name))
+}
+
+fn parse_cmd_timeout(arg: &str) -> Result<CmdTimeout> {
+    let (cmd, ms) = match arg.split_once('=') {
+        Some(split) => split,
+        None => bail!("Expected CMD=MS, got {:?}", arg),
+    };
+
+    let cmd = <crate::gpio::HostCmd as clap::ValueEnum>::from_str(cmd, false)
+        .map_err(|err| anyhow!("Invalid command {:?}, Err: {}", cmd, err))?;
+
+    let ms = ms
+        .parse::<u64>()
+        .map_err(|err| anyhow!("Invalid timeout {:?}, Err: {}", ms, err))?;
+
+    if ms == 0 {
+        bail!("Timeout of {:?} must be greater than 0", cmd);
+    }
+
+    Ok(CmdTimeout { cmd, ms })
+}
+
+#[cfg(feature = "raw_request")]
+#[derive(Clone, Debug)]
+pub struct RawRequest {
+    pub cmd: u8,
+    pub payload: Vec<u8>,
+}
+
+#[cfg(feature = "raw_request")]
+impl std::fmt::Display for RawRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{{ Cmd: {}, Payload: {:02x?} }}", self.cmd, self.payload)
+    }
+}
+
+// Written back the way it is parsed, e.g. `16:0102`
+#[cfg(feature = "raw_request")]
+impl serde::Serialize for RawRequest {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let payload: String = self
+            .payload
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        if payload.is_empty() {
+            serializer.collect_str(&self.cmd)
+        } else {
+            serializer.collect_str(&format_args!("{}:{}", self.cmd, payload))
+        }
+    }
+}
+
+#[cfg(feature = "raw_request")]
+fn parse_raw_request(arg: &str) -> Result<RawRequest> {
+    let (cmd, payload) = arg.split_once(':').unwrap_or((arg, ""));
+
+    let cmd = cmd
+        .parse::<u8>()
+        .map_err(|err| anyhow!("Invalid command {:?}, Err: {}", cmd, err))?;
+
+    if !payload.is_ascii() || payload.len() % 2 != 0 {
+        bail!("Expected a payload of hex bytes, got {:?}", payload);
+    }
+
+    let payload = (0..payload.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&payload[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|err| anyhow!("Invalid payload {:?}, Err: {}", payload, err))?;
+
+    Ok(RawRequest { cmd, payload })
+}
+
+// Serialized with the long option names, `--print-config` output reads as
+// the command line that reproduces it
+#[derive(clap::Parser, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
 #[clap(version, about)]
 pub struct Config {
     /// Enable tracing
@@ -36,24 +255,354 @@ pub struct Config {
     #[clap(short, long, default_value = "cpcd_0")]
     pub instance: String,
 
+    /// Prefix every log line with a tag telling bridges apart
+    #[clap(long, value_enum, default_value_t = LogTag::None)]
+    pub log_tag: LogTag,
+
+    /// Warnings repeated on every packet of a failing link logged per window, the others are counted. 0 logs them all
+    #[clap(long, default_value = "10")]
+    pub warn_burst: u32,
+
+    /// Window of --warn-burst
+    #[clap(long, default_value = "10000")]
+    pub warn_window_ms: u64,
+
     /// Bridge lock directory
     #[clap(short, long, default_value = "/tmp")]
     pub lock_dir: String,
 
+    /// Run without taking the bridge lock
+    #[clap(long, default_value = "false")]
+    pub no_lock: bool,
+
+    /// Wait up to LOCK_WAIT ms for another bridge to release the lock instead of failing right away
+    #[clap(long, conflicts_with = "no_lock", value_parser = clap::value_parser!(u64).range(1..))]
+    pub lock_wait: Option<u64>,
+
     /// Deinit gpio chip and exit process
     #[clap(short, long, default_value = "false")]
     pub deinit: bool,
+
+    /// Print the configuration as parsed, defaults included, as TOML and exit process
+    #[clap(long, default_value = "false")]
+    #[serde(skip)]
+    pub print_config: bool,
+
+    /// Capacity of the reader thread and router worker channels
+    #[clap(long, default_value_t = 16, value_parser = clap::value_parser!(u32).range(1..))]
+    pub channel_capacity: u32,
+
+    /// Policy applied when the GPIO reader thread channel is full
+    #[clap(long, value_enum, default_value_t = Overflow::Block)]
+    pub channel_overflow: Overflow,
+
+    /// How the Kernel Driver reader thread waits for requests
+    #[clap(long, value_enum, default_value_t = DriverRead::Blocking)]
+    pub driver_read: DriverRead,
+
+    /// Receive buffer size in bytes of the Kernel Driver Unicast socket, clamped to net.core.rmem_max
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub netlink_unicast_rcvbuf: Option<u32>,
+
+    /// Receive buffer size in bytes of the Kernel Driver Multicast socket, clamped to net.core.rmem_max
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub netlink_multicast_rcvbuf: Option<u32>,
+
+    /// Kernel Driver multicast groups the reader thread subscribes to, e.g. `CPC_GPIO_GENL_M,CPC_GPIO_GENL_D`
+    #[clap(long, value_delimiter = ',', default_value = crate::driver::GENL_MULTICAST_FAMILY_NAME)]
+    pub netlink_multicast_group: Vec<String>,
+
+    /// Policy applied when the secondary does not support a GPIO config
+    #[clap(long, value_enum, default_value_t = NotSupportedPolicy::Forward)]
+    pub config_not_supported: NotSupportedPolicy,
+
+    /// Policy applied when a value is written to a pin configured as Input
+    #[clap(long, value_enum, default_value_t = InputWritePolicy::Forward)]
+    pub input_write: InputWritePolicy,
+
+    /// Policy applied when a GPIO config does not match the direction of the pin
+    #[clap(long, value_enum, default_value_t = ConfigDirectionPolicy::Warn)]
+    pub config_direction: ConfigDirectionPolicy,
+
+    /// Bias applied to a pin first directed to Input without a config, instead of the firmware default
+    #[clap(long, value_enum)]
+    pub default_bias: Option<DefaultBias>,
+
+    /// How a pin disabled by the Kernel Driver is sent to the secondary
+    #[clap(long, value_enum, default_value_t = DisableMapping::Native)]
+    pub disable_mapping: DisableMapping,
+
+    /// Read a pin back after a value write to confirm that it reached the value
+    #[clap(long, value_enum, default_value_t = VerifyWritesPolicy::Off)]
+    pub verify_writes: VerifyWritesPolicy,
+
+    /// Policy applied to the GPIO names reported by the secondary
+    #[clap(long, value_enum, default_value_t = NamePolicy::Raw)]
+    pub name_policy: NamePolicy,
+
+    /// Name a pin gpio<PIN> instead of failing when the request of its name fails
+    #[clap(long, default_value = "false")]
+    pub tolerate_name_errors: bool,
+
+    /// Only register the first GPIO_COUNT GPIO's with the kernel
+    #[clap(long, value_parser = clap::value_parser!(u8).range(1..))]
+    pub gpio_count: Option<u8>,
+
+    /// Pins neither touched at startup nor registered with the kernel, e.g. `3,7`
+    #[clap(long, value_delimiter = ',')]
+    pub unmanaged_pins: Vec<u8>,
+
+    /// Override the unique id reported by the secondary
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub unique_id: Option<u64>,
+
+    /// Override the reply timeout of a command, e.g. get-gpio-value=5000 (repeatable)
+    #[clap(long, value_name = "CMD=MS", value_parser = parse_cmd_timeout)]
+    pub cmd_timeout: Vec<CmdTimeout>,
+
+    /// Abort a request after this many consecutive sequence number mismatches
+    #[clap(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_seq_mismatches: u32,
+
+    /// Resynchronize the link after this many malformed frames from the secondary, 0 disables it
+    #[clap(long, default_value_t = 3)]
+    pub resync_after_malformed: u32,
+
+    /// Sequence number of the first request to the secondary
+    #[clap(long, default_value_t = 1)]
+    pub first_seq: u8,
+
+    /// Bytes of sequence number, two is negotiated with the secondary and falls back to one when unsupported
+    #[clap(long, value_enum, default_value_t = SeqWidth::One)]
+    pub seq_width: SeqWidth,
+
+    /// Keep up to PIPELINE_DEPTH GPIO name requests in flight during discovery
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=32))]
+    pub pipeline_depth: u8,
+
+    /// Number of router workers handling Kernel Driver requests, requests of a pin are kept in order
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    pub router_workers: u8,
+
+    /// Delay a value read until SETTLE_US after a direction change of the pin
+    #[clap(long, default_value_t = 0)]
+    pub settle_us: u64,
+
+    /// Abort startup once discovery of the secondary exceeds STARTUP_TIMEOUT ms
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub startup_timeout: Option<u64>,
+
+    /// Attempts made to initialize the Kernel Driver before giving up
+    #[clap(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(1..))]
+    pub retry_attempts: u32,
+
+    /// Delay before the first retry
+    #[clap(long, default_value_t = 100)]
+    pub retry_interval_ms: u64,
+
+    /// Factor by which the retry delay grows after every retry
+    #[clap(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..))]
+    pub retry_backoff: u32,
+
+    /// Upper bound of the retry delay
+    #[clap(long, default_value_t = 1000)]
+    pub retry_cap_ms: u64,
+
+    /// Wait up to WAIT_FOR_DRIVER ms for the Kernel Driver to be loaded instead of failing right away
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub wait_for_driver: Option<u64>,
+
+    /// Poll the secondary for new GPIO's every HOT_ADD_INTERVAL_MS and add them to the chip
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub hot_add_interval_ms: Option<u64>,
+
+    /// Stop the bridge once a reader thread spent more than STALL_WINDOW_MS handing over what it read
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub stall_window_ms: Option<u64>,
+
+    /// Read back the output pins every RECONCILE_INTERVAL_MS and rewrite those that diverged from their last written value
+    #[clap(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub reconcile_interval_ms: Option<u64>,
+
+    /// Send the discovery requests again and log the values whenever REDISCOVER_FILE is created, the file is then removed
+    #[clap(long)]
+    pub rediscover_file: Option<std::path::PathBuf>,
+
+    /// Log the raw pad registers of every pin after discovery
+    #[clap(long, default_value = "false")]
+    pub dump_gpio_raw: bool,
+
+    /// Log whether every pin is driven or floating after discovery
+    #[clap(long, default_value = "false")]
+    pub dump_gpio_status: bool,
+
+    /// Write a devicetree overlay fragment describing the discovered chip to EMIT_DT
+    #[clap(long)]
+    pub emit_dt: Option<std::path::PathBuf>,
+
+    /// Print the value changes of the listed pins to stdout instead of serving the Kernel Driver, e.g. `3,7`
+    #[clap(long, value_delimiter = ',')]
+    pub monitor: Vec<u8>,
+
+    /// Interval at which the --monitor pins are polled
+    #[clap(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    pub monitor_interval_ms: u64,
+
+    /// Toggle the pin as fast as the link allows and print the achieved rate to stdout instead of serving the Kernel Driver
+    #[clap(long, conflicts_with = "monitor")]
+    pub bench_toggle: Option<u8>,
+
+    /// Duration of the --bench-toggle run
+    #[clap(long, default_value_t = 5000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub bench_duration_ms: u64,
+
+    /// Write the process id to READY_FILE once the bridge is ready, removed on exit
+    #[clap(long)]
+    pub ready_file: Option<std::path::PathBuf>,
+
+    /// Append lifecycle events (initialized, driver initialized, resync, shutting down) to EVENTS_FILE as JSON lines
+    #[clap(long)]
+    pub events_file: Option<std::path::PathBuf>,
+
+    /// Send the reason of a thread failure to the Kernel Driver before deinit, to be logged by the kernel
+    #[clap(long, default_value = "false")]
+    pub last_will: bool,
+
+    /// Defer the deinit on SIGTERM by SHUTDOWN_GRACE_MS, a SIGUSR1 meanwhile cancels the shutdown. 0 shuts down immediately
+    #[clap(long, default_value = "0")]
+    pub shutdown_grace_ms: u64,
+
+    /// Per-pin presets applied after discovery
+    #[clap(long)]
+    pub init_config: Option<std::path::PathBuf>,
+
+    /// Keep STATE_FILE updated with the last known state of every pin
+    #[clap(long)]
+    pub state_file: Option<std::path::PathBuf>,
+
+    /// Coalesce the pin state changes of STATE_FILE_DEBOUNCE_MS into one write
+    #[clap(long, default_value_t = 100)]
+    pub state_file_debounce_ms: u64,
+
+    /// Serve the bridge health over HTTP on HEALTH_ADDR, e.g. 127.0.0.1:8080
+    #[clap(long)]
+    pub health_addr: Option<std::net::SocketAddr>,
+
+    /// Consider the link down, and answer health requests with 503, after LINK_DOWN_AFTER consecutive requests the secondary did not answer
+    #[clap(long, alias = "health-max-timeouts", default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+    pub link_down_after: u32,
+
+    /// Consider a down link up again after LINK_UP_AFTER consecutive requests the secondary answered
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub link_up_after: u32,
+
+    /// Transport to the secondary, among those built in
+    #[clap(long, value_enum, default_value_t = Transport::default())]
+    pub transport: Transport,
+
+    /// Directory in which CPCd creates its instance sockets
+    #[cfg(feature = "gpio_cpc")]
+    #[clap(long, default_value = "/dev/shm")]
+    pub cpcd_socket_dir: std::path::PathBuf,
+
+    /// Give up connecting to CPCd after CPCD_INIT_TIMEOUT_MS
+    #[cfg(feature = "gpio_cpc")]
+    #[clap(long, default_value_t = 2000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub cpcd_init_timeout_ms: u64,
+
+    /// Give up opening the CPC Endpoint after ENDPOINT_OPEN_TIMEOUT_MS
+    #[cfg(feature = "gpio_cpc")]
+    #[clap(long, default_value_t = 2000, value_parser = clap::value_parser!(u64).range(1..))]
+    pub endpoint_open_timeout_ms: u64,
+
+    /// Write libcpc tracing to LIBCPC_TRACE_FILE instead of stderr, enables libcpc tracing
+    #[cfg(feature = "gpio_cpc")]
+    #[clap(long)]
+    pub libcpc_trace_file: Option<std::path::PathBuf>,
+
+    /// Number of GPIO's exposed by the mock secondary
+    #[cfg(feature = "gpio_mock")]
+    #[clap(long, default_value_t = 16)]
+    pub mock_gpio_count: u8,
+
+    /// Maximum GPIO count reported by the mock secondary, defaults to MOCK_GPIO_COUNT
+    #[cfg(feature = "gpio_mock")]
+    #[clap(long)]
+    pub mock_max_gpio_count: Option<u8>,
+
+    /// Value of a mock GPIO once it is disabled
+    #[cfg(feature = "gpio_mock")]
+    #[clap(long, value_enum, default_value_t = MockDisablePolicy::ForceLow)]
+    pub mock_disable_policy: MockDisablePolicy,
+
+    /// Command the mock secondary answers with UnsupportedCmdIs (repeatable)
+    #[cfg(feature = "gpio_mock")]
+    #[clap(long, value_enum)]
+    #[serde(serialize_with = "serialize_value_names")]
+    pub mock_unsupported_cmd: Vec<crate::gpio::HostCmd>,
+
+    /// Command the mock secondary never answers, like a hung secondary (repeatable)
+    #[cfg(feature = "gpio_mock")]
+    #[clap(long, value_enum)]
+    #[serde(serialize_with = "serialize_value_names")]
+    pub mock_unanswered_cmd: Vec<crate::gpio::HostCmd>,
+
+    /// Interval at which the gpio-sim lines are checked for values driven by consumers, pin state changes are mirrored at once
+    #[cfg(feature = "gpio_sim")]
+    #[clap(long, default_value_t = 250, value_parser = clap::value_parser!(u64).range(1..))]
+    pub sim_poll_interval_ms: u64,
+
+    /// Emit key and switch events on the transitions of the input pins mapped in UINPUT_MAP
+    #[cfg(feature = "uinput")]
+    #[clap(long)]
+    pub uinput_map: Option<std::path::PathBuf>,
+
+    /// Interval at which the --uinput-map pins are polled
+    #[cfg(feature = "uinput")]
+    #[clap(long, default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..))]
+    pub uinput_poll_interval_ms: u64,
+
+    /// Send CMD with the hex PAYLOAD after discovery and log the raw reply, e.g. 16:0102 (repeatable, unstable)
+    #[cfg(feature = "raw_request")]
+    #[clap(long, value_name = "CMD[:PAYLOAD]", value_parser = parse_raw_request)]
+    pub raw_request: Vec<RawRequest>,
+
+    /// Packet trace to replay instead of a live secondary
+    #[cfg(feature = "gpio_replay")]
+    #[clap(long)]
+    pub replay_trace: Option<std::path::PathBuf>,
 }
 
 pub struct TraceConfig {
     pub bridge: log::LevelFilter,
     pub libcpc: bool,
+    #[cfg(feature = "gpio_cpc")]
+    pub libcpc_file: Option<std::path::PathBuf>,
+}
+
+// Logged at startup, the levels in effect are those of the log crate
+impl std::fmt::Display for TraceConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Bridge: {}, Libcpc: {}",
+            self.bridge,
+            if self.libcpc { "On" } else { "Off" }
+        )?;
+        #[cfg(feature = "gpio_cpc")]
+        if let Some(file) = &self.libcpc_file {
+            write!(f, ", Libcpc File: {}", file.display())?;
+        }
+        Ok(())
+    }
 }
 
 pub fn trace(config: &Config) -> TraceConfig {
     let mut trace_config = TraceConfig {
         bridge: log::LevelFilter::Info,
         libcpc: false,
+        #[cfg(feature = "gpio_cpc")]
+        libcpc_file: config.libcpc_trace_file.clone(),
     };
 
     match config.trace {
@@ -70,29 +619,531 @@ pub fn trace(config: &Config) -> TraceConfig {
         }
     }
 
+    #[cfg(feature = "gpio_cpc")]
+    if trace_config.libcpc_file.is_some() {
+        trace_config.libcpc = true;
+    }
+
     trace_config
 }
 
-pub fn lock_bridge(path: &std::path::Path) -> Result<file_lock::FileLock> {
-    let lock = if let Ok(lock) = file_lock::FileLock::lock(
+// libcpc only traces to stderr. The returned copy of stderr keeps the bridge
+// logs where they were, stderr itself then goes to the trace file.
+#[cfg(feature = "gpio_cpc")]
+pub fn redirect_libcpc_trace(path: &std::path::Path) -> Result<std::fs::File> {
+    use std::os::fd::{AsFd, AsRawFd};
+
+    let trace_file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|err| {
+            anyhow!(
+                "Failed to open libcpc trace file ({}), Err: {}",
+                path.display(),
+                err
+            )
+        })?;
+
+    let stderr = std::io::stderr().as_fd().try_clone_to_owned()?;
+
+    // SAFETY: trace_file is open until the end of this function and stderr
+    // for the life of the process, dup2 atomically closes stderr and points it
+    // at the trace file. No Rust handle owns fd 2 so none is left dangling.
+    if unsafe { libc::dup2(trace_file.as_raw_fd(), std::io::stderr().as_raw_fd()) } < 0 {
+        bail!(
+            "Failed to redirect libcpc tracing to {}, Err: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        );
+    }
+
+    Ok(std::fs::File::from(stderr))
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ChannelConfig {
+    pub capacity: usize,
+    pub overflow: Overflow,
+}
+
+pub fn channel_config(config: &Config) -> ChannelConfig {
+    ChannelConfig {
+        capacity: config.channel_capacity as usize,
+        overflow: config.channel_overflow,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SocketConfig {
+    pub read: DriverRead,
+    pub unicast_rcvbuf: Option<usize>,
+    pub multicast_rcvbuf: Option<usize>,
+    pub multicast_groups: Vec<String>,
+}
+
+pub fn socket_config(config: &Config) -> SocketConfig {
+    SocketConfig {
+        read: config.driver_read,
+        unicast_rcvbuf: config.netlink_unicast_rcvbuf.map(|size| size as usize),
+        multicast_rcvbuf: config.netlink_multicast_rcvbuf.map(|size| size as usize),
+        multicast_groups: config.netlink_multicast_group.clone(),
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub interval_ms: u64,
+    pub backoff: u32,
+    pub cap_ms: u64,
+    pub timeout_ms: Option<u64>,
+}
+
+impl RetryPolicy {
+    /// The same delays, retried until `timeout_ms` elapsed rather than for a
+    /// number of attempts.
+    pub fn with_timeout(&self, timeout_ms: u64) -> Self {
+        Self {
+            attempts: u32::MAX,
+            timeout_ms: Some(timeout_ms),
+            ..*self
+        }
+    }
+
+    /// Delay before the attempt following the failed `attempt` (1-based),
+    /// `elapsed` since the first one. `None` once all attempts are used up or
+    /// the timeout elapsed.
+    pub fn delay_ms(&self, attempt: u32, elapsed: Duration) -> Option<u64> {
+        if attempt >= self.attempts {
+            return None;
+        }
+
+        if let Some(timeout_ms) = self.timeout_ms {
+            if elapsed.as_millis() >= timeout_ms as u128 {
+                return None;
+            }
+        }
+
+        let factor = (self.backoff as u64).saturating_pow(attempt - 1);
+        Some(self.interval_ms.saturating_mul(factor).min(self.cap_ms))
+    }
+}
+
+pub fn retry_policy(config: &Config) -> RetryPolicy {
+    RetryPolicy {
+        attempts: config.retry_attempts,
+        interval_ms: config.retry_interval_ms,
+        backoff: config.retry_backoff,
+        cap_ms: config.retry_cap_ms,
+        timeout_ms: None,
+    }
+}
+
+pub struct ChannelSender<T> {
+    name: &'static str,
+    config: ChannelConfig,
+    sender: mpsc::SyncSender<T>,
+    // Only reached for DropOldest, a strong reference would keep the channel
+    // connected after its consumer is gone
+    receiver: Weak<Mutex<mpsc::Receiver<T>>>,
+    full_warnings: WarnLimiter,
+}
+
+impl<T> ChannelSender<T> {
+    pub fn send(&self, item: T) -> Result<()> {
+        let item = match self.sender.try_send(item) {
+            Ok(()) => {
+                self.full_warnings
+                    .clear(format_args!("{} channel overflow", self.name));
+                return Ok(());
+            }
+            Err(mpsc::TrySendError::Full(item)) => item,
+            Err(mpsc::TrySendError::Disconnected(_)) => {
+                bail!("{} channel is disconnected", self.name)
+            }
+        };
+
+        // The bridge waits for a reply before issuing the next request,
+        // a full channel means a reply is not being consumed.
+        self.full_warnings.warn(format_args!(
+            "{} channel is full (Capacity: {}, Overflow: {:?})",
+            self.name, self.config.capacity, self.config.overflow
+        ));
+
+        match self.config.overflow {
+            Overflow::Block => self
+                .sender
+                .send(item)
+                .map_err(|err| anyhow!("{} channel, Err: {}", self.name, err)),
+            Overflow::DropOldest => {
+                let receiver = match self.receiver.upgrade() {
+                    Some(receiver) => receiver,
+                    None => bail!("{} channel is disconnected", self.name),
+                };
+                let _oldest = receiver
+                    .lock()
+                    .map_err(|err| anyhow!("{}", err))?
+                    .try_recv();
+
+                match self.sender.try_send(item) {
+                    Ok(()) => Ok(()),
+                    Err(err) => bail!("{} channel, Err: {}", self.name, err),
+                }
+            }
+        }
+    }
+}
+
+pub fn sync_channel<T>(
+    name: &'static str,
+    config: &ChannelConfig,
+) -> (ChannelSender<T>, Arc<Mutex<mpsc::Receiver<T>>>) {
+    let (sender, receiver) = mpsc::sync_channel(config.capacity);
+    let receiver = Arc::new(Mutex::new(receiver));
+
+    let sender = ChannelSender {
+        name,
+        config: *config,
+        sender,
+        receiver: Arc::downgrade(&receiver),
+        full_warnings: WarnLimiter::new(),
+    };
+
+    (sender, receiver)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct WarnLimit {
+    pub burst: u32,
+    pub window: Duration,
+}
+
+pub fn warn_limit(config: &Config) -> WarnLimit {
+    WarnLimit {
+        burst: config.warn_burst,
+        window: Duration::from_millis(config.warn_window_ms),
+    }
+}
+
+// Shared by every limiter, see --warn-burst
+static WARN_LIMIT: std::sync::RwLock<WarnLimit> = std::sync::RwLock::new(WarnLimit {
+    burst: 10,
+    window: Duration::from_secs(10),
+});
+
+pub fn set_warn_limit(limit: WarnLimit) {
+    if let Ok(mut warn_limit) = WARN_LIMIT.write() {
+        *warn_limit = limit;
+    }
+}
+
+#[derive(Default)]
+struct WarnWindow {
+    start: Option<Instant>,
+    logged: u32,
+    suppressed: u64,
+}
+
+impl WarnWindow {
+    // The warnings suppressed since the last one logged when this one is to
+    // be logged, None when it is suppressed
+    fn allow(&mut self, limit: &WarnLimit, now: Instant) -> Option<u64> {
+        if limit.burst == 0 {
+            return Some(0);
+        }
+
+        if self
+            .start
+            .is_none_or(|start| now.duration_since(start) >= limit.window)
+        {
+            self.start = Some(now);
+            self.logged = 0;
+        }
+
+        if self.logged < limit.burst {
+            self.logged += 1;
+            return Some(std::mem::take(&mut self.suppressed));
+        }
+
+        self.suppressed += 1;
+        None
+    }
+
+    fn clear(&mut self) -> u64 {
+        self.start = None;
+        self.logged = 0;
+        std::mem::take(&mut self.suppressed)
+    }
+}
+
+// Keeps a warning repeated on every packet of a failing link from flooding
+// the log. The first warnings of a window are logged, the count of the ones
+// suppressed after is logged with the first of the next window, or once the
+// condition clears.
+pub struct WarnLimiter {
+    window: Mutex<WarnWindow>,
+}
+
+impl WarnLimiter {
+    pub fn new() -> Self {
+        Self {
+            window: Mutex::new(WarnWindow::default()),
+        }
+    }
+
+    pub fn warn(&self, args: std::fmt::Arguments) {
+        let limit = match WARN_LIMIT.read() {
+            Ok(limit) => *limit,
+            Err(_) => return log::warn!("{}", args),
+        };
+
+        let allowed = match self.window.lock() {
+            Ok(mut window) => window.allow(&limit, Instant::now()),
+            Err(_) => Some(0),
+        };
+
+        match allowed {
+            Some(0) => log::warn!("{}", args),
+            Some(suppressed) => log::warn!("{} ({} similar suppressed)", args, suppressed),
+            None => (),
+        }
+    }
+
+    pub fn clear(&self, condition: std::fmt::Arguments) {
+        let suppressed = match self.window.lock() {
+            Ok(mut window) => window.clear(),
+            Err(_) => 0,
+        };
+
+        if suppressed > 0 {
+            log::warn!("{} cleared ({} similar suppressed)", condition, suppressed);
+        }
+    }
+}
+
+static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+
+// Tag prefixed to the log lines of every thread, see --log-tag
+static LOG_TAG: std::sync::RwLock<String> = std::sync::RwLock::new(String::new());
+
+pub fn set_log_tag(tag: impl Into<String>) {
+    if let Ok(mut log_tag) = LOG_TAG.write() {
+        *log_tag = tag.into();
+    }
+}
+
+pub fn log_tag() -> String {
+    match LOG_TAG.read() {
+        Ok(log_tag) => log_tag.clone(),
+        Err(_) => String::new(),
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Monotonic(std::time::Duration);
+impl Monotonic {
+    pub fn start() {
+        START.get_or_init(std::time::Instant::now);
+    }
+    pub fn now() -> Self {
+        Self(START.get_or_init(std::time::Instant::now).elapsed())
+    }
+}
+impl std::fmt::Display for Monotonic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "+{}.{:06}s", self.0.as_secs(), self.0.subsec_micros())
+    }
+}
+
+// Liveness of a reader thread, busy from the moment it read something until it
+// handed it over. A reader waiting for traffic is idle, it cannot be told
+// apart from a silent peer, but one stuck handling what it read is stalled.
+pub struct Heartbeat {
+    pub name: &'static str,
+    // Monotonic ms, 0 while idle
+    busy_since_ms: AtomicU64,
+}
+impl Heartbeat {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            busy_since_ms: AtomicU64::new(0),
+        }
+    }
+
+    pub fn begin(&self) {
+        let now_ms = Monotonic::now().0.as_millis() as u64;
+        self.busy_since_ms.store(now_ms.max(1), Ordering::SeqCst);
+    }
+
+    pub fn end(&self) {
+        self.busy_since_ms.store(0, Ordering::SeqCst);
+    }
+
+    pub fn stalled(&self, window: Duration) -> Option<Duration> {
+        let busy_since_ms = self.busy_since_ms.load(Ordering::SeqCst);
+        if busy_since_ms == 0 {
+            return None;
+        }
+
+        let busy = Monotonic::now()
+            .0
+            .saturating_sub(Duration::from_millis(busy_since_ms));
+        (busy > window).then_some(busy)
+    }
+}
+
+// Without wait_ms, a lock held by another bridge fails right away. Otherwise
+// the lock is tried again with the retry delays, e.g. while the previous
+// bridge of a rolling restart exits.
+pub fn lock_bridge(
+    path: &std::path::Path,
+    wait_ms: Option<u64>,
+    retry_policy: &RetryPolicy,
+) -> Result<file_lock::FileLock> {
+    let now = Instant::now();
+    let mut attempt = 0;
+    let mut lock = loop {
+        attempt += 1;
+        match try_lock_bridge(path) {
+            Ok(lock) => {
+                if attempt > 1 {
+                    log::info!(
+                        "Bridge lock taken after {} ms (Attempt: {})",
+                        now.elapsed().as_millis(),
+                        attempt
+                    );
+                }
+                break lock;
+            }
+            Err(err) => {
+                let timeout_ms = match wait_ms {
+                    Some(timeout_ms) => timeout_ms,
+                    None => bail!(
+                        "The bridge lock ({}) cannot be taken. Err: {}",
+                        path.display(),
+                        err
+                    ),
+                };
+
+                let delay_ms = match retry_policy
+                    .with_timeout(timeout_ms)
+                    .delay_ms(attempt, now.elapsed())
+                {
+                    Some(delay_ms) => delay_ms,
+                    None => bail!(
+                        "The bridge lock ({}) cannot be taken after {} ms. Err: {}",
+                        path.display(),
+                        timeout_ms,
+                        err
+                    ),
+                };
+
+                let holder = match lock_pid(path) {
+                    Some(pid) => pid.to_string(),
+                    None => "unknown".to_string(),
+                };
+                log::info!(
+                    "Waiting for the bridge lock ({}) held by process {}, retrying in {} ms (Attempt: {})",
+                    path.display(),
+                    holder,
+                    delay_ms,
+                    attempt
+                );
+                std::thread::sleep(Duration::from_millis(delay_ms));
+            }
+        }
+    };
+
+    // Named by the error of a bridge that finds the chip already initialized
+    lock.file.set_len(0)?;
+    write!(lock.file, "{}", std::process::id())?;
+
+    Ok(lock)
+}
+
+fn try_lock_bridge(path: &std::path::Path) -> std::io::Result<file_lock::FileLock> {
+    if let Ok(lock) = file_lock::FileLock::lock(
         path,
         false,
         file_lock::FileOptions::new().create(true).append(true),
     ) {
-        lock
+        Ok(lock)
     } else {
-        file_lock::FileLock::lock(path, false, file_lock::FileOptions::new().append(true)).map_err(
-            |err| {
-                anyhow!(
-                    "The bridge lock ({}) cannot be taken. Err: {}",
-                    path.display(),
-                    err
-                )
-            },
-        )?
+        file_lock::FileLock::lock(path, false, file_lock::FileOptions::new().append(true))
+    }
+}
+
+// Process id written in a lock by the bridge holding it
+fn lock_pid(path: &std::path::Path) -> Option<u32> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|pid| pid.trim().parse::<u32>().ok())
+}
+
+// Bridges holding a lock in lock_dir other than this one, as their instance
+// and the process id written in the lock
+pub fn lock_holders(lock_dir: &std::path::Path) -> Vec<(String, Option<u32>)> {
+    let entries = match std::fs::read_dir(lock_dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
     };
 
-    Ok(lock)
+    let mut holders = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let instance = match path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("cpc-gpio-bridge-"))
+            .and_then(|name| name.strip_suffix(".lock"))
+        {
+            Some(instance) => instance.to_string(),
+            None => continue,
+        };
+
+        // A lock taken successfully is not held by anyone, it is released on drop
+        if file_lock::FileLock::lock(&path, false, file_lock::FileOptions::new().read(true)).is_ok()
+        {
+            continue;
+        }
+
+        let pid = lock_pid(&path);
+        if pid == Some(std::process::id()) {
+            continue;
+        }
+
+        holders.push((instance, pid));
+    }
+    holders.sort();
+    holders
+}
+
+pub fn format_lock_holders(
+    lock_dir: &std::path::Path,
+    holders: &[(String, Option<u32>)],
+) -> String {
+    if holders.is_empty() {
+        return format!(
+            "no other bridge holds a lock in {}, it may run with --no-lock or another --lock-dir",
+            lock_dir.display()
+        );
+    }
+
+    let holders: Vec<String> = holders
+        .iter()
+        .map(|(instance, pid)| match pid {
+            Some(pid) => format!("instance {} (PID {})", instance, pid),
+            None => format!("instance {} (PID unknown)", instance),
+        })
+        .collect();
+
+    format!(
+        "bridges holding a lock in {}: {}",
+        lock_dir.display(),
+        holders.join(", ")
+    )
 }
 
 #[derive(Error, Debug)]
@@ -100,28 +1151,150 @@ pub enum ProcessExit {
     #[error(transparent)]
     Context(anyhow::Error),
 }
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ShutdownReason {
+    Signal,
+    DriverUnload,
+    Deinit,
+    GpioThreadFailure,
+    DriverThreadFailure,
+    RouterFailure,
+    HealthFailure,
+    Benchmark,
+    // Any failure not classified by the process loop, e.g. during startup
+    Failure,
+}
+
+#[derive(Error, Debug)]
+#[error("{detail}")]
+pub struct Shutdown {
+    pub reason: ShutdownReason,
+    pub detail: anyhow::Error,
+}
+impl Shutdown {
+    pub fn with_default(err: anyhow::Error, reason: ShutdownReason) -> anyhow::Error {
+        if err.is::<Shutdown>() {
+            err
+        } else {
+            Shutdown {
+                reason,
+                detail: err,
+            }
+            .into()
+        }
+    }
+}
+
 pub fn exit(err: anyhow::Error) -> ! {
+    let (reason, err) = match err.downcast::<Shutdown>() {
+        Ok(shutdown) => (shutdown.reason, shutdown.detail),
+        Err(err) => (ShutdownReason::Failure, err),
+    };
+
+    crate::events::publish(|| crate::events::Event::ShuttingDown {
+        reason,
+        detail: match err.downcast_ref::<ProcessExit>() {
+            Some(context) => context.to_string(),
+            None => err.to_string(),
+        },
+    });
+
     if let Some(context) = err.downcast_ref::<ProcessExit>() {
-        log::info!("{}", context);
+        log::info!("Shutdown {{ Reason: {:?}, Detail: {} }}", reason, context);
         std::process::exit(0);
     } else {
-        log::error!("{}\nBacktrace:\n{}", err, err.backtrace());
+        log::error!(
+            "Shutdown {{ Reason: {:?}, Detail: {} }}\nBacktrace:\n{}",
+            reason,
+            err,
+            err.backtrace()
+        );
         std::process::exit(1);
     }
 }
 
+// Set once discovery and Kernel Driver init completed and the router is
+// processing requests
+pub struct Ready {
+    ready: Mutex<bool>,
+    changed: Condvar,
+    file: Option<std::path::PathBuf>,
+}
+impl Ready {
+    pub fn new(file: Option<std::path::PathBuf>) -> Self {
+        Self {
+            ready: Mutex::new(false),
+            changed: Condvar::new(),
+            file,
+        }
+    }
+
+    pub fn mark(&self) -> Result<()> {
+        if let Some(file) = &self.file {
+            std::fs::write(file, format!("{}\n", std::process::id())).map_err(|err| {
+                anyhow!(
+                    "Failed to write ready file ({}), Err: {}",
+                    file.display(),
+                    err
+                )
+            })?;
+        }
+
+        *self.ready.lock().unwrap_or_else(|err| err.into_inner()) = true;
+        self.changed.notify_all();
+
+        log::info!("Bridge ready");
+
+        Ok(())
+    }
+
+    // Returns whether the bridge became ready within the timeout
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let ready = self.ready.lock().unwrap_or_else(|err| err.into_inner());
+        let (ready, _) = self
+            .changed
+            .wait_timeout_while(ready, timeout, |ready| !*ready)
+            .unwrap_or_else(|err| err.into_inner());
+
+        *ready
+    }
+}
+impl Drop for Ready {
+    // A leftover ready file would report a bridge that is gone
+    fn drop(&mut self) {
+        if let Some(file) = &self.file {
+            if self.wait(Duration::ZERO) {
+                if let Err(err) = std::fs::remove_file(file) {
+                    log::warn!(
+                        "Failed to remove ready file ({}), Err: {}",
+                        file.display(),
+                        err
+                    );
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct ThreadExit {
     pub receiver: Mutex<mio::unix::pipe::Receiver>,
 }
 impl ThreadExit {
     pub fn notify(sender: &mut mio::unix::pipe::Sender, message: &str) {
-        if let Err(err) = sender.write_all(message.as_bytes()) {
-            exit(anyhow!(
+        match sender.write_all(message.as_bytes()) {
+            Ok(()) => (),
+            // The handle owning the receiver was dropped, e.g. its channel
+            // disconnected on shutdown, nobody is left to notify
+            Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {
+                log::debug!("{}, Thread exit not notified, Err: {}", message, err)
+            }
+            Err(err) => exit(anyhow!(
                 "{}, Failed to notify thread exit, Err: {}",
                 message,
                 err
-            ));
+            )),
         }
     }
 }
@@ -140,3 +1313,334 @@ impl std::fmt::Display for ThreadExit {
         write!(f, "{}", message)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heartbeat_stalls_only_while_busy() {
+        let heartbeat = Heartbeat::new("Test");
+        let window = Duration::from_millis(20);
+
+        // An idle reader waits for traffic, it is never stalled
+        std::thread::sleep(window * 2);
+        assert!(heartbeat.stalled(window).is_none());
+
+        heartbeat.begin();
+        assert!(heartbeat.stalled(window).is_none());
+        std::thread::sleep(window * 2);
+        assert!(heartbeat.stalled(window).unwrap() > window);
+
+        heartbeat.end();
+        assert!(heartbeat.stalled(window).is_none());
+    }
+
+    #[cfg(feature = "raw_request")]
+    #[test]
+    fn raw_request_parse() {
+        let request = parse_raw_request("16:01ff").unwrap();
+        assert_eq!(request.cmd, 16);
+        assert_eq!(request.payload, vec![0x01, 0xff]);
+
+        assert!(parse_raw_request("16").unwrap().payload.is_empty());
+        assert!(parse_raw_request("16:1").is_err());
+        assert!(parse_raw_request("16:zz").is_err());
+        assert!(parse_raw_request("256").is_err());
+    }
+
+    #[test]
+    fn print_config_reads_as_the_command_line() {
+        let config = <Config as clap::Parser>::parse_from([
+            "cpc-gpio-bridge",
+            "--cmd-timeout",
+            "get-gpio-value=50",
+            "--unmanaged-pins",
+            "3,4",
+            "--print-config",
+        ]);
+        let printed = toml::to_string(&config).unwrap();
+
+        assert!(printed.contains("instance = \"cpcd_0\"\n"));
+        assert!(printed.contains("cmd-timeout = [\"get-gpio-value=50\"]\n"));
+        assert!(printed.contains("unmanaged-pins = [3, 4]\n"));
+        assert!(printed.contains("seq-width = \"one\"\n"));
+        assert!(!printed.contains("print-config"));
+    }
+
+    #[test]
+    fn lock_wait_parse() {
+        let parse = |args: &[&str]| {
+            <Config as clap::Parser>::try_parse_from(["cpc-gpio-bridge"].iter().chain(args))
+        };
+
+        assert_eq!(parse(&[]).unwrap().lock_wait, None);
+        assert_eq!(
+            parse(&["--lock-wait", "5000"]).unwrap().lock_wait,
+            Some(5000)
+        );
+        assert!(parse(&["--lock-wait", "0"]).is_err());
+        assert!(parse(&["--lock-wait", "5000", "--no-lock"]).is_err());
+    }
+
+    #[test]
+    fn lock_holders_name_instance_and_pid() {
+        let lock_dir = std::path::Path::new("/tmp");
+
+        assert_eq!(
+            format_lock_holders(
+                lock_dir,
+                &[("0".to_string(), Some(1234)), ("1".to_string(), None)]
+            ),
+            "bridges holding a lock in /tmp: instance 0 (PID 1234), instance 1 (PID unknown)"
+        );
+        assert!(format_lock_holders(lock_dir, &[]).starts_with("no other bridge holds a lock"));
+    }
+
+    #[test]
+    fn ready_file_follows_readiness() {
+        let file = std::env::temp_dir().join(format!("ready-{}", std::process::id()));
+        let ready = Ready::new(Some(file.clone()));
+
+        assert!(!ready.wait(Duration::ZERO));
+        assert!(!file.exists());
+
+        ready.mark().unwrap();
+
+        assert!(ready.wait(Duration::ZERO));
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+
+        drop(ready);
+
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn ready_wakes_waiters() {
+        let ready = std::sync::Arc::new(Ready::new(None));
+        let waiter = {
+            let ready = ready.clone();
+            std::thread::spawn(move || ready.wait(Duration::from_secs(5)))
+        };
+
+        ready.mark().unwrap();
+
+        assert!(waiter.join().unwrap());
+    }
+
+    #[test]
+    fn log_tag_is_shared_across_threads() {
+        set_log_tag("cpcd_1");
+        assert_eq!(std::thread::spawn(log_tag).join().unwrap(), "cpcd_1");
+
+        std::thread::spawn(|| set_log_tag("42")).join().unwrap();
+        assert_eq!(log_tag(), "42");
+    }
+
+    #[test]
+    fn retry_policy_backs_off_up_to_cap() {
+        let policy = RetryPolicy {
+            attempts: 6,
+            interval_ms: 100,
+            backoff: 2,
+            cap_ms: 1000,
+            timeout_ms: None,
+        };
+
+        let delays: Vec<Option<u64>> = (1..=6)
+            .map(|attempt| policy.delay_ms(attempt, Duration::ZERO))
+            .collect();
+
+        assert_eq!(
+            delays,
+            vec![Some(100), Some(200), Some(400), Some(800), Some(1000), None]
+        );
+    }
+
+    #[test]
+    fn retry_policy_with_timeout_ignores_attempts() {
+        let policy = RetryPolicy {
+            attempts: 1,
+            interval_ms: 100,
+            backoff: 2,
+            cap_ms: 1000,
+            timeout_ms: None,
+        }
+        .with_timeout(2000);
+
+        assert_eq!(policy.delay_ms(3, Duration::from_millis(1999)), Some(400));
+        assert_eq!(policy.delay_ms(u32::MAX - 1, Duration::ZERO), Some(1000));
+        assert_eq!(policy.delay_ms(1, Duration::from_millis(2000)), None);
+    }
+
+    #[test]
+    fn retry_policy_single_attempt_never_retries() {
+        let policy = RetryPolicy {
+            attempts: 1,
+            interval_ms: 100,
+            backoff: 2,
+            cap_ms: 1000,
+            timeout_ms: None,
+        };
+
+        assert_eq!(policy.delay_ms(1, Duration::ZERO), None);
+    }
+
+    #[test]
+    fn trace_config_display() {
+        let config = <Config as clap::Parser>::parse_from(["cpc-gpio-bridge", "--trace", "all"]);
+
+        assert!(trace(&config)
+            .to_string()
+            .starts_with("Bridge: DEBUG, Libcpc: On"));
+
+        let config = <Config as clap::Parser>::parse_from(["cpc-gpio-bridge"]);
+
+        assert_eq!(trace(&config).to_string(), "Bridge: INFO, Libcpc: Off");
+    }
+
+    #[test]
+    fn warn_window_suppresses_past_the_burst() {
+        let limit = WarnLimit {
+            burst: 2,
+            window: Duration::from_secs(10),
+        };
+        let mut window = WarnWindow::default();
+        let now = Instant::now();
+
+        assert_eq!(window.allow(&limit, now), Some(0));
+        assert_eq!(window.allow(&limit, now), Some(0));
+        assert_eq!(window.allow(&limit, now), None);
+        assert_eq!(window.allow(&limit, now + Duration::from_secs(9)), None);
+        // The first warning of the next window carries the count
+        assert_eq!(window.allow(&limit, now + Duration::from_secs(10)), Some(2));
+        assert_eq!(window.allow(&limit, now + Duration::from_secs(10)), Some(0));
+    }
+
+    #[test]
+    fn warn_window_clear_restarts_the_burst() {
+        let limit = WarnLimit {
+            burst: 1,
+            window: Duration::from_secs(10),
+        };
+        let mut window = WarnWindow::default();
+        let now = Instant::now();
+
+        assert_eq!(window.allow(&limit, now), Some(0));
+        assert_eq!(window.allow(&limit, now), None);
+        assert_eq!(window.clear(), 1);
+        assert_eq!(window.clear(), 0);
+        assert_eq!(window.allow(&limit, now), Some(0));
+    }
+
+    #[test]
+    fn warn_window_burst_zero_logs_everything() {
+        let limit = WarnLimit {
+            burst: 0,
+            window: Duration::from_secs(10),
+        };
+        let mut window = WarnWindow::default();
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            assert_eq!(window.allow(&limit, now), Some(0));
+        }
+    }
+
+    #[test]
+    fn sync_channel_drop_oldest_is_bounded() {
+        let config = ChannelConfig {
+            capacity: 2,
+            overflow: Overflow::DropOldest,
+        };
+        let (sender, receiver) = sync_channel::<u32>("Test", &config);
+
+        for i in 0..100 {
+            sender.send(i).unwrap();
+        }
+
+        let receiver = receiver.lock().unwrap();
+        let items: Vec<u32> = receiver.try_iter().collect();
+
+        assert_eq!(items, vec![98, 99]);
+    }
+
+    #[test]
+    fn sync_channel_block_waits_for_consumer() {
+        let config = ChannelConfig {
+            capacity: 2,
+            overflow: Overflow::Block,
+        };
+        let (sender, receiver) = sync_channel::<u32>("Test", &config);
+        let sent = Arc::new(std::sync::atomic::AtomicU32::new(0));
+        let sent_ref = sent.clone();
+
+        let producer = std::thread::spawn(move || {
+            for i in 0..10 {
+                sender.send(i).unwrap();
+                sent_ref.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            }
+        });
+
+        // Once an item is received, the producer is at most the capacity ahead
+        let mut items = Vec::new();
+        for received in 1..=10 {
+            items.push(receiver.lock().unwrap().recv().unwrap());
+            assert!(sent.load(std::sync::atomic::Ordering::SeqCst) <= received + 2);
+        }
+
+        producer.join().unwrap();
+        assert_eq!(items, (0..10).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn sync_channel_block_fails_once_consumer_dropped() {
+        let config = ChannelConfig {
+            capacity: 1,
+            overflow: Overflow::Block,
+        };
+        let (sender, receiver) = sync_channel::<u32>("Test", &config);
+        sender.send(0).unwrap();
+
+        // Blocked on the full channel or not sent yet, either way it fails
+        let producer = std::thread::spawn(move || sender.send(1));
+        drop(receiver);
+
+        assert!(producer.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn sync_channel_drop_oldest_fails_once_consumer_dropped() {
+        let config = ChannelConfig {
+            capacity: 1,
+            overflow: Overflow::DropOldest,
+        };
+        let (sender, receiver) = sync_channel::<u32>("Test", &config);
+        sender.send(0).unwrap();
+        drop(receiver);
+
+        assert!(sender.send(1).is_err());
+    }
+
+    #[test]
+    fn thread_exit_notify_outlives_the_receiver() {
+        let (mut sender, receiver) = mio::unix::pipe::new().unwrap();
+        drop(receiver);
+
+        ThreadExit::notify(&mut sender, "Reader stopped");
+    }
+
+    #[test]
+    fn shutdown_with_default_keeps_reason() {
+        let err = Shutdown::with_default(anyhow!("poll"), ShutdownReason::RouterFailure);
+        let err = Shutdown::with_default(err, ShutdownReason::Failure);
+
+        assert_eq!(
+            err.downcast::<Shutdown>().unwrap().reason,
+            ShutdownReason::RouterFailure
+        );
+    }
+}
@@ -16,7 +16,7 @@
 
 /* Driver version */
 #define CPC_GPIO_VERSION_MAJOR 1
-#define CPC_GPIO_VERSION_MINOR 1
+#define CPC_GPIO_VERSION_MINOR 3
 #define CPC_GPIO_VERSION_PATCH 0
 
 /* Driver Name */
@@ -39,6 +39,9 @@
 /* GPIO is disabled */
 #define GPIO_LINE_DIRECTION_DISABLED 2
 
+/* Bridge API minor version from which the batch commands are answered */
+#define CPC_GPIO_BRIDGE_BATCH_MINOR 2
+
 struct cpc_gpio_line {
   s32 value;
   s32 direction;
@@ -51,7 +54,14 @@ struct cpc_gpio_chip {
   bool initialized;
   bool registered;
   struct cpc_gpio_line *lines;
+  u32 batch_id;
+  struct semaphore get_multiple_signal;
+  u32 get_multiple_id;
+  struct semaphore set_multiple_signal;
+  u32 set_multiple_id;
+  u32 set_multiple_status;
   struct gpio_chip gc;
+  char *label;
   char **gpio_names;
   u16 gpio_count;
   struct mutex lock;
@@ -78,6 +88,10 @@ enum cpc_gpio_genl_attribute {
   CPC_GPIO_GENL_ATTR_GPIO_VALUE,
   CPC_GPIO_GENL_ATTR_GPIO_CONFIG,
   CPC_GPIO_GENL_ATTR_GPIO_DIRECTION,
+  CPC_GPIO_GENL_ATTR_GPIO_VALUES,
+  CPC_GPIO_GENL_ATTR_GPIO_STATUSES,
+  CPC_GPIO_GENL_ATTR_GPIO_PINS,
+  CPC_GPIO_GENL_ATTR_BATCH_ID,
   __CPC_GPIO_GENL_ATTR_MAX,
 };
 
@@ -90,6 +104,10 @@ enum cpc_gpio_genl_command {
   CPC_GPIO_GENL_CMD_SET_GPIO_VALUE,
   CPC_GPIO_GENL_CMD_SET_GPIO_CONFIG,
   CPC_GPIO_GENL_CMD_SET_GPIO_DIRECTION,
+  CPC_GPIO_GENL_CMD_GET_ALL_GPIO_VALUES,
+  CPC_GPIO_GENL_CMD_SET_GPIO_VALUES,
+  CPC_GPIO_GENL_CMD_ADD_GPIOS,
+  CPC_GPIO_GENL_CMD_UPDATE_NAMES,
   __CPC_GPIO_GENL_CMD_MAX,
 };
 
@@ -98,6 +116,7 @@ enum cpc_status_t {
   CPC_STATUS_NOT_SUPPORTED = 1,
   CPC_STATUS_BROKEN_PIPE = 2,
   CPC_STATUS_PROTOCOL_ERROR = 3,
+  CPC_STATUS_INVALID_ARGUMENT = 4,
   CPC_STATUS_UNKNOWN = UINT_MAX,
 };
 
@@ -114,18 +133,35 @@ int cpc_gpio_genl_callback_set_gpio_config(struct sk_buff *sender_skb,
                                            struct genl_info *info);
 int cpc_gpio_genl_callback_set_gpio_direction(struct sk_buff *sender_skb,
                                               struct genl_info *info);
+int cpc_gpio_genl_callback_exit(struct sk_buff *sender_skb,
+                                struct genl_info *info);
+int cpc_gpio_genl_callback_get_all_gpio_values(struct sk_buff *sender_skb,
+                                               struct genl_info *info);
+int cpc_gpio_genl_callback_set_gpio_values(struct sk_buff *sender_skb,
+                                           struct genl_info *info);
+int cpc_gpio_genl_callback_add_gpios(struct sk_buff *sender_skb,
+                                     struct genl_info *info);
+int cpc_gpio_genl_callback_update_names(struct sk_buff *sender_skb,
+                                        struct genl_info *info);
 
 /* Netlink multicast functions */
 static int cpc_gpio_multicast_get_gpio_value(u64 uid, unsigned int pin);
+static int cpc_gpio_multicast_get_all_gpio_values(u64 uid, u32 batch_id);
 static int cpc_gpio_multicast_set_gpio_value(u64 uid, unsigned int pin,
                                              unsigned int value);
+static int cpc_gpio_multicast_set_gpio_values(u64 uid, u32 batch_id, u32 *pins, u32 *values,
+                                              int count);
 static int cpc_gpio_multicast_set_gpio_config(u64 uid, unsigned int pin, unsigned int config);
 static int cpc_gpio_multicast_set_gpio_direction(u64 uid, unsigned int pin, unsigned int direction);
 static int cpc_gpio_multicast_exit(const char *exit_message);
 
 /* Callbacks for gpiolib */
 static int cpc_gpio_get(struct gpio_chip *gc, unsigned int pin);
+static int cpc_gpio_get_multiple(struct gpio_chip *gc, unsigned long *mask,
+                                 unsigned long *bits);
 static void cpc_gpio_set(struct gpio_chip *gc, unsigned int pin, int value);
+static void cpc_gpio_set_multiple(struct gpio_chip *gc, unsigned long *mask,
+                                  unsigned long *bits);
 static int cpc_gpio_direction_output(struct gpio_chip *gc, unsigned int pin, int value);
 static int cpc_gpio_direction_input(struct gpio_chip *gc, unsigned int pin);
 static int cpc_gpio_get_direction(struct gpio_chip *gc, unsigned int pin);
@@ -138,15 +174,25 @@ static int cpc_gpio_direction_disabled(struct gpio_chip *gc, unsigned int pin);
 static struct cpc_gpio_chip* cpc_find_chip(u64 uid);
 static int cpc_register_chip(struct cpc_gpio_chip *chip);
 static int cpc_status_to_errno(enum cpc_status_t status);
+static void cpc_gpio_reset_signal(struct semaphore *signal);
+static int cpc_gpio_parse_gpio_names(struct nlattr *na, u32 gpio_count, char ***gpio_names);
+static int cpc_gpio_add_lines(u64 uid, u32 first_line, u32 count, char **new_names);
+static int cpc_gpio_rename_chip(u64 uid, char *chip_label, u32 gpio_count, char **new_names);
 
 /* Internal functions that require careful locking */
 static struct cpc_gpio_chip* __cpc_find_chip(u64 uid);
 static void __cpc_free_chip(struct cpc_gpio_chip *chip);
 static void __cpc_unregister_chip(struct cpc_gpio_chip *chip);
 static bool __cpc_gpiochip_is_requested(struct cpc_gpio_chip *chip);
+static int __cpc_gpiochip_add_again(struct cpc_gpio_chip *chip);
+static u32 __cpc_gpio_next_batch_id(struct cpc_gpio_chip *chip);
 static int __cpc_gpio_get(struct cpc_gpio_chip *chip, unsigned int pin);
+static int __cpc_gpio_get_multiple(struct cpc_gpio_chip *chip, unsigned long *mask,
+                                   unsigned long *bits);
 static int __cpc_gpio_set(struct cpc_gpio_chip *chip, unsigned int pin,
                           int value);
+static int __cpc_gpio_set_multiple(struct cpc_gpio_chip *chip, unsigned long *mask,
+                                   unsigned long *bits);
 static int __cpc_gpio_set_config(struct gpio_chip *gc, unsigned int pin,
                                  int config);
 static int ____cpc_gpio_set_config(struct cpc_gpio_chip *chip, unsigned int pin,
@@ -218,6 +264,7 @@ static void __cpc_free_chip(struct cpc_gpio_chip *chip)
   pr_info("%s: uid: %llu\n", __func__, chip->uid);
 
   kfree(chip->lines);
+  kfree(chip->label);
 
   for (i = 0; i < chip->gpio_count; i++) {
     kfree(chip->gpio_names[i]);
@@ -252,6 +299,28 @@ static bool __cpc_gpiochip_is_requested(struct cpc_gpio_chip *chip)
   return false;
 }
 
+// Called with chip->lock held on an unregistered chip, the lock is released
+// as gpiochip_add_data reads the directions, which takes chip->lock
+static int __cpc_gpiochip_add_again(struct cpc_gpio_chip *chip)
+{
+  int ret;
+
+  mutex_unlock(&chip->lock);
+
+  ret = gpiochip_add_data(&chip->gc, chip);
+  if (ret) {
+    pr_err("%s: gpiochip_add_data failed: %d\n", __func__, ret);
+    chip->initialized = false;
+    return ret;
+  }
+
+  chip->registered = true;
+
+  pr_info("%s: uid: %llu, ngpio: %u\n", __func__, chip->uid, chip->gc.ngpio);
+
+  return 0;
+}
+
 static struct nla_policy cpc_gpio_genl_policy[__CPC_GPIO_GENL_ATTR_MAX] = {
   [CPC_GPIO_GENL_ATTR_UNSPEC] = { .type = NLA_UNSPEC },
   [CPC_GPIO_GENL_ATTR_STATUS] = { .type = NLA_U32 },
@@ -267,6 +336,10 @@ static struct nla_policy cpc_gpio_genl_policy[__CPC_GPIO_GENL_ATTR_MAX] = {
   [CPC_GPIO_GENL_ATTR_GPIO_VALUE] = { .type = NLA_U32 },
   [CPC_GPIO_GENL_ATTR_GPIO_CONFIG] = { .type = NLA_U32 },
   [CPC_GPIO_GENL_ATTR_GPIO_DIRECTION] = { .type = NLA_U32 },
+  [CPC_GPIO_GENL_ATTR_GPIO_VALUES] = { .type = NLA_BINARY },
+  [CPC_GPIO_GENL_ATTR_GPIO_STATUSES] = { .type = NLA_BINARY },
+  [CPC_GPIO_GENL_ATTR_GPIO_PINS] = { .type = NLA_BINARY },
+  [CPC_GPIO_GENL_ATTR_BATCH_ID] = { .type = NLA_U32 },
 };
 
 struct genl_ops cpc_gpio_genl_ops[] = {
@@ -293,6 +366,26 @@ struct genl_ops cpc_gpio_genl_ops[] = {
   {
     .cmd = CPC_GPIO_GENL_CMD_SET_GPIO_DIRECTION,
     .doit = cpc_gpio_genl_callback_set_gpio_direction,
+  },
+  {
+    .cmd = CPC_GPIO_GENL_CMD_EXIT,
+    .doit = cpc_gpio_genl_callback_exit,
+  },
+  {
+    .cmd = CPC_GPIO_GENL_CMD_GET_ALL_GPIO_VALUES,
+    .doit = cpc_gpio_genl_callback_get_all_gpio_values,
+  },
+  {
+    .cmd = CPC_GPIO_GENL_CMD_SET_GPIO_VALUES,
+    .doit = cpc_gpio_genl_callback_set_gpio_values,
+  },
+  {
+    .cmd = CPC_GPIO_GENL_CMD_ADD_GPIOS,
+    .doit = cpc_gpio_genl_callback_add_gpios,
+  },
+  {
+    .cmd = CPC_GPIO_GENL_CMD_UPDATE_NAMES,
+    .doit = cpc_gpio_genl_callback_update_names,
   }
 };
 
@@ -329,6 +422,8 @@ static int cpc_status_to_errno(enum cpc_status_t status)
       return -EPIPE;
     case CPC_STATUS_PROTOCOL_ERROR:
       return -EPROTO;
+    case CPC_STATUS_INVALID_ARGUMENT:
+      return -EINVAL;
     case CPC_STATUS_UNKNOWN:
       return -EIO;
     default:
@@ -336,7 +431,8 @@ static int cpc_status_to_errno(enum cpc_status_t status)
   }
 }
 
-static int cpc_gpio_register_chip(u64 uid, char *chip_label, u16 ngpio, char **gpio_names)
+static int cpc_gpio_register_chip(u64 uid, char *chip_label, u16 ngpio, char **gpio_names,
+                                  u8 bridge_minor)
 {
   struct cpc_gpio_chip *chip;
   int ret;
@@ -371,6 +467,14 @@ static int cpc_gpio_register_chip(u64 uid, char *chip_label, u16 ngpio, char **g
     goto free_gpio_names;
   }
 
+  // The label is owned by the request, the chip may be registered again later
+  chip->label = kstrdup(chip_label, GFP_KERNEL);
+  if (!chip->label) {
+    kfree(chip);
+    ret = -ENOMEM;
+    goto free_gpio_names;
+  }
+
   chip->gpio_names = gpio_names;
   chip->gpio_count = ngpio;
 
@@ -378,7 +482,7 @@ static int cpc_gpio_register_chip(u64 uid, char *chip_label, u16 ngpio, char **g
 
   // Context
   chip->uid = uid;
-  chip->gc.label = chip_label;
+  chip->gc.label = chip->label;
   chip->gc.base = -1;
   chip->gc.names = (const char * const *) gpio_names;
   chip->gc.ngpio = ngpio;
@@ -391,6 +495,15 @@ static int cpc_gpio_register_chip(u64 uid, char *chip_label, u16 ngpio, char **g
   chip->gc.set_config = cpc_gpio_set_config;
   chip->gc.free = cpc_gpio_free;
 
+  /* gpiolib falls back to one request per line without them */
+  if (bridge_minor >= CPC_GPIO_BRIDGE_BATCH_MINOR) {
+    chip->gc.get_multiple = cpc_gpio_get_multiple;
+    chip->gc.set_multiple = cpc_gpio_set_multiple;
+  }
+
+  sema_init(&chip->get_multiple_signal, 0);
+  sema_init(&chip->set_multiple_signal, 0);
+
   chip->lines = kcalloc(chip->gc.ngpio, sizeof(*chip->lines), GFP_KERNEL);
   if (!chip->lines) {
     ret = -ENOMEM;
@@ -426,6 +539,7 @@ static int cpc_gpio_register_chip(u64 uid, char *chip_label, u16 ngpio, char **g
 
   free_chip:
   mutex_destroy(&chip->lock);
+  kfree(chip->label);
   kfree(chip);
 
   free_gpio_names:
@@ -437,6 +551,181 @@ static int cpc_gpio_register_chip(u64 uid, char *chip_label, u16 ngpio, char **g
   return ret;
 }
 
+// Lines are appended by registering the chip again, gpiolib cannot grow a
+// registered chip. The lines keep their state, their consumers would not.
+static int cpc_gpio_add_lines(u64 uid, u32 first_line, u32 count, char **new_names)
+{
+  struct cpc_gpio_chip *chip;
+  struct cpc_gpio_line *lines = NULL;
+  char **gpio_names = NULL;
+  u16 ngpio;
+  int ret = 0;
+  int i;
+
+  mutex_lock(&cpc_gpio_chip_list_lock);
+
+  chip = __cpc_find_chip(uid);
+  if (!chip || !chip->registered) {
+    pr_err("%s: chip not registered (uid: %llu)\n", __func__, uid);
+    ret = -ENODEV;
+    goto unlock_list;
+  }
+
+  mutex_lock(&chip->lock);
+
+  if (!chip->initialized) {
+    ret = -ENODEV;
+    goto unlock_chip;
+  }
+
+  if (first_line != chip->gc.ngpio || count == 0 || chip->gc.ngpio + count > U16_MAX) {
+    pr_err("%s: %u lines from line %u do not follow the %u registered lines\n", __func__,
+           count, first_line, chip->gc.ngpio);
+    ret = -EINVAL;
+    goto unlock_chip;
+  }
+
+  if (__cpc_gpiochip_is_requested(chip)) {
+    ret = -EBUSY;
+    goto unlock_chip;
+  }
+
+  ngpio = chip->gc.ngpio + count;
+
+  lines = kcalloc(ngpio, sizeof(*lines), GFP_KERNEL);
+  gpio_names = kcalloc(ngpio, sizeof(char *), GFP_KERNEL);
+  if (!lines || !gpio_names) {
+    pr_err("%s: kcalloc failed\n", __func__);
+    kfree(lines);
+    kfree(gpio_names);
+    ret = -ENOMEM;
+    goto unlock_chip;
+  }
+
+  // No request is in flight under chip->lock, the signals start over
+  for (i = 0; i < ngpio; i++) {
+    if (i < chip->gc.ngpio) {
+      lines[i].value = chip->lines[i].value;
+      lines[i].direction = chip->lines[i].direction;
+      lines[i].status = chip->lines[i].status;
+      gpio_names[i] = chip->gpio_names[i];
+    } else {
+      lines[i].direction = GPIO_LINE_DIRECTION_IN;
+      gpio_names[i] = new_names[i - chip->gc.ngpio];
+    }
+    sema_init(&lines[i].signal, 0);
+  }
+
+  __cpc_unregister_chip(chip);
+
+  // The names are now owned by the chip
+  kfree(new_names);
+  new_names = NULL;
+
+  kfree(chip->lines);
+  kfree(chip->gpio_names);
+  chip->lines = lines;
+  chip->gpio_names = gpio_names;
+  chip->gpio_count = ngpio;
+  chip->gc.names = (const char * const *) gpio_names;
+  chip->gc.ngpio = ngpio;
+
+  ret = __cpc_gpiochip_add_again(chip);
+  goto unlock_list;
+
+  unlock_chip:
+  mutex_unlock(&chip->lock);
+
+  unlock_list:
+  mutex_unlock(&cpc_gpio_chip_list_lock);
+
+  if (new_names) {
+    for (i = 0; i < count; i++) {
+      kfree(new_names[i]);
+    }
+    kfree(new_names);
+  }
+
+  return ret;
+}
+
+// gpiolib takes the label and names when the chip is registered, renaming
+// registers it again. The lines and their state are left as they are.
+static int cpc_gpio_rename_chip(u64 uid, char *chip_label, u32 gpio_count, char **new_names)
+{
+  struct cpc_gpio_chip *chip;
+  char *label;
+  int ret = 0;
+  int i;
+
+  mutex_lock(&cpc_gpio_chip_list_lock);
+
+  chip = __cpc_find_chip(uid);
+  if (!chip || !chip->registered) {
+    pr_err("%s: chip not registered (uid: %llu)\n", __func__, uid);
+    ret = -ENODEV;
+    goto unlock_list;
+  }
+
+  mutex_lock(&chip->lock);
+
+  if (!chip->initialized) {
+    ret = -ENODEV;
+    goto unlock_chip;
+  }
+
+  if (gpio_count != chip->gc.ngpio) {
+    pr_err("%s: %u names for the %u registered lines\n", __func__, gpio_count,
+           chip->gc.ngpio);
+    ret = -EINVAL;
+    goto unlock_chip;
+  }
+
+  if (__cpc_gpiochip_is_requested(chip)) {
+    ret = -EBUSY;
+    goto unlock_chip;
+  }
+
+  label = kstrdup(chip_label, GFP_KERNEL);
+  if (!label) {
+    ret = -ENOMEM;
+    goto unlock_chip;
+  }
+
+  __cpc_unregister_chip(chip);
+
+  for (i = 0; i < chip->gpio_count; i++) {
+    kfree(chip->gpio_names[i]);
+  }
+  kfree(chip->gpio_names);
+  kfree(chip->label);
+
+  // The names are now owned by the chip
+  chip->gpio_names = new_names;
+  new_names = NULL;
+  chip->label = label;
+  chip->gc.names = (const char * const *) chip->gpio_names;
+  chip->gc.label = chip->label;
+
+  ret = __cpc_gpiochip_add_again(chip);
+  goto unlock_list;
+
+  unlock_chip:
+  mutex_unlock(&chip->lock);
+
+  unlock_list:
+  mutex_unlock(&cpc_gpio_chip_list_lock);
+
+  if (new_names) {
+    for (i = 0; i < gpio_count; i++) {
+      kfree(new_names[i]);
+    }
+    kfree(new_names);
+  }
+
+  return ret;
+}
+
 static int cpc_gpio_multicast_get_gpio_value(u64 uid, unsigned int pin)
 {
   int rc;
@@ -493,6 +782,62 @@ static int cpc_gpio_multicast_get_gpio_value(u64 uid, unsigned int pin)
   return ret;
 }
 
+static int cpc_gpio_multicast_get_all_gpio_values(u64 uid, u32 batch_id)
+{
+  int rc;
+  int ret = 0;
+  struct sk_buff *skb;
+  void *genl_msg;
+
+  skb = nlmsg_new(NLMSG_GOODSIZE, GFP_KERNEL);
+  if (!skb) {
+    pr_err("%s: nlmsg_new failed\n", __func__);
+    ret = -1;
+    goto done;
+  }
+
+  genl_msg = genlmsg_put(skb, 0, 0,
+                         &cpc_gpio_genl_family, 0,
+                         CPC_GPIO_GENL_CMD_GET_ALL_GPIO_VALUES);
+  if (!genl_msg) {
+    pr_err("%s: genlmsg_put failed\n", __func__);
+    ret = -1;
+    goto done;
+  }
+
+  rc = nla_put_u64_64bit(skb, CPC_GPIO_GENL_ATTR_UNIQUE_ID, uid, CPC_GPIO_GENL_ATTR_UNSPEC);
+  if (rc != 0) {
+    pr_err("%s: nla_put_u64_64bit failed: %d\n", __func__, rc);
+    ret = rc;
+    goto done;
+  }
+
+  rc = nla_put_u32(skb, CPC_GPIO_GENL_ATTR_BATCH_ID, batch_id);
+  if (rc != 0) {
+    pr_err("%s: nla_put_u32 failed: %d\n", __func__, rc);
+    ret = rc;
+    goto done;
+  }
+
+  genlmsg_end(skb, genl_msg);
+  rc = genlmsg_multicast(&cpc_gpio_genl_family, skb, 0, 0, GFP_KERNEL);
+  skb = NULL;
+
+  if (rc != 0 && rc != -ESRCH) {
+    pr_err("%s: genlmsg_multicast failed: %d\n", __func__, rc);
+    ret = rc;
+    goto done;
+  }
+
+  done:
+  if (skb) {
+    nlmsg_free(skb);
+    skb = NULL;
+  }
+
+  return ret;
+}
+
 static int cpc_gpio_multicast_set_gpio_value(u64 uid, unsigned int pin,
                                              unsigned int value)
 {
@@ -557,6 +902,77 @@ static int cpc_gpio_multicast_set_gpio_value(u64 uid, unsigned int pin,
   return ret;
 }
 
+static int cpc_gpio_multicast_set_gpio_values(u64 uid, u32 batch_id, u32 *pins, u32 *values,
+                                              int count)
+{
+  int rc;
+  int ret = 0;
+  struct sk_buff *skb;
+  void *genl_msg;
+
+  skb = nlmsg_new(NLMSG_GOODSIZE, GFP_KERNEL);
+  if (!skb) {
+    pr_err("%s: nlmsg_new failed\n", __func__);
+    ret = -1;
+    goto done;
+  }
+
+  genl_msg = genlmsg_put(skb, 0, 0,
+                         &cpc_gpio_genl_family, 0,
+                         CPC_GPIO_GENL_CMD_SET_GPIO_VALUES);
+  if (!genl_msg) {
+    pr_err("%s: genlmsg_put failed\n", __func__);
+    ret = -1;
+    goto done;
+  }
+
+  rc = nla_put_u64_64bit(skb, CPC_GPIO_GENL_ATTR_UNIQUE_ID, uid, CPC_GPIO_GENL_ATTR_UNSPEC);
+  if (rc != 0) {
+    pr_err("%s: nla_put_u64_64bit failed: %d\n", __func__, rc);
+    ret = rc;
+    goto done;
+  }
+
+  rc = nla_put_u32(skb, CPC_GPIO_GENL_ATTR_BATCH_ID, batch_id);
+  if (rc != 0) {
+    pr_err("%s: nla_put_u32 failed: %d\n", __func__, rc);
+    ret = rc;
+    goto done;
+  }
+
+  rc = nla_put(skb, CPC_GPIO_GENL_ATTR_GPIO_PINS, count * sizeof(u32), pins);
+  if (rc != 0) {
+    pr_err("%s: nla_put failed: %d\n", __func__, rc);
+    ret = rc;
+    goto done;
+  }
+
+  rc = nla_put(skb, CPC_GPIO_GENL_ATTR_GPIO_VALUES, count * sizeof(u32), values);
+  if (rc != 0) {
+    pr_err("%s: nla_put failed: %d\n", __func__, rc);
+    ret = rc;
+    goto done;
+  }
+
+  genlmsg_end(skb, genl_msg);
+  rc = genlmsg_multicast(&cpc_gpio_genl_family, skb, 0, 0, GFP_KERNEL);
+  skb = NULL;
+
+  if (rc != 0 && rc != -ESRCH) {
+    pr_err("%s: genlmsg_multicast failed: %d\n", __func__, rc);
+    ret = rc;
+    goto done;
+  }
+
+  done:
+  if (skb) {
+    nlmsg_free(skb);
+    skb = NULL;
+  }
+
+  return ret;
+}
+
 static int cpc_gpio_multicast_set_gpio_config(u64 uid, unsigned int pin, unsigned int config)
 {
   int rc;
@@ -739,20 +1155,62 @@ static int cpc_gpio_multicast_exit(const char *exit_message)
   return ret;
 }
 
-int cpc_gpio_genl_callback_init(struct sk_buff *sender_skb,
-                                struct genl_info *info)
+// One NUL terminated name per GPIO, back to back
+static int cpc_gpio_parse_gpio_names(struct nlattr *na, u32 gpio_count, char ***gpio_names)
 {
-  struct nlattr *na = NULL;
-  struct sk_buff *reply_skb = NULL;
-  void *msg_head = NULL;
-  char **gpio_names = NULL;
-  char *chip_label = NULL;
-  int i = 0;
-  u32 gpio_count = 0;
-  s32 err = 0;
-  u64 uid = 0;
+  size_t len = 0;
+  int gpio_name_count = 0;
+  int err = 0;
+  int i;
 
-  pr_debug("%s\n", __func__);
+  char *raw_names = (char *) nla_data(na);
+  char **names = kcalloc(gpio_count, sizeof(char *), GFP_KERNEL);
+  if (!names) {
+    pr_err("%s: kcalloc failed\n", __func__);
+    return -ENOMEM;
+  }
+
+  for (i = 0; i < gpio_count; i++) {
+    len = strlen(raw_names) + 1;
+    names[gpio_name_count] = kzalloc(len, GFP_KERNEL);
+    if (!names[gpio_name_count]) {
+      pr_err("%s: kzalloc failed\n", __func__);
+      err = -ENOMEM;
+      break;
+    }
+    memcpy(names[gpio_name_count], raw_names, len);
+    raw_names = raw_names + len;
+    gpio_name_count++;
+  }
+
+  if (gpio_count != gpio_name_count) {
+    pr_err("%s: gpio_count != gpio_name_count\n", __func__);
+    for (i = 0; i < gpio_count; i++) {
+      kfree(names[i]);
+    }
+    kfree(names);
+    return err ? err : -EINVAL;
+  }
+
+  *gpio_names = names;
+
+  return 0;
+}
+
+int cpc_gpio_genl_callback_init(struct sk_buff *sender_skb,
+                                struct genl_info *info)
+{
+  struct nlattr *na = NULL;
+  struct sk_buff *reply_skb = NULL;
+  void *msg_head = NULL;
+  char **gpio_names = NULL;
+  char *chip_label = NULL;
+  u32 gpio_count = 0;
+  u8 bridge_minor = 0;
+  s32 err = 0;
+  u64 uid = 0;
+
+  pr_debug("%s\n", __func__);
 
   if (!info) {
     pr_err("%s: info is NULL\n", __func__);
@@ -797,45 +1255,20 @@ int cpc_gpio_genl_callback_init(struct sk_buff *sender_skb,
     err = -EINVAL;
     goto done;
   } else {
-    size_t len = 0;
-    int gpio_name_count = 0;
-
-    char *raw_names = (char *) nla_data(na);
-    gpio_names = kcalloc(gpio_count, sizeof(char *), GFP_KERNEL);
-    if (!gpio_names) {
-      pr_err("%s: kcalloc failed\n", __func__);
-      err = -ENOMEM;
+    err = cpc_gpio_parse_gpio_names(na, gpio_count, &gpio_names);
+    if (err) {
       goto done;
     }
+  }
 
-    for (i = 0; i < gpio_count; i++) {
-      len = strlen(raw_names) + 1;
-      gpio_names[gpio_name_count] = kzalloc(len, GFP_KERNEL);
-      if (!gpio_names[gpio_name_count]) {
-        pr_err("%s: kzalloc failed\n", __func__);
-        err = -ENOMEM;
-        break;
-      }
-      memcpy(gpio_names[gpio_name_count], raw_names, len);
-      raw_names = raw_names + len;
-      gpio_name_count++;
-    }
-
-    if (gpio_count != gpio_name_count) {
-      pr_err("%s: gpio_count != gpio_name_count\n", __func__);
-      for (i = 0; i < gpio_count; i++) {
-        kfree(gpio_names[i]);
-      }
-      kfree(gpio_names);
-      if (!err) {
-        err = -EINVAL;
-      }
-      goto done;
-    }
+  // Bridges predating the batch commands do not announce their version
+  na = info->attrs[CPC_GPIO_GENL_ATTR_VERSION_MINOR];
+  if (na) {
+    bridge_minor = nla_get_u8(na);
   }
 
   // Register chip
-  err = cpc_gpio_register_chip(uid, chip_label, gpio_count, gpio_names);
+  err = cpc_gpio_register_chip(uid, chip_label, gpio_count, gpio_names, bridge_minor);
 
   done:
   // 1) Prepare message.
@@ -1056,6 +1489,358 @@ int cpc_gpio_genl_callback_get_gpio_value(struct sk_buff *sender_skb,
   return 0;
 }
 
+int cpc_gpio_genl_callback_get_all_gpio_values(struct sk_buff *sender_skb,
+                                               struct genl_info *info)
+{
+  struct cpc_gpio_chip *chip = NULL;
+  struct nlattr *na = NULL;
+  struct nlattr *na_statuses = NULL;
+  u32 *gpio_values;
+  u32 *statuses;
+  u32 batch_id;
+  u32 status;
+  u64 uid;
+  int i;
+
+  if (!info) {
+    pr_err("%s: info is NULL\n", __func__);
+    return -EINVAL;
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_UNIQUE_ID];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_UNIQUE_ID);
+    return -EINVAL;
+  } else {
+    uid = nla_get_u64(na);
+  }
+
+  chip = cpc_find_chip(uid);
+  if (!chip) {
+    pr_err("%s: chip not found (uid: %llu)\n", __func__, uid);
+    return -EINVAL;
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_BATCH_ID];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_BATCH_ID);
+    return -EINVAL;
+  } else {
+    batch_id = nla_get_u32(na);
+  }
+
+  // The reply of a request that already timed out
+  if (batch_id != READ_ONCE(chip->get_multiple_id)) {
+    pr_debug("%s: dropping stale reply (uid: %llu, batch: %u)\n", __func__, uid, batch_id);
+    return 0;
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_STATUS];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_STATUS);
+    return -EINVAL;
+  } else {
+    status = nla_get_u32(na);
+  }
+
+  // Both arrays hold one u32 per line, in line order
+  na = info->attrs[CPC_GPIO_GENL_ATTR_GPIO_VALUES];
+  na_statuses = info->attrs[CPC_GPIO_GENL_ATTR_GPIO_STATUSES];
+  if (!na || !na_statuses
+      || nla_len(na) != chip->gc.ngpio * sizeof(u32)
+      || nla_len(na_statuses) != chip->gc.ngpio * sizeof(u32)) {
+    pr_err("%s: Malformed info->attrs[%d] or info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_GPIO_VALUES, CPC_GPIO_GENL_ATTR_GPIO_STATUSES);
+    return -EINVAL;
+  }
+
+  gpio_values = nla_data(na);
+  statuses = nla_data(na_statuses);
+
+  for (i = 0; i < chip->gc.ngpio; i++) {
+    chip->lines[i].status = status == CPC_STATUS_OK ? statuses[i] : status;
+    chip->lines[i].value = !!gpio_values[i];
+  }
+
+  up(&chip->get_multiple_signal);
+
+  return 0;
+}
+
+int cpc_gpio_genl_callback_set_gpio_values(struct sk_buff *sender_skb,
+                                           struct genl_info *info)
+{
+  struct cpc_gpio_chip *chip = NULL;
+  struct nlattr *na = NULL;
+  u32 batch_id;
+  u64 uid;
+
+  if (!info) {
+    pr_err("%s: info is NULL\n", __func__);
+    return -EINVAL;
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_UNIQUE_ID];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_UNIQUE_ID);
+    return -EINVAL;
+  } else {
+    uid = nla_get_u64(na);
+  }
+
+  chip = cpc_find_chip(uid);
+  if (!chip) {
+    pr_err("%s: chip not found (uid: %llu)\n", __func__, uid);
+    return -EINVAL;
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_BATCH_ID];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_BATCH_ID);
+    return -EINVAL;
+  } else {
+    batch_id = nla_get_u32(na);
+  }
+
+  // The reply of a request that already timed out
+  if (batch_id != READ_ONCE(chip->set_multiple_id)) {
+    pr_debug("%s: dropping stale reply (uid: %llu, batch: %u)\n", __func__, uid, batch_id);
+    return 0;
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_STATUS];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_STATUS);
+    return -EINVAL;
+  } else {
+    chip->set_multiple_status = nla_get_u32(na);
+  }
+
+  up(&chip->set_multiple_signal);
+
+  return 0;
+}
+
+int cpc_gpio_genl_callback_add_gpios(struct sk_buff *sender_skb,
+                                     struct genl_info *info)
+{
+  struct nlattr *na = NULL;
+  struct sk_buff *reply_skb = NULL;
+  void *msg_head = NULL;
+  char **gpio_names = NULL;
+  u32 first_line = 0;
+  u32 gpio_count = 0;
+  s32 err = 0;
+  u64 uid = 0;
+
+  pr_debug("%s\n", __func__);
+
+  if (!info) {
+    pr_err("%s: info is NULL\n", __func__);
+    return -EINVAL;
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_UNIQUE_ID];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_UNIQUE_ID);
+    err = -EINVAL;
+    goto done;
+  } else {
+    uid = nla_get_u64(na);
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_GPIO_PIN];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_GPIO_PIN);
+    err = -EINVAL;
+    goto done;
+  } else {
+    first_line = nla_get_u32(na);
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_GPIO_COUNT];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_GPIO_COUNT);
+    err = -EINVAL;
+    goto done;
+  } else {
+    gpio_count = nla_get_u32(na);
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_GPIO_NAMES];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_GPIO_NAMES);
+    err = -EINVAL;
+    goto done;
+  } else {
+    err = cpc_gpio_parse_gpio_names(na, gpio_count, &gpio_names);
+    if (err) {
+      goto done;
+    }
+  }
+
+  err = cpc_gpio_add_lines(uid, first_line, gpio_count, gpio_names);
+
+  done:
+  // 1) Prepare message.
+  reply_skb = genlmsg_new(NLMSG_GOODSIZE, GFP_KERNEL);
+  if (!reply_skb) {
+    pr_err("%s: genlmsg_new failed\n", __func__);
+    err = -ENOMEM;
+    goto genl_error;
+  }
+
+  msg_head =
+    genlmsg_put(reply_skb, info->snd_portid, info->snd_seq,
+                &cpc_gpio_genl_family, 0, CPC_GPIO_GENL_CMD_ADD_GPIOS);
+  if (!msg_head) {
+    pr_err("%s: genlmsg_put failed\n", __func__);
+    err = -ENOMEM;
+    goto genl_error;
+  }
+
+  // 2) Set message.
+  err = nla_put_u32(reply_skb, CPC_GPIO_GENL_ATTR_STATUS, -err);
+  if (err != 0) {
+    pr_err("%s: nla_put_u32 failed: %d\n", __func__, err);
+    goto genl_error;
+  }
+  genlmsg_end(reply_skb, msg_head);
+
+  // 3) Send message.
+  err = genlmsg_reply(reply_skb, info);
+  reply_skb = NULL;
+  if (err != 0) {
+    pr_err("%s: genlmsg_reply failed: %d\n", __func__, err);
+    goto genl_error;
+  }
+
+  genl_error:
+  if (reply_skb) {
+    nlmsg_free(reply_skb);
+    reply_skb = NULL;
+  }
+
+  return err;
+}
+
+int cpc_gpio_genl_callback_update_names(struct sk_buff *sender_skb,
+                                        struct genl_info *info)
+{
+  struct nlattr *na = NULL;
+  struct sk_buff *reply_skb = NULL;
+  void *msg_head = NULL;
+  char **gpio_names = NULL;
+  char *chip_label = NULL;
+  u32 gpio_count = 0;
+  s32 err = 0;
+  u64 uid = 0;
+
+  pr_debug("%s\n", __func__);
+
+  if (!info) {
+    pr_err("%s: info is NULL\n", __func__);
+    return -EINVAL;
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_UNIQUE_ID];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_UNIQUE_ID);
+    err = -EINVAL;
+    goto done;
+  } else {
+    uid = nla_get_u64(na);
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_GPIO_COUNT];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_GPIO_COUNT);
+    err = -EINVAL;
+    goto done;
+  } else {
+    gpio_count = nla_get_u32(na);
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_CHIP_LABEL];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_CHIP_LABEL);
+    err = -EINVAL;
+    goto done;
+  } else {
+    chip_label = nla_data(na);
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_GPIO_NAMES];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_GPIO_NAMES);
+    err = -EINVAL;
+    goto done;
+  } else {
+    err = cpc_gpio_parse_gpio_names(na, gpio_count, &gpio_names);
+    if (err) {
+      goto done;
+    }
+  }
+
+  err = cpc_gpio_rename_chip(uid, chip_label, gpio_count, gpio_names);
+
+  done:
+  // 1) Prepare message.
+  reply_skb = genlmsg_new(NLMSG_GOODSIZE, GFP_KERNEL);
+  if (!reply_skb) {
+    pr_err("%s: genlmsg_new failed\n", __func__);
+    err = -ENOMEM;
+    goto genl_error;
+  }
+
+  msg_head =
+    genlmsg_put(reply_skb, info->snd_portid, info->snd_seq,
+                &cpc_gpio_genl_family, 0, CPC_GPIO_GENL_CMD_UPDATE_NAMES);
+  if (!msg_head) {
+    pr_err("%s: genlmsg_put failed\n", __func__);
+    err = -ENOMEM;
+    goto genl_error;
+  }
+
+  // 2) Set message.
+  err = nla_put_u32(reply_skb, CPC_GPIO_GENL_ATTR_STATUS, -err);
+  if (err != 0) {
+    pr_err("%s: nla_put_u32 failed: %d\n", __func__, err);
+    goto genl_error;
+  }
+  genlmsg_end(reply_skb, msg_head);
+
+  // 3) Send message.
+  err = genlmsg_reply(reply_skb, info);
+  reply_skb = NULL;
+  if (err != 0) {
+    pr_err("%s: genlmsg_reply failed: %d\n", __func__, err);
+    goto genl_error;
+  }
+
+  genl_error:
+  if (reply_skb) {
+    nlmsg_free(reply_skb);
+    reply_skb = NULL;
+  }
+
+  return err;
+}
+
 int cpc_gpio_genl_callback_set_gpio_value(struct sk_buff *sender_skb,
                                           struct genl_info *info)
 {
@@ -1110,6 +1895,39 @@ int cpc_gpio_genl_callback_set_gpio_value(struct sk_buff *sender_skb,
   return 0;
 }
 
+// Last words of a failing bridge, logged before it deinitializes its chip
+int cpc_gpio_genl_callback_exit(struct sk_buff *sender_skb,
+                                struct genl_info *info)
+{
+  struct nlattr *na = NULL;
+  u64 uid;
+
+  if (!info) {
+    pr_err("%s: info is NULL\n", __func__);
+    return -EINVAL;
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_UNIQUE_ID];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_UNIQUE_ID);
+    return -EINVAL;
+  } else {
+    uid = nla_get_u64(na);
+  }
+
+  na = info->attrs[CPC_GPIO_GENL_ATTR_MESSAGE];
+  if (!na) {
+    pr_err("%s: No info->attrs[%d]\n", __func__,
+           CPC_GPIO_GENL_ATTR_MESSAGE);
+    return -EINVAL;
+  }
+
+  pr_err("%s: bridge exited (uid: %llu): %s\n", __func__, uid, (char *)nla_data(na));
+
+  return 0;
+}
+
 int cpc_gpio_genl_callback_set_gpio_config(struct sk_buff *sender_skb,
                                            struct genl_info *info)
 {
@@ -1258,6 +2076,76 @@ static int cpc_gpio_get(struct gpio_chip *gc, unsigned int pin)
   return value;
 }
 
+/* 0 is never issued, it marks a batch request that is no longer awaited */
+static u32 __cpc_gpio_next_batch_id(struct cpc_gpio_chip *chip)
+{
+  chip->batch_id++;
+  if (chip->batch_id == 0) {
+    chip->batch_id++;
+  }
+
+  return chip->batch_id;
+}
+
+/* Takes back the signal of a reply that arrived after its request timed out */
+static void cpc_gpio_reset_signal(struct semaphore *signal)
+{
+  while (down_trylock(signal) == 0) {
+  }
+}
+
+static int __cpc_gpio_get_multiple(struct cpc_gpio_chip *chip, unsigned long *mask,
+                                   unsigned long *bits)
+{
+  int ret = -EPIPE;
+  unsigned long timeout = msecs_to_jiffies(CPC_GPIO_TIMEOUT_MSEC);
+  unsigned int pin;
+
+  WRITE_ONCE(chip->get_multiple_id, __cpc_gpio_next_batch_id(chip));
+  cpc_gpio_reset_signal(&chip->get_multiple_signal);
+
+  cpc_gpio_multicast_get_all_gpio_values(chip->uid, chip->get_multiple_id);
+
+  if (down_timeout(&chip->get_multiple_signal, timeout) != 0) {
+    WRITE_ONCE(chip->get_multiple_id, 0);
+    pr_err("%s: cpc-gpio-bridge (uid: %llu) is unresponsive\n", __func__, chip->uid);
+    return ret;
+  }
+
+  ret = 0;
+  for_each_set_bit(pin, mask, chip->gc.ngpio) {
+    pr_debug("%s: uid = %llu, pin = %u, value = %d, status = %d\n", __func__, chip->uid, pin,
+             chip->lines[pin].value, chip->lines[pin].status);
+    ret = cpc_status_to_errno(chip->lines[pin].status);
+    if (ret != 0) {
+      break;
+    }
+    __assign_bit(pin, bits, chip->lines[pin].value);
+  }
+
+  return ret;
+}
+
+static int cpc_gpio_get_multiple(struct gpio_chip *gc, unsigned long *mask,
+                                 unsigned long *bits)
+{
+  struct cpc_gpio_chip *chip = gpiochip_get_data(gc);
+  int ret;
+
+  mutex_lock(&chip->lock);
+
+  if (!chip->initialized) {
+    mutex_unlock(&chip->lock);
+    return -ENODEV;
+  }
+
+  ret = __cpc_gpio_get_multiple(chip, mask, bits);
+
+  mutex_unlock(&chip->lock);
+
+  return ret;
+}
+
 static int __cpc_gpio_set(struct cpc_gpio_chip *chip, unsigned int pin,
                           int value)
 {
@@ -1294,6 +2182,74 @@ static void cpc_gpio_set(struct gpio_chip *gc, unsigned int pin, int value)
   mutex_unlock(&chip->lock);
 }
 
+static int __cpc_gpio_set_multiple(struct cpc_gpio_chip *chip, unsigned long *mask,
+                                   unsigned long *bits)
+{
+  int ret = -EPIPE;
+  unsigned long timeout = msecs_to_jiffies(CPC_GPIO_TIMEOUT_MSEC);
+  unsigned int pin;
+  u32 *pins;
+  u32 *values;
+  int count = 0;
+
+  pins = kcalloc(chip->gc.ngpio, sizeof(u32), GFP_KERNEL);
+  values = kcalloc(chip->gc.ngpio, sizeof(u32), GFP_KERNEL);
+  if (!pins || !values) {
+    pr_err("%s: kcalloc failed\n", __func__);
+    ret = -ENOMEM;
+    goto done;
+  }
+
+  for_each_set_bit(pin, mask, chip->gc.ngpio) {
+    pins[count] = pin;
+    values[count] = test_bit(pin, bits);
+    count++;
+  }
+
+  WRITE_ONCE(chip->set_multiple_id, __cpc_gpio_next_batch_id(chip));
+  cpc_gpio_reset_signal(&chip->set_multiple_signal);
+
+  cpc_gpio_multicast_set_gpio_values(chip->uid, chip->set_multiple_id, pins, values, count);
+
+  if (down_timeout(&chip->set_multiple_signal, timeout) != 0) {
+    WRITE_ONCE(chip->set_multiple_id, 0);
+    pr_err("%s: cpc-gpio-bridge (uid: %llu) is unresponsive\n", __func__, chip->uid);
+    goto done;
+  }
+
+  pr_debug("%s: uid = %llu, count = %d, status = %d\n", __func__, chip->uid, count,
+           chip->set_multiple_status);
+  ret = cpc_status_to_errno(chip->set_multiple_status);
+  if (ret == 0) {
+    for_each_set_bit(pin, mask, chip->gc.ngpio) {
+      chip->lines[pin].value = test_bit(pin, bits);
+    }
+  }
+
+  done:
+  kfree(pins);
+  kfree(values);
+
+  return ret;
+}
+
+static void cpc_gpio_set_multiple(struct gpio_chip *gc, unsigned long *mask,
+                                  unsigned long *bits)
+{
+  struct cpc_gpio_chip *chip = gpiochip_get_data(gc);
+
+  mutex_lock(&chip->lock);
+
+  if (!chip->initialized) {
+    mutex_unlock(&chip->lock);
+    return;
+  }
+
+  __cpc_gpio_set_multiple(chip, mask, bits);
+
+  mutex_unlock(&chip->lock);
+}
+
 static int ____cpc_gpio_set_config(struct cpc_gpio_chip *chip, unsigned int pin,
                                    int config)
 {
@@ -1353,6 +2309,18 @@ static int cpc_gpio_set_config(struct gpio_chip *gc, unsigned int pin,
     case PIN_CONFIG_DRIVE_PUSH_PULL:
       return __cpc_gpio_set_config(gc, pin, config_param);
 
+    /* Only enabling a buffer can be forwarded */
+    case PIN_CONFIG_INPUT_ENABLE:
+      if (pinconf_to_config_argument(config) == 0) {
+        break;
+      }
+      return __cpc_gpio_set_config(gc, pin, config_param);
+    case PIN_CONFIG_OUTPUT_ENABLE:
+      if (pinconf_to_config_argument(config) == 0) {
+        break;
+      }
+      return __cpc_gpio_set_config(gc, pin, config_param);
+
     case PIN_CONFIG_PERSIST_STATE:
       return 0;
     default: